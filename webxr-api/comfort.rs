@@ -0,0 +1,289 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Comfort features, which the session thread applies the same way for every device.
+
+use crate::ApiSpace;
+use crate::BaseSpace;
+use crate::Event;
+use crate::Floor;
+use crate::Frame;
+use crate::FrameUpdateEvent;
+use crate::InputFrame;
+use crate::Native;
+use crate::Space;
+use crate::View;
+use crate::Views;
+
+use euclid::{Angle, RigidTransform3D, Rotation3D, Vector3D};
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// How much of the vignette is left after each frame, once the movement causing it stops
+const VIGNETTE_DECAY: f32 = 0.9;
+
+/// Options for the comfort features of a session, see `Session::set_comfort_options`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComfortOptions {
+    /// Whether to darken the edges of the view while the viewer moves
+    pub vignette: bool,
+    /// The linear speed of the viewer at which the vignette is strongest, in meters per second
+    pub vignette_linear_speed: f32,
+    /// The angular speed of the viewer at which the vignette is strongest, in radians per second
+    pub vignette_angular_speed: f32,
+}
+
+impl Default for ComfortOptions {
+    fn default() -> Self {
+        ComfortOptions {
+            vignette: false,
+            vignette_linear_speed: 2.0,
+            vignette_angular_speed: PI / 2.0,
+        }
+    }
+}
+
+/// Works out the strength of the vignette, from the velocity of the viewer
+/// and the locomotion reported by the embedder
+#[derive(Default)]
+pub(crate) struct Vignette {
+    pub(crate) options: ComfortOptions,
+    locomotion: f32,
+    strength: f32,
+}
+
+impl Vignette {
+    /// Artificial movement of the viewer, between 0 (none) and 1 (as fast as it gets),
+    /// which devices can't know about from tracking
+    pub(crate) fn set_locomotion(&mut self, locomotion: f32) {
+        self.locomotion = locomotion.clamp(0.0, 1.0);
+    }
+
+    /// The strength of the vignette for a frame, between 0 (none) and 1
+    pub(crate) fn strength(&mut self, frame: &Frame) -> f32 {
+        if !self.options.vignette {
+            self.strength = 0.0;
+            return 0.0;
+        }
+        let mut target = self.locomotion;
+        if let Some(ref pose) = frame.pose {
+            if let Some(velocity) = pose.linear_velocity {
+                target = target.max(velocity.length() / self.options.vignette_linear_speed);
+            }
+            if let Some(velocity) = pose.angular_velocity {
+                target = target.max(velocity.length() / self.options.vignette_angular_speed);
+            }
+        }
+        // Come on at once, but fade out gradually so that the edges don't flicker
+        self.strength = target.min(1.0).max(self.strength * VIGNETTE_DECAY);
+        self.strength
+    }
+}
+
+/// Turns native space around the viewer, for snap turning. Devices keep reporting
+/// poses in their own native space, which are then moved into the turned one.
+///
+/// This lives in the event relay, which every frame and event goes through on its way
+/// to content, and spaces that content hands back are turned back with `space_to_device`.
+#[derive(Default)]
+pub(crate) struct Reorientation {
+    /// From the device's native space to the one content sees
+    offset: Option<RigidTransform3D<f32, Native, Native>>,
+}
+
+impl Reorientation {
+    /// Turn the viewer by `angle` radians (counterclockwise seen from above, so to the left)
+    /// on the spot, returning the pose of the new origin of each turned reference space
+    /// in its old one, for `Event::ReferenceSpaceChanged`
+    pub(crate) fn turn(
+        &mut self,
+        angle: f32,
+        viewer: Option<Vector3D<f32, Native>>,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> [(BaseSpace, RigidTransform3D<f32, ApiSpace, ApiSpace>); 3] {
+        let mut around = viewer.unwrap_or_else(Vector3D::zero);
+        around.y = 0.0;
+        let rotation: Rotation3D<f32, Native, Native> = Rotation3D::around_y(Angle::radians(angle));
+        let turn = RigidTransform3D::from_translation(-around)
+            .then(&RigidTransform3D::from_rotation(rotation))
+            .then(&RigidTransform3D::from_translation(around));
+        let offset = self.offset.unwrap_or_else(RigidTransform3D::identity);
+        self.offset = Some(offset.then(&turn));
+        let in_native = RigidTransform3D::from_untyped(&turn.inverse().to_untyped());
+        let in_floor = in_floor_space(turn.inverse(), floor_transform);
+        [
+            (BaseSpace::Local, in_native),
+            (BaseSpace::Floor, in_floor),
+            (BaseSpace::BoundedFloor, in_floor),
+        ]
+    }
+
+    /// From the device's native space to the one content sees
+    pub(crate) fn offset(&self) -> RigidTransform3D<f32, Native, Native> {
+        self.offset.unwrap_or_else(RigidTransform3D::identity)
+    }
+
+    /// Move a space that content created, relative to the turned spaces, back into
+    /// the device's own, for hit test sources and placed layers
+    pub(crate) fn space_to_device(
+        &self,
+        space: Space,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> Space {
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => return space,
+        };
+        let to_device = match space.base {
+            BaseSpace::Local => RigidTransform3D::from_untyped(&offset.inverse().to_untyped()),
+            BaseSpace::Floor | BaseSpace::BoundedFloor => {
+                in_floor_space(offset.inverse(), floor_transform)
+            }
+            // These follow the viewer and inputs, which are turned along with everything else
+            BaseSpace::Viewer
+            | BaseSpace::TargetRay(_)
+            | BaseSpace::Grip(_)
+            | BaseSpace::Joint(..) => {
+                return space;
+            }
+        };
+        Space {
+            base: space.base,
+            offset: space.offset.then(&to_device),
+        }
+    }
+
+    /// Move everything in a frame into the turned native space
+    pub(crate) fn apply(&self, frame: &mut Frame) {
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => return,
+        };
+        if let Some(ref mut pose) = frame.pose {
+            pose.transform = pose.transform.then(&offset);
+            pose.linear_velocity = rotate(&offset, pose.linear_velocity);
+            pose.angular_velocity = rotate(&offset, pose.angular_velocity);
+            match pose.views {
+                Views::Inline => (),
+                Views::Mono(ref mut view) => turn_view(view, &offset),
+                Views::Stereo(ref mut left, ref mut right) => {
+                    turn_view(left, &offset);
+                    turn_view(right, &offset);
+                }
+                Views::StereoSecondary(ref mut left, ref mut right, ref mut secondaries) => {
+                    turn_view(left, &offset);
+                    turn_view(right, &offset);
+                    for secondary in secondaries {
+                        turn_view(secondary, &offset);
+                    }
+                }
+                Views::Cubemap(
+                    ref mut front,
+                    ref mut left,
+                    ref mut right,
+                    ref mut top,
+                    ref mut bottom,
+                    ref mut back,
+                ) => {
+                    turn_view(front, &offset);
+                    turn_view(left, &offset);
+                    turn_view(right, &offset);
+                    turn_view(top, &offset);
+                    turn_view(bottom, &offset);
+                    turn_view(back, &offset);
+                }
+                Views::Custom(ref mut views) => {
+                    for view in views {
+                        turn_view(view, &offset);
+                    }
+                }
+            }
+        }
+        for input in &mut frame.inputs {
+            turn_input(input, &offset);
+        }
+        for result in &mut frame.hit_test_results {
+            result.space = result.space.then(&offset);
+            result.normal = offset.rotation.transform_vector3d(result.normal);
+        }
+        for image in &mut frame.tracked_images {
+            image.pose = image.pose.then(&offset);
+        }
+        for event in &mut frame.events {
+            if let FrameUpdateEvent::UpdateDetectedMesh(ref mut mesh) = *event {
+                mesh.pose = mesh.pose.then(&offset);
+            }
+        }
+    }
+
+    /// Move the frames and input poses sent with an event into the turned native space
+    pub(crate) fn apply_to_event(&self, event: &mut Event) {
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => return,
+        };
+        match *event {
+            Event::Select(_, _, _, _, ref mut frame) => self.apply(Arc::make_mut(frame)),
+            Event::TransientInputStart(_, ref mut input)
+            | Event::InputChanged(_, ref mut input) => turn_input(input, &offset),
+            _ => (),
+        }
+    }
+}
+
+/// The floor spaces are offset from native space, so a transform of native space is
+/// conjugated by the floor transform to get the same transform of the floor spaces
+fn in_floor_space(
+    transform: RigidTransform3D<f32, Native, Native>,
+    floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+) -> RigidTransform3D<f32, ApiSpace, ApiSpace> {
+    let floor = floor_transform
+        .map(|floor| floor.to_untyped())
+        .unwrap_or_else(RigidTransform3D::identity);
+    let in_floor = floor.inverse().then(&transform.to_untyped()).then(&floor);
+    RigidTransform3D::from_untyped(&in_floor)
+}
+
+fn rotate(
+    offset: &RigidTransform3D<f32, Native, Native>,
+    velocity: Option<Vector3D<f32, Native>>,
+) -> Option<Vector3D<f32, Native>> {
+    velocity.map(|velocity| offset.rotation.transform_vector3d(velocity))
+}
+
+fn turn_input(input: &mut InputFrame, offset: &RigidTransform3D<f32, Native, Native>) {
+    input.target_ray_origin = input.target_ray_origin.map(|o| o.then(offset));
+    input.grip_origin = input.grip_origin.map(|o| o.then(offset));
+    input.linear_velocity = rotate(offset, input.linear_velocity);
+    input.angular_velocity = rotate(offset, input.angular_velocity);
+    if let Some(ref mut hand) = input.hand {
+        *hand = Arc::new(hand.map(|joint_frame, _| {
+            joint_frame.map(|mut joint_frame| {
+                joint_frame.pose = joint_frame.pose.then(offset);
+                joint_frame.linear_velocity = rotate(offset, joint_frame.linear_velocity);
+                joint_frame.angular_velocity = rotate(offset, joint_frame.angular_velocity);
+                joint_frame
+            })
+        }));
+    }
+}
+
+fn turn_view<Eye>(view: &mut View<Eye>, offset: &RigidTransform3D<f32, Native, Native>) {
+    view.transform = view.transform.then(offset);
+}
+
+/// A change to the comfort features of a session, which embedders can send to every
+/// session with `MainThreadRegistry::send_comfort_msg`
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum ComfortMsg {
+    SetOptions(ComfortOptions),
+    /// How fast the embedder is moving the viewer artificially, between 0 and 1,
+    /// to strengthen the vignette
+    SetLocomotion(f32),
+    /// Turn the viewer by this many radians to the left (or right, if negative)
+    SnapTurn(f32),
+}
@@ -0,0 +1,31 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use euclid::RigidTransform3D;
+
+use std::sync::Arc;
+
+/// Normalized view coordinates, from (0, 0) at the top left of the view to (1, 1)
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum NormView {}
+
+/// Normalized depth buffer coordinates, from (0, 0) at the start of the buffer to (1, 1)
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum NormDepthBuffer {}
+
+/// How far away the real world is from the viewer, for sessions with the `depth-sensing` feature
+/// https://immersive-web.github.io/depth-sensing/#xrcpudepthinformation
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepthInformation {
+    pub width: u32,
+    pub height: u32,
+    pub norm_depth_buffer_from_norm_view: RigidTransform3D<f32, NormView, NormDepthBuffer>,
+    /// What to multiply the values in `data` by to get meters
+    pub raw_value_to_meters: f32,
+    /// The depth of each pixel, row by row, as a native-endian `u16`
+    pub data: Arc<[u8]>,
+}
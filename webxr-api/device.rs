@@ -5,24 +5,38 @@
 //! Traits to be implemented by backends
 
 use crate::ContextId;
+use crate::DeviceChangeNotifier;
+use crate::DeviceResources;
+use crate::DwellOptions;
 use crate::EnvironmentBlendMode;
 use crate::Error;
 use crate::Event;
+use crate::Feature;
 use crate::Floor;
 use crate::Frame;
+use crate::FrameSubscriptions;
 use crate::HitTestId;
+use crate::HitTestLimits;
 use crate::HitTestSource;
+use crate::InputId;
 use crate::InputSource;
+use crate::LatencyMarker;
+use crate::LayerGeometry;
 use crate::LayerId;
 use crate::LayerInit;
+use crate::LayerLimits;
+use crate::LayerQuality;
 use crate::Native;
+use crate::PendingSession;
 use crate::Quitter;
+use crate::SelectArbitrationOptions;
 use crate::Sender;
-use crate::Session;
 use crate::SessionBuilder;
 use crate::SessionInit;
 use crate::SessionMode;
+use crate::Space;
 use crate::Viewports;
+use crate::Visibility;
 
 use euclid::{Point2D, RigidTransform3D};
 
@@ -33,8 +47,57 @@ pub trait DiscoveryAPI<GL>: 'static {
         mode: SessionMode,
         init: &SessionInit,
         xr: SessionBuilder<GL>,
-    ) -> Result<Session, Error>;
+    ) -> Result<PendingSession, Error>;
     fn supports_session(&self, mode: SessionMode) -> bool;
+
+    /// Whether `request_session` creates sessions on a thread of their own with
+    /// `SessionBuilder::spawn`, and finds out there whether the device supports them.
+    /// The registry doesn't ask these discoveries `supports_session` before requesting
+    /// a session, since answering it may take as long as creating the session.
+    fn spawns_sessions(&self) -> bool {
+        false
+    }
+
+    /// Called when the discovery is registered. Discoveries that can tell when their
+    /// device comes and goes should notify the registry when it does.
+    fn set_device_change_notifier(&mut self, _notifier: DeviceChangeNotifier) {}
+
+    /// A description of the device for `Registry::enumerate_devices`, or `None` if it
+    /// isn't available right now. This shouldn't create a session.
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        Some(DeviceDescriptor {
+            name: String::from("Unknown device"),
+            vendor: String::new(),
+            modes: self.supported_modes(),
+            features: vec![],
+        })
+    }
+
+    /// The session modes the device supports, according to `supports_session`
+    fn supported_modes(&self) -> Vec<SessionMode> {
+        [
+            SessionMode::Inline,
+            SessionMode::ImmersiveVR,
+            SessionMode::ImmersiveAR,
+        ]
+        .iter()
+        .copied()
+        .filter(|&mode| self.supports_session(mode))
+        .collect()
+    }
+}
+
+/// What an embedder can show about a device in a device picker,
+/// see `Registry::enumerate_devices`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceDescriptor {
+    pub name: String,
+    /// The vendor of the device, or of the runtime it is used through
+    pub vendor: String,
+    pub modes: Vec<SessionMode>,
+    /// The features sessions on the device can be granted, as checked by `SessionInit::validate`
+    pub features: Vec<Feature>,
 }
 
 /// A trait for using an XR device
@@ -45,6 +108,13 @@ pub trait DeviceAPI: 'static {
     /// Destroy a layer
     fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId);
 
+    /// Move all layers from one GL context to another, e.g. after context loss
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error>;
+
     /// The transform from native coordinates to the floor.
     fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>>;
 
@@ -66,25 +136,116 @@ pub trait DeviceAPI: 'static {
     /// Quit the session
     fn quit(&mut self);
 
+    /// Release everything held by the device (layers, runtime handles, helper threads).
+    /// Called exactly once, after the session has stopped running.
+    fn shutdown(&mut self) {}
+
     fn set_quitter(&mut self, quitter: Quitter);
 
     fn update_clip_planes(&mut self, near: f32, far: f32);
 
+    /// Whether the session is being shown to the user, which devices that can
+    /// hide or blur sessions report changes to with `Event::VisibilityChange`
+    fn visibility(&self) -> Visibility {
+        Visibility::Visible
+    }
+
     fn environment_blend_mode(&self) -> EnvironmentBlendMode {
         // for VR devices, override for AR
         EnvironmentBlendMode::Opaque
     }
 
-    fn granted_features(&self) -> &[String];
+    fn granted_features(&self) -> &[Feature];
 
-    fn request_hit_test(&mut self, _source: HitTestSource) {
-        panic!("This device does not support requesting hit tests");
+    /// Whether the device can evaluate hit tests. Sessions on devices that can't
+    /// are never granted the `hit-test` feature, and never asked for hit tests.
+    fn supports_hit_test(&self) -> bool {
+        false
     }
 
-    fn cancel_hit_test(&mut self, _id: HitTestId) {
-        panic!("This device does not support hit tests");
+    fn request_hit_test(&mut self, _source: HitTestSource) {}
+
+    fn cancel_hit_test(&mut self, _id: HitTestId) {}
+
+    /// Trigger a haptic pulse on an input source, with an intensity between 0 and 1
+    /// and a duration in milliseconds. Devices without haptic actuators ignore this.
+    fn trigger_haptic(&mut self, _id: InputId, _intensity: f32, _duration: f64) {}
+
+    /// Set the fixed foveation level of a layer, between 0 and 1.
+    /// Devices that can't foveate ignore this.
+    fn set_foveation_level(&mut self, _context_id: ContextId, _layer_id: LayerId, _level: f32) {}
+
+    /// Set how the compositor filters a layer, or leave it to the runtime with `None`.
+    /// Devices that can't filter layers ignore this.
+    fn set_layer_quality(
+        &mut self,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _quality: Option<LayerQuality>,
+    ) {
     }
 
+    /// Move a quad, cylinder, equirect or cube layer, starting with the next frame.
+    /// Devices that don't support those layers ignore this.
+    fn set_layer_geometry(
+        &mut self,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _geometry: LayerGeometry,
+    ) {
+    }
+
+    /// Attach a quad, cylinder or equirect layer to a space, starting with the next frame.
+    /// Devices that don't support those layers ignore this.
+    fn attach_layer(&mut self, _context_id: ContextId, _layer_id: LayerId, _space: Space) {}
+
+    /// Render the view at `view` (in the order of the fields of `Views`) into a viewport
+    /// scaled by `scale`, starting with the next frame. Devices that can't render into
+    /// part of a viewport ignore this.
+    fn request_viewport_scale(&mut self, _view: usize, _scale: f32) {}
+
+    /// Content only uses the parts of frames in `subscriptions`, so the others
+    /// don't need to be computed
+    fn set_frame_subscriptions(&mut self, _subscriptions: FrameSubscriptions) {}
+
+    /// Darken the edges of the views by `strength`, between 0 (not at all) and 1,
+    /// when the current frame is ended. See `LayerManager::set_vignette`.
+    fn set_vignette(&mut self, _strength: f32) {}
+
+    /// Content has been snap turned, so its native space is the device's moved by
+    /// `reorientation`. Frames and events are turned on their way to content, so this is
+    /// only needed for poses the device hands out some other way.
+    fn set_reorientation(&mut self, _reorientation: RigidTransform3D<f32, Native, Native>) {}
+
+    /// Draw `marker` on the views when the current frame is ended, or stop with `None`.
+    /// See `LayerManager::set_latency_marker`.
+    fn set_latency_marker(&mut self, _marker: Option<LatencyMarker>) {}
+
+    /// Select with gaze input that has no button when it dwells, or stop with `None`.
+    /// Devices without such input ignore this.
+    fn set_dwell_select(&mut self, _options: Option<DwellOptions>) {}
+
+    /// Arbitrate between select and squeeze for inputs whose gestures for them overlap,
+    /// see `SelectArbiter`. Devices whose inputs can't do both at once ignore this.
+    fn set_select_arbitration(&mut self, _options: SelectArbitrationOptions) {}
+
+    /// Render the same view to both eyes, from halfway between them, starting with the
+    /// next frame. Devices without stereo views ignore this.
+    fn set_mono_rendering(&mut self, _mono: bool) {}
+
+    /// What the device is holding on to, for `Session::report_resources`
+    fn resources(&self) -> DeviceResources {
+        DeviceResources::default()
+    }
+
+    /// Called when the session is paused, between frames. No frames are requested
+    /// until `resume` is called, so devices can release what they can in the meantime.
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    /// Switch to the supported frame rate closest to `rate`, returning the new rate.
+    /// Devices should send `Event::FrameRateChanged` when the rate changes.
     fn update_frame_rate(&mut self, rate: f32) -> f32 {
         rate
     }
@@ -96,6 +257,16 @@ pub trait DeviceAPI: 'static {
     fn reference_space_bounds(&self) -> Option<Vec<Point2D<f32, Floor>>> {
         None
     }
+
+    /// The limits on the layers this device can render
+    fn layer_limits(&self) -> LayerLimits {
+        LayerLimits::default()
+    }
+
+    /// The limits on the hit tests this device evaluates for each session
+    fn hit_test_limits(&self) -> HitTestLimits {
+        HitTestLimits::default()
+    }
 }
 
 impl<GL: 'static> DiscoveryAPI<GL> for Box<dyn DiscoveryAPI<GL>> {
@@ -104,11 +275,23 @@ impl<GL: 'static> DiscoveryAPI<GL> for Box<dyn DiscoveryAPI<GL>> {
         mode: SessionMode,
         init: &SessionInit,
         xr: SessionBuilder<GL>,
-    ) -> Result<Session, Error> {
+    ) -> Result<PendingSession, Error> {
         (&mut **self).request_session(mode, init, xr)
     }
 
     fn supports_session(&self, mode: SessionMode) -> bool {
         (&**self).supports_session(mode)
     }
+
+    fn spawns_sessions(&self) -> bool {
+        (**self).spawns_sessions()
+    }
+
+    fn set_device_change_notifier(&mut self, notifier: DeviceChangeNotifier) {
+        (**self).set_device_change_notifier(notifier)
+    }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        (**self).device_descriptor()
+    }
 }
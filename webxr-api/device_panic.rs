@@ -0,0 +1,116 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Keeping a panic in a device backend from taking down more than its own session.
+//!
+//! By default, a panic on a session thread is caught, and the session ends with an error
+//! rather than leaving content waiting on a thread that has gone away. Embedders can
+//! log the panic with a `DevicePanicHandler`, see `MainThreadRegistry::set_device_panic_policy`.
+
+use crate::Error;
+use crate::SessionId;
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Once};
+
+/// What happens when a device panics on its session thread
+#[derive(Clone)]
+pub enum DevicePanicPolicy {
+    /// Let the panic unwind the session thread, as it would any other thread
+    Propagate,
+    /// Catch the panic and end the session, sending content `Event::SessionError`
+    /// with `Error::DevicePanicked` before `Event::SessionEnd`. The handler, if any,
+    /// is given the panic to log. This is the default.
+    Isolate(Option<Arc<dyn DevicePanicHandler>>),
+}
+
+impl Default for DevicePanicPolicy {
+    fn default() -> Self {
+        DevicePanicPolicy::Isolate(None)
+    }
+}
+
+/// A panic that was caught on a session thread
+#[derive(Debug)]
+pub struct DevicePanic {
+    pub message: String,
+    /// Where the device panicked. This is `None` if the panic hook was replaced
+    /// after the first session started, since the hook is what captures it.
+    pub backtrace: Option<Backtrace>,
+}
+
+/// A hook that is told about panics caught by `DevicePanicPolicy::Isolate`, on the
+/// thread that panicked, after the panic hook has run
+pub trait DevicePanicHandler: 'static + Send + Sync {
+    fn device_panicked(&self, id: SessionId, panic: &DevicePanic);
+}
+
+impl<F> DevicePanicHandler for F
+where
+    F: 'static + Send + Sync + Fn(SessionId, &DevicePanic),
+{
+    fn device_panicked(&self, id: SessionId, panic: &DevicePanic) {
+        self(id, panic)
+    }
+}
+
+thread_local! {
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+impl DevicePanicPolicy {
+    /// Run part of a session thread, turning a panic into `Error::DevicePanicked`
+    /// if panics are isolated
+    pub(crate) fn run<R>(&self, id: SessionId, f: impl FnOnce() -> R) -> Result<R, Error> {
+        let handler = match *self {
+            DevicePanicPolicy::Propagate => return Ok(f()),
+            DevicePanicPolicy::Isolate(ref handler) => handler,
+        };
+        install_hook();
+        CATCHING.with(|catching| catching.set(true));
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        CATCHING.with(|catching| catching.set(false));
+        result.map_err(|payload| {
+            let panic = DevicePanic {
+                message: panic_message(&*payload),
+                backtrace: BACKTRACE.with(|backtrace| backtrace.borrow_mut().take()),
+            };
+            if let Some(ref handler) = *handler {
+                handler.device_panicked(id, &panic);
+            }
+            Error::DevicePanicked(panic.message)
+        })
+    }
+}
+
+/// Wrap the panic hook so that it keeps the backtrace of panics that are going to be
+/// caught, since it is gone by the time `catch_unwind` returns
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) {
+                let backtrace = Backtrace::force_capture();
+                BACKTRACE.with(|slot| *slot.borrow_mut() = Some(backtrace));
+            }
+            previous(info);
+        }));
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
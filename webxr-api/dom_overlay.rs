@@ -0,0 +1,63 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Types for the `dom-overlay` feature, which shows an element of the page on top
+//! of an immersive session.
+//! https://immersive-web.github.io/dom-overlays/
+
+use crate::Viewport;
+
+use euclid::{Point2D, Rect, Size2D};
+
+/// Configuration for the `dom-overlay` feature, given in `SessionInit::dom_overlay`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomOverlayInit {
+    /// The size of the overlay's root element, before the device has placed it
+    pub size: Size2D<f32, Viewport>,
+}
+
+// The size comes from the element's layout, which can't be NaN
+impl Eq for DomOverlayInit {}
+
+/// https://immersive-web.github.io/dom-overlays/#enumdef-xrdomoverlaytype
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum DomOverlayType {
+    /// The overlay covers the whole screen, as on handheld devices
+    Screen,
+    Floating,
+    HeadLocked,
+}
+
+/// Where the device is showing the overlay this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomOverlayState {
+    pub overlay_type: DomOverlayType,
+    /// The area of the screen (or of the view, for the other types) covered by the overlay
+    pub rect: Rect<f32, Viewport>,
+}
+
+/// https://w3c.github.io/pointerevents/#pointerevent-interface
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum DomOverlayPointerKind {
+    Down,
+    Move,
+    Up,
+    Cancel,
+}
+
+/// Pointer input that landed on the overlay, which the embedder should
+/// dispatch to the overlay element's document
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DomOverlayPointer {
+    /// Distinguishes simultaneous pointers, such as fingers on a touch screen
+    pub pointer_id: u32,
+    pub kind: DomOverlayPointerKind,
+    /// The position of the pointer, relative to the top left of `DomOverlayState::rect`
+    pub position: Point2D<f32, Viewport>,
+}
@@ -0,0 +1,149 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Selecting with gaze input that has no button, by holding the target ray still.
+//!
+//! While a session has dwell options, devices with such input report how far along
+//! each dwell is with `Event::DwellProgress`, so that the embedder can draw an indicator,
+//! and select once the ray has been held still for long enough.
+
+use crate::Event;
+use crate::EventBuffer;
+use crate::Frame;
+use crate::InputId;
+use crate::Native;
+use crate::SelectEvent;
+use crate::SelectGesture;
+use crate::SelectKind;
+
+use euclid::Vector3D;
+
+use std::sync::Arc;
+
+/// How gaze input selects by dwelling, see `Session::set_dwell_select`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DwellOptions {
+    /// How far the target ray can move while dwelling, in radians
+    pub threshold: f32,
+    /// How long the target ray has to dwell to select, in milliseconds
+    pub duration: f64,
+}
+
+impl Default for DwellOptions {
+    fn default() -> DwellOptions {
+        DwellOptions {
+            threshold: 0.05,
+            duration: 1000.0,
+        }
+    }
+}
+
+/// Keeps track of the dwells of gaze inputs, for devices that support dwell selection
+#[derive(Clone, Debug)]
+pub struct DwellSelect {
+    options: DwellOptions,
+    dwells: Vec<Dwell>,
+}
+
+#[derive(Clone, Debug)]
+struct Dwell {
+    id: InputId,
+    /// The direction of the target ray when the dwell started
+    direction: Vector3D<f32, Native>,
+    /// The `predicted_display_time` of the frame the dwell started in
+    start: f64,
+    progress: f32,
+    /// Whether the dwell has already selected, in which case the ray
+    /// has to move away before it can select again
+    selected: bool,
+}
+
+impl DwellSelect {
+    pub fn new(options: DwellOptions) -> DwellSelect {
+        DwellSelect {
+            options,
+            dwells: vec![],
+        }
+    }
+
+    /// Update the dwells of the inputs of `frame` in `gaze_inputs`, reporting
+    /// their progress and any selects to `events`
+    pub fn update(&mut self, frame: &Frame, gaze_inputs: &[InputId], events: &mut EventBuffer) {
+        let now = frame.predicted_display_time;
+        let min_dot = self.options.threshold.cos();
+        // The frame time is in nanoseconds
+        let duration = self.options.duration * 1_000_000.0;
+        let mut select_frame = None;
+
+        let mut dwells = Vec::with_capacity(self.dwells.len());
+        for input in &frame.inputs {
+            if !gaze_inputs.contains(&input.id) {
+                continue;
+            }
+            let direction = match input.target_ray_origin {
+                Some(origin) => origin
+                    .rotation
+                    .transform_vector3d(Vector3D::new(0., 0., -1.)),
+                None => continue,
+            };
+            let previous = self
+                .dwells
+                .iter()
+                .position(|dwell| dwell.id == input.id)
+                .map(|i| self.dwells.swap_remove(i));
+            let mut dwell = match previous {
+                Some(dwell) if dwell.direction.dot(direction) >= min_dot => dwell,
+                previous => {
+                    if previous.is_some_and(|dwell| dwell.progress > 0.0) {
+                        events.callback(Event::DwellProgress(input.id, 0.0));
+                    }
+                    Dwell {
+                        id: input.id,
+                        direction,
+                        start: now,
+                        progress: 0.0,
+                        selected: false,
+                    }
+                }
+            };
+            if !dwell.selected {
+                let progress = (((now - dwell.start) / duration) as f32).min(1.0);
+                if progress != dwell.progress {
+                    dwell.progress = progress;
+                    events.callback(Event::DwellProgress(input.id, progress));
+                }
+                if progress >= 1.0 {
+                    dwell.selected = true;
+                    let select_frame = select_frame
+                        .get_or_insert_with(|| Arc::new(frame.for_event()))
+                        .clone();
+                    for &event in &[SelectEvent::Start, SelectEvent::Select] {
+                        events.callback(Event::Select(
+                            input.id,
+                            SelectKind::Select,
+                            event,
+                            SelectGesture::Dwell,
+                            select_frame.clone(),
+                        ));
+                    }
+                }
+            }
+            dwells.push(dwell);
+        }
+
+        // Inputs that went away or lost tracking end their dwell
+        self.reset(events);
+        self.dwells = dwells;
+    }
+
+    /// End every dwell, as when the session loses focus, reporting the progress that is lost
+    pub fn reset(&mut self, events: &mut EventBuffer) {
+        for dwell in self.dwells.drain(..) {
+            if dwell.progress > 0.0 {
+                events.callback(Event::DwellProgress(dwell.id, 0.0));
+            }
+        }
+    }
+}
@@ -2,14 +2,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::Feature;
+
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
 
+use std::fmt;
+
 /// Errors that can be produced by XR.
 
 // TODO: this is currently incomplete!
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub enum Error {
     NoMatchingDevice,
@@ -17,5 +21,97 @@ pub enum Error {
     ThreadCreationError,
     InlineSession,
     UnsupportedFeature(String),
-    BackendSpecific(String),
+    /// A layer request exceeded the device's `LayerLimits`
+    LayerLimitExceeded,
+    /// A hit test request exceeded the device's `HitTestLimits`
+    HitTestLimitExceeded,
+    /// The session request was cancelled with `SessionRequestHandle::cancel`
+    RequestCancelled,
+    /// The session request was refused by the registry's `PermissionGate`
+    PermissionDenied,
+    /// An immersive session is already running, and the registry's
+    /// `ImmersiveSessionPolicy` is to reject other immersive requests
+    ImmersiveSessionActive,
+    /// Content used a feature that wasn't granted to the session
+    FeatureNotGranted(Feature),
+    /// The XR runtime couldn't be loaded or didn't respond, with the reason why
+    RuntimeUnavailable(Backend, String),
+    /// The device couldn't allocate the images for a layer, with the reason why
+    SwapchainCreationFailed(Backend, String),
+    /// The graphics device the session renders with went away, so the session can't continue
+    GraphicsDeviceLost(Backend),
+    /// The device panicked on its session thread, with the panic message,
+    /// see `DevicePanicPolicy::Isolate`
+    DevicePanicked(String),
+    /// A message came from a process built with a different version of the IPC protocol,
+    /// with that process's version, see `Registry::handshake`
+    ProtocolMismatch(u32),
+    BackendSpecific(Backend, String),
+}
+
+/// The backend that an `Error` came from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum Backend {
+    GlWindow,
+    Headless,
+    OpenXr,
+    /// The layer manager that renders to surfman surfaces, used by several backends
+    Surfman,
+    /// Recording sessions and playing them back
+    Replay,
+    Simulator,
 }
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Backend::GlWindow => "glwindow",
+            Backend::Headless => "headless",
+            Backend::OpenXr => "OpenXR",
+            Backend::Surfman => "surfman",
+            Backend::Replay => "replay",
+            Backend::Simulator => "simulator",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoMatchingDevice => write!(f, "no device supports the requested session"),
+            Error::CommunicationError => write!(f, "lost contact with the XR thread"),
+            Error::ThreadCreationError => write!(f, "couldn't start the session thread"),
+            Error::InlineSession => write!(f, "not available in inline sessions"),
+            Error::UnsupportedFeature(ref feature) => write!(f, "{} isn't supported", feature),
+            Error::LayerLimitExceeded => write!(f, "too many layers for this device"),
+            Error::HitTestLimitExceeded => write!(f, "too many hit tests for this device"),
+            Error::RequestCancelled => write!(f, "the session request was cancelled"),
+            Error::PermissionDenied => write!(f, "permission to start the session was denied"),
+            Error::ImmersiveSessionActive => write!(f, "an immersive session is already active"),
+            Error::FeatureNotGranted(ref feature) => {
+                write!(f, "{} wasn't granted to this session", feature)
+            }
+            Error::RuntimeUnavailable(backend, ref reason) => {
+                write!(f, "the {} runtime is unavailable: {}", backend, reason)
+            }
+            Error::SwapchainCreationFailed(backend, ref reason) => {
+                write!(f, "{} couldn't create a swapchain: {}", backend, reason)
+            }
+            Error::GraphicsDeviceLost(backend) => {
+                write!(f, "{} lost its graphics device", backend)
+            }
+            Error::DevicePanicked(ref message) => write!(f, "the device panicked: {}", message),
+            Error::ProtocolMismatch(version) => write!(
+                f,
+                "the other process speaks protocol version {}, but this one speaks {}",
+                version,
+                crate::PROTOCOL_VERSION
+            ),
+            Error::BackendSpecific(backend, ref message) => write!(f, "{}: {}", backend, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
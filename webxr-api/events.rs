@@ -4,13 +4,21 @@
 
 use euclid::RigidTransform3D;
 
+use log::warn;
+
+use std::sync::Arc;
+
 use crate::ApiSpace;
 use crate::BaseSpace;
+use crate::DomOverlayPointer;
+use crate::Error;
+use crate::Feature;
 use crate::Frame;
 use crate::InputFrame;
 use crate::InputId;
 use crate::InputSource;
 use crate::SelectEvent;
+use crate::SelectGesture;
 use crate::SelectKind;
 use crate::Sender;
 
@@ -21,21 +29,71 @@ pub enum Event {
     AddInput(InputSource),
     /// Input source disconnected
     RemoveInput(InputId),
-    /// Input updated (this is a disconnect+reconnect)
+    /// Input updated, for example because its target ray mode or profiles changed
+    /// (this is a disconnect+reconnect)
     UpdateInput(InputId, InputSource),
+    /// A transient input source, such as a touch on the screen, appeared, with its first frame.
+    /// It selects with `Select` events like any other input, and then goes away with
+    /// `TransientInputEnd` rather than `RemoveInput`.
+    TransientInputStart(InputSource, InputFrame),
+    /// A transient input source went away, after any select it made has ended
+    TransientInputEnd(InputId),
     /// Session ended by device
     SessionEnd,
+    /// The session is ending because of an error, and `SessionEnd` follows
+    SessionError(Error),
     /// Session focused/blurred/etc
     VisibilityChange(Visibility),
-    /// Selection started / ended
-    Select(InputId, SelectKind, SelectEvent, Frame),
+    /// Selection started / ended, with the gesture that caused it and the frame
+    /// it happened in (see `Frame::for_event`), which is shared between the events
+    /// of the same frame
+    Select(InputId, SelectKind, SelectEvent, SelectGesture, Arc<Frame>),
     /// Input from an input source has changed
     InputChanged(InputId, InputFrame),
     /// Reference space has changed
     ReferenceSpaceChanged(BaseSpace, RigidTransform3D<f32, ApiSpace, ApiSpace>),
+    /// A privacy-sensitive feature produced data for the first time in this session,
+    /// see `Feature::is_sensitive`
+    SensitiveFeatureUsed(Feature),
+    /// The rate at which the device produces frames has changed
+    FrameRateChanged(f32),
+    /// Pointer input on the overlay, for sessions with the `dom-overlay` feature
+    DomOverlayPointer(DomOverlayPointer),
+    /// How far along the dwell of a gaze input is, between 0 and 1, where it selects.
+    /// This is 0 when the dwell is broken. See `Session::set_dwell_select`.
+    DwellProgress(InputId, f32),
+}
+
+impl Event {
+    /// Whether this is a lifecycle event, which goes to the urgent event dest
+    /// if there is one, see `Session::set_urgent_event_dest`
+    pub fn is_urgent(&self) -> bool {
+        matches!(
+            *self,
+            Event::SessionEnd | Event::SessionError(_) | Event::VisibilityChange(_)
+        )
+    }
+
+    /// Whether the session ends with this event, which content needs to clean up
+    fn is_terminal(&self) -> bool {
+        matches!(*self, Event::SessionEnd | Event::SessionError(_))
+    }
+
+    /// Whether an input comes or goes with this event, which content needs
+    /// to make sense of the events and frames of that input
+    fn is_input_lifecycle(&self) -> bool {
+        matches!(
+            *self,
+            Event::AddInput(_)
+                | Event::RemoveInput(_)
+                | Event::UpdateInput(..)
+                | Event::TransientInputStart(..)
+                | Event::TransientInputEnd(_)
+        )
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub enum Visibility {
     /// Session fully displayed to user
@@ -46,31 +104,97 @@ pub enum Visibility {
     Hidden,
 }
 
+/// How many events an `EventBuffer` holds on to while it has nowhere to send them,
+/// and which it drops once it is full. The oldest events are dropped first, except
+/// for inputs coming and going, which content needs to make sense of later events,
+/// and so are never dropped. See `MainThreadRegistry::set_event_buffer_options`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventBufferOptions {
+    pub capacity: usize,
+    /// Drop all but the latest `VisibilityChange` before dropping anything else,
+    /// since only the latest visibility matters
+    pub coalesce_visibility: bool,
+    /// Never drop `SessionEnd` and `SessionError`, even if that means going over capacity
+    pub keep_terminal: bool,
+}
+
+impl Default for EventBufferOptions {
+    fn default() -> EventBufferOptions {
+        EventBufferOptions {
+            capacity: 256,
+            coalesce_visibility: true,
+            keep_terminal: true,
+        }
+    }
+}
+
 /// Convenience structure for buffering up events
 /// when no event callback has been set
 pub enum EventBuffer {
-    Buffered(Vec<Event>),
+    Buffered {
+        events: Vec<Event>,
+        options: EventBufferOptions,
+        /// How many events have been dropped since the buffer filled up
+        dropped: usize,
+    },
     Sink(Sender<Event>),
 }
 
 impl Default for EventBuffer {
     fn default() -> Self {
-        EventBuffer::Buffered(vec![])
+        EventBuffer::new(EventBufferOptions::default())
     }
 }
 
 impl EventBuffer {
+    /// A buffer with the given options, usually from `SessionBuilder::event_buffer`
+    pub fn new(options: EventBufferOptions) -> EventBuffer {
+        EventBuffer::Buffered {
+            events: vec![],
+            options,
+            dropped: 0,
+        }
+    }
+
     pub fn callback(&mut self, event: Event) {
         match *self {
-            EventBuffer::Buffered(ref mut events) => events.push(event),
+            EventBuffer::Buffered {
+                ref mut events,
+                ref options,
+                ref mut dropped,
+            } => {
+                let was_dropping = *dropped > 0;
+                buffer(events, options, dropped, event);
+                // Once the buffer is full, most events drop another one, so this is only said
+                // the first time, and how many were dropped is said once there's a dest
+                if !was_dropping && *dropped > 0 {
+                    warn!("Dropping XR events, since nothing has taken them");
+                }
+            }
             EventBuffer::Sink(ref dest) => {
                 let _ = dest.send(event);
             }
         }
     }
 
+    /// The number of events waiting for somewhere to be sent
+    pub fn buffered(&self) -> usize {
+        match *self {
+            EventBuffer::Buffered { ref events, .. } => events.len(),
+            EventBuffer::Sink(_) => 0,
+        }
+    }
+
     pub fn upgrade(&mut self, dest: Sender<Event>) {
-        if let EventBuffer::Buffered(ref mut events) = *self {
+        if let EventBuffer::Buffered {
+            ref mut events,
+            dropped,
+            ..
+        } = *self
+        {
+            if dropped > 0 {
+                warn!("Dropped {} XR events, since nothing took them", dropped);
+            }
             for event in events.drain(..) {
                 let _ = dest.send(event);
             }
@@ -78,3 +202,51 @@ impl EventBuffer {
         *self = EventBuffer::Sink(dest)
     }
 }
+
+/// Add an event to a buffer, making room for it if the buffer is full
+fn buffer(
+    events: &mut Vec<Event>,
+    options: &EventBufferOptions,
+    dropped: &mut usize,
+    event: Event,
+) {
+    if events.len() < options.capacity {
+        events.push(event);
+        return;
+    }
+    let is_visibility = |event: &Event| matches!(*event, Event::VisibilityChange(_));
+    if options.coalesce_visibility {
+        let latest = if is_visibility(&event) {
+            None
+        } else {
+            events.iter().rposition(is_visibility)
+        };
+        let mut index = 0;
+        let len = events.len();
+        events.retain(|event| {
+            let keep = !is_visibility(event) || Some(index) == latest;
+            index += 1;
+            keep
+        });
+        *dropped += len - events.len();
+    }
+    let droppable = |event: &Event| {
+        let keep = event.is_input_lifecycle() || (options.keep_terminal && event.is_terminal());
+        !keep
+    };
+    if events.len() >= options.capacity {
+        match events.iter().position(droppable) {
+            Some(oldest) => {
+                events.remove(oldest);
+                *dropped += 1;
+            }
+            // Everything buffered has to be kept, so the new event goes if it can
+            None if droppable(&event) => {
+                *dropped += 1;
+                return;
+            }
+            None => (),
+        }
+    }
+    events.push(event);
+}
@@ -0,0 +1,122 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A feature descriptor that content can request for a session
+/// https://immersive-web.github.io/webxr/#feature-descriptor
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum Feature {
+    Viewer,
+    Local,
+    LocalFloor,
+    BoundedFloor,
+    Unbounded,
+    Anchors,
+    CameraAccess,
+    DepthSensing,
+    DomOverlay,
+    EyeTracking,
+    HandTracking,
+    HitTest,
+    ImageTracking,
+    Layers,
+    LightEstimation,
+    MeshDetection,
+    PlaneDetection,
+    SecondaryViews,
+    /// A feature descriptor that this crate doesn't know about,
+    /// which no device will support
+    Unknown(String),
+}
+
+impl Feature {
+    /// The feature descriptor, as content spells it
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Feature::Viewer => "viewer",
+            Feature::Local => "local",
+            Feature::LocalFloor => "local-floor",
+            Feature::BoundedFloor => "bounded-floor",
+            Feature::Unbounded => "unbounded",
+            Feature::Anchors => "anchors",
+            Feature::CameraAccess => "camera-access",
+            Feature::DepthSensing => "depth-sensing",
+            Feature::DomOverlay => "dom-overlay",
+            Feature::EyeTracking => "eye-tracking",
+            Feature::HandTracking => "hand-tracking",
+            Feature::HitTest => "hit-test",
+            Feature::ImageTracking => "image-tracking",
+            Feature::Layers => "layers",
+            Feature::LightEstimation => "light-estimation",
+            Feature::MeshDetection => "mesh-detection",
+            Feature::PlaneDetection => "plane-detection",
+            Feature::SecondaryViews => "secondary-views",
+            Feature::Unknown(ref name) => name,
+        }
+    }
+
+    /// Whether this feature's data can reveal something about the user or their surroundings.
+    /// Devices report `Event::SensitiveFeatureUsed` the first time one of these produces data.
+    pub fn is_sensitive(&self) -> bool {
+        matches!(
+            *self,
+            Feature::CameraAccess
+                | Feature::DepthSensing
+                | Feature::EyeTracking
+                | Feature::HandTracking
+        )
+    }
+}
+
+impl From<&str> for Feature {
+    fn from(name: &str) -> Feature {
+        match name {
+            "viewer" => Feature::Viewer,
+            "local" => Feature::Local,
+            "local-floor" => Feature::LocalFloor,
+            "bounded-floor" => Feature::BoundedFloor,
+            "unbounded" => Feature::Unbounded,
+            "anchors" => Feature::Anchors,
+            "camera-access" => Feature::CameraAccess,
+            "depth-sensing" => Feature::DepthSensing,
+            "dom-overlay" => Feature::DomOverlay,
+            "eye-tracking" => Feature::EyeTracking,
+            "hand-tracking" => Feature::HandTracking,
+            "hit-test" => Feature::HitTest,
+            "image-tracking" => Feature::ImageTracking,
+            "layers" => Feature::Layers,
+            "light-estimation" => Feature::LightEstimation,
+            "mesh-detection" => Feature::MeshDetection,
+            "plane-detection" => Feature::PlaneDetection,
+            "secondary-views" => Feature::SecondaryViews,
+            _ => Feature::Unknown(name.into()),
+        }
+    }
+}
+
+impl From<String> for Feature {
+    fn from(name: String) -> Feature {
+        match Feature::from(&*name) {
+            Feature::Unknown(_) => Feature::Unknown(name),
+            feature => feature,
+        }
+    }
+}
+
+impl FromStr for Feature {
+    type Err = std::convert::Infallible;
+
+    fn from_str(name: &str) -> Result<Feature, Self::Err> {
+        Ok(Feature::from(name))
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -2,23 +2,37 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::DepthInformation;
+use crate::DetectedMesh;
+use crate::DomOverlayState;
+use crate::Error;
 use crate::Floor;
 use crate::HitTestId;
 use crate::HitTestResult;
 use crate::InputFrame;
+use crate::MeshId;
 use crate::Native;
+use crate::Receiver;
 use crate::SubImages;
+use crate::TrackedImage;
 use crate::Viewer;
 use crate::Viewports;
 use crate::Views;
 
+use euclid::Point2D;
 use euclid::RigidTransform3D;
+use euclid::Vector3D;
+use std::sync::Arc;
 
 /// The per-frame data that is provided by the device.
 /// https://www.w3.org/TR/webxr/#xrframe
 // TODO: other fields?
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "ipc",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self")
+)]
 pub struct Frame {
     /// The pose information of the viewer
     pub pose: Option<ViewerPose>,
@@ -34,8 +48,140 @@ pub struct Frame {
     /// The hit test results for this frame, if any
     pub hit_test_results: Vec<HitTestResult>,
 
-    /// The average point in time this XRFrame is expected to be displayed on the devices' display
+    /// The images from `SessionInit::tracked_images` that are being tracked this frame
+    pub tracked_images: Vec<TrackedImage>,
+
+    /// Where the overlay is shown, for sessions with the `dom-overlay` feature
+    pub dom_overlay: Option<DomOverlayState>,
+
+    /// The depth of what the viewer sees, for sessions with the `depth-sensing` feature
+    pub depth_information: Option<DepthInformation>,
+
+    /// The average point in time this XRFrame is expected to be displayed on the devices' display,
+    /// in nanoseconds since some point that only matters when comparing frames of the same session
     pub predicted_display_time: f64,
+
+    /// How long the device expects to display each frame for, in nanoseconds, if it knows
+    pub predicted_display_period: Option<f64>,
+
+    /// Roughly how long content has to render this frame, in nanoseconds from when it was
+    /// sent, before it is too late for the device to submit it in time. This is worked out
+    /// by the session thread from the display period and how long the device has recently
+    /// taken to submit frames, so that content can scale its work down before frames drop.
+    /// It is `None` if the device doesn't know its display period.
+    pub render_budget: Option<f64>,
+}
+
+#[cfg(feature = "ipc")]
+crate::protocol::versioned!(Frame);
+
+impl Frame {
+    /// A copy of the frame to send with events, without the parts
+    /// that are only needed to render it
+    pub fn for_event(&self) -> Frame {
+        Frame {
+            pose: self.pose.clone(),
+            inputs: self.inputs.clone(),
+            events: vec![],
+            sub_images: vec![],
+            hit_test_results: self.hit_test_results.clone(),
+            tracked_images: self.tracked_images.clone(),
+            dom_overlay: self.dom_overlay,
+            depth_information: None,
+            predicted_display_time: self.predicted_display_time,
+            predicted_display_period: self.predicted_display_period,
+            render_budget: self.render_budget,
+        }
+    }
+}
+
+/// The optional parts of `Frame` that content actually uses. Devices can skip
+/// computing the rest, and the session thread leaves them out of the frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameSubscriptions {
+    /// `InputFrame::hand`
+    pub hand_joints: bool,
+    /// `Frame::hit_test_results`
+    pub hit_tests: bool,
+    /// The `UpdateDetectedMesh` and `DetectedMeshRemoved` events
+    pub meshes: bool,
+    /// `Frame::depth_information`
+    pub depth: bool,
+}
+
+impl Default for FrameSubscriptions {
+    fn default() -> Self {
+        FrameSubscriptions {
+            hand_joints: true,
+            hit_tests: true,
+            meshes: true,
+            depth: true,
+        }
+    }
+}
+
+impl FrameSubscriptions {
+    /// Remove the parts of a frame that nobody is subscribed to.
+    /// Meshes are left alone, since they are sent as changes that can't be dropped.
+    pub fn filter(&self, frame: &mut Frame) {
+        if !self.hand_joints {
+            for input in &mut frame.inputs {
+                input.hand = None;
+            }
+        }
+        if !self.hit_tests {
+            frame.hit_test_results.clear();
+        }
+        if !self.depth {
+            frame.depth_information = None;
+        }
+    }
+}
+
+/// The receiving end of a session's frame channel, which hands out only the newest
+/// frame that has arrived. If content stalls, it catches up with the freshest pose
+/// rather than working through a backlog of old frames.
+pub struct FrameMailbox {
+    receiver: Receiver<Frame>,
+    dropped: u64,
+}
+
+impl FrameMailbox {
+    pub fn new(receiver: Receiver<Frame>) -> FrameMailbox {
+        FrameMailbox {
+            receiver,
+            dropped: 0,
+        }
+    }
+
+    /// Wait for a frame, returning `None` once the session has gone away
+    pub fn recv(&mut self) -> Option<Frame> {
+        let frame = self.receiver.recv().ok()?;
+        Some(self.newest(frame))
+    }
+
+    /// Get a frame if one has arrived, without waiting
+    pub fn try_recv(&mut self) -> Option<Frame> {
+        let frame = self.receiver.try_recv().ok()?;
+        Some(self.newest(frame))
+    }
+
+    /// How many frames were dropped because a newer one had arrived
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+
+    fn newest(&mut self, mut frame: Frame) -> Frame {
+        while let Ok(mut newer) = self.receiver.try_recv() {
+            // The events describe changes, so none of them can be lost
+            frame.events.append(&mut newer.events);
+            newer.events = frame.events;
+            frame = newer;
+            self.dropped += 1;
+        }
+        frame
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -43,7 +189,37 @@ pub struct Frame {
 pub enum FrameUpdateEvent {
     UpdateFloorTransform(Option<RigidTransform3D<f32, Native, Floor>>),
     UpdateViewports(Viewports),
+    /// The bounds of the `bounded-floor` space changed, see `Session::reference_space_bounds`
+    UpdateBoundsGeometry(Vec<Point2D<f32, Floor>>),
     HitTestSourceAdded(HitTestId),
+    /// The device turned the hit test source away, for example because it is over the
+    /// device's `HitTestLimits`
+    HitTestSourceRejected(HitTestId, Error),
+    /// A mesh was detected, or an already detected mesh changed
+    UpdateDetectedMesh(DetectedMesh),
+    DetectedMeshRemoved(MeshId),
+    /// Data from an experimental or vendor-specific feature of the device
+    Extension(ExtensionEvent),
+}
+
+/// Per-frame data from a device feature that isn't part of this API (yet),
+/// so that backends can try things out without adding to `FrameUpdateEvent`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionEvent {
+    /// Names the extension and the kind of data, such as `"vendor-feature/update"`.
+    /// Embedders should ignore keys they don't know about.
+    pub key: String,
+    pub encoding: ExtensionEncoding,
+    pub data: Arc<[u8]>,
+}
+
+/// How the data of an `ExtensionEvent` is serialized
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtensionEncoding {
+    Json,
+    Cbor,
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +231,12 @@ pub struct ViewerPose {
     /// This is the inverse of the view matrix.
     pub transform: RigidTransform3D<f32, Viewer, Native>,
 
+    /// The linear velocity of the viewer in native coordinates, in meters per second
+    pub linear_velocity: Option<Vector3D<f32, Native>>,
+
+    /// The angular velocity of the viewer in native coordinates, in radians per second
+    pub angular_velocity: Option<Vector3D<f32, Native>>,
+
     // The various views
     pub views: Views,
 }
@@ -0,0 +1,192 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Holding the frame rate by rendering fewer pixels when content can't keep up.
+//!
+//! The session thread measures how much of each display period content and the device
+//! spend on a frame, and lowers the viewport scale while that stays too high, raising it
+//! again once there is room. If the scale is as low as it is allowed to go, the frame rate
+//! can be lowered instead. This works the same way for every device.
+
+use crate::DeviceAPI;
+
+use std::time::Duration;
+
+/// How the resolution governor behaves, see `Session::set_resolution_governor`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct GovernorOptions {
+    /// The lowest viewport scale the governor goes down to
+    pub min_scale: f32,
+    /// The highest viewport scale the governor goes up to
+    pub max_scale: f32,
+    /// How much the viewport scale changes at a time
+    pub step: f32,
+    /// The part of the display period that frames can take before the scale goes down
+    pub high_load: f32,
+    /// The part of the display period that frames have to stay under for the scale to go up
+    pub low_load: f32,
+    /// How many frames in a row have to be over or under the load before the scale changes
+    pub frames: u32,
+    /// Whether to lower the frame rate when frames take too long at the lowest scale
+    pub lower_frame_rate: bool,
+}
+
+impl Default for GovernorOptions {
+    fn default() -> GovernorOptions {
+        GovernorOptions {
+            min_scale: 0.5,
+            max_scale: 1.0,
+            step: 0.05,
+            high_load: 0.9,
+            low_load: 0.7,
+            frames: 30,
+            lower_frame_rate: false,
+        }
+    }
+}
+
+/// Adjusts the viewport scale of a session, and maybe its frame rate,
+/// from how long its frames take
+pub(crate) struct ResolutionGovernor {
+    options: GovernorOptions,
+    scale: f32,
+    /// The scales content requested for each view, which the governor's scale applies on top of
+    requested_scales: Vec<f32>,
+    /// How many frames in a row have been over `high_load` (if positive)
+    /// or under `low_load` (if negative)
+    streak: i32,
+    /// The frame rate before the governor lowered it, and the rate it lowered it to
+    lowered_frame_rate: Option<(f32, f32)>,
+}
+
+impl ResolutionGovernor {
+    /// Start governing, on top of the scales content already requested for each view
+    pub(crate) fn new(options: GovernorOptions, requested_scales: &[f32]) -> ResolutionGovernor {
+        ResolutionGovernor {
+            options,
+            scale: options.max_scale,
+            requested_scales: requested_scales.to_vec(),
+            streak: 0,
+            lowered_frame_rate: None,
+        }
+    }
+
+    /// Content requested a scale for a view, see `Session::request_viewport_scale`
+    pub(crate) fn request_viewport_scale(
+        &mut self,
+        view: usize,
+        scale: f32,
+        device: &mut impl DeviceAPI,
+    ) {
+        if self.requested_scales.len() <= view {
+            self.requested_scales.resize(view + 1, 1.0);
+        }
+        self.requested_scales[view] = scale;
+        device.request_viewport_scale(view, scale * self.scale);
+    }
+
+    /// Content picked a frame rate, which the governor shouldn't undo
+    pub(crate) fn frame_rate_updated(&mut self) {
+        self.lowered_frame_rate = None;
+    }
+
+    /// A frame took `frame_time`, in a display period of `period`
+    pub(crate) fn frame_ended(
+        &mut self,
+        frame_time: Duration,
+        period: Duration,
+        device: &mut impl DeviceAPI,
+    ) {
+        let load = frame_time.as_secs_f32() / period.as_secs_f32();
+        if load > self.options.high_load {
+            self.streak = self.streak.max(0) + 1;
+        } else if load < self.options.low_load {
+            self.streak = self.streak.min(0) - 1;
+        } else {
+            self.streak = 0;
+        }
+        let frames = self.options.frames.max(1) as i32;
+        if self.streak >= frames {
+            self.streak = 0;
+            self.step_down(period, device);
+        } else if self.streak <= -frames {
+            self.streak = 0;
+            self.step_up(load, device);
+        }
+    }
+
+    fn step_down(&mut self, period: Duration, device: &mut impl DeviceAPI) {
+        if self.scale > self.options.min_scale {
+            let scale = (self.scale - self.options.step).max(self.options.min_scale);
+            self.set_scale(scale, device);
+        } else if self.options.lower_frame_rate && self.lowered_frame_rate.is_none() {
+            self.lower_frame_rate(1.0 / period.as_secs_f32(), device);
+        }
+    }
+
+    /// Raise the frame rate back up, or else the scale, as long as frames
+    /// would still take less than `high_load` afterwards
+    fn step_up(&mut self, load: f32, device: &mut impl DeviceAPI) {
+        if let Some((original_rate, lowered_rate)) = self.lowered_frame_rate {
+            if load * original_rate / lowered_rate < self.options.high_load {
+                self.lowered_frame_rate = None;
+                device.update_frame_rate(original_rate);
+            }
+            return;
+        }
+        if self.scale >= self.options.max_scale {
+            return;
+        }
+        let scale = (self.scale + self.options.step).min(self.options.max_scale);
+        // The time it takes to render goes with the number of pixels
+        let predicted_load = load * (scale / self.scale).powi(2);
+        if predicted_load < self.options.high_load {
+            self.set_scale(scale, device);
+        }
+    }
+
+    /// Switch to the next supported frame rate below `current_rate`, if there is one
+    fn lower_frame_rate(&mut self, current_rate: f32, device: &mut impl DeviceAPI) {
+        // The rate worked out from the display period isn't exact
+        let lower_rate = device
+            .supported_frame_rates()
+            .into_iter()
+            .filter(|&rate| rate < current_rate * 0.95)
+            .fold(None, |max: Option<f32>, rate| {
+                Some(max.map_or(rate, |max| max.max(rate)))
+            });
+        if let Some(lower_rate) = lower_rate {
+            let original_rate = device
+                .supported_frame_rates()
+                .into_iter()
+                .min_by(|a, b| {
+                    (a - current_rate)
+                        .abs()
+                        .partial_cmp(&(b - current_rate).abs())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap_or(current_rate);
+            let lowered_rate = device.update_frame_rate(lower_rate);
+            self.lowered_frame_rate = Some((original_rate, lowered_rate));
+        }
+    }
+
+    fn set_scale(&mut self, scale: f32, device: &mut impl DeviceAPI) {
+        self.scale = scale;
+        let views = device.viewports().viewports.len();
+        for view in 0..views {
+            let requested = self.requested_scales.get(view).copied().unwrap_or(1.0);
+            device.request_viewport_scale(view, requested * scale);
+        }
+    }
+
+    /// Undo what the governor did, when it is turned off
+    pub(crate) fn reset(&mut self, device: &mut impl DeviceAPI) {
+        self.set_scale(1.0, device);
+        if let Some((original_rate, _)) = self.lowered_frame_rate.take() {
+            device.update_frame_rate(original_rate);
+        }
+    }
+}
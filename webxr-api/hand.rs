@@ -1,11 +1,12 @@
 use crate::Native;
 use euclid::RigidTransform3D;
+use euclid::Vector3D;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct HandSpace;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct Hand<J> {
     pub wrist: Option<J>,
@@ -19,7 +20,7 @@ pub struct Hand<J> {
     pub little: Finger<J>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct Finger<J> {
     pub metacarpal: Option<J>,
@@ -34,6 +35,10 @@ pub struct Finger<J> {
 pub struct JointFrame {
     pub pose: RigidTransform3D<f32, HandSpace, Native>,
     pub radius: f32,
+    /// In native coordinates, in meters per second
+    pub linear_velocity: Option<Vector3D<f32, Native>>,
+    /// In native coordinates, in radians per second
+    pub angular_velocity: Option<Vector3D<f32, Native>>,
 }
 
 impl Default for JointFrame {
@@ -41,6 +46,8 @@ impl Default for JointFrame {
         Self {
             pose: RigidTransform3D::identity(),
             radius: 0.,
+            linear_velocity: None,
+            angular_velocity: None,
         }
     }
 }
@@ -1,12 +1,18 @@
 use crate::ApiSpace;
 use crate::Native;
 use crate::Space;
+use euclid::Box3D;
 use euclid::Point3D;
 use euclid::RigidTransform3D;
 use euclid::Rotation3D;
 use euclid::Vector3D;
+use std::cmp::Ordering;
 use std::f32::EPSILON;
 use std::iter::FromIterator;
+use std::ops::Range;
+
+// The most triangles to keep in a leaf of a `TriangleBvh`
+const MAX_LEAF_TRIANGLES: usize = 4;
 
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
@@ -50,11 +56,54 @@ pub struct EntityTypes {
     pub mesh: bool,
 }
 
+/// Device-reported limits on the hit tests that each session can make
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct HitTestLimits {
+    /// The maximum number of hit test sources a session can have at once
+    pub max_sources: usize,
+    /// The maximum number of results reported for each source every frame,
+    /// the nearest ones are kept
+    pub max_results_per_source: usize,
+}
+
+impl Default for HitTestLimits {
+    fn default() -> Self {
+        HitTestLimits {
+            max_sources: 64,
+            max_results_per_source: 16,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct HitTestResult {
     pub id: HitTestId,
     pub space: RigidTransform3D<f32, HitTestSpace, Native>,
+    /// The normal of the surface that was hit, pointing towards the side the ray came from
+    pub normal: Vector3D<f32, Native>,
+    /// How far along the ray the hit is, in metres
+    pub distance: f32,
+    /// The entity that was hit
+    pub entity: EntityId,
+    pub entity_type: EntityType,
+}
+
+/// Something in the world that hit tests can hit, which keeps its id as long as
+/// the device tracks it
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntityId(pub u32);
+
+/// Where a ray hit a triangle of a `TriangleBvh`
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleHit {
+    pub space: RigidTransform3D<f32, HitTestSpace, Native>,
+    pub normal: Vector3D<f32, Native>,
+    pub distance: f32,
+    pub entity: EntityId,
+    pub entity_type: EntityType,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -152,8 +201,8 @@ impl Triangle {
 
             // this is not part of the Möller-Trumbore algorithm, the hit test spec
             // requires it has an orientation such that the Y axis points along
-            // the triangle normal
-            let normal = edge1.cross(edge2).normalize();
+            // the triangle normal, on the side that was hit
+            let normal = self.facing_normal(ray.direction);
             let y = Vector3D::new(0., 1., 0.);
             let dot = normal.dot(y);
             let rotation = if dot > -EPSILON && dot < EPSILON {
@@ -177,3 +226,171 @@ impl Triangle {
         None
     }
 }
+
+/// A bounding volume hierarchy over the triangles of a world, so that hit tests
+/// against large worlds don't have to check every triangle
+#[derive(Clone, Debug, Default)]
+pub struct TriangleBvh {
+    triangles: Vec<(Triangle, EntityType, EntityId)>,
+    nodes: Vec<BvhNode>,
+}
+
+#[derive(Clone, Debug)]
+struct BvhNode {
+    bounds: Box3D<f32, Native>,
+    contents: BvhContents,
+}
+
+#[derive(Clone, Debug)]
+enum BvhContents {
+    /// The range of `TriangleBvh::triangles` in this node
+    Leaf(Range<usize>),
+    /// The indices of the two child nodes
+    Inner(usize, usize),
+}
+
+impl TriangleBvh {
+    pub fn new<I>(triangles: I) -> TriangleBvh
+    where
+        I: IntoIterator<Item = (Triangle, EntityType, EntityId)>,
+    {
+        let mut bvh = TriangleBvh {
+            triangles: triangles.into_iter().collect(),
+            nodes: vec![],
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(0..bvh.triangles.len());
+        }
+        bvh
+    }
+
+    /// Add the node for `triangles[range]` and its children, returning its index
+    fn build(&mut self, range: Range<usize>) -> usize {
+        let triangles = &mut self.triangles[range.clone()];
+        let bounds =
+            Box3D::from_points(triangles.iter().flat_map(|(triangle, _, _)| {
+                vec![triangle.first, triangle.second, triangle.third]
+            }));
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            contents: BvhContents::Leaf(range.clone()),
+        });
+        if triangles.len() <= MAX_LEAF_TRIANGLES {
+            return index;
+        }
+
+        // Split the triangles in half along the longest axis of the node
+        let size = bounds.size();
+        let axis = |point: Point3D<f32, Native>| {
+            if size.width >= size.height && size.width >= size.depth {
+                point.x
+            } else if size.height >= size.depth {
+                point.y
+            } else {
+                point.z
+            }
+        };
+        triangles.sort_by(|(a, _, _), (b, _, _)| {
+            axis(a.centroid())
+                .partial_cmp(&axis(b.centroid()))
+                .unwrap_or(Ordering::Equal)
+        });
+        let middle = range.start + range.len() / 2;
+        let left = self.build(range.start..middle);
+        let right = self.build(middle..range.end);
+        self.nodes[index].contents = BvhContents::Inner(left, right);
+        index
+    }
+
+    /// Intersect a ray with the triangles of the given types, nearest hit first
+    pub fn intersect(&self, ray: Ray<Native>, types: EntityTypes) -> Vec<TriangleHit> {
+        let mut hits = vec![];
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !ray.hits_box(&node.bounds) {
+                continue;
+            }
+            match node.contents {
+                BvhContents::Leaf(ref range) => hits.extend(
+                    self.triangles[range.clone()]
+                        .iter()
+                        .filter(|&&(_, ty, _)| types.is_type(ty))
+                        .filter_map(|&(triangle, entity_type, entity)| {
+                            let space = triangle.intersect(ray)?;
+                            let normal = triangle.facing_normal(ray.direction);
+                            Some(TriangleHit {
+                                space,
+                                normal,
+                                distance: (space.translation - ray.origin).length(),
+                                entity,
+                                entity_type,
+                            })
+                        }),
+                ),
+                BvhContents::Inner(left, right) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        hits.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+        });
+        hits
+    }
+}
+
+impl Triangle {
+    /// The normal of the side the vertices go anticlockwise around
+    pub fn normal(&self) -> Vector3D<f32, Native> {
+        let edge1 = self.second - self.first;
+        let edge2 = self.third - self.first;
+        edge1.cross(edge2).normalize()
+    }
+
+    /// The normal of the side facing back along `direction`, which is the side
+    /// that a ray going in that direction hits
+    pub fn facing_normal(&self, direction: Vector3D<f32, Native>) -> Vector3D<f32, Native> {
+        let normal = self.normal();
+        if normal.dot(direction) > 0. {
+            -normal
+        } else {
+            normal
+        }
+    }
+
+    fn centroid(&self) -> Point3D<f32, Native> {
+        ((self.first.to_vector() + self.second.to_vector() + self.third.to_vector()) / 3.)
+            .to_point()
+    }
+}
+
+impl Ray<Native> {
+    /// Whether the ray passes through a box, using the slab method
+    fn hits_box(&self, bounds: &Box3D<f32, Native>) -> bool {
+        let axes = [
+            (self.origin.x, self.direction.x, bounds.min.x, bounds.max.x),
+            (self.origin.y, self.direction.y, bounds.min.y, bounds.max.y),
+            (self.origin.z, self.direction.z, bounds.min.z, bounds.max.z),
+        ];
+        let mut near = 0.;
+        let mut far = f32::INFINITY;
+        for &(origin, direction, min, max) in &axes {
+            // Rays parallel to an axis divide by zero, and the infinities work out
+            let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+            near = t0.min(t1).max(near);
+            far = t0.max(t1).min(far);
+            if near > far {
+                return false;
+            }
+        }
+        true
+    }
+}
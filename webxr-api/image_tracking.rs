@@ -0,0 +1,51 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::default::Size2D;
+use euclid::RigidTransform3D;
+
+/// An image that the embedder would like the device to track
+/// https://immersive-web.github.io/marker-tracking/#dictdef-xrtrackedimageinit
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackedImageInit {
+    /// The image contents, as RGBA8 pixels
+    pub data: Vec<u8>,
+    /// The size of the image, in pixels
+    pub size: Size2D<u32>,
+    /// The expected width of the physical image, in meters
+    pub width_in_meters: f32,
+}
+
+// The width comes from a WebIDL `float`, which can't be NaN
+impl Eq for TrackedImageInit {}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a tracked image
+pub struct TrackedImageSpace;
+
+/// https://immersive-web.github.io/marker-tracking/#enumdef-xrimagetrackingstate
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrackingState {
+    /// The image is currently being tracked
+    Tracked,
+    /// The image was tracked previously, and its pose is being estimated
+    Emulated,
+}
+
+/// https://immersive-web.github.io/marker-tracking/#xrimagetrackingresult
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackedImage {
+    /// The index of the image in `SessionInit::tracked_images`
+    pub index: u32,
+    pub pose: RigidTransform3D<f32, TrackedImageSpace, Native>,
+    /// The measured width of the physical image, in meters
+    pub width: f32,
+    pub tracking_state: TrackingState,
+}
@@ -0,0 +1,154 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A device for inline sessions that doesn't need any XR hardware.
+//!
+//! Inline content renders straight to its canvas, so there are no layers or
+//! viewports, and the viewer stays at the origin of native space.
+
+use crate::ContextId;
+use crate::DeviceAPI;
+use crate::DiscoveryAPI;
+use crate::Error;
+use crate::Event;
+use crate::EventBuffer;
+use crate::Feature;
+use crate::Floor;
+use crate::Frame;
+use crate::InputSource;
+use crate::LayerId;
+use crate::LayerInit;
+use crate::Native;
+use crate::PendingSession;
+use crate::Quitter;
+use crate::Sender;
+use crate::SessionBuilder;
+use crate::SessionInit;
+use crate::SessionMode;
+use crate::ViewerPose;
+use crate::Viewports;
+use crate::Views;
+
+use euclid::RigidTransform3D;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Inline sessions have no display to sync to, so frames are paced to this rate
+const FRAME_RATE: f32 = 60.0;
+
+/// Vends inline sessions. The registry falls back to this when none of
+/// its registered discoveries support an inline session.
+pub struct InlineDiscovery;
+
+impl<GL: 'static> DiscoveryAPI<GL> for InlineDiscovery {
+    fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+        xr: SessionBuilder<GL>,
+    ) -> Result<PendingSession, Error> {
+        if mode != SessionMode::Inline {
+            return Err(Error::NoMatchingDevice);
+        }
+        let granted_features = init.validate(mode, &[])?;
+        let events = xr.event_buffer();
+        xr.spawn(move |_| Ok(InlineDevice::new(granted_features, events)))
+    }
+
+    fn supports_session(&self, mode: SessionMode) -> bool {
+        mode == SessionMode::Inline
+    }
+}
+
+struct InlineDevice {
+    events: EventBuffer,
+    granted_features: Vec<Feature>,
+    start: Instant,
+    last_frame_time: Option<Instant>,
+}
+
+impl InlineDevice {
+    fn new(granted_features: Vec<Feature>, events: EventBuffer) -> InlineDevice {
+        InlineDevice {
+            events,
+            granted_features,
+            start: Instant::now(),
+            last_frame_time: None,
+        }
+    }
+}
+
+impl DeviceAPI for InlineDevice {
+    fn create_layer(&mut self, _: ContextId, _: LayerInit) -> Result<LayerId, Error> {
+        Err(Error::InlineSession)
+    }
+
+    fn destroy_layer(&mut self, _: ContextId, _: LayerId) {}
+
+    fn migrate_context(&mut self, _: ContextId, _: ContextId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
+        None
+    }
+
+    fn viewports(&self) -> Viewports {
+        Viewports { viewports: vec![] }
+    }
+
+    fn begin_animation_frame(&mut self, _: &[(ContextId, LayerId)]) -> Option<Frame> {
+        if let Some(last_frame_time) = self.last_frame_time {
+            let next_frame_time = last_frame_time + Duration::from_secs_f32(1.0 / FRAME_RATE);
+            let now = Instant::now();
+            if next_frame_time > now {
+                thread::sleep(next_frame_time - now);
+            }
+        }
+        self.last_frame_time = Some(Instant::now());
+        let period = 1e9 / FRAME_RATE as f64;
+        let pose = ViewerPose {
+            transform: RigidTransform3D::identity(),
+            linear_velocity: None,
+            angular_velocity: None,
+            views: Views::Inline,
+        };
+        Some(Frame {
+            pose: Some(pose),
+            inputs: vec![],
+            events: vec![],
+            sub_images: vec![],
+            hit_test_results: vec![],
+            tracked_images: vec![],
+            dom_overlay: None,
+            depth_information: None,
+            predicted_display_time: self.start.elapsed().as_nanos() as f64 + period,
+            predicted_display_period: Some(period),
+            render_budget: None,
+        })
+    }
+
+    fn end_animation_frame(&mut self, _: &[(ContextId, LayerId)]) {}
+
+    fn initial_inputs(&self) -> Vec<InputSource> {
+        vec![]
+    }
+
+    fn set_event_dest(&mut self, dest: Sender<Event>) {
+        self.events.upgrade(dest)
+    }
+
+    fn quit(&mut self) {
+        self.events.callback(Event::SessionEnd)
+    }
+
+    fn set_quitter(&mut self, _: Quitter) {}
+
+    fn update_clip_planes(&mut self, _: f32, _: f32) {}
+
+    fn granted_features(&self) -> &[Feature] {
+        &self.granted_features
+    }
+}
@@ -8,11 +8,50 @@ use crate::JointFrame;
 use crate::Native;
 
 use euclid::RigidTransform3D;
+use euclid::Vector3D;
+use std::sync::Arc;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputId(pub u32);
 
+// The ids handed out by `TransientInputIds`, which devices don't use for other inputs
+const FIRST_TRANSIENT_INPUT_ID: u32 = 1 << 16;
+const LAST_TRANSIENT_INPUT_ID: u32 = 1 << 24;
+
+impl InputId {
+    /// Whether this is the id of a transient input source, see `TransientInputIds`
+    pub fn is_transient(&self) -> bool {
+        (FIRST_TRANSIENT_INPUT_ID..LAST_TRANSIENT_INPUT_ID).contains(&self.0)
+    }
+}
+
+/// Hands out ids for transient input sources, such as touches on the screen in handheld AR,
+/// which only last as long as the touch. Ids are reused once they run out.
+#[derive(Clone, Debug)]
+pub struct TransientInputIds {
+    next: u32,
+}
+
+impl Default for TransientInputIds {
+    fn default() -> TransientInputIds {
+        TransientInputIds {
+            next: FIRST_TRANSIENT_INPUT_ID,
+        }
+    }
+}
+
+impl TransientInputIds {
+    pub fn next_id(&mut self) -> InputId {
+        let id = InputId(self.next);
+        self.next += 1;
+        if self.next == LAST_TRANSIENT_INPUT_ID {
+            self.next = FIRST_TRANSIENT_INPUT_ID;
+        }
+        id
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub enum Handedness {
@@ -21,7 +60,7 @@ pub enum Handedness {
     Right,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub enum TargetRayMode {
     Gaze,
@@ -34,6 +73,8 @@ pub enum TargetRayMode {
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputSource {
     pub handedness: Handedness,
+    /// This can change while the source is connected, for example when a tracked hand
+    /// is lowered and the device falls back to gaze, which is reported with `Event::UpdateInput`
     pub target_ray_mode: TargetRayMode,
     pub id: InputId,
     pub supports_grip: bool,
@@ -41,17 +82,61 @@ pub struct InputSource {
     pub profiles: Vec<String>,
 }
 
+impl InputSource {
+    /// An input source driven by eye tracking, available with the `eye-tracking` feature.
+    /// Its target ray follows the user's gaze, and it has no grip.
+    pub fn eye_gaze(id: InputId) -> InputSource {
+        InputSource {
+            handedness: Handedness::None,
+            target_ray_mode: TargetRayMode::Gaze,
+            id,
+            supports_grip: false,
+            hand_support: None,
+            profiles: vec![],
+        }
+    }
+
+    /// A transient input source for a touch on the screen in handheld AR,
+    /// with `id` from `TransientInputIds`
+    pub fn screen_touch(id: InputId) -> InputSource {
+        InputSource {
+            handedness: Handedness::None,
+            target_ray_mode: TargetRayMode::Screen,
+            id,
+            supports_grip: false,
+            hand_support: None,
+            profiles: vec!["generic-touchscreen".into()],
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputFrame {
     pub id: InputId,
     pub target_ray_origin: Option<RigidTransform3D<f32, Input, Native>>,
     pub grip_origin: Option<RigidTransform3D<f32, Input, Native>>,
+    /// The linear velocity of the grip (or the target ray, if there is no grip)
+    /// in native coordinates, in meters per second
+    pub linear_velocity: Option<Vector3D<f32, Native>>,
+    /// The angular velocity of the grip (or the target ray, if there is no grip)
+    /// in native coordinates, in radians per second
+    pub angular_velocity: Option<Vector3D<f32, Native>>,
     pub pressed: bool,
-    pub hand: Option<Box<Hand<JointFrame>>>,
+    /// Shared, so that copies of the frame such as the ones sent with select events
+    /// don't copy every joint
+    pub hand: Option<Arc<Hand<JointFrame>>>,
     pub squeezed: bool,
+    /// How far the primary button (usually a trigger) is pressed, between 0 and 1
+    pub select_value: f32,
+    /// How far the squeeze button is pressed, between 0 and 1
+    pub squeeze_value: f32,
     pub button_values: Vec<f32>,
     pub axis_values: Vec<f32>,
+    /// Bit `i` is set if the button at `button_values[i]` is pressed
+    pub buttons_pressed: u32,
+    /// Bit `i` is set if the button at `button_values[i]` is touched
+    pub buttons_touched: u32,
     pub input_changed: bool,
 }
 
@@ -72,3 +157,35 @@ pub enum SelectKind {
     Select,
     Squeeze,
 }
+
+/// What the user did to cause a select event, so that embedders can describe
+/// it in terms of the input method they are actually using
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectGesture {
+    /// The primary button of a controller, usually a trigger
+    Trigger,
+    /// The grip button of a controller
+    Grip,
+    /// Touching the thumb and index finger together, with hand tracking
+    Pinch,
+    /// Tapping a touch screen
+    ScreenTap,
+    /// Holding a gaze input still, see `DwellOptions`
+    Dwell,
+    Other,
+}
+
+impl SelectGesture {
+    /// The gesture that usually causes `kind` of select on an input source
+    /// with the given target ray mode, for devices that can't tell
+    pub fn typical(target_ray_mode: TargetRayMode, kind: SelectKind) -> SelectGesture {
+        match (target_ray_mode, kind) {
+            (TargetRayMode::TrackedPointer, SelectKind::Select) => SelectGesture::Trigger,
+            (TargetRayMode::TrackedPointer, SelectKind::Squeeze) => SelectGesture::Grip,
+            (TargetRayMode::TransientPointer, SelectKind::Select) => SelectGesture::Pinch,
+            (TargetRayMode::Screen, SelectKind::Select) => SelectGesture::ScreenTap,
+            _ => SelectGesture::Other,
+        }
+    }
+}
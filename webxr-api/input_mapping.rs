@@ -0,0 +1,167 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Remapping and disabling the buttons and axes of inputs before content sees them,
+//! for embedders that keep some of them for themselves, such as a button for their menu.
+//!
+//! The mapping is applied by the session thread, so it works the same way for every device.
+
+use crate::Event;
+use crate::Frame;
+use crate::InputFrame;
+use crate::InputId;
+use crate::InputSource;
+
+use std::sync::Arc;
+
+/// Which buttons and axes to remap or disable for each interaction profile,
+/// see `MainThreadRegistry::set_input_mapping`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputMapping {
+    pub profiles: Vec<ProfileMapping>,
+}
+
+/// The mapping for the inputs with an interaction profile. An input gets the mapping
+/// of the first of its profiles that has one, so the most specific profile wins.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileMapping {
+    /// The profile, as in `InputSource::profiles`
+    pub profile: String,
+    /// Indices into `InputFrame::button_values`
+    pub buttons: Vec<ControlMapping>,
+    /// Indices into `InputFrame::axis_values`
+    pub axes: Vec<ControlMapping>,
+}
+
+/// Moves a button or axis to `to`, or disables it with `None`. Controls that are moved
+/// away from read as released unless something else is moved onto them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlMapping {
+    pub from: usize,
+    pub to: Option<usize>,
+}
+
+impl InputMapping {
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    fn for_profiles(&self, profiles: &[String]) -> Option<&ProfileMapping> {
+        profiles.iter().find_map(|profile| {
+            self.profiles
+                .iter()
+                .find(|mapping| mapping.profile == *profile)
+        })
+    }
+}
+
+impl ProfileMapping {
+    fn apply(&self, input: &mut InputFrame) {
+        remap(
+            &self.buttons,
+            &mut input.button_values,
+            &mut [&mut input.buttons_pressed, &mut input.buttons_touched],
+        );
+        remap(&self.axes, &mut input.axis_values, &mut []);
+    }
+}
+
+/// Applies an `InputMapping`, keeping track of the profiles of inputs from the events
+/// that add and update them. Selects and squeezes are left alone.
+pub(crate) struct InputMapper {
+    mapping: InputMapping,
+    profiles: Vec<(InputId, Vec<String>)>,
+}
+
+impl InputMapper {
+    pub(crate) fn new(mapping: InputMapping, inputs: &[InputSource]) -> InputMapper {
+        let mut mapper = InputMapper {
+            mapping,
+            profiles: vec![],
+        };
+        for source in inputs {
+            mapper.add(source);
+        }
+        mapper
+    }
+
+    fn add(&mut self, source: &InputSource) {
+        self.remove(source.id);
+        self.profiles.push((source.id, source.profiles.clone()));
+    }
+
+    fn remove(&mut self, id: InputId) {
+        self.profiles.retain(|&(input_id, _)| input_id != id);
+    }
+
+    /// Learn about inputs from `event`, and apply the mapping to any input data in it
+    pub(crate) fn map_event(&mut self, event: &mut Event) {
+        match *event {
+            Event::AddInput(ref source) | Event::UpdateInput(_, ref source) => self.add(source),
+            Event::RemoveInput(id) | Event::TransientInputEnd(id) => self.remove(id),
+            Event::TransientInputStart(ref source, ref mut input) => {
+                self.add(source);
+                self.map_input(input);
+            }
+            Event::InputChanged(_, ref mut input) => self.map_input(input),
+            Event::Select(.., ref mut frame) => self.map_frame(Arc::make_mut(frame)),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn map_frame(&self, frame: &mut Frame) {
+        for input in &mut frame.inputs {
+            self.map_input(input);
+        }
+    }
+
+    fn map_input(&self, input: &mut InputFrame) {
+        let mapping = self
+            .profiles
+            .iter()
+            .find(|&&(id, _)| id == input.id)
+            .and_then(|(_, profiles)| self.mapping.for_profiles(profiles));
+        if let Some(mapping) = mapping {
+            mapping.apply(input);
+        }
+    }
+}
+
+/// Move the controls in `values`, and their bits in `masks`, as `mappings` say
+fn remap(mappings: &[ControlMapping], values: &mut [f32], masks: &mut [&mut u32]) {
+    if mappings.is_empty() {
+        return;
+    }
+    let original_values = values.to_vec();
+    let original_masks: Vec<u32> = masks.iter().map(|mask| **mask).collect();
+    for mapping in mappings {
+        if let Some(value) = values.get_mut(mapping.from) {
+            *value = 0.0;
+            for mask in masks.iter_mut() {
+                **mask &= !bit(mapping.from);
+            }
+        }
+    }
+    for mapping in mappings {
+        let (to, value) = match (mapping.to, original_values.get(mapping.from)) {
+            (Some(to), Some(&value)) if to < values.len() => (to, value),
+            _ => continue,
+        };
+        values[to] = value;
+        for (mask, original) in masks.iter_mut().zip(&original_masks) {
+            if original & bit(mapping.from) != 0 {
+                **mask |= bit(to);
+            } else {
+                **mask &= !bit(to);
+            }
+        }
+    }
+}
+
+fn bit(index: usize) -> u32 {
+    1u32.checked_shl(index as u32).unwrap_or(0)
+}
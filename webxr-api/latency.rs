@@ -0,0 +1,82 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Measuring how long frames take to get from the device to the display.
+//!
+//! While a session has a latency destination, each frame's index is drawn in the
+//! corner of every view by the layer manager, and the time the frame was submitted
+//! is sent to the destination. Something watching the display, such as a photodiode
+//! rig or the headless device's layer contents, can then match what it sees up with
+//! when it was submitted.
+
+use crate::Viewport;
+
+use euclid::Point2D;
+use euclid::Rect;
+use euclid::Size2D;
+
+use std::time::SystemTime;
+
+// The number of bits of the frame index that are drawn
+const MARKER_BITS: u32 = 16;
+
+// The size of the square drawn for each bit, in pixels
+const MARKER_SQUARE_SIZE: i32 = 16;
+
+/// The mark drawn on a frame, a row of squares in the bottom left corner of each view,
+/// one for each of the low bits of the frame index, lowest first. The squares for bits
+/// that are set are white, and the others are black, so the first square flips every frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyMarker {
+    pub frame_index: u64,
+}
+
+impl LatencyMarker {
+    /// The squares of the marker in `viewport`, and whether each is white.
+    /// Squares that don't fit in the viewport are left out.
+    pub fn squares(&self, viewport: Rect<i32, Viewport>) -> Vec<(Rect<i32, Viewport>, bool)> {
+        let size = Size2D::new(MARKER_SQUARE_SIZE, MARKER_SQUARE_SIZE);
+        (0..MARKER_BITS)
+            .map(|bit| {
+                let origin = Point2D::new(
+                    viewport.origin.x + bit as i32 * size.width,
+                    viewport.origin.y,
+                );
+                let white = self.frame_index & (1 << bit) != 0;
+                (Rect::new(origin, size), white)
+            })
+            .filter(|(square, _)| viewport.contains_rect(square))
+            .collect()
+    }
+
+    /// Read the marker back out of the RGBA8 contents of a layer that is `width` pixels
+    /// wide, from the bottom row up. Only the low bits of the frame index are drawn,
+    /// so the index read back wraps around.
+    pub fn read(pixels: &[u8], width: i32, viewport: Rect<i32, Viewport>) -> Option<LatencyMarker> {
+        let marker = LatencyMarker { frame_index: 0 };
+        let squares = marker.squares(viewport);
+        if squares.len() != MARKER_BITS as usize {
+            return None;
+        }
+        let mut frame_index = 0;
+        for (bit, (square, _)) in squares.iter().enumerate() {
+            let center = square.center();
+            let offset = (center.y * width + center.x) as usize * 4;
+            if *pixels.get(offset)? > 127 {
+                frame_index |= 1 << bit;
+            }
+        }
+        Some(LatencyMarker { frame_index })
+    }
+}
+
+/// When a frame was submitted to the device, see `Session::set_latency_dest`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameSubmission {
+    /// The frame index that the frame's `LatencyMarker` was drawn with
+    pub frame_index: u64,
+    pub submitted: SystemTime,
+}
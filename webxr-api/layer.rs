@@ -2,11 +2,17 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::ApiSpace;
 use crate::Error;
+use crate::LatencyMarker;
+use crate::Space;
 use crate::Viewport;
 use crate::Viewports;
 
+use euclid::Point2D;
 use euclid::Rect;
+use euclid::RigidTransform3D;
+use euclid::Rotation3D;
 use euclid::Size2D;
 
 use std::fmt::Debug;
@@ -103,8 +109,103 @@ pub trait LayerManagerAPI<GL: GLTypes> {
         layer_id: LayerId,
     );
 
+    /// Re-bind every layer belonging to `old_context_id` to `new_context_id`,
+    /// recreating any GL resources in the new context. Layer ids are preserved.
+    /// This is used to recover from WebGL context loss without ending the session.
+    fn migrate_context(
+        &mut self,
+        device: &mut GL::Device,
+        contexts: &mut dyn GLContexts<GL>,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error>;
+
     fn layers(&self) -> &[(ContextId, LayerId)];
 
+    /// The number of swapchains held for the layers, if the layer manager knows
+    fn swapchain_count(&self) -> Option<usize> {
+        None
+    }
+
+    /// An estimate of the texture memory held for the layers in bytes,
+    /// including depth/stencil textures, if the layer manager knows
+    fn texture_memory(&self) -> Option<u64> {
+        None
+    }
+
+    /// Set the fixed foveation level of a layer, between 0 (none) and 1 (maximum).
+    /// https://immersive-web.github.io/layers/#dom-xrcompositionlayer-fixedfoveation
+    fn set_foveation_level(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _level: f32,
+    ) {
+    }
+
+    /// Set how the compositor filters a layer, starting with the next frame,
+    /// or leave it to the runtime with `None`. See `LayerQuality`.
+    fn set_layer_quality(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _quality: Option<LayerQuality>,
+    ) {
+    }
+
+    /// Move a quad, cylinder, equirect or cube layer, starting with the next frame.
+    /// https://immersive-web.github.io/layers/#xrquadlayer
+    fn set_layer_geometry(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _geometry: LayerGeometry,
+    ) {
+    }
+
+    /// Attach a quad, cylinder or equirect layer to `space`, starting with the next frame,
+    /// so that its geometry is relative to it. Unlike content moving the layer itself,
+    /// the layer is moved when the device locates `space`, so it doesn't lag a frame behind.
+    fn attach_layer(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _context_id: ContextId,
+        _layer_id: LayerId,
+        _space: Space,
+    ) {
+    }
+
+    /// Darken the edges of every view by `strength`, between 0 (not at all) and 1,
+    /// from the end of the current frame on. This is for the comfort vignette.
+    fn set_vignette(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _strength: f32,
+    ) {
+    }
+
+    /// Draw `marker` on every view from the end of the current frame on,
+    /// or stop drawing it with `None`. See `LatencyMarker`.
+    fn set_latency_marker(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _marker: Option<LatencyMarker>,
+    ) {
+    }
+
+    /// Release whatever the layers can do without while no frames are rendered,
+    /// because the session is paused. `begin_frame` recreates anything released.
+    fn release_surfaces(&mut self, _device: &mut GL::Device, _contexts: &mut dyn GLContexts<GL>) {}
+
     fn begin_frame(
         &mut self,
         device: &mut GL::Device,
@@ -141,6 +242,65 @@ impl LayerManager {
         self.0.destroy_layer(&mut (), &mut (), context_id, layer_id);
     }
 
+    pub fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.0
+            .migrate_context(&mut (), &mut (), old_context_id, new_context_id)
+    }
+
+    pub fn set_foveation_level(&mut self, context_id: ContextId, layer_id: LayerId, level: f32) {
+        self.0
+            .set_foveation_level(&mut (), &mut (), context_id, layer_id, level)
+    }
+
+    pub fn set_layer_quality(
+        &mut self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        quality: Option<LayerQuality>,
+    ) {
+        self.0
+            .set_layer_quality(&mut (), &mut (), context_id, layer_id, quality)
+    }
+
+    pub fn set_layer_geometry(
+        &mut self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        geometry: LayerGeometry,
+    ) {
+        self.0
+            .set_layer_geometry(&mut (), &mut (), context_id, layer_id, geometry)
+    }
+
+    pub fn attach_layer(&mut self, context_id: ContextId, layer_id: LayerId, space: Space) {
+        self.0
+            .attach_layer(&mut (), &mut (), context_id, layer_id, space)
+    }
+
+    pub fn set_vignette(&mut self, strength: f32) {
+        self.0.set_vignette(&mut (), &mut (), strength)
+    }
+
+    pub fn swapchain_count(&self) -> Option<usize> {
+        self.0.swapchain_count()
+    }
+
+    pub fn texture_memory(&self) -> Option<u64> {
+        self.0.texture_memory()
+    }
+
+    pub fn set_latency_marker(&mut self, marker: Option<LatencyMarker>) {
+        self.0.set_latency_marker(&mut (), &mut (), marker)
+    }
+
+    pub fn release_surfaces(&mut self) {
+        self.0.release_surfaces(&mut (), &mut ())
+    }
+
     pub fn begin_frame(
         &mut self,
         layers: &[(ContextId, LayerId)],
@@ -219,7 +379,7 @@ impl LayerId {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub enum LayerInit {
     // https://www.w3.org/TR/webxr/#dictdef-xrwebgllayerinit
@@ -230,6 +390,8 @@ pub enum LayerInit {
         alpha: bool,
         ignore_depth_values: bool,
         framebuffer_scale_factor: f32,
+        /// The color formats the embedder can render to, most preferred first
+        color_formats: Vec<ColorFormat>,
     },
     // https://immersive-web.github.io/layers/#xrprojectionlayerinittype
     ProjectionLayer {
@@ -237,11 +399,77 @@ pub enum LayerInit {
         stencil: bool,
         alpha: bool,
         scale_factor: f32,
+        /// The color formats the embedder can render to, most preferred first
+        color_formats: Vec<ColorFormat>,
+    },
+    // https://immersive-web.github.io/layers/#xrquadlayerinittype, and the init types of
+    // the other layers that are placed in the world rather than covering the views
+    CompositionLayer {
+        space: Space,
+        /// The size of the texture for each eye
+        view_pixel_size: Size2D<i32, Viewport>,
+        layout: LayerLayout,
+        geometry: LayerGeometry,
+        /// The color formats the embedder can render to, most preferred first
+        color_formats: Vec<ColorFormat>,
     },
-    // TODO: other layer types
+}
+
+/// The shape of a layer that is placed in the world, and where it is relative to its space
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum LayerGeometry {
+    // https://immersive-web.github.io/layers/#xrquadlayertype
+    Quad {
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        /// The size of the quad, in meters
+        width: f32,
+        height: f32,
+    },
+    // https://immersive-web.github.io/layers/#xrcylinderlayertype
+    Cylinder {
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        radius: f32,
+        /// The angle of the visible part of the cylinder, in radians
+        central_angle: f32,
+        /// The width of the visible part of the cylinder divided by its height
+        aspect_ratio: f32,
+    },
+    // https://immersive-web.github.io/layers/#xrequirectlayertype
+    Equirect {
+        transform: RigidTransform3D<f32, ApiSpace, ApiSpace>,
+        /// The radius of the sphere, or zero for a sphere infinitely far away
+        radius: f32,
+        central_horizontal_angle: f32,
+        upper_vertical_angle: f32,
+        lower_vertical_angle: f32,
+    },
+    // https://immersive-web.github.io/layers/#xrcubelayertype
+    Cube {
+        orientation: Rotation3D<f32, ApiSpace, ApiSpace>,
+    },
+}
+
+impl LayerGeometry {
+    pub fn layer_type(&self) -> LayerType {
+        match self {
+            LayerGeometry::Quad { .. } => LayerType::QuadLayer,
+            LayerGeometry::Cylinder { .. } => LayerType::CylinderLayer,
+            LayerGeometry::Equirect { .. } => LayerType::EquirectLayer,
+            LayerGeometry::Cube { .. } => LayerType::CubeLayer,
+        }
+    }
 }
 
 impl LayerInit {
+    pub fn layer_type(&self) -> LayerType {
+        match self {
+            LayerInit::WebGLLayer { .. } => LayerType::WebGLLayer,
+            LayerInit::ProjectionLayer { .. } => LayerType::ProjectionLayer,
+            LayerInit::CompositionLayer { geometry, .. } => geometry.layer_type(),
+        }
+    }
+
     pub fn texture_size(&self, viewports: &Viewports) -> Size2D<i32, Viewport> {
         match self {
             LayerInit::WebGLLayer {
@@ -259,12 +487,150 @@ impl LayerInit {
                     .size;
                 (native_size.to_f32() * *scale).to_i32()
             }
+            LayerInit::CompositionLayer {
+                view_pixel_size,
+                layout,
+                ..
+            } => layout.texture_size(*view_pixel_size),
+        }
+    }
+
+    /// The number of samples per pixel to allocate for the layer, given the number
+    /// that the device recommends. Only antialiased WebGL layers are multisampled.
+    pub fn sample_count(&self, recommended: u32) -> u32 {
+        match self {
+            LayerInit::WebGLLayer {
+                antialias: true, ..
+            } => recommended.max(1),
+            LayerInit::WebGLLayer { .. }
+            | LayerInit::ProjectionLayer { .. }
+            | LayerInit::CompositionLayer { .. } => 1,
+        }
+    }
+
+    /// Pick the color format for the layer out of the formats that the device
+    /// `supports`, in the device's order of preference. This is the embedder's most
+    /// preferred format that the device supports, or the device's own preference if
+    /// the embedder has none. It is `None` if the device supports none of the embedder's
+    /// formats, since the embedder can't handle any other.
+    pub fn pick_color_format(&self, supports: &[ColorFormat]) -> Option<ColorFormat> {
+        let preferred = match self {
+            LayerInit::WebGLLayer { color_formats, .. }
+            | LayerInit::ProjectionLayer { color_formats, .. }
+            | LayerInit::CompositionLayer { color_formats, .. } => color_formats,
+        };
+        if preferred.is_empty() {
+            return supports.first().copied();
+        }
+        preferred
+            .iter()
+            .find(|format| supports.contains(format))
+            .copied()
+    }
+}
+
+/// The formats that a layer's color texture can have
+/// https://immersive-web.github.io/layers/#xrprojectionlayerinittype
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum ColorFormat {
+    Rgba8,
+    Rgba8Srgb,
+    Bgra8,
+    Bgra8Srgb,
+}
+
+impl ColorFormat {
+    /// Whether the device converts colors written to the texture from linear to sRGB
+    pub fn is_srgb(&self) -> bool {
+        match self {
+            ColorFormat::Rgba8Srgb | ColorFormat::Bgra8Srgb => true,
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => false,
         }
     }
 }
 
+/// How much of a filter the compositor applies to a layer, see `LayerQuality`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum LayerFilter {
+    Off,
+    Normal,
+    /// Better looking than `Normal`, but costs the compositor more
+    Quality,
+}
+
+/// Filters for the compositor to apply to a layer when it shows it. Runtimes differ
+/// a lot in what they do by default, which affects how readable text in layers is,
+/// so embedders can pick for themselves. Devices that can't filter layers ignore this.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub struct LayerQuality {
+    /// Reduces flicker in detailed content, at the cost of some blur
+    pub supersampling: LayerFilter,
+    /// Makes text and edges crisper, at the cost of some aliasing
+    pub sharpening: LayerFilter,
+}
+
+/// The kinds of layer that can be created, one per `LayerInit` variant
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum LayerType {
+    WebGLLayer,
+    ProjectionLayer,
+    QuadLayer,
+    CylinderLayer,
+    EquirectLayer,
+    CubeLayer,
+}
+
+/// Device-reported limits on the layers a session can create
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub struct LayerLimits {
+    /// The maximum number of layers that can be created, and so rendered, at once
+    pub max_layers: usize,
+    /// The maximum size of a layer's texture
+    pub max_texture_size: Size2D<i32, Viewport>,
+    pub supported_layer_types: Vec<LayerType>,
+}
+
+impl Default for LayerLimits {
+    fn default() -> LayerLimits {
+        LayerLimits {
+            // The minimum that OpenXR runtimes are required to support
+            max_layers: 16,
+            max_texture_size: Size2D::new(8192, 8192),
+            supported_layer_types: vec![LayerType::WebGLLayer, LayerType::ProjectionLayer],
+        }
+    }
+}
+
+impl LayerLimits {
+    /// Check that a layer created with `init` fits within these limits
+    pub fn check_layer(&self, init: &LayerInit, viewports: &Viewports) -> Result<(), Error> {
+        let layer_type = init.layer_type();
+        if !self.supported_layer_types.contains(&layer_type) {
+            return Err(Error::UnsupportedFeature(format!("{:?}", layer_type)));
+        }
+        let size = init.texture_size(viewports);
+        if size.width > self.max_texture_size.width || size.height > self.max_texture_size.height {
+            return Err(Error::LayerLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Check that the number of layers being rendered fits within these limits
+    pub fn check_layer_count(&self, count: usize) -> Result<(), Error> {
+        if count > self.max_layers {
+            return Err(Error::LayerLimitExceeded);
+        }
+        Ok(())
+    }
+}
+
 /// https://immersive-web.github.io/layers/#enumdef-xrlayerlayout
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub enum LayerLayout {
     // TODO: Default
@@ -276,10 +642,42 @@ pub enum LayerLayout {
     StereoTopBottom,
 }
 
+impl LayerLayout {
+    /// The size of the texture that holds views of `view_size` laid out this way
+    pub fn texture_size(&self, view_size: Size2D<i32, Viewport>) -> Size2D<i32, Viewport> {
+        match self {
+            LayerLayout::Mono => view_size,
+            LayerLayout::StereoLeftRight => Size2D::new(view_size.width * 2, view_size.height),
+            LayerLayout::StereoTopBottom => Size2D::new(view_size.width, view_size.height * 2),
+        }
+    }
+
+    /// The parts of the texture shown to the left and right eyes
+    pub fn eye_rects(&self, view_size: Size2D<i32, Viewport>) -> [Rect<i32, Viewport>; 2] {
+        let left = Rect::new(Point2D::zero(), view_size);
+        let right = match self {
+            LayerLayout::Mono => left,
+            LayerLayout::StereoLeftRight => Rect::new(Point2D::new(view_size.width, 0), view_size),
+            LayerLayout::StereoTopBottom => Rect::new(Point2D::new(0, view_size.height), view_size),
+        };
+        [left, right]
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub struct SubImages {
     pub layer_id: LayerId,
+    /// The number of samples per pixel of the layer's color and depth/stencil buffers.
+    /// If this is more than one, the depth/stencil texture is a `TEXTURE_2D_MULTISAMPLE`
+    /// texture and the color buffer is only reachable through `framebuffer`.
+    pub sample_count: u32,
+    /// The format that was picked for the layer's color texture
+    pub color_format: ColorFormat,
+    /// The framebuffer to render into, for layers whose color buffer isn't a texture, such as
+    /// multisampled ones. The color textures of the sub images then get what was rendered
+    /// once the frame has ended.
+    pub framebuffer: Option<u32>,
     pub sub_image: Option<SubImage>,
     pub view_sub_images: Vec<SubImage>,
 }
@@ -3,35 +3,93 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 //! This crate defines the Rust API for WebXR. It is implemented by the `webxr` crate.
+//!
+//! It has no native dependencies. Graphics types are supplied through `GLTypes` by the
+//! crate that renders, such as `webxr` with surfman, so crates that only need the messages,
+//! like Servo's script crate, can depend on this one with just the `ipc` feature.
 
+mod comfort;
+mod depth_sensing;
 mod device;
+mod device_panic;
+mod dom_overlay;
+mod dwell;
 mod error;
 mod events;
+mod feature;
 mod frame;
+mod governor;
 mod hand;
 mod hittest;
+mod image_tracking;
+mod inline;
 mod input;
+mod input_mapping;
+mod latency;
 mod layer;
+mod mesh;
 mod mock;
+mod observer;
+mod pacing;
+mod pose_graph;
+mod protocol;
 mod registry;
+#[cfg(feature = "replay")]
+mod replay;
+mod select_arbitration;
 mod session;
+mod soak;
 mod space;
+mod stats;
+mod trusted_ui;
+mod usage;
 pub mod util;
 mod view;
 
+pub use comfort::ComfortMsg;
+pub use comfort::ComfortOptions;
+
 pub use device::DeviceAPI;
+pub use device::DeviceDescriptor;
 pub use device::DiscoveryAPI;
 
+pub use device_panic::DevicePanic;
+pub use device_panic::DevicePanicHandler;
+pub use device_panic::DevicePanicPolicy;
+
+pub use depth_sensing::DepthInformation;
+pub use depth_sensing::NormDepthBuffer;
+pub use depth_sensing::NormView;
+
+pub use dom_overlay::DomOverlayInit;
+pub use dom_overlay::DomOverlayPointer;
+pub use dom_overlay::DomOverlayPointerKind;
+pub use dom_overlay::DomOverlayState;
+pub use dom_overlay::DomOverlayType;
+
+pub use dwell::DwellOptions;
+pub use dwell::DwellSelect;
+
+pub use error::Backend;
 pub use error::Error;
 
 pub use events::Event;
 pub use events::EventBuffer;
+pub use events::EventBufferOptions;
 pub use events::Visibility;
 
+pub use feature::Feature;
+
+pub use frame::ExtensionEncoding;
+pub use frame::ExtensionEvent;
 pub use frame::Frame;
+pub use frame::FrameMailbox;
+pub use frame::FrameSubscriptions;
 pub use frame::FrameUpdateEvent;
 pub use frame::ViewerPose;
 
+pub use governor::GovernorOptions;
+
 pub use hand::Finger;
 pub use hand::FingerJoint;
 pub use hand::Hand;
@@ -39,79 +97,169 @@ pub use hand::HandSpace;
 pub use hand::Joint;
 pub use hand::JointFrame;
 
+pub use hittest::EntityId;
 pub use hittest::EntityType;
 pub use hittest::EntityTypes;
 pub use hittest::HitTestId;
+pub use hittest::HitTestLimits;
 pub use hittest::HitTestResult;
 pub use hittest::HitTestSource;
 pub use hittest::HitTestSpace;
 pub use hittest::Ray;
 pub use hittest::Triangle;
+pub use hittest::TriangleBvh;
+pub use hittest::TriangleHit;
+
+pub use image_tracking::TrackedImage;
+pub use image_tracking::TrackedImageInit;
+pub use image_tracking::TrackedImageSpace;
+pub use image_tracking::TrackingState;
+
+pub use inline::InlineDiscovery;
 
 pub use input::Handedness;
 pub use input::InputFrame;
 pub use input::InputId;
 pub use input::InputSource;
 pub use input::SelectEvent;
+pub use input::SelectGesture;
 pub use input::SelectKind;
 pub use input::TargetRayMode;
+pub use input::TransientInputIds;
 
+pub use input_mapping::ControlMapping;
+pub use input_mapping::InputMapping;
+pub use input_mapping::ProfileMapping;
+
+pub use latency::FrameSubmission;
+pub use latency::LatencyMarker;
+
+pub use layer::ColorFormat;
 pub use layer::ContextId;
 pub use layer::GLContexts;
 pub use layer::GLTypes;
+pub use layer::LayerFilter;
+pub use layer::LayerGeometry;
 pub use layer::LayerGrandManager;
 pub use layer::LayerGrandManagerAPI;
 pub use layer::LayerId;
 pub use layer::LayerInit;
 pub use layer::LayerLayout;
+pub use layer::LayerLimits;
 pub use layer::LayerManager;
 pub use layer::LayerManagerAPI;
 pub use layer::LayerManagerFactory;
+pub use layer::LayerQuality;
+pub use layer::LayerType;
 pub use layer::SubImage;
 pub use layer::SubImages;
 
+pub use mesh::DetectedMesh;
+pub use mesh::MeshId;
+pub use mesh::MeshSpace;
+
+pub use mock::MockAnchorId;
+pub use mock::MockAnchorMsg;
 pub use mock::MockButton;
 pub use mock::MockButtonType;
+pub use mock::MockColocation;
 pub use mock::MockDeviceInit;
 pub use mock::MockDeviceMsg;
 pub use mock::MockDiscoveryAPI;
+pub use mock::MockHapticPulse;
 pub use mock::MockInputInit;
 pub use mock::MockInputMsg;
+pub use mock::MockLayerContents;
 pub use mock::MockRegion;
+pub use mock::MockTimelineEntry;
 pub use mock::MockViewInit;
 pub use mock::MockViewsInit;
 pub use mock::MockWorld;
+pub use mock::Room;
 
+pub use observer::SessionObserver;
+pub use pacing::FramePacing;
+
+pub use pose_graph::PoseGraph;
+pub use pose_graph::PoseGraphEdge;
+pub use pose_graph::PoseGraphNode;
+pub use pose_graph::PoseGraphSpace;
+pub use protocol::PROTOCOL_VERSION;
+
+pub use registry::CancellationToken;
+pub use registry::DeviceChangeNotifier;
+pub use registry::ImmersiveSessionPolicy;
 pub use registry::MainThreadRegistry;
 pub use registry::MainThreadWaker;
+pub use registry::PermissionDecision;
+pub use registry::PermissionGate;
 pub use registry::Registry;
+pub use registry::SessionRequestHandle;
+
+#[cfg(feature = "replay")]
+pub use replay::RecordedEntry;
+#[cfg(feature = "replay")]
+pub use replay::RecordedMsg;
+#[cfg(feature = "replay")]
+pub use replay::Recording;
+#[cfg(feature = "replay")]
+pub use replay::RecordingInfo;
+#[cfg(feature = "replay")]
+pub use replay::SessionRecorder;
+
+pub use select_arbitration::ProfileArbitration;
+pub use select_arbitration::SelectArbiter;
+pub use select_arbitration::SelectArbitration;
+pub use select_arbitration::SelectArbitrationOptions;
 
 pub use session::EnvironmentBlendMode;
 pub use session::MainThreadSession;
+pub use session::PendingSession;
 pub use session::Quitter;
 pub use session::Session;
 pub use session::SessionBuilder;
 pub use session::SessionId;
 pub use session::SessionInit;
 pub use session::SessionMode;
+pub use session::SessionProgress;
+pub use session::SessionProgressReporter;
 pub use session::SessionThread;
+pub(crate) use session::SessionThreadHandle;
+
+pub use soak::CountingAllocator;
+pub use soak::DeviceResources;
+pub use soak::ResourceReport;
+pub use soak::SoakOptions;
 
 pub use space::ApiSpace;
 pub use space::BaseSpace;
 pub use space::Space;
 
-pub use view::Capture;
+pub use stats::FrameStats;
+
+pub use trusted_ui::TrustedUiFuture;
+pub use trusted_ui::TrustedUiPrompt;
+pub use trusted_ui::TrustedUiProvider;
+pub use trusted_ui::TrustedUiResult;
+
+pub use usage::InputUsage;
+pub use usage::UsageOptions;
+pub use usage::UsageSummary;
+
+pub use view::CameraIntrinsics;
 pub use view::CubeBack;
 pub use view::CubeBottom;
 pub use view::CubeLeft;
 pub use view::CubeRight;
 pub use view::CubeTop;
+pub use view::CustomEye;
 pub use view::Display;
 pub use view::Floor;
 pub use view::Input;
 pub use view::LeftEye;
 pub use view::Native;
 pub use view::RightEye;
+pub use view::Secondary;
 pub use view::SomeEye;
 pub use view::View;
 pub use view::Viewer;
@@ -127,11 +275,13 @@ pub use view::LEFT_EYE;
 pub use view::RIGHT_EYE;
 pub use view::VIEWER;
 
-#[cfg(feature = "ipc")]
-use std::thread;
-
 use std::time::Duration;
 
+// Without the `ipc` feature, messages are moved through `std::sync::mpsc` channels rather
+// than serialized. Large payloads, such as mesh geometry, hand joints and the frames sent
+// with select events, are held in `Arc`s so that they can be shared within the process
+// without being copied, and are serialized by value when they do cross a process boundary.
+
 #[cfg(feature = "ipc")]
 pub use ipc_channel::ipc::IpcSender as Sender;
 
@@ -162,14 +312,5 @@ pub fn recv_timeout<T>(
 where
     T: serde::Serialize + for<'a> serde::Deserialize<'a>,
 {
-    // Sigh, polling, sigh.
-    let mut delay = timeout / 1000;
-    while delay < timeout {
-        if let Ok(msg) = receiver.try_recv() {
-            return Ok(msg);
-        }
-        thread::sleep(delay);
-        delay = delay * 2;
-    }
-    receiver.try_recv()
+    receiver.try_recv_timeout(timeout)
 }
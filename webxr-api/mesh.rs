@@ -0,0 +1,34 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a detected mesh
+pub struct MeshSpace;
+
+/// A mesh of the real world, detected by the device.
+/// The geometry is shared, so devices can send a mesh again without copying it.
+/// https://immersive-web.github.io/real-world-meshing/#xrmesh
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetectedMesh {
+    pub id: MeshId,
+    pub vertices: Arc<[Point3D<f32, MeshSpace>]>,
+    /// Triangle list, three indices into `vertices` per triangle
+    pub indices: Arc<[u32]>,
+    pub pose: RigidTransform3D<f32, MeshSpace, Native>,
+    /// The time at which the mesh was last changed, in the same units as
+    /// `Frame::predicted_display_time`
+    pub last_changed: f64,
+}
@@ -2,29 +2,41 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::ApiSpace;
+use crate::CameraIntrinsics;
+use crate::CustomEye;
+use crate::DepthInformation;
 use crate::DiscoveryAPI;
 use crate::Display;
+use crate::DomOverlayPointer;
 use crate::EntityType;
 use crate::Error;
+use crate::ExtensionEvent;
+use crate::Feature;
 use crate::Floor;
+use crate::Hand;
 use crate::Handedness;
 use crate::Input;
 use crate::InputId;
 use crate::InputSource;
+use crate::JointFrame;
+use crate::LayerId;
 use crate::LeftEye;
 use crate::Native;
 use crate::Receiver;
 use crate::RightEye;
+use crate::Secondary;
 use crate::SelectEvent;
 use crate::SelectKind;
 use crate::Sender;
 use crate::TargetRayMode;
+use crate::TrackedImage;
 use crate::Triangle;
 use crate::Viewer;
 use crate::Viewport;
 use crate::Visibility;
 
-use euclid::{Point2D, Rect, RigidTransform3D, Transform3D};
+use euclid::{Point2D, Rect, RigidTransform3D, Size2D, Transform3D};
 
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
@@ -47,10 +59,35 @@ pub struct MockDeviceInit {
     pub supports_ar: bool,
     pub viewer_origin: Option<RigidTransform3D<f32, Viewer, Native>>,
     pub views: MockViewsInit,
-    pub supported_features: Vec<String>,
+    pub supported_features: Vec<Feature>,
     pub world: Option<MockWorld>,
+    /// Read back the contents of layers at the end of each frame,
+    /// so they can be retrieved with `MockDeviceMsg::GetLayerContents`
+    pub export_layer_contents: bool,
+    /// The bounds of the `bounded-floor` space, as in `MockDeviceMsg::SetBoundsGeometry`
+    /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrdeviceinit-boundscoordinates
+    pub bounds_geometry: Vec<Point2D<f32, Floor>>,
+    /// Put the device in a room with other mock devices, or `None` to keep it to itself
+    pub colocation: Option<MockColocation>,
 }
 
+/// Where a mock device is in a room it shares with other mock devices, as if several
+/// users were in the same room with their own headsets. Devices in a room share its world,
+/// so `MockDeviceMsg::SetWorld` on one of them changes it for all, and its shared anchors.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockColocation {
+    /// The name of the room, which devices with the same name share
+    pub room: String,
+    /// Where the device's native origin is in the room
+    pub origin: RigidTransform3D<f32, Native, Room>,
+}
+
+/// The space of a room that colocated mock devices are in, see `MockColocation`
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum Room {}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct MockViewInit<Eye> {
@@ -59,6 +96,9 @@ pub struct MockViewInit<Eye> {
     pub viewport: Rect<i32, Viewport>,
     /// field of view values, in radians
     pub fov: Option<(f32, f32, f32, f32)>,
+    /// The intrinsics of the camera that records the view, if one does,
+    /// such as the first-person observer camera of a secondary view
+    pub camera: Option<CameraIntrinsics>,
 }
 
 #[derive(Clone, Debug)]
@@ -66,6 +106,14 @@ pub struct MockViewInit<Eye> {
 pub enum MockViewsInit {
     Mono(MockViewInit<Viewer>),
     Stereo(MockViewInit<LeftEye>, MockViewInit<RightEye>),
+    /// Stereo views, with secondary views for sessions that have the `secondary-views` feature
+    StereoSecondary(
+        MockViewInit<LeftEye>,
+        MockViewInit<RightEye>,
+        Vec<MockViewInit<Secondary>>,
+    ),
+    /// Any number of views, like a quad-view headset or a rig of several displays
+    Custom(Vec<MockViewInit<CustomEye>>),
 }
 
 #[derive(Debug)]
@@ -74,14 +122,112 @@ pub enum MockDeviceMsg {
     SetViewerOrigin(Option<RigidTransform3D<f32, Viewer, Native>>),
     SetFloorOrigin(Option<RigidTransform3D<f32, Floor, Native>>),
     SetViews(MockViewsInit),
+    /// Start or stop the secondary views, as a first-person observer camera does when it
+    /// starts or stops capturing. Stopped views keep their viewports, but aren't in frames.
+    SetSecondaryViewsActive(bool),
+    /// Change the features the device supports, as in `MockDeviceInit::supported_features`.
+    /// This only affects sessions requested afterwards, running sessions keep their features.
+    SetSupportedFeatures(Vec<Feature>),
+    /// Stop producing frames on a timer, and only produce them when `AdvanceFrame` asks
+    /// for them, so that tests can count animation frames exactly. With `false`, frames
+    /// are produced on a timer again.
+    SetManualFrameClock(bool),
+    /// Let this many more frames end, with the manual frame clock. Frames of every session
+    /// count. The first frame of a session begins without one, but a session only
+    /// handles messages between frames, so ending it may need one.
+    AdvanceFrame(u32),
     AddInputSource(MockInputInit),
     MessageInputSource(InputId, MockInputMsg),
     VisibilityChange(Visibility),
+    /// Give the sessions focus again after `Blur`, making them `Visibility::Visible`
+    Focus,
+    /// Take focus away from the sessions, as when the system shows its own UI on top of
+    /// them. They become `Visibility::VisibleBlurred`, and get no input until `Focus`.
+    Blur,
     SetWorld(MockWorld),
     ClearWorld,
     Disconnect(Sender<()>),
+    /// Change the bounds of the `bounded-floor` space, which sessions
+    /// are told about with `FrameUpdateEvent::UpdateBoundsGeometry`
     SetBoundsGeometry(Vec<Point2D<f32, Floor>>),
     SimulateResetPose,
+    /// Tap the screen, with a transient input source whose target ray starts at the
+    /// given origin, which selects and then goes away
+    SimulateScreenTap(RigidTransform3D<f32, Input, Native>),
+    /// Send pointer input on the overlay to sessions with the `dom-overlay` feature
+    SimulateDomOverlayPointer(DomOverlayPointer),
+    /// Deliver an extension event with the next frame of every session
+    SimulateExtensionEvent(ExtensionEvent),
+    /// Set where the user is looking, for sessions with the `eye-tracking` feature.
+    /// If unset, the gaze follows the viewer.
+    SetEyeGaze(Option<RigidTransform3D<f32, Input, Native>>),
+    /// Get the contents of each layer as of the last frame it was rendered in.
+    /// Requires `MockDeviceInit::export_layer_contents`.
+    GetLayerContents(Sender<Vec<MockLayerContents>>),
+    /// Whether anchors that content asks for from now on get created
+    /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrdevice-setanchorcreationcallback
+    SetAnchorCreationResult(bool),
+    /// Set the depth given to sessions with the `depth-sensing` feature, or stop giving one
+    /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrdevice-setdepthsensingdata
+    SetDepthSensingData(Option<DepthInformation>),
+    /// Set which images are being tracked and where, for sessions with the `image-tracking`
+    /// feature. Each image's index is into a session's `SessionInit::tracked_images`,
+    /// and sessions that asked for fewer images don't get the rest.
+    SetTrackedImages(Vec<TrackedImage>),
+    /// Switch every tracked-pointer input to a different interaction profile, as when
+    /// the user puts down one kind of controller and picks up another. Each input's
+    /// profiles and buttons are replaced, and sessions get one `Event::UpdateInput` for it.
+    SimulateInteractionProfileChange(Vec<String>, Vec<MockButton>),
+    /// Handle messages at the start of later frames rather than straight away, so that
+    /// tests can script an interaction without racing the device's render loop
+    PlayTimeline(Vec<MockTimelineEntry>),
+    MessageAnchor(MockAnchorId, MockAnchorMsg),
+    /// Move the device within its room, as in `MockColocation::origin`.
+    /// The world of the room stays put, so it moves the other way in native space.
+    SetRoomOrigin(RigidTransform3D<f32, Native, Room>),
+    /// Place an anchor that every device in the room can find by name, at a pose in this
+    /// device's native space, or remove it with `None`
+    SetSharedAnchor(String, Option<RigidTransform3D<f32, ApiSpace, Native>>),
+    /// Get where a shared anchor is in this device's native space, if it has been placed
+    GetSharedAnchor(
+        String,
+        Sender<Option<RigidTransform3D<f32, ApiSpace, Native>>>,
+    ),
+}
+
+/// A message in a timeline for the mock device, see `MockDeviceMsg::PlayTimeline`
+#[derive(Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockTimelineEntry {
+    /// How many frames after the timeline arrives to handle `msg` in, where 0 is the next
+    /// frame. Frames of every session count. Messages for the same frame are handled
+    /// in the order they are in the timeline.
+    pub frame: u32,
+    pub msg: MockDeviceMsg,
+}
+
+/// An anchor created on the mock device, numbered from 0 in the order they were created
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockAnchorId(pub u32);
+
+/// https://immersive-web.github.io/webxr-test-api/#fakexranchorcontroller
+#[derive(Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum MockAnchorMsg {
+    SetOrigin(RigidTransform3D<f32, ApiSpace, Native>),
+    PauseTracking,
+    ResumeTracking,
+    StopTracking,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockLayerContents {
+    pub layer_id: LayerId,
+    pub size: Size2D<i32, Viewport>,
+    /// The layer's pixels, in RGBA8 format
+    pub data: Vec<u8>,
 }
 
 #[derive(Clone, Debug)]
@@ -101,6 +247,8 @@ pub enum MockInputMsg {
     SetProfiles(Vec<String>),
     SetPointerOrigin(Option<RigidTransform3D<f32, Input, Native>>),
     SetGripOrigin(Option<RigidTransform3D<f32, Input, Native>>),
+    /// Set the joints of the hand the input tracks, which also makes it support those joints
+    SetHandJoints(Box<Hand<JointFrame>>),
     /// Note: SelectEvent::Select here refers to a complete Select event,
     /// not just the end event, i.e. it refers to
     /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrinputcontroller-simulateselect
@@ -109,8 +257,20 @@ pub enum MockInputMsg {
     Reconnect,
     SetSupportedButtons(Vec<MockButton>),
     UpdateButtonState(MockButton),
+    /// Take the haptic pulses triggered on this input since the last call
+    GetHapticPulses(Sender<Vec<MockHapticPulse>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockHapticPulse {
+    pub intensity: f32,
+    /// The duration of the pulse, in milliseconds
+    pub duration: f64,
 }
 
+/// A part of the mock world. Hit tests that hit it report its index in
+/// `MockWorld::regions` as the `EntityId`, and its type as the entity type.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct MockRegion {
@@ -0,0 +1,150 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Read-only views of a running session, for embedder features such as a 2D preview of
+//! what the user sees, which need the session's frames and events without taking them
+//! away from content.
+
+use crate::channel;
+use crate::comfort::Reorientation;
+use crate::input_mapping::InputMapper;
+use crate::ApiSpace;
+use crate::BaseSpace;
+use crate::Error;
+use crate::Event;
+use crate::Floor;
+use crate::Frame;
+use crate::Native;
+use crate::Receiver;
+use crate::Sender;
+use crate::Space;
+
+use euclid::{RigidTransform3D, Vector3D};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Gets a copy of every frame and event content gets from a session, see `Session::observe`.
+/// The frames are the ones sent with events, without any sub images. Dropping the
+/// receivers stops the copies.
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionObserver {
+    pub frames: Receiver<Frame>,
+    pub events: Receiver<Event>,
+}
+
+/// Sits between the device and content once a session has observers, an input mapping,
+/// an urgent event dest or has been snap turned, copying the events the device sends to
+/// the observers, mapping their inputs, turning their poses and sending lifecycle events
+/// on their own channel
+pub(crate) struct EventRelay {
+    sender: Sender<Event>,
+    dests: Arc<Mutex<RelayDests>>,
+}
+
+#[derive(Default)]
+struct RelayDests {
+    content: Option<Sender<Event>>,
+    /// Where lifecycle events go instead of `content`, if set
+    urgent: Option<Sender<Event>>,
+    observers: Vec<Sender<Event>>,
+    input_mapper: Option<InputMapper>,
+    reorientation: Reorientation,
+}
+
+impl RelayDests {
+    fn send(&mut self, mut event: Event) {
+        if let Some(ref mut input_mapper) = self.input_mapper {
+            input_mapper.map_event(&mut event);
+        }
+        self.reorientation.apply_to_event(&mut event);
+        self.observers
+            .retain(|observer| observer.send(event.clone()).is_ok());
+        let dest = match self.urgent {
+            Some(ref urgent) if event.is_urgent() => Some(urgent),
+            _ => self.content.as_ref(),
+        };
+        if let Some(dest) = dest {
+            let _ = dest.send(event);
+        }
+    }
+}
+
+impl EventRelay {
+    pub(crate) fn new() -> Result<EventRelay, Error> {
+        let (sender, receiver) = channel().or(Err(Error::CommunicationError))?;
+        let dests = Arc::new(Mutex::new(RelayDests::default()));
+        let relay_dests = dests.clone();
+        thread::spawn(move || {
+            // This ends once the device and the session thread have let go of the relay
+            while let Ok(event) = receiver.recv() {
+                relay_dests.lock().unwrap().send(event);
+            }
+        });
+        Ok(EventRelay { sender, dests })
+    }
+
+    /// Where the device should send events to instead of content
+    pub(crate) fn sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+
+    pub(crate) fn set_content_dest(&self, dest: Sender<Event>) {
+        self.dests.lock().unwrap().content = Some(dest);
+    }
+
+    pub(crate) fn set_urgent_dest(&self, dest: Sender<Event>) {
+        self.dests.lock().unwrap().urgent = Some(dest);
+    }
+
+    pub(crate) fn add_observer(&self, dest: Sender<Event>) {
+        self.dests.lock().unwrap().observers.push(dest);
+    }
+
+    pub(crate) fn set_input_mapper(&self, input_mapper: InputMapper) {
+        self.dests.lock().unwrap().input_mapper = Some(input_mapper);
+    }
+
+    /// Apply the input mapping, if there is one, and any snap turns to a frame
+    /// on its way to content
+    pub(crate) fn map_frame(&self, frame: &mut Frame) {
+        let dests = self.dests.lock().unwrap();
+        if let Some(ref input_mapper) = dests.input_mapper {
+            input_mapper.map_frame(frame);
+        }
+        dests.reorientation.apply(frame);
+    }
+
+    /// Snap turn everything on its way to content, see `Reorientation::turn`
+    pub(crate) fn turn(
+        &self,
+        angle: f32,
+        viewer: Option<Vector3D<f32, Native>>,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> [(BaseSpace, RigidTransform3D<f32, ApiSpace, ApiSpace>); 3] {
+        let mut dests = self.dests.lock().unwrap();
+        dests.reorientation.turn(angle, viewer, floor_transform)
+    }
+
+    /// From the device's native space to the turned one content sees, see `Reorientation::offset`
+    pub(crate) fn reorientation(&self) -> RigidTransform3D<f32, Native, Native> {
+        self.dests.lock().unwrap().reorientation.offset()
+    }
+
+    /// Undo any snap turns for a space content created, see `Reorientation::space_to_device`
+    pub(crate) fn space_to_device(
+        &self,
+        space: Space,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> Space {
+        let dests = self.dests.lock().unwrap();
+        dests.reorientation.space_to_device(space, floor_transform)
+    }
+
+    /// Send an event that comes from the session thread rather than the device. This goes
+    /// through the same channel as the device's events, so that it stays in order with them.
+    pub(crate) fn send(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+}
@@ -0,0 +1,98 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! How long sessions that run on the main thread wait for content to render each frame.
+//!
+//! `MainThreadSession::run_one_frame` handles the session's messages until content has
+//! rendered a frame, but gives the main thread back if it has to wait too long for the
+//! next message, so that the embedder isn't blocked by slow content.
+
+use std::time::Duration;
+
+// How long to wait for each message by default
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(5);
+
+// The bounds of the waits chosen by `FramePacing::Adaptive`
+const MIN_ADAPTIVE_TIMEOUT: Duration = Duration::from_millis(1);
+const MAX_ADAPTIVE_TIMEOUT: Duration = Duration::from_millis(20);
+
+// How much longer than recent frames took to wait with `FramePacing::Adaptive`
+const ADAPTIVE_HEADROOM: f64 = 1.5;
+
+// How much each frame counts towards the average with `FramePacing::Adaptive`
+const ADAPTIVE_SMOOTHING: f64 = 0.1;
+
+/// How a session running on the main thread paces frames, see `Session::set_frame_pacing`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum FramePacing {
+    /// Wait until content has rendered the frame, however long that takes. While content
+    /// has no frame to render, because the render loop hasn't started or the session is
+    /// paused or hidden, this waits as long as the default `Fixed` pacing does instead.
+    BlockUntilFrame,
+    /// Wait up to this long for each message before giving the main thread back
+    Fixed(Duration),
+    /// Like `Fixed`, with a wait that follows how long content has recently taken
+    /// to render frames, so that the main thread is held only as long as it has to be
+    Adaptive,
+}
+
+impl Default for FramePacing {
+    fn default() -> FramePacing {
+        FramePacing::Fixed(DEFAULT_TIMEOUT)
+    }
+}
+
+/// Chooses how long to wait for messages, following a `FramePacing`
+pub(crate) struct FramePacer {
+    pacing: FramePacing,
+    /// How long content has spent rendering recent frames, on average
+    average: Duration,
+    /// How long the current frame has been waited for so far
+    waited: Duration,
+}
+
+impl Default for FramePacer {
+    fn default() -> FramePacer {
+        FramePacer {
+            pacing: FramePacing::default(),
+            average: DEFAULT_TIMEOUT,
+            waited: Duration::ZERO,
+        }
+    }
+}
+
+impl FramePacer {
+    pub(crate) fn set_pacing(&mut self, pacing: FramePacing) {
+        self.pacing = pacing;
+    }
+
+    /// How long to wait for the next message, or `None` to wait until it comes.
+    /// Only sessions where content is `rendering` a frame block until it comes.
+    pub(crate) fn timeout(&self, rendering: bool) -> Option<Duration> {
+        match self.pacing {
+            FramePacing::BlockUntilFrame if rendering => None,
+            FramePacing::BlockUntilFrame => Some(DEFAULT_TIMEOUT),
+            FramePacing::Fixed(timeout) => Some(timeout),
+            FramePacing::Adaptive => Some(
+                self.average
+                    .mul_f64(ADAPTIVE_HEADROOM)
+                    .clamp(MIN_ADAPTIVE_TIMEOUT, MAX_ADAPTIVE_TIMEOUT),
+            ),
+        }
+    }
+
+    /// Count time spent waiting for the current frame, which may take several calls
+    /// to `run_one_frame` if content is slow
+    pub(crate) fn waited(&mut self, waited: Duration, frame_done: bool) {
+        self.waited += waited;
+        if frame_done {
+            let average = self.average.as_secs_f64();
+            let waited = self.waited.as_secs_f64();
+            let average = average + (waited - average) * ADAPTIVE_SMOOTHING;
+            self.average = Duration::from_secs_f64(average);
+            self.waited = Duration::ZERO;
+        }
+    }
+}
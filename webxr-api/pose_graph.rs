@@ -0,0 +1,166 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A debugging view of the transforms making up a frame.
+
+use crate::Floor;
+use crate::Frame;
+use crate::InputId;
+use crate::Joint;
+use crate::Native;
+use crate::View;
+use crate::Viewer;
+use crate::Views;
+
+use euclid::RigidTransform3D;
+
+use std::cell::RefCell;
+
+/// The coordinates of whichever `PoseGraphNode` an edge is about
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseGraphSpace;
+
+/// A space that appears in a `PoseGraph`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum PoseGraphNode {
+    Native,
+    Floor,
+    Viewer,
+    /// The view at this index, in the order of the fields in `Views`
+    View(usize),
+    TargetRay(InputId),
+    Grip(InputId),
+    Joint(InputId, Joint),
+}
+
+/// The pose of `child` in the coordinates of `parent`
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseGraphEdge {
+    pub parent: PoseGraphNode,
+    pub child: PoseGraphNode,
+    pub transform: RigidTransform3D<f32, PoseGraphSpace, PoseGraphSpace>,
+}
+
+/// A dump of how the spaces of a frame relate to each other, rooted at native space.
+/// Views are children of the viewer, everything else is a child of native space.
+/// Spaces the device could not locate this frame are left out.
+///
+/// Anchors and application-created reference spaces are not included, since their
+/// offsets are only known to the content process.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseGraph {
+    pub predicted_display_time: f64,
+    pub edges: Vec<PoseGraphEdge>,
+}
+
+impl PoseGraph {
+    pub fn from_frame(
+        frame: &Frame,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> PoseGraph {
+        let mut edges = Vec::new();
+        if let Some(floor_transform) = floor_transform {
+            edges.push(PoseGraphEdge {
+                parent: PoseGraphNode::Native,
+                child: PoseGraphNode::Floor,
+                transform: edge_transform(floor_transform.inverse()),
+            });
+        }
+        if let Some(ref pose) = frame.pose {
+            edges.push(PoseGraphEdge {
+                parent: PoseGraphNode::Native,
+                child: PoseGraphNode::Viewer,
+                transform: edge_transform(pose.transform),
+            });
+            let native_to_viewer = pose.transform.inverse();
+            let mut add_view = |index, transform| {
+                edges.push(PoseGraphEdge {
+                    parent: PoseGraphNode::Viewer,
+                    child: PoseGraphNode::View(index),
+                    transform,
+                })
+            };
+            match pose.views {
+                Views::Inline => (),
+                Views::Mono(ref view) => add_view(0, view_in_viewer(view, &native_to_viewer)),
+                Views::Stereo(ref left, ref right) => {
+                    add_view(0, view_in_viewer(left, &native_to_viewer));
+                    add_view(1, view_in_viewer(right, &native_to_viewer));
+                }
+                Views::StereoSecondary(ref left, ref right, ref secondaries) => {
+                    add_view(0, view_in_viewer(left, &native_to_viewer));
+                    add_view(1, view_in_viewer(right, &native_to_viewer));
+                    for (index, secondary) in secondaries.iter().enumerate() {
+                        add_view(2 + index, view_in_viewer(secondary, &native_to_viewer));
+                    }
+                }
+                Views::Cubemap(ref front, ref left, ref right, ref top, ref bottom, ref back) => {
+                    add_view(0, view_in_viewer(front, &native_to_viewer));
+                    add_view(1, view_in_viewer(left, &native_to_viewer));
+                    add_view(2, view_in_viewer(right, &native_to_viewer));
+                    add_view(3, view_in_viewer(top, &native_to_viewer));
+                    add_view(4, view_in_viewer(bottom, &native_to_viewer));
+                    add_view(5, view_in_viewer(back, &native_to_viewer));
+                }
+                Views::Custom(ref views) => {
+                    for (index, view) in views.iter().enumerate() {
+                        add_view(index, view_in_viewer(view, &native_to_viewer));
+                    }
+                }
+            }
+        }
+        for input in &frame.inputs {
+            if let Some(target_ray_origin) = input.target_ray_origin {
+                edges.push(PoseGraphEdge {
+                    parent: PoseGraphNode::Native,
+                    child: PoseGraphNode::TargetRay(input.id),
+                    transform: edge_transform(target_ray_origin),
+                });
+            }
+            if let Some(grip_origin) = input.grip_origin {
+                edges.push(PoseGraphEdge {
+                    parent: PoseGraphNode::Native,
+                    child: PoseGraphNode::Grip(input.id),
+                    transform: edge_transform(grip_origin),
+                });
+            }
+            if let Some(ref hand) = input.hand {
+                // `Hand::map` only takes `Fn` closures, so collect the joints through a cell
+                let joints = RefCell::new(Vec::new());
+                hand.map(|joint_frame, joint| {
+                    if let Some(joint_frame) = joint_frame {
+                        joints.borrow_mut().push(PoseGraphEdge {
+                            parent: PoseGraphNode::Native,
+                            child: PoseGraphNode::Joint(input.id, joint),
+                            transform: edge_transform(joint_frame.pose),
+                        });
+                    }
+                    None::<()>
+                });
+                edges.extend(joints.into_inner());
+            }
+        }
+        PoseGraph {
+            predicted_display_time: frame.predicted_display_time,
+            edges,
+        }
+    }
+}
+
+fn view_in_viewer<Eye>(
+    view: &View<Eye>,
+    native_to_viewer: &RigidTransform3D<f32, Native, Viewer>,
+) -> RigidTransform3D<f32, PoseGraphSpace, PoseGraphSpace> {
+    edge_transform(view.transform.then(native_to_viewer))
+}
+
+fn edge_transform<Src, Dst>(
+    transform: RigidTransform3D<f32, Src, Dst>,
+) -> RigidTransform3D<f32, PoseGraphSpace, PoseGraphSpace> {
+    RigidTransform3D::from_untyped(&transform.to_untyped())
+}
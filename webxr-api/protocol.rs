@@ -0,0 +1,107 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Keeping processes built from different revisions of webxr from misreading each other.
+//!
+//! With the `ipc` feature, `RegistryMsg`, `SessionMsg` and `Frame` are serialized after the
+//! protocol version of the process that sent them, and one with a different version fails
+//! to deserialize with `Error::ProtocolMismatch` rather than reading the rest as garbage.
+//! Content processes can check that the main process speaks their version before sending
+//! it anything with `Registry::handshake`.
+
+#[cfg(feature = "ipc")]
+use crate::Error;
+
+#[cfg(feature = "ipc")]
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+
+#[cfg(feature = "ipc")]
+use std::fmt;
+#[cfg(feature = "ipc")]
+use std::marker::PhantomData;
+
+/// The version of the messages sent between processes. This must be bumped whenever the
+/// serialized form of `RegistryMsg`, `SessionMsg` or `Frame`, or anything in them, changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Implement `Serialize` and `Deserialize` for a type that derives them with
+/// `#[serde(remote = "Self")]`, writing the protocol version before the value
+#[cfg(feature = "ipc")]
+macro_rules! versioned {
+    ($type:ident) => {
+        impl serde::Serialize for $type {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                struct Unversioned<'a>(&'a $type);
+                impl serde::Serialize for Unversioned<'_> {
+                    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                    where
+                        S: serde::Serializer,
+                    {
+                        $type::serialize(self.0, serializer)
+                    }
+                }
+                serde::Serialize::serialize(
+                    &(crate::PROTOCOL_VERSION, Unversioned(self)),
+                    serializer,
+                )
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $type {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct Unversioned($type);
+                impl<'de> serde::Deserialize<'de> for Unversioned {
+                    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                    where
+                        D: serde::Deserializer<'de>,
+                    {
+                        $type::deserialize(deserializer).map(Unversioned)
+                    }
+                }
+                crate::protocol::deserialize_versioned(deserializer).map(|Unversioned(value)| value)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "ipc")]
+pub(crate) use versioned;
+
+/// Read the protocol version written by `versioned!`, and only if it is this process's
+/// version, the value after it
+#[cfg(feature = "ipc")]
+pub(crate) fn deserialize_versioned<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct VersionedVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for VersionedVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a protocol version followed by a message")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let version: u32 = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            if version != PROTOCOL_VERSION {
+                return Err(de::Error::custom(Error::ProtocolMismatch(version)));
+            }
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))
+        }
+    }
+
+    deserializer.deserialize_tuple(2, VersionedVisitor(PhantomData))
+}
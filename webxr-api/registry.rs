@@ -2,15 +2,26 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::session::PendingSessionState;
+use crate::session::SessionSettings;
+use crate::session::SpawnedThread;
+use crate::ComfortMsg;
+use crate::DeviceDescriptor;
+use crate::DevicePanicPolicy;
 use crate::DiscoveryAPI;
 use crate::Error;
+use crate::EventBufferOptions;
 use crate::Frame;
 use crate::GLTypes;
+use crate::InlineDiscovery;
+use crate::InputMapping;
 use crate::LayerGrandManager;
 use crate::MainThreadSession;
 use crate::MockDeviceInit;
 use crate::MockDeviceMsg;
 use crate::MockDiscoveryAPI;
+use crate::PendingSession;
+use crate::Quitter;
 use crate::Receiver;
 use crate::Sender;
 use crate::Session;
@@ -18,30 +29,172 @@ use crate::SessionBuilder;
 use crate::SessionId;
 use crate::SessionInit;
 use crate::SessionMode;
+use crate::SessionProgress;
+use crate::SessionThreadHandle;
+use crate::PROTOCOL_VERSION;
 
 use log::warn;
 
+use std::collections::VecDeque;
+use std::iter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct Registry {
+    handshake: Sender<Handshake>,
     sender: Sender<RegistryMsg>,
     waker: MainThreadWakerImpl,
 }
 
+/// A content process's protocol version, and where to send the main thread's, see
+/// `Registry::handshake`. Unlike `RegistryMsg`, this must never change, so that
+/// processes with any version can read it.
+type Handshake = (u32, Sender<u32>);
+
+/// A handle to a pending `Registry::request_session` call, which can be used to abort it,
+/// for example if the page navigates away. Dropping the handle does not cancel the request.
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct SessionRequestHandle {
+    sender: Sender<()>,
+}
+
+impl SessionRequestHandle {
+    /// Cancel the session request. If it has not been handled yet, it fails with
+    /// `Error::RequestCancelled`. If a session was already created for it, that session
+    /// is shut down, along with its device and thread.
+    pub fn cancel(self) {
+        let _ = self.sender.send(());
+    }
+}
+
 pub struct MainThreadRegistry<GL> {
     discoveries: Vec<Box<dyn DiscoveryAPI<GL>>>,
     sessions: Vec<Box<dyn MainThreadSession>>,
+    threads: Vec<SessionThreadHandle>,
+    cancellations: Vec<(Receiver<()>, Quitter)>,
     mocks: Vec<Box<dyn MockDiscoveryAPI<GL>>>,
+    handshake_sender: Sender<Handshake>,
+    handshake_receiver: Receiver<Handshake>,
     sender: Sender<RegistryMsg>,
     receiver: Receiver<RegistryMsg>,
     waker: MainThreadWakerImpl,
     grand_manager: LayerGrandManager<GL>,
+    permission_gate: Option<Box<dyn PermissionGate>>,
+    immersive_session_policy: ImmersiveSessionPolicy,
+    device_panic_policy: DevicePanicPolicy,
+    input_mapping: InputMapping,
+    event_buffer_options: EventBufferOptions,
+    /// The running immersive session, only one of which is allowed at a time
+    immersive_session: Option<SessionId>,
+    queued_requests: VecDeque<SessionRequest>,
+    /// Requests whose device is creating their session on its own thread
+    pending_requests: Vec<PendingRequest>,
+    immersive_watchers: Vec<Sender<()>>,
+    device_change_watchers: Vec<Sender<()>>,
     next_session_id: u32,
 }
 
+/// Lets a discovery tell the registry when a device becomes available or goes away,
+/// for example when a headset is plugged in, see `DiscoveryAPI::set_device_change_notifier`
+#[derive(Clone)]
+pub struct DeviceChangeNotifier {
+    sender: Sender<RegistryMsg>,
+    waker: MainThreadWakerImpl,
+}
+
+impl DeviceChangeNotifier {
+    pub fn notify(&self) {
+        let _ = self.sender.send(RegistryMsg::DeviceChanged);
+        self.waker.wake();
+    }
+}
+
+/// What the registry does with an immersive session request while
+/// another immersive session is running. Inline sessions are always allowed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ImmersiveSessionPolicy {
+    /// Fail the request with `Error::ImmersiveSessionActive`, as the spec says to.
+    /// This is the default.
+    /// https://immersive-web.github.io/webxr/#dom-xrsystem-requestsession
+    #[default]
+    Reject,
+    /// Hold on to the request until the running session has ended, for embedders
+    /// that would rather wait for the thread of a session that content has just ended
+    /// to exit than have the request fail.
+    Queue,
+}
+
+/// Tells the registry how creating a session on a device's own thread went,
+/// see `SessionBuilder::spawn`
+pub(crate) struct SessionCreationNotifier {
+    sender: Sender<RegistryMsg>,
+    waker: MainThreadWakerImpl,
+}
+
+impl SessionCreationNotifier {
+    pub(crate) fn created(&self, id: SessionId, result: Result<Session, Error>) {
+        let _ = self.sender.send(RegistryMsg::SessionCreated(id, result));
+        self.waker.wake();
+    }
+}
+
+/// A session request that hasn't been answered yet
+struct SessionRequest {
+    mode: SessionMode,
+    init: SessionInit,
+    dest: Sender<Result<Session, Error>>,
+    raf_sender: Sender<Frame>,
+    progress: Option<Sender<SessionProgress>>,
+    cancel: RequestCancellation,
+}
+
+/// A session request whose device is creating the session on its own thread
+struct PendingRequest {
+    id: SessionId,
+    request: SessionRequest,
+    /// The discovery to try next if the device fails to create the session
+    next_discovery: usize,
+    thread: SpawnedThread,
+}
+
+/// What a `PermissionGate` decided to do with a session request
+pub enum PermissionDecision {
+    Allow,
+    Deny,
+    /// Carry on with a different `SessionInit`, for example with some features removed
+    Modify(SessionInit),
+}
+
+/// A hook that is consulted before a session request reaches any device, so that
+/// embedders can enforce their privacy policy in one place
+pub trait PermissionGate: 'static {
+    fn check_session_request(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+    ) -> PermissionDecision;
+}
+
+impl<F> PermissionGate for F
+where
+    F: 'static + FnMut(SessionMode, &SessionInit) -> PermissionDecision,
+{
+    fn check_session_request(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+    ) -> PermissionDecision {
+        self(mode, init)
+    }
+}
+
 pub trait MainThreadWaker: 'static + Send {
     fn clone_box(&self) -> Box<dyn MainThreadWaker>;
     fn wake(&self);
@@ -87,6 +240,21 @@ impl MainThreadWakerImpl {
 }
 
 impl Registry {
+    /// Check that the main thread speaks the same version of the IPC protocol as this
+    /// process, waiting for its answer, so that a content process built from a different
+    /// revision of webxr can do without WebXR rather than send messages that can't be read.
+    /// This must not be called on the thread that runs the `MainThreadRegistry`.
+    pub fn handshake(&mut self) -> Result<(), Error> {
+        let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
+        let _ = self.handshake.send((PROTOCOL_VERSION, sender));
+        self.waker.wake();
+        match receiver.recv() {
+            Ok(version) if version == PROTOCOL_VERSION => Ok(()),
+            Ok(version) => Err(Error::ProtocolMismatch(version)),
+            Err(_) => Err(Error::CommunicationError),
+        }
+    }
+
     pub fn supports_session(&mut self, mode: SessionMode, dest: Sender<Result<(), Error>>) {
         let _ = self.sender.send(RegistryMsg::SupportsSession(mode, dest));
         self.waker.wake();
@@ -98,14 +266,66 @@ impl Registry {
         init: SessionInit,
         dest: Sender<Result<Session, Error>>,
         animation_frame_handler: Sender<Frame>,
-    ) {
+    ) -> Result<SessionRequestHandle, Error> {
+        self.send_session_request(mode, init, dest, animation_frame_handler, None)
+    }
+
+    /// Like `request_session`, also sending `progress` how far the device has got with
+    /// creating the session, so that embedders can show it while the user waits
+    pub fn request_session_with_progress(
+        &mut self,
+        mode: SessionMode,
+        init: SessionInit,
+        dest: Sender<Result<Session, Error>>,
+        animation_frame_handler: Sender<Frame>,
+        progress: Sender<SessionProgress>,
+    ) -> Result<SessionRequestHandle, Error> {
+        self.send_session_request(mode, init, dest, animation_frame_handler, Some(progress))
+    }
+
+    fn send_session_request(
+        &mut self,
+        mode: SessionMode,
+        init: SessionInit,
+        dest: Sender<Result<Session, Error>>,
+        animation_frame_handler: Sender<Frame>,
+        progress: Option<Sender<SessionProgress>>,
+    ) -> Result<SessionRequestHandle, Error> {
+        let (sender, cancel) = crate::channel().or(Err(Error::CommunicationError))?;
         let _ = self.sender.send(RegistryMsg::RequestSession(
             mode,
             init,
             dest,
             animation_frame_handler,
+            progress,
+            cancel,
         ));
         self.waker.wake();
+        Ok(SessionRequestHandle { sender })
+    }
+
+    /// Get descriptions of the registered devices that are available, in the order they
+    /// are tried for session requests, such as to let the user pick one. No sessions are
+    /// created, but some backends have to start up their runtime to describe it.
+    pub fn enumerate_devices(&mut self, dest: Sender<Vec<DeviceDescriptor>>) {
+        let _ = self.sender.send(RegistryMsg::EnumerateDevices(dest));
+        self.waker.wake();
+    }
+
+    /// Get a message on `dest` whenever the immersive session ends,
+    /// and no queued request has taken its place
+    pub fn watch_immersive_availability(&mut self, dest: Sender<()>) {
+        let _ = self
+            .sender
+            .send(RegistryMsg::WatchImmersiveAvailability(dest));
+        self.waker.wake();
+    }
+
+    /// Get a message on `dest` whenever a device becomes available or goes away,
+    /// for `navigator.xr`'s `devicechange` event
+    pub fn watch_device_changes(&mut self, dest: Sender<()>) {
+        let _ = self.sender.send(RegistryMsg::WatchDeviceChanges(dest));
+        self.waker.wake();
     }
 
     pub fn simulate_device_connection(
@@ -126,36 +346,63 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         grand_manager: LayerGrandManager<GL>,
     ) -> Result<Self, Error> {
         let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
+        let (handshake_sender, handshake_receiver) =
+            crate::channel().or(Err(Error::CommunicationError))?;
         let discoveries = Vec::new();
         let sessions = Vec::new();
+        let threads = Vec::new();
+        let cancellations = Vec::new();
         let mocks = Vec::new();
         let waker = MainThreadWakerImpl::new(waker)?;
         Ok(MainThreadRegistry {
             discoveries,
             sessions,
+            threads,
+            cancellations,
             mocks,
+            handshake_sender,
+            handshake_receiver,
             sender,
             receiver,
             waker,
             grand_manager,
+            permission_gate: None,
+            immersive_session_policy: ImmersiveSessionPolicy::default(),
+            device_panic_policy: DevicePanicPolicy::default(),
+            input_mapping: InputMapping::default(),
+            event_buffer_options: EventBufferOptions::default(),
+            immersive_session: None,
+            queued_requests: VecDeque::new(),
+            pending_requests: Vec::new(),
+            immersive_watchers: Vec::new(),
+            device_change_watchers: Vec::new(),
             next_session_id: 0,
         })
     }
 
     pub fn registry(&self) -> Registry {
         Registry {
+            handshake: self.handshake_sender.clone(),
             sender: self.sender.clone(),
             waker: self.waker.clone(),
         }
     }
 
-    pub fn register<D>(&mut self, discovery: D)
+    pub fn register<D>(&mut self, mut discovery: D)
     where
         D: DiscoveryAPI<GL>,
     {
+        discovery.set_device_change_notifier(self.device_change_notifier());
         self.discoveries.push(Box::new(discovery));
     }
 
+    fn device_change_notifier(&self) -> DeviceChangeNotifier {
+        DeviceChangeNotifier {
+            sender: self.sender.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+
     pub fn register_mock<D>(&mut self, discovery: D)
     where
         D: MockDiscoveryAPI<GL>,
@@ -163,6 +410,35 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         self.mocks.push(Box::new(discovery));
     }
 
+    pub fn set_permission_gate<G>(&mut self, gate: G)
+    where
+        G: PermissionGate,
+    {
+        self.permission_gate = Some(Box::new(gate));
+    }
+
+    pub fn set_immersive_session_policy(&mut self, policy: ImmersiveSessionPolicy) {
+        self.immersive_session_policy = policy;
+    }
+
+    /// Decide what happens when a device panics on the thread of a session that is
+    /// requested from now on. Devices that run on the main thread aren't affected.
+    pub fn set_device_panic_policy(&mut self, policy: DevicePanicPolicy) {
+        self.device_panic_policy = policy;
+    }
+
+    /// Remap or disable buttons and axes of inputs before content sees them, for sessions
+    /// that are requested from now on, such as to keep a button for the embedder's menu
+    pub fn set_input_mapping(&mut self, mapping: InputMapping) {
+        self.input_mapping = mapping;
+    }
+
+    /// Choose how many events devices hold on to for sessions that are requested from
+    /// now on, until content has somewhere to send them
+    pub fn set_event_buffer_options(&mut self, options: EventBufferOptions) {
+        self.event_buffer_options = options;
+    }
+
     pub fn run_on_main_thread<S>(&mut self, session: S)
     where
         S: MainThreadSession,
@@ -171,13 +447,120 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
     }
 
     pub fn run_one_frame(&mut self) {
+        while let Ok((version, dest)) = self.handshake_receiver.try_recv() {
+            if version != PROTOCOL_VERSION {
+                warn!(
+                    "{}, so its messages will be dropped",
+                    Error::ProtocolMismatch(version)
+                );
+            }
+            let _ = dest.send(PROTOCOL_VERSION);
+        }
         while let Ok(msg) = self.receiver.try_recv() {
             self.handle_msg(msg);
         }
+        // Devices creating a session on their own thread find out about
+        // cancellations through the request's `CancellationToken`
+        for pending in &self.pending_requests {
+            pending.request.cancel.poll();
+        }
         for session in &mut self.sessions {
             session.run_one_frame();
         }
         self.sessions.retain(|session| session.running());
+        self.threads.retain(|thread| !thread.finished());
+        self.cancellations
+            .retain(|(cancel, quitter)| match poll_cancellation(cancel) {
+                Cancellation::Requested => {
+                    quitter.shutdown();
+                    false
+                }
+                Cancellation::Pending => true,
+                Cancellation::Dropped => false,
+            });
+        self.update_immersive_session();
+    }
+
+    /// Notice when the immersive session has ended, and hand its slot to the next request
+    fn update_immersive_session(&mut self) {
+        if let Some(id) = self.immersive_session {
+            let running = self.sessions.iter().any(|session| session.id() == Some(id))
+                || self.threads.iter().any(|thread| thread.id() == id)
+                || self.pending_requests.iter().any(|pending| pending.id == id);
+            if running {
+                return;
+            }
+            self.immersive_session = None;
+            while self.immersive_session.is_none() {
+                let request = match self.queued_requests.pop_front() {
+                    Some(request) => request,
+                    None => break,
+                };
+                self.request_session(request);
+            }
+            if self.immersive_session.is_none() {
+                self.immersive_watchers
+                    .retain(|watcher| watcher.send(()).is_ok());
+            }
+        }
+    }
+
+    /// End every session, releasing their devices and layers. Sessions running on
+    /// their own threads are given until `timeout` to exit before being detached.
+    /// Requests whose device is still creating the session are cancelled, and their
+    /// session is shut down as soon as it starts.
+    pub fn shutdown(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        self.cancellations.clear();
+        for request in self.queued_requests.drain(..) {
+            let _ = request.dest.send(Err(Error::RequestCancelled));
+        }
+        for pending in self.pending_requests.drain(..) {
+            let _ = pending.request.dest.send(Err(Error::RequestCancelled));
+            self.threads.push(pending.thread.into_handle(pending.id));
+        }
+        self.immersive_session = None;
+        for session in &mut self.sessions {
+            session.shutdown();
+        }
+        self.sessions.clear();
+        for thread in &self.threads {
+            thread.request_shutdown();
+        }
+        for thread in self.threads.drain(..) {
+            thread.join(deadline);
+        }
+    }
+
+    /// Suspend every session without ending them, for example while the embedder
+    /// is in the background
+    pub fn pause(&mut self) {
+        for session in &mut self.sessions {
+            session.pause();
+        }
+        for thread in &self.threads {
+            thread.pause();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        for session in &mut self.sessions {
+            session.resume();
+        }
+        for thread in &self.threads {
+            thread.resume();
+        }
+    }
+
+    /// Change the comfort features of every session, for example to snap turn
+    /// the viewer when the user asks the embedder to
+    pub fn send_comfort_msg(&mut self, msg: ComfortMsg) {
+        for session in &mut self.sessions {
+            session.send_comfort_msg(msg);
+        }
+        for thread in &self.threads {
+            thread.send_comfort_msg(msg);
+        }
     }
 
     pub fn running(&self) -> bool {
@@ -189,49 +572,208 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
             RegistryMsg::SupportsSession(mode, dest) => {
                 let _ = dest.send(self.supports_session(mode));
             }
-            RegistryMsg::RequestSession(mode, init, dest, raf_sender) => {
-                let _ = dest.send(self.request_session(mode, init, raf_sender));
+            RegistryMsg::RequestSession(mode, init, dest, raf_sender, progress, cancel) => {
+                let request = SessionRequest {
+                    mode,
+                    init,
+                    dest,
+                    raf_sender,
+                    progress,
+                    cancel: RequestCancellation::new(cancel),
+                };
+                if mode != SessionMode::Inline
+                    && self.immersive_session.is_some()
+                    && self.immersive_session_policy == ImmersiveSessionPolicy::Queue
+                {
+                    self.queued_requests.push_back(request);
+                } else {
+                    self.request_session(request);
+                }
+            }
+            RegistryMsg::SessionCreated(id, result) => self.spawned_session_created(id, result),
+            RegistryMsg::EnumerateDevices(dest) => {
+                let _ = dest.send(self.enumerate_devices());
             }
+            RegistryMsg::WatchImmersiveAvailability(dest) => {
+                self.immersive_watchers.push(dest);
+            }
+            RegistryMsg::WatchDeviceChanges(dest) => {
+                self.device_change_watchers.push(dest);
+            }
+            RegistryMsg::DeviceChanged => self.device_changed(),
             RegistryMsg::SimulateDeviceConnection(init, dest) => {
                 let _ = dest.send(self.simulate_device_connection(init));
             }
         }
     }
 
+    fn device_changed(&mut self) {
+        self.device_change_watchers
+            .retain(|watcher| watcher.send(()).is_ok());
+    }
+
     fn supports_session(&mut self, mode: SessionMode) -> Result<(), Error> {
         for discovery in &self.discoveries {
             if discovery.supports_session(mode) {
                 return Ok(());
             }
         }
+        // See `InlineDiscovery`
+        if mode == SessionMode::Inline {
+            return Ok(());
+        }
         Err(Error::NoMatchingDevice)
     }
 
-    fn request_session(
-        &mut self,
-        mode: SessionMode,
-        init: SessionInit,
-        raf_sender: Sender<Frame>,
-    ) -> Result<Session, Error> {
-        for discovery in &mut self.discoveries {
-            if discovery.supports_session(mode) {
-                let raf_sender = raf_sender.clone();
+    fn enumerate_devices(&self) -> Vec<DeviceDescriptor> {
+        self.discoveries
+            .iter()
+            .filter_map(|discovery| discovery.device_descriptor())
+            .collect()
+    }
+
+    /// Answer a new session request, or one that was queued
+    fn request_session(&mut self, mut request: SessionRequest) {
+        if request.mode != SessionMode::Inline && self.immersive_session.is_some() {
+            let _ = request.dest.send(Err(Error::ImmersiveSessionActive));
+            return;
+        }
+        let decision = self
+            .permission_gate
+            .as_mut()
+            .map(|gate| gate.check_session_request(request.mode, &request.init));
+        match decision {
+            None | Some(PermissionDecision::Allow) => (),
+            Some(PermissionDecision::Modify(init)) => request.init = init,
+            Some(PermissionDecision::Deny) => {
+                let _ = request.dest.send(Err(Error::PermissionDenied));
+                return;
+            }
+        }
+        self.try_discoveries(request, 0);
+    }
+
+    /// Ask the discoveries from `first` on for a session, until one creates it or starts
+    /// creating it on a thread of its own, in which case the request is answered later
+    fn try_discoveries(&mut self, request: SessionRequest, first: usize) {
+        let mode = request.mode;
+        // Inline sessions don't need hardware, so they are always available
+        let mut inline = InlineDiscovery;
+        let discoveries = self
+            .discoveries
+            .iter_mut()
+            .map(|discovery| &mut **discovery as &mut dyn DiscoveryAPI<GL>)
+            .chain(iter::once(&mut inline as &mut dyn DiscoveryAPI<GL>))
+            .enumerate()
+            .skip(first);
+        let mut result = Err(Error::NoMatchingDevice);
+        for (index, discovery) in discoveries {
+            if request.cancel.is_cancelled() {
+                result = Err(Error::RequestCancelled);
+                break;
+            }
+            if discovery.spawns_sessions() || discovery.supports_session(mode) {
                 let id = SessionId(self.next_session_id);
                 self.next_session_id += 1;
+                let settings = SessionSettings {
+                    layer_grand_manager: self.grand_manager.clone(),
+                    device_panic_policy: self.device_panic_policy.clone(),
+                    input_mapping: self.input_mapping.clone(),
+                    event_buffer_options: self.event_buffer_options,
+                    notifier: SessionCreationNotifier {
+                        sender: self.sender.clone(),
+                        waker: self.waker.clone(),
+                    },
+                };
                 let xr = SessionBuilder::new(
                     &mut self.sessions,
-                    raf_sender,
-                    self.grand_manager.clone(),
+                    settings,
+                    request.raf_sender.clone(),
                     id,
+                    request.cancel.token(),
+                    request.progress.clone(),
                 );
-                match discovery.request_session(mode, &init, xr) {
-                    Ok(session) => return Ok(session),
+                match discovery.request_session(mode, &request.init, xr) {
+                    Ok(session) => {
+                        result = Ok((index, id, session));
+                        break;
+                    }
+                    Err(Error::RequestCancelled) => {
+                        result = Err(Error::RequestCancelled);
+                        break;
+                    }
                     Err(err) => warn!("XR device error {:?}", err),
                 }
             }
         }
-        warn!("no device could support the session");
-        Err(Error::NoMatchingDevice)
+        match result {
+            Ok((index, id, PendingSession { state })) => match state {
+                PendingSessionState::Created(session) => self.session_created(request, session),
+                PendingSessionState::Spawned(thread) => {
+                    // The slot is taken while the session is created,
+                    // so that other requests wait for it
+                    if mode != SessionMode::Inline {
+                        self.immersive_session = Some(id);
+                    }
+                    self.pending_requests.push(PendingRequest {
+                        id,
+                        request,
+                        next_discovery: index + 1,
+                        thread,
+                    });
+                }
+            },
+            Err(error) => {
+                if let Error::NoMatchingDevice = error {
+                    warn!("no device could support the session");
+                }
+                let _ = request.dest.send(Err(error));
+            }
+        }
+    }
+
+    /// Answer a request with the session a device created for it
+    fn session_created(&mut self, request: SessionRequest, session: Session) {
+        // Device initialization can take a while, so check
+        // whether the request was cancelled in the meantime
+        let quitter = session.quitter();
+        match request.cancel.poll() {
+            Cancellation::Requested => {
+                quitter.shutdown();
+                let _ = request.dest.send(Err(Error::RequestCancelled));
+                return;
+            }
+            Cancellation::Pending => self.cancellations.push((request.cancel.receiver, quitter)),
+            Cancellation::Dropped => (),
+        }
+        if request.mode != SessionMode::Inline {
+            self.immersive_session = Some(session.id());
+        }
+        let _ = request.dest.send(Ok(session));
+    }
+
+    /// A device has finished creating a session on its own thread
+    fn spawned_session_created(&mut self, id: SessionId, result: Result<Session, Error>) {
+        let index = match self.pending_requests.iter().position(|p| p.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let pending = self.pending_requests.remove(index);
+        match result {
+            Ok(session) => {
+                self.threads.push(pending.thread.into_handle(pending.id));
+                self.session_created(pending.request, session);
+            }
+            Err(Error::RequestCancelled) => {
+                let _ = pending.request.dest.send(Err(Error::RequestCancelled));
+            }
+            Err(err) => {
+                warn!("XR device error {:?}", err);
+                // If this fails too, the immersive slot is freed once the registry
+                // notices that the session isn't running
+                self.try_discoveries(pending.request, pending.next_discovery);
+            }
+        }
     }
 
     fn simulate_device_connection(
@@ -240,8 +782,10 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
     ) -> Result<Sender<MockDeviceMsg>, Error> {
         for mock in &mut self.mocks {
             let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
-            if let Ok(discovery) = mock.simulate_device_connection(init.clone(), receiver) {
+            if let Ok(mut discovery) = mock.simulate_device_connection(init.clone(), receiver) {
+                discovery.set_device_change_notifier(self.device_change_notifier());
                 self.discoveries.insert(0, discovery);
+                self.device_changed();
                 return Ok(sender);
             }
         }
@@ -249,14 +793,100 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
     }
 }
 
-#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+enum Cancellation {
+    Requested,
+    Pending,
+    Dropped,
+}
+
+/// Whether a session request has been cancelled, as the registry last saw it.
+/// Backends that set up on a thread of their own can check this between steps,
+/// see `SessionBuilder::cancellation`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Whether a session request has been cancelled, checked by the registry
+pub(crate) struct RequestCancellation {
+    receiver: Receiver<()>,
+    /// Polling takes the cancellation out of the channel, so it is remembered here,
+    /// where the device creating the session can see it
+    requested: CancellationToken,
+}
+
+impl RequestCancellation {
+    fn new(receiver: Receiver<()>) -> RequestCancellation {
+        RequestCancellation {
+            receiver,
+            requested: CancellationToken::default(),
+        }
+    }
+
+    fn poll(&self) -> Cancellation {
+        if self.requested.is_cancelled() {
+            return Cancellation::Requested;
+        }
+        let cancellation = poll_cancellation(&self.receiver);
+        if let Cancellation::Requested = cancellation {
+            self.requested.0.store(true, Ordering::SeqCst);
+        }
+        cancellation
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        matches!(self.poll(), Cancellation::Requested)
+    }
+
+    pub(crate) fn token(&self) -> CancellationToken {
+        self.requested.clone()
+    }
+}
+
+#[cfg(not(feature = "ipc"))]
+fn poll_cancellation(cancel: &Receiver<()>) -> Cancellation {
+    match cancel.try_recv() {
+        Ok(()) => Cancellation::Requested,
+        Err(std::sync::mpsc::TryRecvError::Empty) => Cancellation::Pending,
+        Err(std::sync::mpsc::TryRecvError::Disconnected) => Cancellation::Dropped,
+    }
+}
+
+#[cfg(feature = "ipc")]
+fn poll_cancellation(cancel: &Receiver<()>) -> Cancellation {
+    match cancel.try_recv() {
+        Ok(()) => Cancellation::Requested,
+        Err(ipc_channel::ipc::TryRecvError::Empty) => Cancellation::Pending,
+        Err(ipc_channel::ipc::TryRecvError::IpcError(_)) => Cancellation::Dropped,
+    }
+}
+
+#[cfg_attr(
+    feature = "ipc",
+    derive(Serialize, Deserialize),
+    serde(remote = "Self")
+)]
 enum RegistryMsg {
     RequestSession(
         SessionMode,
         SessionInit,
         Sender<Result<Session, Error>>,
         Sender<Frame>,
+        Option<Sender<SessionProgress>>,
+        Receiver<()>,
     ),
+    SessionCreated(SessionId, Result<Session, Error>),
     SupportsSession(SessionMode, Sender<Result<(), Error>>),
+    EnumerateDevices(Sender<Vec<DeviceDescriptor>>),
+    WatchImmersiveAvailability(Sender<()>),
+    WatchDeviceChanges(Sender<()>),
+    DeviceChanged,
     SimulateDeviceConnection(MockDeviceInit, Sender<Result<Sender<MockDeviceMsg>, Error>>),
 }
+
+#[cfg(feature = "ipc")]
+crate::protocol::versioned!(RegistryMsg);
@@ -0,0 +1,208 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Recording what a session on a real device sends to content, so that it can be played
+//! back later by a replay device, turning traces from real headsets into reproducible tests.
+//!
+//! A recording is the format version, a `RecordingInfo`, and then a `RecordedEntry` for
+//! every frame and event, encoded with bincode.
+
+use crate::Backend;
+use crate::EnvironmentBlendMode;
+use crate::Error;
+use crate::Event;
+use crate::Feature;
+use crate::Floor;
+use crate::Frame;
+use crate::InputSource;
+use crate::Native;
+use crate::Session;
+use crate::Viewports;
+
+use euclid::{Point2D, RigidTransform3D};
+
+use serde::{Deserialize, Serialize};
+
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// The version of the format recordings are written in. Recordings
+/// in other versions can't be read.
+const RECORDING_VERSION: u32 = 2;
+
+/// What the recorded session was like when the recording started
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordingInfo {
+    pub environment_blend_mode: EnvironmentBlendMode,
+    pub granted_features: Vec<Feature>,
+    pub initial_inputs: Vec<InputSource>,
+    pub viewports: Viewports,
+    pub floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    pub reference_space_bounds: Option<Vec<Point2D<f32, Floor>>>,
+    pub supported_frame_rates: Vec<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum RecordedMsg {
+    /// A frame, without its sub images
+    Frame(Box<Frame>),
+    Event(Box<Event>),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedEntry {
+    /// When content was sent the message, in milliseconds since the recording started
+    pub time: f64,
+    pub msg: RecordedMsg,
+}
+
+/// A recording read back with `Recording::read`
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub info: RecordingInfo,
+    /// The frames and events, in the order content was sent them
+    pub entries: Vec<RecordedEntry>,
+}
+
+impl Recording {
+    pub fn read(reader: impl Read) -> Result<Recording, Error> {
+        let mut reader = BufReader::new(reader);
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(recording_error)?;
+        if version != RECORDING_VERSION {
+            return Err(Error::BackendSpecific(
+                Backend::Replay,
+                format!("can't read recordings in version {}", version),
+            ));
+        }
+        let info = bincode::deserialize_from(&mut reader).map_err(recording_error)?;
+        let mut entries = vec![];
+        loop {
+            match bincode::deserialize_from(&mut reader) {
+                Ok(entry) => entries.push(entry),
+                // A recording ends wherever the session did, which may be partway through
+                // an entry if the process went away while it was being written
+                Err(e) if is_eof(&e) => break,
+                Err(e) => return Err(recording_error(e)),
+            }
+        }
+        Ok(Recording { info, entries })
+    }
+}
+
+/// Writes the frames and events content gets from a session to a recording
+/// as they happen, see `SessionRecorder::start`
+pub struct SessionRecorder {
+    writer: Arc<Mutex<RecordingWriter>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+struct RecordingWriter {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    start: Instant,
+    /// The first error writing the recording, after which nothing more is written
+    error: Option<Error>,
+}
+
+impl RecordingWriter {
+    fn write(&mut self, msg: RecordedMsg) {
+        if self.error.is_some() {
+            return;
+        }
+        let is_frame = matches!(msg, RecordedMsg::Frame(_));
+        let entry = RecordedEntry {
+            time: self.start.elapsed().as_secs_f64() * 1000.0,
+            msg,
+        };
+        let mut result = bincode::serialize_into(&mut self.writer, &entry);
+        // Flushing once a frame keeps most of the recording if the process goes away
+        if result.is_ok() && is_frame {
+            result = self.writer.flush().map_err(Into::into);
+        }
+        if let Err(e) = result {
+            log::warn!("Couldn't write to the session recording: {}", e);
+            self.error = Some(recording_error(e));
+        }
+    }
+}
+
+impl SessionRecorder {
+    /// Start recording `session` to `writer`, until the session ends. This should
+    /// be called before the session's render loop starts, so that no frames are missed.
+    pub fn start(
+        session: &Session,
+        writer: impl Write + Send + 'static,
+    ) -> Result<SessionRecorder, Error> {
+        let info = RecordingInfo {
+            environment_blend_mode: session.environment_blend_mode(),
+            granted_features: session.granted_features().to_vec(),
+            initial_inputs: session.initial_inputs().to_vec(),
+            viewports: Viewports {
+                viewports: session.viewports().to_vec(),
+            },
+            floor_transform: session.floor_transform(),
+            reference_space_bounds: session.reference_space_bounds(),
+            supported_frame_rates: session.supported_frame_rates().to_vec(),
+        };
+        let mut writer = BufWriter::new(Box::new(writer) as Box<dyn Write + Send>);
+        bincode::serialize_into(&mut writer, &RECORDING_VERSION).map_err(recording_error)?;
+        bincode::serialize_into(&mut writer, &info).map_err(recording_error)?;
+
+        let observer = session.observe_for_recording()?;
+        let writer = Arc::new(Mutex::new(RecordingWriter {
+            writer,
+            start: Instant::now(),
+            error: None,
+        }));
+        let frames = observer.frames;
+        let frame_writer = writer.clone();
+        let frame_thread = thread::spawn(move || {
+            while let Ok(frame) = frames.recv() {
+                frame_writer
+                    .lock()
+                    .unwrap()
+                    .write(RecordedMsg::Frame(Box::new(frame)));
+            }
+        });
+        let events = observer.events;
+        let event_writer = writer.clone();
+        let event_thread = thread::spawn(move || {
+            while let Ok(event) = events.recv() {
+                event_writer
+                    .lock()
+                    .unwrap()
+                    .write(RecordedMsg::Event(Box::new(event)));
+            }
+        });
+        Ok(SessionRecorder {
+            writer,
+            threads: vec![frame_thread, event_thread],
+        })
+    }
+
+    /// Wait for the session to end and the rest of the recording to be written,
+    /// returning the first error writing it if there was one
+    pub fn finish(self) -> Result<(), Error> {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(error) = writer.error.take() {
+            return Err(error);
+        }
+        writer.writer.flush().map_err(|e| recording_error(e.into()))
+    }
+}
+
+fn is_eof(error: &bincode::Error) -> bool {
+    match **error {
+        bincode::ErrorKind::Io(ref e) => e.kind() == io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
+
+fn recording_error(error: bincode::Error) -> Error {
+    Error::BackendSpecific(Backend::Replay, error.to_string())
+}
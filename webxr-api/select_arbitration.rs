@@ -0,0 +1,159 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Keeping select and squeeze from both happening for one physical action.
+//!
+//! With some inputs, such as tracked hands, the gesture for select and the gesture for
+//! squeeze overlap, so both start together and content sees two contradictory selects.
+//! Devices can pass the select events of such inputs through a `SelectArbiter`, which
+//! only lets one kind be in progress at a time.
+
+use crate::SelectEvent;
+use crate::SelectKind;
+
+/// How select events are arbitrated for each interaction profile,
+/// see `Session::set_select_arbitration`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelectArbitrationOptions {
+    /// The arbitration for inputs without a profile in `profiles`,
+    /// or `None` to leave them alone
+    pub default: Option<SelectArbitration>,
+    pub profiles: Vec<ProfileArbitration>,
+}
+
+/// The arbitration for the inputs with an interaction profile. An input gets the
+/// arbitration of the first of its profiles that has one, so the most specific profile wins.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileArbitration {
+    /// The profile, as in `InputSource::profiles`
+    pub profile: String,
+    /// How to arbitrate, or `None` to leave these inputs alone
+    pub arbitration: Option<SelectArbitration>,
+}
+
+/// Only one of select and squeeze can be in progress at a time. The other kind is
+/// ignored until it is released, unless it has priority and starts soon enough after
+/// the first, in which case the first kind is cancelled instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelectArbitration {
+    /// The kind that wins when both start at once
+    pub priority: SelectKind,
+    /// How long after the other kind starts the kind with priority can still
+    /// take over, in milliseconds
+    pub window: f64,
+}
+
+impl Default for SelectArbitration {
+    fn default() -> SelectArbitration {
+        SelectArbitration {
+            priority: SelectKind::Squeeze,
+            window: 100.0,
+        }
+    }
+}
+
+impl SelectArbitrationOptions {
+    /// The arbitration for an input with these profiles
+    pub fn for_profiles(&self, profiles: &[String]) -> Option<SelectArbitration> {
+        profiles
+            .iter()
+            .find_map(|profile| {
+                self.profiles
+                    .iter()
+                    .find(|arbitration| arbitration.profile == *profile)
+            })
+            .map_or(self.default, |arbitration| arbitration.arbitration)
+    }
+}
+
+/// Arbitrates the select events of one input, for devices that support select arbitration
+#[derive(Clone, Debug)]
+pub struct SelectArbiter {
+    arbitration: SelectArbitration,
+    /// The kind in progress, and when it started
+    active: Option<(SelectKind, f64)>,
+    /// The kind that is being ignored until it is released
+    ignored: Option<SelectKind>,
+}
+
+impl SelectArbiter {
+    pub fn new(arbitration: SelectArbitration) -> SelectArbiter {
+        SelectArbiter {
+            arbitration,
+            active: None,
+            ignored: None,
+        }
+    }
+
+    /// Whether neither kind is in progress or being ignored, so that the arbiter
+    /// can be replaced without losing track of a gesture
+    pub fn is_idle(&self) -> bool {
+        self.active.is_none() && self.ignored.is_none()
+    }
+
+    /// Pass the input's select and squeeze events for a frame through the arbiter,
+    /// getting the events to send instead, in order. `time` is in the same units as
+    /// `Frame::predicted_display_time`.
+    pub fn arbitrate(
+        &mut self,
+        select: Option<SelectEvent>,
+        squeeze: Option<SelectEvent>,
+        time: f64,
+    ) -> Vec<(SelectKind, SelectEvent)> {
+        let mut events = [(SelectKind::Select, select), (SelectKind::Squeeze, squeeze)];
+        // Releases go first so that the other kind can start in the same frame,
+        // and then the kind with priority starts first
+        let priority = self.arbitration.priority;
+        events.sort_by_key(|&(kind, event)| (event == Some(SelectEvent::Start), kind != priority));
+        let mut arbitrated = vec![];
+        for &(kind, event) in &events {
+            if let Some(event) = event {
+                self.event(kind, event, time, &mut arbitrated);
+            }
+        }
+        arbitrated
+    }
+
+    fn event(
+        &mut self,
+        kind: SelectKind,
+        event: SelectEvent,
+        time: f64,
+        arbitrated: &mut Vec<(SelectKind, SelectEvent)>,
+    ) {
+        if event != SelectEvent::Start {
+            if self.ignored == Some(kind) {
+                self.ignored = None;
+            } else {
+                if matches!(self.active, Some((active, _)) if active == kind) {
+                    self.active = None;
+                }
+                arbitrated.push((kind, event));
+            }
+            return;
+        }
+        match self.active {
+            Some((active, _)) if active == kind => arbitrated.push((kind, event)),
+            Some((active, start)) => {
+                // The frame time is in nanoseconds
+                let soon_enough = time - start <= self.arbitration.window * 1_000_000.0;
+                if kind == self.arbitration.priority && soon_enough {
+                    arbitrated.push((active, SelectEvent::End));
+                    arbitrated.push((kind, event));
+                    self.active = Some((kind, time));
+                    self.ignored = Some(active);
+                } else {
+                    self.ignored = Some(kind);
+                }
+            }
+            None => {
+                arbitrated.push((kind, event));
+                self.active = Some((kind, time));
+            }
+        }
+    }
+}
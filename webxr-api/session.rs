@@ -3,41 +3,85 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::channel;
+use crate::comfort::Vignette;
+use crate::governor::ResolutionGovernor;
+use crate::input_mapping::InputMapper;
+use crate::observer::EventRelay;
+use crate::pacing::FramePacer;
+use crate::registry::SessionCreationNotifier;
+use crate::soak::SoakMonitor;
+use crate::space::SpacePoses;
+use crate::stats::FrameStatsRecorder;
+use crate::usage::UsageRecorder;
+use crate::ApiSpace;
+use crate::CancellationToken;
+use crate::ComfortMsg;
+use crate::ComfortOptions;
 use crate::ContextId;
 use crate::DeviceAPI;
+use crate::DevicePanicPolicy;
+use crate::DomOverlayInit;
+use crate::DwellOptions;
 use crate::Error;
 use crate::Event;
+use crate::EventBuffer;
+use crate::EventBufferOptions;
+use crate::Feature;
 use crate::Floor;
 use crate::Frame;
+use crate::FramePacing;
+use crate::FrameStats;
+use crate::FrameSubmission;
+use crate::FrameSubscriptions;
 use crate::FrameUpdateEvent;
+use crate::GovernorOptions;
 use crate::HitTestId;
+use crate::HitTestLimits;
 use crate::HitTestSource;
+use crate::InputId;
+use crate::InputMapping;
 use crate::InputSource;
+use crate::LatencyMarker;
+use crate::LayerGeometry;
 use crate::LayerGrandManager;
 use crate::LayerId;
 use crate::LayerInit;
+use crate::LayerLimits;
+use crate::LayerQuality;
 use crate::Native;
+use crate::PoseGraph;
 use crate::Receiver;
+use crate::ResourceReport;
+use crate::SelectArbitrationOptions;
 use crate::Sender;
+use crate::SessionObserver;
+use crate::SoakOptions;
+use crate::Space;
+use crate::TrackedImageInit;
+use crate::UsageOptions;
+use crate::UsageSummary;
 use crate::Viewport;
 use crate::Viewports;
+use crate::Visibility;
 
 use euclid::Point2D;
 use euclid::Rect;
 use euclid::RigidTransform3D;
 use euclid::Size2D;
+use euclid::Vector3D;
 
 use log::warn;
 
+use std::sync::mpsc;
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
 
-// How long to wait for an rAF.
-static TIMEOUT: Duration = Duration::from_millis(5);
-
 /// https://www.w3.org/TR/webxr/#xrsessionmode-enum
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
@@ -51,35 +95,41 @@ pub enum SessionMode {
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct SessionInit {
-    pub required_features: Vec<String>,
-    pub optional_features: Vec<String>,
+    pub required_features: Vec<Feature>,
+    pub optional_features: Vec<Feature>,
     /// Secondary views are enabled with the `secondary-view` feature
     /// but for performance reasons we also ask users to enable this pref
     /// for now.
     pub first_person_observer_view: bool,
+    /// Images to track, used by the `image-tracking` feature
+    pub tracked_images: Vec<TrackedImageInit>,
+    /// The overlay to show, used by the `dom-overlay` feature
+    pub dom_overlay: Option<DomOverlayInit>,
 }
 
 impl SessionInit {
     /// Helper function for validating a list of requested features against
-    /// a list of supported features for a given mode
-    pub fn validate(&self, mode: SessionMode, supported: &[String]) -> Result<Vec<String>, Error> {
+    /// a list of supported features for a given mode. The `hit-test` feature
+    /// should only be supported by devices that support hit tests, see
+    /// `DeviceAPI::supports_hit_test`.
+    pub fn validate(
+        &self,
+        mode: SessionMode,
+        supported: &[Feature],
+    ) -> Result<Vec<Feature>, Error> {
+        // viewer and local in immersive are granted by default
+        // https://immersive-web.github.io/webxr/#default-features
+        let granted_by_default = |f: &Feature| {
+            *f == Feature::Viewer || (*f == Feature::Local && mode != SessionMode::Inline)
+        };
         for f in &self.required_features {
-            // viewer and local in immersive are granted by default
-            // https://immersive-web.github.io/webxr/#default-features
-            if f == "viewer" || (f == "local" && mode != SessionMode::Inline) {
-                continue;
-            }
-
-            if !supported.contains(f) {
-                return Err(Error::UnsupportedFeature(f.into()));
+            if !granted_by_default(f) && !supported.contains(f) {
+                return Err(Error::UnsupportedFeature(f.to_string()));
             }
         }
         let mut granted = self.required_features.clone();
         for f in &self.optional_features {
-            if f == "viewer"
-                || (f == "local" && mode != SessionMode::Inline)
-                || supported.contains(f)
-            {
+            if granted_by_default(f) || supported.contains(f) {
                 granted.push(f.clone());
             }
         }
@@ -87,12 +137,11 @@ impl SessionInit {
         Ok(granted)
     }
 
-    pub fn feature_requested(&self, f: &str) -> bool {
+    pub fn feature_requested(&self, f: &Feature) -> bool {
         self.required_features
             .iter()
             .chain(self.optional_features.iter())
-            .find(|x| *x == f)
-            .is_some()
+            .any(|x| x == f)
     }
 }
 
@@ -107,22 +156,65 @@ pub enum EnvironmentBlendMode {
 
 // The messages that are sent from the content thread to the session thread.
 #[derive(Debug)]
-#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "ipc",
+    derive(Serialize, Deserialize),
+    serde(remote = "Self")
+)]
 enum SessionMsg {
     CreateLayer(ContextId, LayerInit, Sender<Result<LayerId, Error>>),
     DestroyLayer(ContextId, LayerId),
     SetLayers(Vec<(ContextId, LayerId)>),
+    MigrateContext(ContextId, ContextId, Sender<Result<(), Error>>),
+    SetFoveationLevel(ContextId, LayerId, f32),
+    SetLayerQuality(ContextId, LayerId, Option<LayerQuality>),
+    SetLayerGeometry(ContextId, LayerId, LayerGeometry),
+    AttachLayer(ContextId, LayerId, Space),
     SetEventDest(Sender<Event>),
+    SetUrgentEventDest(Sender<Event>),
+    AddObserver(Sender<Frame>, Sender<Event>, /* whole frames */ bool),
     UpdateClipPlanes(/* near */ f32, /* far */ f32),
     StartRenderLoop,
     RenderAnimationFrame,
     RequestHitTest(HitTestSource),
     CancelHitTest(HitTestId),
     UpdateFrameRate(f32, Sender<f32>),
+    UpdateTargetFrameRate(f32),
+    /// Stop producing frames until `Resume`, without ending the session
+    Pause,
+    Resume,
+    TriggerHaptic(InputId, /* intensity */ f32, /* duration */ f64),
+    RequestViewportScale(/* view */ usize, /* scale */ f32),
+    SetResolutionGovernor(Option<GovernorOptions>),
+    SetPoseGraphDest(Option<Sender<PoseGraph>>),
+    SetLatencyDest(Option<Sender<FrameSubmission>>),
+    RecordUsage(UsageOptions, Sender<UsageSummary>),
+    ReportResources(SoakOptions, Sender<ResourceReport>),
+    /// Sent by the `SoakMonitor`'s timer when a resource report is due
+    ResourceReportDue,
+    SetFrameSubscriptions(FrameSubscriptions),
+    SetDwellSelect(Option<DwellOptions>),
+    SetSelectArbitration(SelectArbitrationOptions),
+    SetMonoRendering(bool),
+    SetFramePacing(FramePacing),
+    Comfort(ComfortMsg),
     Quit,
+    /// End the session immediately, even if it is in the middle of a render loop,
+    /// and release everything held by the device
+    Shutdown,
     GetBoundsGeometry(Sender<Option<Vec<Point2D<f32, Floor>>>>),
+    GetFrameStats(Sender<FrameStats>),
+    GetVisibility(Sender<Visibility>),
+    GetPose(
+        Space,
+        /* base */ Space,
+        Sender<Option<RigidTransform3D<f32, ApiSpace, ApiSpace>>>,
+    ),
 }
 
+#[cfg(feature = "ipc")]
+crate::protocol::versioned!(SessionMsg);
+
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 #[derive(Clone)]
 pub struct Quitter {
@@ -133,6 +225,10 @@ impl Quitter {
     pub fn quit(&self) {
         let _ = self.sender.send(SessionMsg::Quit);
     }
+
+    pub(crate) fn shutdown(&self) {
+        let _ = self.sender.send(SessionMsg::Shutdown);
+    }
 }
 
 /// An object that represents an XR session.
@@ -142,12 +238,17 @@ impl Quitter {
 pub struct Session {
     floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
     viewports: Viewports,
+    /// The bounds from the latest `FrameUpdateEvent::UpdateBoundsGeometry`, if the
+    /// device has sent one, after which it tells the session about every change
+    bounds_geometry: Option<Vec<Point2D<f32, Floor>>>,
     sender: Sender<SessionMsg>,
     environment_blend_mode: EnvironmentBlendMode,
     initial_inputs: Vec<InputSource>,
-    granted_features: Vec<String>,
+    granted_features: Vec<Feature>,
     id: SessionId,
     supported_frame_rates: Vec<f32>,
+    layer_limits: LayerLimits,
+    hit_test_limits: HitTestLimits,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -159,16 +260,54 @@ impl Session {
         self.id
     }
 
+    pub(crate) fn quitter(&self) -> Quitter {
+        Quitter {
+            sender: self.sender.clone(),
+        }
+    }
+
     pub fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
         self.floor_transform.clone()
     }
 
     pub fn reference_space_bounds(&self) -> Option<Vec<Point2D<f32, Floor>>> {
+        if let Some(ref bounds) = self.bounds_geometry {
+            return Some(bounds.clone());
+        }
         let (sender, receiver) = channel().ok()?;
         let _ = self.sender.send(SessionMsg::GetBoundsGeometry(sender));
         receiver.recv().ok()?
     }
 
+    /// The timings of the render loop since the last time they were requested,
+    /// and how much texture memory the session's layers take up
+    pub fn request_stats(&self) -> Option<FrameStats> {
+        let (sender, receiver) = channel().ok()?;
+        let _ = self.sender.send(SessionMsg::GetFrameStats(sender));
+        receiver.recv().ok()
+    }
+
+    /// Whether the session is currently being shown to the user, for content that
+    /// needs it before the next `Event::VisibilityChange`
+    pub fn visibility_state(&self) -> Option<Visibility> {
+        let (sender, receiver) = channel().ok()?;
+        let _ = self.sender.send(SessionMsg::GetVisibility(sender));
+        receiver.recv().ok()
+    }
+
+    /// The pose of `space` relative to `base` as of the latest frame, for placing things
+    /// such as embedder UI outside of the render loop. This is `None` if either space
+    /// isn't being tracked.
+    pub fn request_pose(
+        &self,
+        space: Space,
+        base: Space,
+    ) -> Option<RigidTransform3D<f32, ApiSpace, ApiSpace>> {
+        let (sender, receiver) = channel().ok()?;
+        let _ = self.sender.send(SessionMsg::GetPose(space, base, sender));
+        receiver.recv().ok()?
+    }
+
     pub fn initial_inputs(&self) -> &[InputSource] {
         &self.initial_inputs
     }
@@ -181,7 +320,8 @@ impl Session {
         &self.viewports.viewports
     }
 
-    /// A resolution large enough to contain all the viewports.
+    /// A resolution large enough to contain all the viewports, however many
+    /// views the device has and however they are packed.
     /// https://immersive-web.github.io/webxr/#recommended-webgl-framebuffer-resolution
     ///
     /// Returns None if the session is inline
@@ -194,7 +334,19 @@ impl Session {
             .map(|rect| Size2D::new(rect.max_x(), rect.max_y()))
     }
 
+    /// The limits on the layers this session can create and render
+    pub fn layer_limits(&self) -> &LayerLimits {
+        &self.layer_limits
+    }
+
+    /// The limits on the hit tests this session can make. Hit test sources
+    /// over the limit are rejected with `FrameUpdateEvent::HitTestSourceRejected`.
+    pub fn hit_test_limits(&self) -> &HitTestLimits {
+        &self.hit_test_limits
+    }
+
     pub fn create_layer(&self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.layer_limits.check_layer(&init, &self.viewports)?;
         let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
         let _ = self
             .sender
@@ -209,8 +361,73 @@ impl Session {
             .send(SessionMsg::DestroyLayer(context_id, layer_id));
     }
 
-    pub fn set_layers(&self, layers: Vec<(ContextId, LayerId)>) {
+    /// Re-bind the layers created with `old_context_id` to `new_context_id`.
+    /// This allows recovering from WebGL context loss without ending the session,
+    /// the layer ids stay the same but their textures are recreated.
+    pub fn migrate_context(
+        &self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self.sender.send(SessionMsg::MigrateContext(
+            old_context_id,
+            new_context_id,
+            sender,
+        ));
+        receiver.recv().map_err(|_| Error::CommunicationError)?
+    }
+
+    /// Set the fixed foveation level of a layer, between 0 (none) and 1 (maximum)
+    pub fn set_foveation_level(&self, context_id: ContextId, layer_id: LayerId, level: f32) {
+        let level = level.clamp(0.0, 1.0);
+        let _ = self
+            .sender
+            .send(SessionMsg::SetFoveationLevel(context_id, layer_id, level));
+    }
+
+    /// Set the supersampling and sharpening the compositor applies to a layer,
+    /// or leave them to the runtime with `None`, which is the default
+    pub fn set_layer_quality(
+        &self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        quality: Option<LayerQuality>,
+    ) {
+        let _ = self
+            .sender
+            .send(SessionMsg::SetLayerQuality(context_id, layer_id, quality));
+    }
+
+    /// Move a quad, cylinder, equirect or cube layer
+    pub fn set_layer_geometry(
+        &self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        geometry: LayerGeometry,
+    ) {
+        let _ = self
+            .sender
+            .send(SessionMsg::SetLayerGeometry(context_id, layer_id, geometry));
+    }
+
+    /// Attach a quad, cylinder or equirect layer to a space, usually the grip or target ray
+    /// space of an input, for overlays such as wrist menus and controller tooltips. The
+    /// device moves the layer with the input every frame, so it doesn't lag behind it the
+    /// way it would if the layer were moved with `set_layer_geometry`. This is meant for
+    /// trusted overlays drawn by the embedder, and shouldn't be exposed to content.
+    pub fn attach_layer(&self, context_id: ContextId, layer_id: LayerId, space: Space) {
+        let _ = self
+            .sender
+            .send(SessionMsg::AttachLayer(context_id, layer_id, space));
+    }
+
+    /// Set the layers rendered from the next frame on, which fails if there are
+    /// more of them than `LayerLimits::max_layers`
+    pub fn set_layers(&self, layers: Vec<(ContextId, LayerId)>) -> Result<(), Error> {
+        self.layer_limits.check_layer_count(layers.len())?;
         let _ = self.sender.send(SessionMsg::SetLayers(layers));
+        Ok(())
     }
 
     pub fn start_render_loop(&mut self) {
@@ -221,14 +438,139 @@ impl Session {
         let _ = self.sender.send(SessionMsg::UpdateClipPlanes(near, far));
     }
 
+    /// Get a read-only view of the session, which gets a copy of every frame and event,
+    /// for embedder features such as a preview of what the user sees
+    pub fn observe(&self) -> Result<SessionObserver, Error> {
+        self.add_observer(false)
+    }
+
+    /// Like `observe`, but with the whole of each frame other than its sub images
+    #[cfg(feature = "replay")]
+    pub(crate) fn observe_for_recording(&self) -> Result<SessionObserver, Error> {
+        self.add_observer(true)
+    }
+
+    fn add_observer(&self, whole_frames: bool) -> Result<SessionObserver, Error> {
+        let (frame_sender, frames) = channel().or(Err(Error::CommunicationError))?;
+        let (event_sender, events) = channel().or(Err(Error::CommunicationError))?;
+        self.sender
+            .send(SessionMsg::AddObserver(
+                frame_sender,
+                event_sender,
+                whole_frames,
+            ))
+            .map_err(|_| Error::CommunicationError)?;
+        Ok(SessionObserver { frames, events })
+    }
+
     pub fn set_event_dest(&mut self, dest: Sender<Event>) {
         let _ = self.sender.send(SessionMsg::SetEventDest(dest));
     }
 
+    /// Send lifecycle events (see `Event::is_urgent`) to `dest` rather than to the event
+    /// dest, so that they don't wait behind a backlog of other events, such as selects.
+    /// Content should drain `dest` before handling the other events. Lifecycle events
+    /// can then arrive ahead of events that were sent before them.
+    pub fn set_urgent_event_dest(&mut self, dest: Sender<Event>) {
+        let _ = self.sender.send(SessionMsg::SetUrgentEventDest(dest));
+    }
+
+    /// Turn on or configure the comfort features, see `ComfortOptions`
+    pub fn set_comfort_options(&mut self, options: ComfortOptions) {
+        let _ = self
+            .sender
+            .send(SessionMsg::Comfort(ComfortMsg::SetOptions(options)));
+    }
+
+    /// Report artificial locomotion of the viewer, between 0 (none) and 1 (as fast as it gets),
+    /// so that the vignette can be shown while the viewer is being moved
+    pub fn set_locomotion(&mut self, locomotion: f32) {
+        let _ = self
+            .sender
+            .send(SessionMsg::Comfort(ComfortMsg::SetLocomotion(locomotion)));
+    }
+
+    /// Turn the viewer on the spot by `angle` radians to the left (or right, if negative),
+    /// by turning the local and floor reference spaces around them
+    pub fn snap_turn(&mut self, angle: f32) {
+        let _ = self
+            .sender
+            .send(SessionMsg::Comfort(ComfortMsg::SnapTurn(angle)));
+    }
+
+    /// Declare which optional parts of frames content uses, so that the others
+    /// don't have to be computed and sent. By default everything is sent.
+    pub fn set_frame_subscriptions(&mut self, subscriptions: FrameSubscriptions) {
+        let _ = self
+            .sender
+            .send(SessionMsg::SetFrameSubscriptions(subscriptions));
+    }
+
+    /// Have gaze input that has no button select when its target ray is held still,
+    /// or stop with `None`. Progress is reported with `Event::DwellProgress`.
+    pub fn set_dwell_select(&mut self, options: Option<DwellOptions>) {
+        let _ = self.sender.send(SessionMsg::SetDwellSelect(options));
+    }
+
+    /// Keep select and squeeze from both happening for one physical action,
+    /// for the interaction profiles where their gestures overlap
+    pub fn set_select_arbitration(&mut self, options: SelectArbitrationOptions) {
+        let _ = self.sender.send(SessionMsg::SetSelectArbitration(options));
+    }
+
+    /// Render the same view to both eyes, with no distance between them, for users
+    /// who can't see or are uncomfortable with stereo. This can be switched at any time.
+    pub fn set_mono_rendering(&mut self, mono: bool) {
+        let _ = self.sender.send(SessionMsg::SetMonoRendering(mono));
+    }
+
+    /// Change how long the session waits for content to render each frame before giving
+    /// the main thread back, if it runs on the main thread. Sessions on threads of their
+    /// own always wait.
+    pub fn set_frame_pacing(&mut self, pacing: FramePacing) {
+        let _ = self.sender.send(SessionMsg::SetFramePacing(pacing));
+    }
+
+    /// Start (or with `None`, stop) receiving a dump of the pose graph of every frame,
+    /// for debugging content that ends up in the wrong place
+    pub fn set_pose_graph_dest(&mut self, dest: Option<Sender<PoseGraph>>) {
+        let _ = self.sender.send(SessionMsg::SetPoseGraphDest(dest));
+    }
+
+    /// Start (or with `None`, stop) marking every frame with its index, and sending
+    /// when each frame was submitted to the device, for measuring latency. See `LatencyMarker`.
+    pub fn set_latency_dest(&mut self, dest: Option<Sender<FrameSubmission>>) {
+        let _ = self.sender.send(SessionMsg::SetLatencyDest(dest));
+    }
+
+    /// Start sampling how the session is used, for analytics. The summary is sent
+    /// to `dest` when the session ends, or when this is called again.
+    pub fn record_usage(&mut self, options: UsageOptions, dest: Sender<UsageSummary>) {
+        let _ = self.sender.send(SessionMsg::RecordUsage(options, dest));
+    }
+
+    /// Start sending what the session holds on to every `options.interval`, for finding
+    /// leaks in sessions that run for hours. Reports stop when `dest` is dropped,
+    /// or when this is called again.
+    pub fn report_resources(&mut self, options: SoakOptions, dest: Sender<ResourceReport>) {
+        let _ = self.sender.send(SessionMsg::ReportResources(options, dest));
+    }
+
     pub fn render_animation_frame(&mut self) {
         let _ = self.sender.send(SessionMsg::RenderAnimationFrame);
     }
 
+    /// Suspend the session without ending it, for example while the embedder is in the
+    /// background. The render loop stops and the device can release some of its resources.
+    pub fn pause(&self) {
+        let _ = self.sender.send(SessionMsg::Pause);
+    }
+
+    /// Resume a session suspended with `pause`
+    pub fn resume(&self) {
+        let _ = self.sender.send(SessionMsg::Resume);
+    }
+
     pub fn end_session(&mut self) {
         let _ = self.sender.send(SessionMsg::Quit);
     }
@@ -237,14 +579,28 @@ impl Session {
         match event {
             FrameUpdateEvent::UpdateFloorTransform(floor) => self.floor_transform = floor,
             FrameUpdateEvent::UpdateViewports(vp) => self.viewports = vp,
+            FrameUpdateEvent::UpdateBoundsGeometry(bounds) => self.bounds_geometry = Some(bounds),
             FrameUpdateEvent::HitTestSourceAdded(_) => (),
+            FrameUpdateEvent::HitTestSourceRejected(..) => (),
+            FrameUpdateEvent::UpdateDetectedMesh(_) => (),
+            FrameUpdateEvent::DetectedMeshRemoved(_) => (),
+            FrameUpdateEvent::Extension(_) => (),
         }
     }
 
-    pub fn granted_features(&self) -> &[String] {
+    pub fn granted_features(&self) -> &[Feature] {
         &self.granted_features
     }
 
+    /// Check that `feature` was granted, before using an API that needs it
+    pub fn require_feature(&self, feature: Feature) -> Result<(), Error> {
+        if self.granted_features.contains(&feature) {
+            Ok(())
+        } else {
+            Err(Error::FeatureNotGranted(feature))
+        }
+    }
+
     pub fn request_hit_test(&self, source: HitTestSource) {
         let _ = self.sender.send(SessionMsg::RequestHitTest(source));
     }
@@ -253,13 +609,44 @@ impl Session {
         let _ = self.sender.send(SessionMsg::CancelHitTest(id));
     }
 
+    /// Request a frame rate, replying with the rate the device actually picked
     pub fn update_frame_rate(&mut self, rate: f32, sender: Sender<f32>) {
         let _ = self.sender.send(SessionMsg::UpdateFrameRate(rate, sender));
     }
 
+    /// Request a frame rate without waiting for the result. The device sends
+    /// `Event::FrameRateChanged` if the rate changes.
+    /// https://immersive-web.github.io/webxr/#dom-xrsession-updatetargetframerate
+    pub fn update_target_frame_rate(&mut self, rate: f32) {
+        let _ = self.sender.send(SessionMsg::UpdateTargetFrameRate(rate));
+    }
+
     pub fn supported_frame_rates(&self) -> &[f32] {
         &self.supported_frame_rates
     }
+
+    /// Trigger a haptic pulse on an input source.
+    /// https://w3c.github.io/gamepad/extensions.html#dom-gamepadhapticactuator-pulse
+    pub fn trigger_haptic(&self, id: InputId, intensity: f32, duration: f64) {
+        let _ = self
+            .sender
+            .send(SessionMsg::TriggerHaptic(id, intensity, duration));
+    }
+
+    /// Render a view into a smaller part of its viewport, from the next frame on.
+    /// https://immersive-web.github.io/webxr/#dom-xrview-requestviewportscale
+    pub fn request_viewport_scale(&self, view: usize, scale: f32) {
+        let _ = self
+            .sender
+            .send(SessionMsg::RequestViewportScale(view, scale));
+    }
+
+    /// Lower the viewport scale (and maybe the frame rate) automatically while frames
+    /// take too long, and raise it again when they don't, or stop with `None`.
+    /// Scales requested by content are scaled down further by the governor.
+    pub fn set_resolution_governor(&self, options: Option<GovernorOptions>) {
+        let _ = self.sender.send(SessionMsg::SetResolutionGovernor(options));
+    }
 }
 
 #[derive(PartialEq)]
@@ -275,24 +662,58 @@ pub struct SessionThread<Device> {
     sender: Sender<SessionMsg>,
     layers: Vec<(ContextId, LayerId)>,
     pending_layers: Option<Vec<(ContextId, LayerId)>>,
+    /// The layers that have been created and not destroyed yet
+    created_layers: Vec<LayerId>,
     frame_count: u64,
     frame_sender: Sender<Frame>,
+    pose_graph_dest: Option<Sender<PoseGraph>>,
+    latency_dest: Option<Sender<FrameSubmission>>,
+    usage: Option<UsageRecorder>,
+    soak: Option<SoakMonitor>,
+    frame_subscriptions: FrameSubscriptions,
+    stats: FrameStatsRecorder,
+    governor: Option<ResolutionGovernor>,
+    /// The scales content requested for each view, which a new governor starts from
+    viewport_scales: Vec<f32>,
+    vignette: Vignette,
+    space_poses: SpacePoses,
+    /// Where the viewer was in the last frame, which snap turns are centered on
+    viewer_position: Option<Vector3D<f32, Native>>,
+    event_dest: Option<Sender<Event>>,
+    /// Copies events to observers, once there are any
+    event_relay: Option<EventRelay>,
+    /// Where to send copies of frames, and whether they get the whole frame
+    frame_observers: Vec<(Sender<Frame>, bool)>,
     running: bool,
     device: Device,
     id: SessionId,
     render_state: RenderState,
+    /// Whether the device has begun a frame that hasn't been ended yet
+    frame_in_flight: bool,
+    paused: bool,
+    /// Whether the render loop is waiting for the session to be resumed
+    frame_pending: bool,
+    /// How long `run_one_frame` waits for messages
+    pacer: FramePacer,
 }
 
 impl<Device> SessionThread<Device>
 where
     Device: DeviceAPI,
 {
-    pub fn new(
+    pub fn new(device: Device, frame_sender: Sender<Frame>, id: SessionId) -> Result<Self, Error> {
+        let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
+        SessionThread::with_channel(device, frame_sender, id, sender, receiver)
+    }
+
+    /// Like `new`, with the channel that the session's messages come in on
+    fn with_channel(
         mut device: Device,
         frame_sender: Sender<Frame>,
         id: SessionId,
+        sender: Sender<SessionMsg>,
+        receiver: Receiver<SessionMsg>,
     ) -> Result<Self, Error> {
-        let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
         device.set_quitter(Quitter {
             sender: sender.clone(),
         });
@@ -306,11 +727,30 @@ where
             device,
             layers,
             pending_layers,
+            created_layers: Vec::new(),
             frame_count,
             frame_sender,
+            pose_graph_dest: None,
+            latency_dest: None,
+            usage: None,
+            soak: None,
+            frame_subscriptions: FrameSubscriptions::default(),
+            stats: FrameStatsRecorder::default(),
+            governor: None,
+            viewport_scales: vec![],
+            vignette: Vignette::default(),
+            space_poses: SpacePoses::default(),
+            viewer_position: None,
+            event_dest: None,
+            event_relay: None,
+            frame_observers: vec![],
             running,
             id,
             render_state: RenderState::NotInRenderLoop,
+            frame_in_flight: false,
+            paused: false,
+            frame_pending: false,
+            pacer: FramePacer::default(),
         })
     }
 
@@ -322,123 +762,479 @@ where
         let environment_blend_mode = self.device.environment_blend_mode();
         let granted_features = self.device.granted_features().into();
         let supported_frame_rates = self.device.supported_frame_rates();
+        let layer_limits = self.device.layer_limits();
+        let hit_test_limits = self.device.hit_test_limits();
         Session {
             floor_transform,
             viewports,
+            bounds_geometry: None,
             sender,
             initial_inputs,
             environment_blend_mode,
             granted_features,
             id: self.id,
             supported_frame_rates,
+            layer_limits,
+            hit_test_limits,
         }
     }
 
     pub fn run(&mut self) {
-        loop {
-            if let Ok(msg) = self.receiver.recv() {
-                if !self.handle_msg(msg) {
-                    self.running = false;
-                    break;
-                }
-            } else {
+        while let Ok(msg) = self.receiver.recv() {
+            if !self.handle_msg(msg) {
                 break;
             }
         }
+        self.running = false;
+        self.release_resources();
     }
 
     fn handle_msg(&mut self, msg: SessionMsg) -> bool {
         log::debug!("processing {:?}", msg);
         match msg {
             SessionMsg::SetEventDest(dest) => {
-                self.device.set_event_dest(dest);
+                self.event_dest = Some(dest.clone());
+                match self.event_relay {
+                    Some(ref relay) => {
+                        relay.set_content_dest(dest);
+                        self.device.set_event_dest(relay.sender());
+                    }
+                    None => self.device.set_event_dest(dest),
+                }
+                // Devices only report changes, so this is sent after any events they buffered
+                // for the state to start out right
+                self.send_event(Event::VisibilityChange(self.device.visibility()));
+            }
+            SessionMsg::SetUrgentEventDest(dest) => match self.event_relay() {
+                Ok(relay) => relay.set_urgent_dest(dest),
+                Err(e) => log::warn!("Couldn't set the urgent event dest: {:?}", e),
+            },
+            SessionMsg::AddObserver(frames, events, whole_frames) => {
+                let _ = events.send(Event::VisibilityChange(self.device.visibility()));
+                match self.event_relay() {
+                    Ok(relay) => relay.add_observer(events),
+                    Err(e) => {
+                        log::warn!("Couldn't add a session observer: {:?}", e);
+                        return true;
+                    }
+                }
+                self.frame_observers.push((frames, whole_frames));
             }
-            SessionMsg::RequestHitTest(source) => {
-                self.device.request_hit_test(source);
+            SessionMsg::RequestHitTest(mut source) => {
+                if self.device.supports_hit_test() {
+                    source.space = self.space_to_device(source.space);
+                    self.device.request_hit_test(source);
+                } else {
+                    log::warn!("Dropping hit test request on a device without hit tests");
+                }
             }
             SessionMsg::CancelHitTest(id) => {
-                self.device.cancel_hit_test(id);
+                if self.device.supports_hit_test() {
+                    self.device.cancel_hit_test(id);
+                }
             }
             SessionMsg::CreateLayer(context_id, layer_init, sender) => {
-                let result = self.device.create_layer(context_id, layer_init);
+                let result = self
+                    .device
+                    .layer_limits()
+                    .check_layer_count(self.created_layers.len() + 1)
+                    .and_then(|()| self.device.create_layer(context_id, layer_init));
+                if let Ok(layer_id) = result {
+                    self.created_layers.push(layer_id);
+                }
                 let _ = sender.send(result);
             }
             SessionMsg::DestroyLayer(context_id, layer_id) => {
+                self.created_layers.retain(|&other_id| layer_id != other_id);
                 self.layers.retain(|&(_, other_id)| layer_id != other_id);
                 self.device.destroy_layer(context_id, layer_id);
             }
             SessionMsg::SetLayers(layers) => {
                 self.pending_layers = Some(layers);
             }
-            SessionMsg::StartRenderLoop => {
-                if let Some(layers) = self.pending_layers.take() {
-                    self.layers = layers;
-                }
-                let frame = match self.device.begin_animation_frame(&self.layers[..]) {
-                    Some(frame) => frame,
-                    None => {
-                        warn!("Device stopped providing frames, exiting");
-                        return false;
+            SessionMsg::MigrateContext(old_context_id, new_context_id, sender) => {
+                let result = self.device.migrate_context(old_context_id, new_context_id);
+                if result.is_ok() {
+                    let pending_layers = self.pending_layers.iter_mut().flatten();
+                    for (context_id, _) in self.layers.iter_mut().chain(pending_layers) {
+                        if *context_id == old_context_id {
+                            *context_id = new_context_id;
+                        }
                     }
-                };
+                }
+                let _ = sender.send(result);
+            }
+            SessionMsg::SetFoveationLevel(context_id, layer_id, level) => {
+                self.device.set_foveation_level(context_id, layer_id, level);
+            }
+            SessionMsg::SetLayerQuality(context_id, layer_id, quality) => {
+                self.device.set_layer_quality(context_id, layer_id, quality);
+            }
+            SessionMsg::SetLayerGeometry(context_id, layer_id, geometry) => {
+                self.device
+                    .set_layer_geometry(context_id, layer_id, geometry);
+            }
+            SessionMsg::AttachLayer(context_id, layer_id, space) => {
+                let space = self.space_to_device(space);
+                self.device.attach_layer(context_id, layer_id, space);
+            }
+            SessionMsg::StartRenderLoop => {
                 self.render_state = RenderState::InRenderLoop;
-                let _ = self.frame_sender.send(frame);
+                if self.paused {
+                    self.frame_pending = true;
+                } else if !self.frame_in_flight {
+                    // Content never has more than one frame waiting for it
+                    return self.begin_frame();
+                }
             }
             SessionMsg::UpdateClipPlanes(near, far) => self.device.update_clip_planes(near, far),
             SessionMsg::RenderAnimationFrame => {
+                let frame_index = self.frame_count;
                 self.frame_count += 1;
 
+                let start = Instant::now();
                 self.device.end_animation_frame(&self.layers[..]);
+                let frame_time = self.stats.ended(start.elapsed());
+                // Not every device knows its display period, so fall back to measuring it
+                let period = self.stats.period().or(self.stats.frame_interval());
+                if let (Some(governor), Some(frame_time), Some(period)) =
+                    (self.governor.as_mut(), frame_time, period)
+                {
+                    governor.frame_ended(frame_time, period, &mut self.device);
+                }
+                if let Some(ref dest) = self.latency_dest {
+                    let _ = dest.send(FrameSubmission {
+                        frame_index,
+                        submitted: SystemTime::now(),
+                    });
+                }
+                self.frame_in_flight = false;
 
                 if self.render_state == RenderState::PendingQuit {
                     self.quit();
                     return false;
                 }
 
-                if let Some(layers) = self.pending_layers.take() {
-                    self.layers = layers;
+                if self.paused {
+                    // The pause was requested while this frame was in flight
+                    self.device.pause();
+                    self.frame_pending = true;
+                } else {
+                    return self.begin_frame();
                 }
-                #[allow(unused_mut)]
-                let mut frame = match self.device.begin_animation_frame(&self.layers[..]) {
-                    Some(frame) => frame,
-                    None => {
-                        warn!("Device stopped providing frames, exiting");
-                        return false;
+            }
+            SessionMsg::Pause => {
+                if !self.paused {
+                    self.paused = true;
+                    self.stats.interrupt();
+                    // If a frame is in flight, the device is paused once it has ended
+                    if !self.frame_in_flight {
+                        self.device.pause();
                     }
-                };
-
-                let _ = self.frame_sender.send(frame);
+                }
+            }
+            SessionMsg::Resume => {
+                if self.paused {
+                    self.paused = false;
+                    if !self.frame_in_flight {
+                        self.device.resume();
+                    }
+                    if self.frame_pending {
+                        self.frame_pending = false;
+                        return self.begin_frame();
+                    }
+                }
             }
             SessionMsg::UpdateFrameRate(rate, sender) => {
+                if let Some(ref mut governor) = self.governor {
+                    governor.frame_rate_updated();
+                }
                 let new_framerate = self.device.update_frame_rate(rate);
                 let _ = sender.send(new_framerate);
             }
+            SessionMsg::UpdateTargetFrameRate(rate) => {
+                if let Some(ref mut governor) = self.governor {
+                    governor.frame_rate_updated();
+                }
+                self.device.update_frame_rate(rate);
+            }
+            SessionMsg::TriggerHaptic(id, intensity, duration) => {
+                self.device.trigger_haptic(id, intensity, duration);
+            }
+            SessionMsg::RequestViewportScale(view, scale) => {
+                if self.viewport_scales.len() <= view {
+                    self.viewport_scales.resize(view + 1, 1.0);
+                }
+                self.viewport_scales[view] = scale;
+                match self.governor {
+                    Some(ref mut governor) => {
+                        governor.request_viewport_scale(view, scale, &mut self.device)
+                    }
+                    None => self.device.request_viewport_scale(view, scale),
+                }
+            }
+            SessionMsg::SetResolutionGovernor(options) => {
+                if let Some(mut governor) = self.governor.take() {
+                    governor.reset(&mut self.device);
+                }
+                self.governor =
+                    options.map(|options| ResolutionGovernor::new(options, &self.viewport_scales));
+            }
+            SessionMsg::SetPoseGraphDest(dest) => {
+                self.pose_graph_dest = dest;
+            }
+            SessionMsg::SetLatencyDest(dest) => {
+                if dest.is_none() {
+                    self.device.set_latency_marker(None);
+                }
+                self.latency_dest = dest;
+            }
+            SessionMsg::RecordUsage(options, dest) => {
+                self.usage = Some(UsageRecorder::new(options, dest));
+            }
+            SessionMsg::ReportResources(options, dest) => {
+                let sender = self.sender.clone();
+                let due = move || sender.send(SessionMsg::ResourceReportDue).is_ok();
+                self.soak = Some(SoakMonitor::new(options, dest, due));
+            }
+            SessionMsg::ResourceReportDue => {
+                if let Some(ref mut soak) = self.soak {
+                    let resources = self.device.resources();
+                    if !soak.report(self.frame_count, self.layers.len(), resources) {
+                        self.soak = None;
+                    }
+                }
+            }
+            SessionMsg::SetFrameSubscriptions(subscriptions) => {
+                self.frame_subscriptions = subscriptions;
+                self.device.set_frame_subscriptions(subscriptions);
+            }
+            SessionMsg::SetDwellSelect(options) => {
+                self.device.set_dwell_select(options);
+            }
+            SessionMsg::SetSelectArbitration(options) => {
+                self.device.set_select_arbitration(options);
+            }
+            SessionMsg::SetMonoRendering(mono) => self.device.set_mono_rendering(mono),
+            SessionMsg::SetFramePacing(pacing) => self.pacer.set_pacing(pacing),
+            SessionMsg::Comfort(msg) => self.handle_comfort_msg(msg),
             SessionMsg::Quit => {
-                if self.render_state == RenderState::NotInRenderLoop {
+                // A paused render loop has no frame in flight to finish first
+                if self.render_state == RenderState::NotInRenderLoop || !self.frame_in_flight {
                     self.quit();
                     return false;
                 } else {
                     self.render_state = RenderState::PendingQuit;
                 }
             }
+            SessionMsg::Shutdown => {
+                self.quit();
+                return false;
+            }
             SessionMsg::GetBoundsGeometry(sender) => {
                 let bounds = self.device.reference_space_bounds();
                 let _ = sender.send(bounds);
             }
+            SessionMsg::GetFrameStats(sender) => {
+                let mut stats = self.stats.take();
+                stats.texture_memory = self.device.resources().texture_memory;
+                let _ = sender.send(stats);
+            }
+            SessionMsg::GetVisibility(sender) => {
+                let _ = sender.send(self.device.visibility());
+            }
+            SessionMsg::GetPose(space, base, sender) => {
+                let floor_transform = self.device.floor_transform();
+                let _ = sender.send(self.space_poses.pose(&space, &base, floor_transform));
+            }
+        }
+        true
+    }
+
+    fn handle_comfort_msg(&mut self, msg: ComfortMsg) {
+        match msg {
+            ComfortMsg::SetOptions(options) => self.vignette.options = options,
+            ComfortMsg::SetLocomotion(locomotion) => self.vignette.set_locomotion(locomotion),
+            ComfortMsg::SnapTurn(angle) => {
+                let viewer_position = self.viewer_position;
+                let floor_transform = self.device.floor_transform();
+                let (changes, reorientation) = match self.event_relay() {
+                    Ok(relay) => (
+                        relay.turn(angle, viewer_position, floor_transform),
+                        relay.reorientation(),
+                    ),
+                    Err(e) => {
+                        log::warn!("Couldn't snap turn: {:?}", e);
+                        return;
+                    }
+                };
+                self.device.set_reorientation(reorientation);
+                for (space, transform) in changes {
+                    self.send_event(Event::ReferenceSpaceChanged(space, transform));
+                }
+            }
+        }
+    }
+
+    /// Begin a frame and send it to content, returning false if the device has stopped
+    fn begin_frame(&mut self) -> bool {
+        if let Some(layers) = self.pending_layers.take() {
+            self.layers = layers;
         }
+        let start = Instant::now();
+        let frame = match self.device.begin_animation_frame(&self.layers[..]) {
+            Some(frame) => frame,
+            None => {
+                warn!("Device stopped providing frames, exiting");
+                return false;
+            }
+        };
+        let period = frame
+            .predicted_display_period
+            .map(|period| Duration::from_nanos(period as u64));
+        self.stats.began(start.elapsed(), period);
+        self.frame_in_flight = true;
+        self.send_frame(frame);
         true
     }
 
+    fn send_frame(&mut self, mut frame: Frame) {
+        self.frame_subscriptions.filter(&mut frame);
+        if let Some(ref relay) = self.event_relay {
+            relay.map_frame(&mut frame);
+        }
+        if let Some(ref pose) = frame.pose {
+            self.viewer_position = Some(pose.transform.translation);
+        }
+        self.space_poses.update(&frame);
+        if let Some(ref mut usage) = self.usage {
+            usage.record(&frame);
+        }
+        let strength = self.vignette.strength(&frame);
+        self.device.set_vignette(strength);
+        if self.latency_dest.is_some() {
+            // This frame is ended before the frame count goes up
+            self.device.set_latency_marker(Some(LatencyMarker {
+                frame_index: self.frame_count,
+            }));
+        }
+        if let Some(ref dest) = self.pose_graph_dest {
+            let floor_transform = self.device.floor_transform();
+            let _ = dest.send(PoseGraph::from_frame(&frame, floor_transform));
+        }
+        frame.render_budget = self
+            .stats
+            .render_budget()
+            .map(|budget| budget.as_nanos() as f64);
+        if !self.frame_observers.is_empty() {
+            let observed = frame.for_event();
+            let whole = Frame {
+                sub_images: vec![],
+                ..frame.clone()
+            };
+            self.frame_observers
+                .retain(|&(ref observer, whole_frames)| {
+                    let frame = if whole_frames { &whole } else { &observed };
+                    observer.send(frame.clone()).is_ok()
+                });
+        }
+        self.stats.sent();
+        let _ = self.frame_sender.send(frame);
+    }
+
+    /// The relay between the device and content, which is set up when it is first needed
+    fn event_relay(&mut self) -> Result<&EventRelay, Error> {
+        if self.event_relay.is_none() {
+            let relay = EventRelay::new()?;
+            // Until content has somewhere for events to go, the device holds on to them
+            if let Some(ref dest) = self.event_dest {
+                relay.set_content_dest(dest.clone());
+                self.device.set_event_dest(relay.sender());
+            }
+            self.event_relay = Some(relay);
+        }
+        Ok(self.event_relay.as_ref().unwrap())
+    }
+
+    /// Map the buttons and axes of inputs before they get to content. The relay sees inputs
+    /// come and go, so this is done before the device has a chance to send any events.
+    pub(crate) fn set_input_mapping(&mut self, mapping: InputMapping) {
+        if mapping.is_empty() {
+            return;
+        }
+        let inputs = self.device.initial_inputs();
+        match self.event_relay() {
+            Ok(relay) => relay.set_input_mapper(InputMapper::new(mapping, &inputs)),
+            Err(e) => log::warn!("Couldn't map session inputs: {:?}", e),
+        }
+    }
+
+    /// Undo any snap turns for a space content created, before the device sees it
+    fn space_to_device(&self, space: Space) -> Space {
+        match self.event_relay {
+            Some(ref relay) => relay.space_to_device(space, self.device.floor_transform()),
+            None => space,
+        }
+    }
+
+    /// Send an event from the session thread itself to content and any observers
+    fn send_event(&self, event: Event) {
+        match self.event_relay {
+            Some(ref relay) => relay.send(event),
+            None => {
+                if let Some(ref dest) = self.event_dest {
+                    let _ = dest.send(event);
+                }
+            }
+        }
+    }
+
+    /// End the session after the device panicked. The device is in an unknown state,
+    /// so its resources aren't released, it is only dropped.
+    fn end_with_error(&mut self, error: Error) {
+        self.running = false;
+        self.send_event(Event::SessionError(error));
+        self.send_event(Event::SessionEnd);
+    }
+
     fn quit(&mut self) {
         self.render_state = RenderState::NotInRenderLoop;
         self.device.quit();
     }
+
+    /// Called once the session has stopped running, to drop the layers
+    /// and let the device release its resources.
+    fn release_resources(&mut self) {
+        self.layers.clear();
+        self.pending_layers = None;
+        self.created_layers.clear();
+        // This sends the usage summary
+        self.usage = None;
+        self.soak = None;
+        self.device.shutdown();
+    }
 }
 
 /// Devices that need to can run sessions on the main thread.
 pub trait MainThreadSession: 'static {
     fn run_one_frame(&mut self);
     fn running(&self) -> bool;
+
+    /// End the session and release its resources, whatever state it is in.
+    fn shutdown(&mut self) {}
+
+    /// Suspend the session without ending it, see `Session::pause`
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    /// Change the comfort features of the session, see `Session::set_comfort_options`
+    fn send_comfort_msg(&mut self, _msg: ComfortMsg) {}
+
+    /// The id of the session, if it was built by a `SessionBuilder`
+    fn id(&self) -> Option<SessionId> {
+        None
+    }
 }
 
 impl<Device> MainThreadSession for SessionThread<Device>
@@ -447,18 +1243,174 @@ where
 {
     fn run_one_frame(&mut self) {
         let frame_count = self.frame_count;
+        let start = Instant::now();
         while frame_count == self.frame_count && self.running {
-            if let Ok(msg) = crate::recv_timeout(&self.receiver, TIMEOUT) {
+            // Content doesn't render frames for sessions that are paused or hidden
+            let rendering = self.frame_in_flight
+                && !self.paused
+                && self.device.visibility() != Visibility::Hidden;
+            let msg = match self.pacer.timeout(rendering) {
+                Some(timeout) => crate::recv_timeout(&self.receiver, timeout).ok(),
+                None => self.receiver.recv().ok(),
+            };
+            if let Some(msg) = msg {
                 self.running = self.handle_msg(msg);
+                if !self.running {
+                    self.release_resources();
+                }
             } else {
                 break;
             }
         }
+        self.pacer
+            .waited(start.elapsed(), frame_count != self.frame_count);
     }
 
     fn running(&self) -> bool {
         self.running
     }
+
+    fn shutdown(&mut self) {
+        if self.running {
+            self.running = false;
+            self.quit();
+            self.release_resources();
+        }
+    }
+
+    fn pause(&mut self) {
+        let _ = self.sender.send(SessionMsg::Pause);
+    }
+
+    fn resume(&mut self) {
+        let _ = self.sender.send(SessionMsg::Resume);
+    }
+
+    fn send_comfort_msg(&mut self, msg: ComfortMsg) {
+        let _ = self.sender.send(SessionMsg::Comfort(msg));
+    }
+
+    fn id(&self) -> Option<SessionId> {
+        Some(self.id)
+    }
+}
+
+/// A handle to a session running on its own thread, which the registry
+/// uses to shut the session down and join its thread.
+pub(crate) struct SessionThreadHandle {
+    id: SessionId,
+    sender: Sender<SessionMsg>,
+    done: mpsc::Receiver<()>,
+    join_handle: JoinHandle<()>,
+}
+
+impl SessionThreadHandle {
+    pub(crate) fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.join_handle.is_finished()
+    }
+
+    pub(crate) fn request_shutdown(&self) {
+        let _ = self.sender.send(SessionMsg::Shutdown);
+    }
+
+    pub(crate) fn pause(&self) {
+        let _ = self.sender.send(SessionMsg::Pause);
+    }
+
+    pub(crate) fn resume(&self) {
+        let _ = self.sender.send(SessionMsg::Resume);
+    }
+
+    pub(crate) fn send_comfort_msg(&self, msg: ComfortMsg) {
+        let _ = self.sender.send(SessionMsg::Comfort(msg));
+    }
+
+    /// Wait until `deadline` for the session thread to exit. If it doesn't,
+    /// the thread is detached, since there's no way to safely stop it.
+    pub(crate) fn join(self, deadline: Instant) {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.done.recv_timeout(timeout) {
+            Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                let _ = self.join_handle.join();
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                warn!("Session thread did not shut down in time, detaching it");
+            }
+        }
+    }
+}
+
+/// A session thread whose device is still creating the session. Messages sent
+/// to it are handled once the session has started.
+pub(crate) struct SpawnedThread {
+    sender: Sender<SessionMsg>,
+    done: mpsc::Receiver<()>,
+    join_handle: JoinHandle<()>,
+}
+
+impl SpawnedThread {
+    pub(crate) fn into_handle(self, id: SessionId) -> SessionThreadHandle {
+        SessionThreadHandle {
+            id,
+            sender: self.sender,
+            done: self.done,
+            join_handle: self.join_handle,
+        }
+    }
+}
+
+/// A session that a device has created, or is creating on a thread of its own,
+/// see `DiscoveryAPI::request_session`
+pub struct PendingSession {
+    pub(crate) state: PendingSessionState,
+}
+
+pub(crate) enum PendingSessionState {
+    Created(Session),
+    /// The registry is told how it went with a `SessionCreationNotifier`
+    Spawned(SpawnedThread),
+}
+
+/// How far a device has got with creating a session, for embedders to show while the user
+/// waits, see `Registry::request_session_with_progress`. Devices that are quick to create
+/// sessions may not report any of these.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum SessionProgress {
+    /// The runtime the device belongs to has started up
+    InstanceCreated,
+    /// The runtime has begun the session
+    SessionBegun,
+    /// The swapchains that content renders to are ready
+    SwapchainReady,
+}
+
+/// Reports the progress of creating a session, from whichever thread does the work,
+/// see `SessionBuilder::progress_reporter`
+#[derive(Clone)]
+pub struct SessionProgressReporter {
+    sender: Option<Sender<SessionProgress>>,
+}
+
+impl SessionProgressReporter {
+    pub fn report(&self, progress: SessionProgress) {
+        if let Some(ref sender) = self.sender {
+            let _ = sender.send(progress);
+        }
+    }
+}
+
+/// What the registry gives every session it asks a device for, see `SessionBuilder`
+pub(crate) struct SessionSettings<GL> {
+    pub(crate) layer_grand_manager: LayerGrandManager<GL>,
+    pub(crate) device_panic_policy: DevicePanicPolicy,
+    pub(crate) input_mapping: InputMapping,
+    pub(crate) event_buffer_options: EventBufferOptions,
+    pub(crate) notifier: SessionCreationNotifier,
 }
 
 /// A type for building XR sessions
@@ -466,7 +1418,13 @@ pub struct SessionBuilder<'a, GL> {
     sessions: &'a mut Vec<Box<dyn MainThreadSession>>,
     frame_sender: Sender<Frame>,
     layer_grand_manager: LayerGrandManager<GL>,
+    device_panic_policy: DevicePanicPolicy,
+    input_mapping: InputMapping,
+    event_buffer_options: EventBufferOptions,
     id: SessionId,
+    cancel: CancellationToken,
+    progress: Option<Sender<SessionProgress>>,
+    notifier: SessionCreationNotifier,
 }
 
 impl<'a, GL: 'static> SessionBuilder<'a, GL> {
@@ -476,47 +1434,99 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
 
     pub(crate) fn new(
         sessions: &'a mut Vec<Box<dyn MainThreadSession>>,
+        settings: SessionSettings<GL>,
         frame_sender: Sender<Frame>,
-        layer_grand_manager: LayerGrandManager<GL>,
         id: SessionId,
+        cancel: CancellationToken,
+        progress: Option<Sender<SessionProgress>>,
     ) -> Self {
         SessionBuilder {
             sessions,
             frame_sender,
-            layer_grand_manager,
+            layer_grand_manager: settings.layer_grand_manager,
+            device_panic_policy: settings.device_panic_policy,
+            input_mapping: settings.input_mapping,
+            event_buffer_options: settings.event_buffer_options,
             id,
+            cancel,
+            progress,
+            notifier: settings.notifier,
+        }
+    }
+
+    /// Whether the request was cancelled with `SessionRequestHandle::cancel`, which can
+    /// be moved into the device's factory. Backends that take a while to set up on a thread
+    /// of their own can check this between steps, and give up with `Error::RequestCancelled`
+    /// once they have torn down what they set up so far.
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// The buffer that the device should send events to, which holds on to them
+    /// until content has somewhere to send them, as the registry was configured to
+    pub fn event_buffer(&self) -> EventBuffer {
+        EventBuffer::new(self.event_buffer_options)
+    }
+
+    /// Something to report progress with, which can be moved into the device's factory
+    pub fn progress_reporter(&self) -> SessionProgressReporter {
+        SessionProgressReporter {
+            sender: self.progress.clone(),
         }
     }
 
     /// For devices which are happy to hand over thread management to webxr.
-    pub fn spawn<Device, Factory>(self, factory: Factory) -> Result<Session, Error>
+    /// The device is created on its thread, without blocking the registry,
+    /// which answers the session request once it is.
+    pub fn spawn<Device, Factory>(self, factory: Factory) -> Result<PendingSession, Error>
     where
         Factory: 'static + FnOnce(LayerGrandManager<GL>) -> Result<Device, Error> + Send,
         Device: DeviceAPI,
     {
-        let (acks, ackr) = crate::channel().or(Err(Error::CommunicationError))?;
+        let notifier = self.notifier;
         let frame_sender = self.frame_sender;
         let layer_grand_manager = self.layer_grand_manager;
+        let policy = self.device_panic_policy;
+        let input_mapping = self.input_mapping;
         let id = self.id;
-        thread::spawn(move || {
-            match factory(layer_grand_manager)
-                .and_then(|device| SessionThread::new(device, frame_sender, id))
-            {
+        // The channel is made up front, so that the session can be shut down before it starts
+        let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
+        let thread_sender = sender.clone();
+        let (done_sender, done) = mpsc::channel();
+        let join_handle = thread::spawn(move || {
+            match policy
+                .run(id, || factory(layer_grand_manager))
+                .and_then(|device| device)
+                .and_then(|device| {
+                    SessionThread::with_channel(device, frame_sender, id, thread_sender, receiver)
+                }) {
                 Ok(mut thread) => {
-                    let session = thread.new_session();
-                    let _ = acks.send(Ok(session));
-                    thread.run();
-                }
-                Err(err) => {
-                    let _ = acks.send(Err(err));
+                    thread.set_input_mapping(input_mapping);
+                    notifier.created(id, Ok(thread.new_session()));
+                    if let Err(error) = policy.run(id, || thread.run()) {
+                        thread.end_with_error(error);
+                        // The device may panic again while it is dropped
+                        let _ = policy.run(id, move || drop(thread));
+                    }
                 }
+                Err(err) => notifier.created(id, Err(err)),
             }
+            let _ = done_sender.send(());
         });
-        ackr.recv().unwrap_or(Err(Error::CommunicationError))
+        Ok(PendingSession {
+            state: PendingSessionState::Spawned(SpawnedThread {
+                sender,
+                done,
+                join_handle,
+            }),
+        })
     }
 
     /// For devices that need to run on the main thread.
-    pub fn run_on_main_thread<Device, Factory>(self, factory: Factory) -> Result<Session, Error>
+    pub fn run_on_main_thread<Device, Factory>(
+        self,
+        factory: Factory,
+    ) -> Result<PendingSession, Error>
     where
         Factory: 'static + FnOnce(LayerGrandManager<GL>) -> Result<Device, Error>,
         Device: DeviceAPI,
@@ -524,8 +1534,11 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
         let device = factory(self.layer_grand_manager)?;
         let frame_sender = self.frame_sender;
         let mut session_thread = SessionThread::new(device, frame_sender, self.id)?;
+        session_thread.set_input_mapping(self.input_mapping);
         let session = session_thread.new_session();
         self.sessions.push(Box::new(session_thread));
-        Ok(session)
+        Ok(PendingSession {
+            state: PendingSessionState::Created(session),
+        })
     }
 }
@@ -0,0 +1,183 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Reporting the resources a session holds on to, for sessions that run for hours
+//! (such as kiosks), so that memory creep can be noticed and attributed in the field.
+//!
+//! Allocations are only counted if the embedder installs `CountingAllocator`
+//! as its global allocator.
+
+use crate::Sender;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+/// How often a session reports its resources, see `Session::report_resources`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct SoakOptions {
+    /// The time between reports, in minutes. Intervals that aren't a positive
+    /// number of minutes are replaced with the default.
+    pub interval: f64,
+}
+
+impl Default for SoakOptions {
+    fn default() -> SoakOptions {
+        SoakOptions { interval: 10.0 }
+    }
+}
+
+/// What a device holds on to, for `ResourceReport`. Devices leave out what they don't know.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceResources {
+    /// The number of swapchains of the device's layers
+    pub swapchains: Option<usize>,
+    /// An estimate of the texture memory of the device's layers, in bytes
+    pub texture_memory: Option<u64>,
+    /// The number of events the device is holding on to, until it has somewhere to send them
+    pub buffered_events: Option<usize>,
+}
+
+/// The resources a session held on to when the report was made
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceReport {
+    /// How long the session has been reporting, in seconds
+    pub uptime: f64,
+    /// The number of frames the session has rendered
+    pub frames: u64,
+    /// The number of layers content is rendering
+    pub layers: usize,
+    pub device: DeviceResources,
+    /// The number of bytes allocated by the whole process, if `CountingAllocator` is in use
+    pub allocated: Option<isize>,
+    /// How much `allocated` changed since the last report
+    pub allocated_delta: Option<isize>,
+}
+
+/// A global allocator that counts how much memory is allocated, for `ResourceReport`
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            counted(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            counted(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        counted(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            counted(new_size as isize - layout.size() as isize);
+        }
+        new_ptr
+    }
+}
+
+fn counted(bytes: isize) {
+    ALLOCATED.fetch_add(bytes, Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+}
+
+/// The number of bytes currently allocated, if `CountingAllocator` is in use
+fn allocated() -> Option<isize> {
+    if COUNTING.load(Ordering::Relaxed) {
+        Some(ALLOCATED.load(Ordering::Relaxed))
+    } else {
+        None
+    }
+}
+
+/// Sends a `ResourceReport` every interval, until the receiver goes away
+pub(crate) struct SoakMonitor {
+    dest: Sender<ResourceReport>,
+    start: Instant,
+    last_allocated: Option<isize>,
+    /// Tells the timer thread to stop, once the monitor is dropped
+    stopped: Arc<AtomicBool>,
+}
+
+impl SoakMonitor {
+    /// Start monitoring, calling `due` from a timer thread every interval, so that
+    /// reports are made even while the session is idle. The session should call
+    /// `report` when it is told a report is due, and `due` should return false once
+    /// the session has gone away.
+    pub(crate) fn new<F>(options: SoakOptions, dest: Sender<ResourceReport>, due: F) -> SoakMonitor
+    where
+        F: 'static + Send + Fn() -> bool,
+    {
+        let interval = if options.interval.is_finite() && options.interval > 0.0 {
+            options.interval
+        } else {
+            log::warn!(
+                "Ignoring a resource report interval of {} minutes",
+                options.interval
+            );
+            SoakOptions::default().interval
+        };
+        let interval = Duration::from_secs_f64(interval * 60.0);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let timer_stopped = stopped.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if timer_stopped.load(Ordering::Relaxed) || !due() {
+                break;
+            }
+        });
+        SoakMonitor {
+            dest,
+            start: Instant::now(),
+            last_allocated: allocated(),
+            stopped,
+        }
+    }
+
+    /// Send a report, returning false if nobody is listening any more
+    pub(crate) fn report(&mut self, frames: u64, layers: usize, device: DeviceResources) -> bool {
+        let allocated = allocated();
+        let allocated_delta = match (allocated, self.last_allocated) {
+            (Some(allocated), Some(last_allocated)) => Some(allocated - last_allocated),
+            _ => None,
+        };
+        self.last_allocated = allocated;
+        let report = ResourceReport {
+            uptime: self.start.elapsed().as_secs_f64(),
+            frames,
+            layers,
+            device,
+            allocated,
+            allocated_delta,
+        };
+        log::debug!("Session resources: {:?}", report);
+        self.dest.send(report).is_ok()
+    }
+}
+
+impl Drop for SoakMonitor {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -1,5 +1,10 @@
+use crate::Floor;
+use crate::Frame;
+use crate::InputFrame;
 use crate::InputId;
 use crate::Joint;
+use crate::Native;
+use crate::Viewer;
 use euclid::RigidTransform3D;
 
 #[derive(Clone, Copy, Debug)]
@@ -26,3 +31,47 @@ pub struct Space {
     pub base: BaseSpace,
     pub offset: RigidTransform3D<f32, ApiSpace, ApiSpace>,
 }
+
+/// Where the base spaces were as of the latest frame, for `Session::request_pose`
+#[derive(Default)]
+pub(crate) struct SpacePoses {
+    viewer: Option<RigidTransform3D<f32, Viewer, Native>>,
+    inputs: Vec<InputFrame>,
+}
+
+impl SpacePoses {
+    pub(crate) fn update(&mut self, frame: &Frame) {
+        self.viewer = frame.pose.as_ref().map(|pose| pose.transform);
+        self.inputs = frame.inputs.clone();
+    }
+
+    /// The pose of `space` relative to `base`, if both are being tracked
+    pub(crate) fn pose(
+        &self,
+        space: &Space,
+        base: &Space,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> Option<RigidTransform3D<f32, ApiSpace, ApiSpace>> {
+        let space_to_native = self.native_origin(space, floor_transform)?;
+        let base_to_native = self.native_origin(base, floor_transform)?;
+        Some(space_to_native.then(&base_to_native.inverse()))
+    }
+
+    /// The transform from `space` to native coordinates
+    fn native_origin(
+        &self,
+        space: &Space,
+        floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    ) -> Option<RigidTransform3D<f32, ApiSpace, Native>> {
+        let input = |id| self.inputs.iter().find(|input| input.id == id);
+        let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
+            BaseSpace::Local => RigidTransform3D::identity(),
+            BaseSpace::Floor | BaseSpace::BoundedFloor => floor_transform?.inverse().cast_unit(),
+            BaseSpace::Viewer => self.viewer?.cast_unit(),
+            BaseSpace::TargetRay(id) => input(id)?.target_ray_origin?.cast_unit(),
+            BaseSpace::Grip(id) => input(id)?.grip_origin?.cast_unit(),
+            BaseSpace::Joint(id, joint) => input(id)?.hand.as_ref()?.get(joint)?.pose.cast_unit(),
+        };
+        Some(space.offset.then(&origin))
+    }
+}
@@ -0,0 +1,130 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::time::{Duration, Instant};
+
+// How much each frame's submit time counts towards the recent submit time
+const SUBMIT_TIME_WEIGHT: f64 = 0.1;
+
+// How much the time between each pair of frames counts towards the frame interval
+const INTERVAL_WEIGHT: f64 = 0.1;
+
+/// Timings of a session's render loop since the last `Session::request_stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameStats {
+    /// The number of frames that content rendered
+    pub frames: u64,
+    /// The number of display periods that went by without a new frame
+    pub dropped_frames: u64,
+    /// The average time spent waiting for the device to begin a frame
+    pub wait_time: Duration,
+    /// The average time between sending a frame to content and content finishing it
+    pub render_time: Duration,
+    /// The average time the device took to end a frame and submit its layers
+    pub submit_time: Duration,
+    /// How often the device expects to display a frame, if it knows
+    pub predicted_display_period: Option<Duration>,
+    /// An estimate of the texture memory of the session's layers when the stats
+    /// were requested, in bytes, if the device knows
+    pub texture_memory: Option<u64>,
+}
+
+/// Collects the timings for `FrameStats` as the session thread runs its render loop
+#[derive(Default)]
+pub(crate) struct FrameStatsRecorder {
+    stats: FrameStats,
+    last_began: Option<Instant>,
+    sent: Option<Instant>,
+    /// A moving average of how long the device has taken to submit frames
+    recent_submit_time: Option<Duration>,
+    /// A moving average of the time between frames beginning
+    interval: Option<Duration>,
+}
+
+impl FrameStatsRecorder {
+    /// The device began a frame after waiting for `wait_time`
+    pub(crate) fn began(&mut self, wait_time: Duration, period: Option<Duration>) {
+        let now = Instant::now();
+        self.stats.wait_time += wait_time;
+        self.stats.predicted_display_period = period;
+        if let Some(last_began) = self.last_began {
+            let interval = now.duration_since(last_began);
+            self.interval = Some(match self.interval {
+                Some(recent) => {
+                    recent.mul_f64(1.0 - INTERVAL_WEIGHT) + interval.mul_f64(INTERVAL_WEIGHT)
+                }
+                None => interval,
+            });
+            if let Some(period) = period {
+                let periods = interval.as_secs_f64() / period.as_secs_f64();
+                // Allow for some jitter before counting a frame as dropped
+                if periods > 1.5 {
+                    self.stats.dropped_frames += periods.round() as u64 - 1;
+                }
+            }
+        }
+        self.last_began = Some(now);
+    }
+
+    pub(crate) fn sent(&mut self) {
+        self.sent = Some(Instant::now());
+    }
+
+    /// Content finished the frame, which the device took `submit_time` to end.
+    /// Returns how long content and the device spent on the frame, if it was sent.
+    pub(crate) fn ended(&mut self, submit_time: Duration) -> Option<Duration> {
+        let render_time = self.sent.take().map(|sent| sent.elapsed());
+        if let Some(render_time) = render_time {
+            self.stats.render_time += render_time;
+        }
+        self.stats.submit_time += submit_time;
+        self.stats.frames += 1;
+        self.recent_submit_time = Some(match self.recent_submit_time {
+            Some(recent) => {
+                recent.mul_f64(1.0 - SUBMIT_TIME_WEIGHT) + submit_time.mul_f64(SUBMIT_TIME_WEIGHT)
+            }
+            None => submit_time,
+        });
+        render_time.map(|render_time| render_time + submit_time)
+    }
+
+    /// How often the device expects to display a frame, if it knows
+    pub(crate) fn period(&self) -> Option<Duration> {
+        self.stats.predicted_display_period
+    }
+
+    /// How long frames have recently been apart, for devices that don't know their period
+    pub(crate) fn frame_interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// How long content has left to render the frame that began last, see `Frame::render_budget`
+    pub(crate) fn render_budget(&self) -> Option<Duration> {
+        let period = self.stats.predicted_display_period?;
+        let spent = self.last_began?.elapsed() + self.recent_submit_time.unwrap_or_default();
+        Some(period.checked_sub(spent).unwrap_or_default())
+    }
+
+    /// Stop counting until the next frame begins, for example while the session is paused
+    pub(crate) fn interrupt(&mut self) {
+        self.last_began = None;
+    }
+
+    /// The stats since the last call, with the timings averaged over the frames
+    pub(crate) fn take(&mut self) -> FrameStats {
+        let mut stats = FrameStats {
+            predicted_display_period: self.stats.predicted_display_period,
+            ..FrameStats::default()
+        };
+        std::mem::swap(&mut stats, &mut self.stats);
+        if stats.frames > 0 {
+            let frames = stats.frames as u32;
+            stats.wait_time /= frames;
+            stats.render_time /= frames;
+            stats.submit_time /= frames;
+        }
+        stats
+    }
+}
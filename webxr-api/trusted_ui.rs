@@ -0,0 +1,44 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Prompts that are shown by the browser rather than content while a session is running.
+//! Content can draw anything, so these have to come from somewhere it can't imitate,
+//! and each backend shows them in its own way.
+
+use crate::Feature;
+
+/// Provides a way to spawn and interact with trusted prompts
+pub trait TrustedUiProvider: Send {
+    /// Open a prompt, return a way to poll for the result
+    fn open_prompt(&self, prompt: TrustedUiPrompt) -> Box<dyn TrustedUiFuture>;
+    /// Clone self as a trait object
+    fn clone_object(&self) -> Box<dyn TrustedUiProvider>;
+}
+
+/// A way to poll for the result of a prompt
+pub trait TrustedUiFuture {
+    fn poll(&self) -> TrustedUiResult;
+}
+
+/// What a prompt asks the user
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TrustedUiPrompt {
+    /// The context menu the user opens with the system gesture, which lets them leave the session
+    ExitMenu,
+    /// Whether content can use a feature, such as one that is privacy-sensitive
+    Permission(Feature),
+}
+
+/// The result of polling on a prompt
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrustedUiResult {
+    /// Session should exit
+    ExitSession,
+    /// The user agreed to a permission prompt
+    Granted,
+    /// Dialog was dismissed, which refuses a permission prompt
+    Dismissed,
+    /// User has not acted on dialog
+    Pending,
+}
@@ -0,0 +1,169 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Sampling how a session is used, for comfort and usability analytics.
+//!
+//! Only aggregates leave the session thread: which way the viewer faced, relative to
+//! where they started, and how much each input was used. No poses are kept.
+
+use crate::Frame;
+use crate::InputId;
+use crate::Sender;
+
+use euclid::Vector3D;
+
+use std::f32::consts::PI;
+use std::mem;
+
+// The number of buckets in `UsageSummary::yaw_samples`, each covering 30°
+const YAW_BUCKETS: usize = 12;
+
+// The number of buckets in `UsageSummary::pitch_samples`, each covering 30°
+const PITCH_BUCKETS: usize = 6;
+
+/// How a session's usage is sampled, see `Session::record_usage`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsageOptions {
+    /// How many frames to sample each second
+    pub sample_rate: f32,
+}
+
+impl Default for UsageOptions {
+    fn default() -> UsageOptions {
+        UsageOptions { sample_rate: 2.0 }
+    }
+}
+
+/// How a session was used while its usage was being recorded
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsageSummary {
+    /// The number of frames that were sampled
+    pub samples: u32,
+    /// How many samples the viewer was turned each way, relative to the first sample.
+    /// The first bucket starts at 180° to the left and the buckets go clockwise.
+    pub yaw_samples: [u32; YAW_BUCKETS],
+    /// How many samples the viewer was looking each way, from straight down to straight up
+    pub pitch_samples: [u32; PITCH_BUCKETS],
+    pub inputs: Vec<InputUsage>,
+}
+
+/// How much an input was used, see `UsageSummary`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputUsage {
+    pub id: InputId,
+    /// The number of samples the input was tracked in
+    pub tracked_samples: u32,
+    /// The number of times the input was pressed, counted every frame rather than sampled
+    pub presses: u32,
+    pub squeezes: u32,
+}
+
+/// Collects a `UsageSummary` from the frames of a session,
+/// and sends it when recording stops or the session ends
+pub(crate) struct UsageRecorder {
+    dest: Sender<UsageSummary>,
+    /// The time between samples, in nanoseconds
+    interval: f64,
+    last_sample: Option<f64>,
+    /// The yaw of the viewer in the first sample, in radians
+    first_yaw: Option<f32>,
+    summary: UsageSummary,
+    /// Whether each input was pressed and squeezed in the last frame
+    buttons: Vec<(InputId, bool, bool)>,
+}
+
+impl UsageRecorder {
+    pub(crate) fn new(options: UsageOptions, dest: Sender<UsageSummary>) -> UsageRecorder {
+        UsageRecorder {
+            dest,
+            interval: 1_000_000_000.0 / options.sample_rate.max(f32::EPSILON) as f64,
+            last_sample: None,
+            first_yaw: None,
+            summary: UsageSummary::default(),
+            buttons: vec![],
+        }
+    }
+
+    pub(crate) fn record(&mut self, frame: &Frame) {
+        let mut buttons = Vec::with_capacity(frame.inputs.len());
+        for input in &frame.inputs {
+            let (was_pressed, was_squeezed) = self
+                .buttons
+                .iter()
+                .find(|&&(id, ..)| id == input.id)
+                .map_or((false, false), |&(_, pressed, squeezed)| {
+                    (pressed, squeezed)
+                });
+            let usage = self.input_usage(input.id);
+            if input.pressed && !was_pressed {
+                usage.presses += 1;
+            }
+            if input.squeezed && !was_squeezed {
+                usage.squeezes += 1;
+            }
+            buttons.push((input.id, input.pressed, input.squeezed));
+        }
+        self.buttons = buttons;
+
+        let now = frame.predicted_display_time;
+        if self
+            .last_sample
+            .is_some_and(|last_sample| now - last_sample < self.interval)
+        {
+            return;
+        }
+        self.last_sample = Some(now);
+        self.summary.samples += 1;
+
+        if let Some(ref pose) = frame.pose {
+            let forward = pose
+                .transform
+                .rotation
+                .transform_vector3d(Vector3D::new(0., 0., -1.));
+            let yaw = forward.x.atan2(-forward.z);
+            let first_yaw = *self.first_yaw.get_or_insert(yaw);
+            // Wrap the yaw to [-π, π) relative to the first one
+            let yaw = (yaw - first_yaw + 3. * PI).rem_euclid(2. * PI) - PI;
+            let pitch = forward.y.clamp(-1., 1.).asin();
+            self.summary.yaw_samples[bucket(yaw + PI, 2. * PI, YAW_BUCKETS)] += 1;
+            self.summary.pitch_samples[bucket(pitch + PI / 2., PI, PITCH_BUCKETS)] += 1;
+        }
+        for input in &frame.inputs {
+            if input.target_ray_origin.is_some() {
+                self.input_usage(input.id).tracked_samples += 1;
+            }
+        }
+    }
+
+    fn input_usage(&mut self, id: InputId) -> &mut InputUsage {
+        let inputs = &mut self.summary.inputs;
+        let index = match inputs.iter().position(|usage| usage.id == id) {
+            Some(index) => index,
+            None => {
+                inputs.push(InputUsage {
+                    id,
+                    tracked_samples: 0,
+                    presses: 0,
+                    squeezes: 0,
+                });
+                inputs.len() - 1
+            }
+        };
+        &mut inputs[index]
+    }
+}
+
+impl Drop for UsageRecorder {
+    fn drop(&mut self) {
+        let _ = self.dest.send(mem::take(&mut self.summary));
+    }
+}
+
+/// Which of `count` buckets `value` falls in, when they evenly cover 0 to `range`
+fn bucket(value: f32, range: f32, count: usize) -> usize {
+    ((value / range * count as f32) as usize).min(count - 1)
+}
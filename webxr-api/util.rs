@@ -1,7 +1,33 @@
+use crate::DetectedMesh;
+use crate::Display;
+use crate::Error;
+use crate::Event;
+use crate::EventBuffer;
+use crate::Feature;
 use crate::FrameUpdateEvent;
 use crate::HitTestId;
+use crate::HitTestLimits;
 use crate::HitTestSource;
+use crate::MeshId;
+use crate::Native;
+use crate::View;
+use crate::Viewport;
+use crate::Viewports;
+use crate::Views;
+use euclid::Box3D;
+use euclid::Point3D;
+use euclid::Rect;
+use euclid::Size2D;
 use euclid::Transform3D;
+use euclid::Vector3D;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+/// The default number of mesh vertices to deliver per frame
+pub const MESH_VERTEX_BUDGET: usize = 16384;
+
+/// The smallest scale a viewport can be shrunk to by `ViewportScales`
+pub const MIN_VIEWPORT_SCALE: f32 = 0.25;
 
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
@@ -40,21 +66,109 @@ impl ClipPlanes {
     }
 }
 
+/// Keeps track of which sensitive features have already been reported as used
+#[derive(Clone, Debug, Default)]
+pub struct FeatureUsage {
+    reported: Vec<Feature>,
+}
+
+impl FeatureUsage {
+    /// Called whenever `feature` produces data, reports it the first time
+    pub fn used(&mut self, feature: Feature, events: &mut EventBuffer) {
+        if !feature.is_sensitive() || self.reported.contains(&feature) {
+            return;
+        }
+        self.reported.push(feature.clone());
+        events.callback(Event::SensitiveFeatureUsed(feature));
+    }
+}
+
+/// The viewport scales content has requested, for devices that support
+/// `Session::request_viewport_scale`
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct ViewportScales {
+    scales: Vec<f32>,
+    /// Scales requested since the last frame began, which only take effect at the next one
+    uncommitted_scales: Vec<(usize, f32)>,
+}
+
+impl ViewportScales {
+    pub fn request(&mut self, view: usize, scale: f32) {
+        let scale = scale.clamp(MIN_VIEWPORT_SCALE, 1.0);
+        self.uncommitted_scales.push((view, scale));
+    }
+
+    /// Apply the scales requested since the last call. Devices call this as they begin
+    /// an animation frame, so that the viewports of a frame don't change underneath content.
+    pub fn commit_scales(&mut self) {
+        for (view, scale) in self.uncommitted_scales.drain(..) {
+            if self.scales.len() <= view {
+                self.scales.resize(view + 1, 1.0);
+            }
+            self.scales[view] = scale;
+        }
+    }
+
+    /// The part of `viewport` that the view at `view` should be rendered into
+    pub fn scaled(&self, view: usize, viewport: Rect<i32, Viewport>) -> Rect<i32, Viewport> {
+        let scale = self.scales.get(view).cloned().unwrap_or(1.0);
+        let size = viewport.size.to_f32() * scale;
+        Rect::new(
+            viewport.origin,
+            size.round().to_i32().max(Size2D::new(1, 1)),
+        )
+    }
+
+    pub fn scaled_viewports(&self, viewports: &Viewports) -> Viewports {
+        let viewports = viewports
+            .viewports
+            .iter()
+            .enumerate()
+            .map(|(view, &viewport)| self.scaled(view, viewport))
+            .collect();
+        Viewports { viewports }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
-/// Holds on to hit tests
+/// Holds on to hit tests, turning away any beyond its `HitTestLimits`
 pub struct HitTestList {
     tests: Vec<HitTestSource>,
     uncommitted_tests: Vec<HitTestSource>,
+    rejected_tests: Vec<HitTestId>,
+    limits: HitTestLimits,
 }
 
 impl HitTestList {
+    pub fn with_limits(limits: HitTestLimits) -> HitTestList {
+        HitTestList {
+            limits,
+            ..HitTestList::default()
+        }
+    }
+
+    pub fn limits(&self) -> &HitTestLimits {
+        &self.limits
+    }
+
     pub fn request_hit_test(&mut self, source: HitTestSource) {
-        self.uncommitted_tests.push(source)
+        if self.tests.len() + self.uncommitted_tests.len() >= self.limits.max_sources {
+            self.rejected_tests.push(source.id);
+        } else {
+            self.uncommitted_tests.push(source)
+        }
     }
 
     pub fn commit_tests(&mut self) -> Vec<FrameUpdateEvent> {
         let mut events = vec![];
+        for id in self.rejected_tests.drain(..) {
+            events.push(FrameUpdateEvent::HitTestSourceRejected(
+                id,
+                Error::HitTestLimitExceeded,
+            ));
+        }
         for test in self.uncommitted_tests.drain(..) {
             events.push(FrameUpdateEvent::HitTestSourceAdded(test.id));
             self.tests.push(test);
@@ -72,6 +186,77 @@ impl HitTestList {
     }
 }
 
+#[derive(Clone, Debug, Default)]
+/// Holds on to detected meshes, and delivers changes to them a few at a time
+/// so that large meshes don't flood the frame channel
+pub struct MeshList {
+    meshes: Vec<DetectedMesh>,
+    changed: VecDeque<MeshId>,
+    removed: Vec<MeshId>,
+}
+
+impl MeshList {
+    pub fn update_mesh(&mut self, mesh: DetectedMesh) {
+        if !self.changed.contains(&mesh.id) {
+            self.changed.push_back(mesh.id);
+        }
+        if let Some(existing) = self.meshes.iter_mut().find(|m| m.id == mesh.id) {
+            *existing = mesh;
+        } else {
+            self.meshes.push(mesh);
+        }
+    }
+
+    pub fn remove_mesh(&mut self, id: MeshId) {
+        let len = self.meshes.len();
+        self.meshes.retain(|m| m.id != id);
+        self.changed.retain(|&m| m != id);
+        if self.meshes.len() != len {
+            self.removed.push(id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let ids: Vec<_> = self.meshes.iter().map(|m| m.id).collect();
+        for id in ids {
+            self.remove_mesh(id);
+        }
+    }
+
+    /// Produce the events for this frame. Removals are always delivered, but changed
+    /// meshes are only delivered until `vertex_budget` vertices have been sent,
+    /// the rest wait for later frames. At least one changed mesh is sent per frame,
+    /// so that meshes larger than the budget still make progress.
+    pub fn commit_changes(&mut self, vertex_budget: usize) -> Vec<FrameUpdateEvent> {
+        let mut events: Vec<_> = self
+            .removed
+            .drain(..)
+            .map(FrameUpdateEvent::DetectedMeshRemoved)
+            .collect();
+        let mut sent = 0;
+        while let Some(&id) = self.changed.front() {
+            let mesh = match self.meshes.iter().find(|m| m.id == id) {
+                Some(mesh) => mesh,
+                None => {
+                    self.changed.pop_front();
+                    continue;
+                }
+            };
+            if sent > 0 && sent + mesh.vertices.len() > vertex_budget {
+                break;
+            }
+            sent += mesh.vertices.len().max(1);
+            events.push(FrameUpdateEvent::UpdateDetectedMesh(mesh.clone()));
+            self.changed.pop_front();
+        }
+        events
+    }
+
+    pub fn meshes(&self) -> &[DetectedMesh] {
+        &self.meshes
+    }
+}
+
 #[inline]
 /// Construct a projection matrix given the four angles from the center for the faces of the viewing frustum
 pub fn fov_to_projection_matrix<T, U>(
@@ -127,3 +312,196 @@ pub fn frustum_to_projection_matrix<T, U>(
         0.,
     )
 }
+
+/// A plane bounding a `Frustum`. Points `p` with `normal.dot(p) + distance >= 0` are inside it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrustumPlane {
+    /// The unit normal of the plane, pointing into the frustum
+    pub normal: Vector3D<f32, Native>,
+    pub distance: f32,
+}
+
+impl FrustumPlane {
+    fn new(a: f32, b: f32, c: f32, d: f32) -> FrustumPlane {
+        let normal = Vector3D::new(a, b, c);
+        let length = normal.length();
+        FrustumPlane {
+            normal: normal / length,
+            distance: d / length,
+        }
+    }
+
+    /// How far `point` is from the plane, which is negative if it is outside
+    pub fn distance_to(&self, point: Point3D<f32, Native>) -> f32 {
+        self.normal.dot(point.to_vector()) + self.distance
+    }
+}
+
+/// The space that can be seen from one or more views, for culling a scene in native space
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frustum {
+    /// The left, right, bottom, top, near and far planes, in that order
+    pub planes: [FrustumPlane; 6],
+}
+
+/// The index of the near plane in `Frustum::planes`
+const NEAR_PLANE: usize = 4;
+
+impl Frustum {
+    /// The frustum of a view, from its transform and projection
+    pub fn from_view<Eye>(view: &View<Eye>) -> Frustum {
+        let m = native_to_display(view);
+        // The planes are sums of the column for w and the columns for x, y and z
+        let plane = |x: f32, y: f32, z: f32| {
+            FrustumPlane::new(
+                m.m14 + x * m.m11 + y * m.m12 + z * m.m13,
+                m.m24 + x * m.m21 + y * m.m22 + z * m.m23,
+                m.m34 + x * m.m31 + y * m.m32 + z * m.m33,
+                m.m44 + x * m.m41 + y * m.m42 + z * m.m43,
+            )
+        };
+        Frustum {
+            planes: [
+                plane(1., 0., 0.),
+                plane(-1., 0., 0.),
+                plane(0., 1., 0.),
+                plane(0., -1., 0.),
+                plane(0., 0., 1.),
+                plane(0., 0., -1.),
+            ],
+        }
+    }
+
+    /// Whether any of a sphere might be inside the frustum
+    pub fn intersects_sphere(&self, center: Point3D<f32, Native>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance_to(center) >= -radius)
+    }
+
+    /// Whether any of a box might be inside the frustum
+    pub fn intersects_box(&self, bounds: &Box3D<f32, Native>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the normal
+            let corner = Point3D::new(
+                if plane.normal.x >= 0. {
+                    bounds.max.x
+                } else {
+                    bounds.min.x
+                },
+                if plane.normal.y >= 0. {
+                    bounds.max.y
+                } else {
+                    bounds.min.y
+                },
+                if plane.normal.z >= 0. {
+                    bounds.max.z
+                } else {
+                    bounds.min.z
+                },
+            );
+            plane.distance_to(corner) >= 0.
+        })
+    }
+}
+
+/// A frustum that contains the frusta of all of the views rendered from the viewer's eyes,
+/// for culling a scene once rather than once per view. Each of its planes is a plane of
+/// one of the views, moved out until it contains the other views too.
+///
+/// Secondary views are left out, since a first-person observer camera can be anywhere
+/// and would make the frustum much larger, see `secondary_view_frusta`.
+/// This is `None` for inline sessions, which leave their views to content.
+pub fn combined_frustum(views: &Views) -> Option<Frustum> {
+    let volumes = primary_view_volumes(views);
+    let mut planes = volumes.first()?.0.planes;
+    for (i, plane) in planes.iter_mut().enumerate() {
+        let corners = volumes.iter().flat_map(|(_, corners)| corners.iter());
+        // How far the corners of every view are outside the plane of each view
+        let outside = |plane: &FrustumPlane| {
+            -corners
+                .clone()
+                .map(|&corner| plane.distance_to(corner))
+                .fold(0., f32::min)
+        };
+        let (outside, closest) = volumes
+            .iter()
+            .map(|(frustum, _)| (outside(&frustum.planes[i]), frustum.planes[i]))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))?;
+        *plane = FrustumPlane {
+            distance: closest.distance + outside,
+            ..closest
+        };
+    }
+    Some(Frustum { planes })
+}
+
+/// The frusta of the views rendered from the viewer's eyes, in the order of `Views`,
+/// all with the near plane of `combined_frustum`, so that a scene can be culled against
+/// the near plane once and against the rest of the planes of each view
+pub fn view_frusta(views: &Views) -> Vec<Frustum> {
+    let near = match combined_frustum(views) {
+        Some(combined) => combined.planes[NEAR_PLANE],
+        None => return vec![],
+    };
+    primary_view_volumes(views)
+        .into_iter()
+        .map(|(mut frustum, _)| {
+            frustum.planes[NEAR_PLANE] = near;
+            frustum
+        })
+        .collect()
+}
+
+/// The frusta of the secondary views, such as a first-person observer camera,
+/// which are culled on their own
+pub fn secondary_view_frusta(views: &Views) -> Vec<Frustum> {
+    match *views {
+        Views::StereoSecondary(_, _, ref secondary) => {
+            secondary.iter().map(Frustum::from_view).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The frustum and corners of each view rendered from the viewer's eyes
+fn primary_view_volumes(views: &Views) -> Vec<(Frustum, [Point3D<f32, Native>; 8])> {
+    match *views {
+        Views::Inline => vec![],
+        Views::Mono(ref view) => vec![view_volume(view)],
+        Views::Stereo(ref left, ref right) | Views::StereoSecondary(ref left, ref right, _) => {
+            vec![view_volume(left), view_volume(right)]
+        }
+        Views::Cubemap(ref front, ref left, ref right, ref top, ref bottom, ref back) => vec![
+            view_volume(front),
+            view_volume(left),
+            view_volume(right),
+            view_volume(top),
+            view_volume(bottom),
+            view_volume(back),
+        ],
+        Views::Custom(ref views) => views.iter().map(view_volume).collect(),
+    }
+}
+
+fn view_volume<Eye>(view: &View<Eye>) -> (Frustum, [Point3D<f32, Native>; 8]) {
+    let mut corners = [Point3D::origin(); 8];
+    let display_to_native = native_to_display(view).inverse();
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let unit = |bit: usize| if i & bit == 0 { -1. } else { 1. };
+        let point = Point3D::new(unit(1), unit(2), unit(4));
+        if let Some(point) = display_to_native.and_then(|m| m.transform_point3d(point)) {
+            *corner = point;
+        }
+    }
+    (Frustum::from_view(view), corners)
+}
+
+fn native_to_display<Eye>(view: &View<Eye>) -> Transform3D<f32, Native, Display> {
+    view.transform
+        .inverse()
+        .to_transform()
+        .then(&view.projection)
+}
@@ -4,9 +4,12 @@
 
 //! This crate uses `euclid`'s typed units, and exposes different coordinate spaces.
 
+use euclid::Point2D;
 use euclid::Rect;
 use euclid::RigidTransform3D;
+use euclid::Size2D;
 use euclid::Transform3D;
+use euclid::Vector2D;
 
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
@@ -62,6 +65,12 @@ pub enum CubeBottom {}
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub enum CubeBack {}
 
+/// The coordinate space of one of the views of a device with any number of them,
+/// see `Views::Custom`
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum CustomEye {}
+
 /// Pattern-match on eyes
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
@@ -106,10 +115,12 @@ pub enum Viewport {}
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub enum Input {}
 
-/// The coordinate space of a secondary capture view
+/// The coordinate space of a secondary view, such as a first-person observer camera
+/// or one of the inset views of a quad-view headset
+/// https://immersive-web.github.io/webxr/#secondary-view
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
-pub enum Capture {}
+pub enum Secondary {}
 
 /// For each eye, the pose of that eye,
 /// its projection onto its display.
@@ -121,6 +132,11 @@ pub enum Capture {}
 pub struct View<Eye> {
     pub transform: RigidTransform3D<f32, Eye, Native>,
     pub projection: Transform3D<f32, Eye, Display>,
+    /// The scale the device suggests rendering this view's viewport at, if it has one
+    /// https://immersive-web.github.io/webxr/#dom-xrview-recommendedviewportscale
+    pub recommended_viewport_scale: Option<f32>,
+    /// The physical camera that records this view, for observer camera views
+    pub camera: Option<CameraIntrinsics>,
 }
 
 impl<Eye> Default for View<Eye> {
@@ -128,6 +144,8 @@ impl<Eye> Default for View<Eye> {
         View {
             transform: RigidTransform3D::identity(),
             projection: Transform3D::identity(),
+            recommended_viewport_scale: None,
+            camera: None,
         }
     }
 }
@@ -137,6 +155,50 @@ impl<Eye> View<Eye> {
         View {
             transform: self.transform.cast_unit(),
             projection: Transform3D::from_untyped(&self.projection.to_untyped()),
+            recommended_viewport_scale: self.recommended_viewport_scale,
+            camera: self.camera,
+        }
+    }
+}
+
+/// The intrinsics of a camera that records a view, such as the first-person observer view,
+/// so that embedders can line its footage up with what was rendered for the view
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct CameraIntrinsics {
+    /// The size of the camera image, in pixels
+    pub size: Size2D<i32, Viewport>,
+    /// The focal length along each axis, in pixels
+    pub focal_length: Vector2D<f32, Viewport>,
+    /// Where the optical axis meets the image, in pixels from its top left corner
+    pub principal_point: Point2D<f32, Viewport>,
+    /// How long after the frame's predicted display time the image was captured,
+    /// in the same units as `Frame::predicted_display_time`
+    pub time_offset: f64,
+}
+
+impl CameraIntrinsics {
+    /// The intrinsics of an ideal camera given the four angles from the center
+    /// for the faces of its viewing frustum, as in `util::fov_to_projection_matrix`,
+    /// which captures `time_offset` after the frame's predicted display time
+    pub fn from_fov(
+        left: f32,
+        right: f32,
+        top: f32,
+        bottom: f32,
+        size: Size2D<i32, Viewport>,
+        time_offset: f64,
+    ) -> CameraIntrinsics {
+        let (left, right, top, bottom) = (left.tan(), right.tan(), top.tan(), bottom.tan());
+        let focal_length = Vector2D::new(
+            size.width as f32 / (right - left),
+            size.height as f32 / (top - bottom),
+        );
+        CameraIntrinsics {
+            size,
+            focal_length,
+            principal_point: Point2D::new(-left * focal_length.x, top * focal_length.y),
+            time_offset,
         }
     }
 }
@@ -149,7 +211,9 @@ pub enum Views {
     Inline,
     Mono(View<Viewer>),
     Stereo(View<LeftEye>, View<RightEye>),
-    StereoCapture(View<LeftEye>, View<RightEye>, View<Capture>),
+    /// Stereo views along with any number of secondary views,
+    /// which are only reported while they are active
+    StereoSecondary(View<LeftEye>, View<RightEye>, Vec<View<Secondary>>),
     Cubemap(
         View<Viewer>,
         View<CubeLeft>,
@@ -158,6 +222,9 @@ pub enum Views {
         View<CubeBottom>,
         View<CubeBack>,
     ),
+    /// Any number of views, in the order of their viewports, for devices that don't fit
+    /// the other variants, such as quad-view headsets or rigs of several displays
+    Custom(Vec<View<CustomEye>>),
 }
 
 /// A list of viewports per-eye in the order of fields in Views.
@@ -168,3 +235,19 @@ pub enum Views {
 pub struct Viewports {
     pub viewports: Vec<Rect<i32, Viewport>>,
 }
+
+impl Viewports {
+    /// Viewports of the given sizes, packed left to right along the top of the texture
+    pub fn side_by_side(sizes: impl IntoIterator<Item = Size2D<i32, Viewport>>) -> Viewports {
+        let mut x = 0;
+        let viewports = sizes
+            .into_iter()
+            .map(|size| {
+                let viewport = Rect::new(Point2D::new(x, 0), size);
+                x += size.width;
+                viewport
+            })
+            .collect();
+        Viewports { viewports }
+    }
+}
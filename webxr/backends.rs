@@ -0,0 +1,56 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Which backends this build of webxr has, and whether they can be used on this system,
+//! so that embedders can show accurate settings and diagnostics.
+
+use webxr_api::Backend;
+
+/// A backend that was compiled in, see `backends`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackendInfo {
+    pub backend: Backend,
+    pub status: BackendStatus,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendStatus {
+    /// The backend can be used, which doesn't mean that a device is connected
+    Available,
+    /// The backend can't be used on this system, for the given reason
+    Unavailable(String),
+}
+
+/// The backends that were compiled in, and whether each can be used. This only checks
+/// what the backends need from the system, such as the OpenXR loader, without starting
+/// a runtime, so it is cheap enough to call whenever the embedder shows it.
+pub fn backends() -> Vec<BackendInfo> {
+    vec![
+        #[cfg(feature = "openxr-api")]
+        BackendInfo {
+            backend: Backend::OpenXr,
+            status: crate::openxr::loader_status(),
+        },
+        #[cfg(feature = "glwindow")]
+        BackendInfo {
+            backend: Backend::GlWindow,
+            status: BackendStatus::Available,
+        },
+        #[cfg(feature = "headless")]
+        BackendInfo {
+            backend: Backend::Headless,
+            status: BackendStatus::Available,
+        },
+        #[cfg(feature = "replay")]
+        BackendInfo {
+            backend: Backend::Replay,
+            status: BackendStatus::Available,
+        },
+        #[cfg(feature = "simulator")]
+        BackendInfo {
+            backend: Backend::Simulator,
+            status: BackendStatus::Available,
+        },
+    ]
+}
@@ -3,31 +3,273 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::SurfmanGL;
+use euclid::{Rect, Size2D};
 use glow as gl;
 use glow::Context as Gl;
 use glow::HasContext;
+use glow::PixelPackData;
+use glow::PixelUnpackData;
 use std::collections::HashMap;
 use std::num::NonZero;
 use surfman::Device as SurfmanDevice;
+#[cfg(feature = "openxr-api")]
+use webxr_api::ColorFormat;
 use webxr_api::ContextId;
 use webxr_api::GLContexts;
+use webxr_api::LatencyMarker;
 use webxr_api::LayerId;
+use webxr_api::Viewport;
+
+// How much of each side of a view the vignette covers at full strength
+const MAX_VIGNETTE: f32 = 0.3;
+
+// How far the vignette takes to fade from clear to black, as a fraction of the view
+const VIGNETTE_FEATHER: f32 = 0.5;
+
+// A triangle that covers the whole viewport, without any vertex buffers
+const VIGNETTE_VERTEX_SHADER: &str = "
+  out vec2 vPosition;
+  void main() {
+    vPosition = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2)) * 2.0 - 1.0;
+    gl_Position = vec4(vPosition, 0.0, 1.0);
+  }
+";
+
+// Black, fading in smoothly with the distance from the center of the view
+const VIGNETTE_FRAGMENT_SHADER: &str = "
+  uniform float uInner;
+  uniform float uFeather;
+  uniform float uStrength;
+  in vec2 vPosition;
+  out vec4 color;
+  void main() {
+    float alpha = smoothstep(uInner, uInner + uFeather, length(vPosition));
+    color = vec4(0.0, 0.0, 0.0, alpha * uStrength);
+  }
+";
+
+// From EXT_texture_format_BGRA8888, which glow doesn't have
+#[cfg(feature = "openxr-api")]
+const BGRA8_EXT: u32 = 0x93A1;
+
+const BLACK: [f32; 4] = [0., 0., 0., 1.];
+const WHITE: [f32; 4] = [1., 1., 1., 1.];
 
 pub(crate) fn framebuffer(framebuffer: u32) -> Option<gl::NativeFramebuffer> {
     NonZero::new(framebuffer).map(gl::NativeFramebuffer)
 }
 
-// A utility to clear a color texture and optional depth/stencil texture
+/// The texture target of a layer texture with `samples` samples per pixel
+pub(crate) fn texture_target(samples: u32) -> u32 {
+    if samples > 1 {
+        gl::TEXTURE_2D_MULTISAMPLE
+    } else {
+        gl::TEXTURE_2D
+    }
+}
+
+/// An estimate of the memory taken by a texture of `size` with `samples` samples per pixel,
+/// in bytes. The color formats of layers and their depth/stencil format all take four bytes.
+pub(crate) fn texture_memory(size: Size2D<i32, Viewport>, samples: u32) -> u64 {
+    size.width.max(0) as u64 * size.height.max(0) as u64 * samples.max(1) as u64 * 4
+}
+
+/// Allocate a depth/stencil texture for a layer, multisampled if `samples` is more than one
+pub(crate) fn create_depth_stencil_texture(
+    gl: &Gl,
+    size: Size2D<i32, Viewport>,
+    samples: u32,
+) -> Option<gl::NativeTexture> {
+    unsafe {
+        let texture = gl.create_texture().ok();
+        let target = texture_target(samples);
+        gl.bind_texture(target, texture);
+        if samples > 1 {
+            gl.tex_storage_2d_multisample(
+                target,
+                samples as i32,
+                gl::DEPTH24_STENCIL8,
+                size.width,
+                size.height,
+                true,
+            );
+        } else {
+            gl.tex_image_2d(
+                target,
+                0,
+                gl::DEPTH24_STENCIL8 as _,
+                size.width,
+                size.height,
+                0,
+                gl::DEPTH_STENCIL,
+                gl::UNSIGNED_INT_24_8,
+                PixelUnpackData::Slice(None),
+            );
+        }
+        gl.bind_texture(target, None);
+        texture
+    }
+}
+
+/// The internal format of a multisampled color renderbuffer for a layer with `format`,
+/// or None if there isn't one that can be resolved into the layer's swapchain images.
+/// Desktop GL converts between formats when resolving, but GLES needs them to match.
+#[cfg(feature = "openxr-api")]
+pub(crate) fn multisampled_color_format(gl: &Gl, format: ColorFormat) -> Option<u32> {
+    if !gl.version().is_embedded {
+        return match format {
+            ColorFormat::Rgba8 | ColorFormat::Bgra8 => Some(gl::RGBA8),
+            ColorFormat::Rgba8Srgb | ColorFormat::Bgra8Srgb => Some(gl::SRGB8_ALPHA8),
+        };
+    }
+    match format {
+        ColorFormat::Rgba8 => Some(gl::RGBA8),
+        ColorFormat::Rgba8Srgb => Some(gl::SRGB8_ALPHA8),
+        ColorFormat::Bgra8
+            if gl
+                .supported_extensions()
+                .contains("GL_EXT_texture_format_BGRA8888") =>
+        {
+            Some(BGRA8_EXT)
+        }
+        // GLES has no sRGB format with BGRA channel order
+        ColorFormat::Bgra8 | ColorFormat::Bgra8Srgb => None,
+    }
+}
+
+/// Allocate a color texture for a layer to render into, which is copied into the layer's
+/// swapchain image with `GlClearer::resolve`
+#[cfg(feature = "openxr-api")]
+pub(crate) fn create_color_texture(
+    gl: &Gl,
+    size: Size2D<i32, Viewport>,
+    format: ColorFormat,
+) -> Option<gl::NativeTexture> {
+    let internal_format = if format.is_srgb() {
+        gl::SRGB8_ALPHA8
+    } else {
+        gl::RGBA8
+    };
+    unsafe {
+        let texture = gl.create_texture().ok();
+        gl.bind_texture(gl::TEXTURE_2D, texture);
+        gl.tex_image_2d(
+            gl::TEXTURE_2D,
+            0,
+            internal_format as _,
+            size.width,
+            size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            PixelUnpackData::Slice(None),
+        );
+        gl.bind_texture(gl::TEXTURE_2D, None);
+        texture
+    }
+}
+
+/// Allocate a multisampled color renderbuffer for a layer to render into, which is resolved
+/// into the layer's single-sampled texture with `GlClearer::resolve`. `format` is the layer's,
+/// which `multisampled_color_format` has to have a format for.
+#[cfg(feature = "openxr-api")]
+pub(crate) fn create_multisampled_color_renderbuffer(
+    gl: &Gl,
+    size: Size2D<i32, Viewport>,
+    samples: u32,
+    format: ColorFormat,
+) -> Option<gl::NativeRenderbuffer> {
+    let internal_format = multisampled_color_format(gl, format)?;
+    unsafe {
+        let renderbuffer = gl.create_renderbuffer().ok();
+        gl.bind_renderbuffer(gl::RENDERBUFFER, renderbuffer);
+        gl.renderbuffer_storage_multisample(
+            gl::RENDERBUFFER,
+            samples as i32,
+            internal_format,
+            size.width,
+            size.height,
+        );
+        gl.bind_renderbuffer(gl::RENDERBUFFER, None);
+        renderbuffer
+    }
+}
+
+/// The color buffer of a layer, which `GlClearer` attaches to the layer's framebuffer
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum ColorAttachment {
+    /// A texture, with its texture target
+    Texture(Option<gl::NativeTexture>, u32),
+    /// A multisampled renderbuffer, see `create_multisampled_color_renderbuffer`
+    #[cfg(feature = "openxr-api")]
+    Renderbuffer(gl::NativeRenderbuffer),
+}
+
+// A utility to clear a color buffer and optional depth/stencil texture
 pub(crate) struct GlClearer {
     fbos: HashMap<
-        (
-            LayerId,
-            Option<gl::NativeTexture>,
-            Option<gl::NativeTexture>,
-        ),
+        (LayerId, ColorAttachment, Option<gl::NativeTexture>),
         Option<gl::NativeFramebuffer>,
     >,
     should_reverse_winding: bool,
+    vignette_programs: HashMap<ContextId, Option<VignetteProgram>>,
+}
+
+/// The shader program that draws the vignette in one context
+#[derive(Clone, Copy)]
+struct VignetteProgram {
+    program: gl::NativeProgram,
+    vao: Option<gl::NativeVertexArray>,
+    inner: Option<gl::NativeUniformLocation>,
+    feather: Option<gl::NativeUniformLocation>,
+    strength: Option<gl::NativeUniformLocation>,
+}
+
+impl VignetteProgram {
+    fn new(gl: &Gl) -> Option<VignetteProgram> {
+        let header = if gl.version().is_embedded {
+            "#version 300 es\nprecision mediump float;\n"
+        } else {
+            "#version 330 core\n"
+        };
+        unsafe {
+            let program = gl.create_program().ok()?;
+            let vertex_shader = gl.create_shader(gl::VERTEX_SHADER).ok()?;
+            let fragment_shader = gl.create_shader(gl::FRAGMENT_SHADER).ok()?;
+            gl.shader_source(
+                vertex_shader,
+                &format!("{}{}", header, VIGNETTE_VERTEX_SHADER),
+            );
+            gl.compile_shader(vertex_shader);
+            gl.attach_shader(program, vertex_shader);
+            gl.shader_source(
+                fragment_shader,
+                &format!("{}{}", header, VIGNETTE_FRAGMENT_SHADER),
+            );
+            gl.compile_shader(fragment_shader);
+            gl.attach_shader(program, fragment_shader);
+            gl.link_program(program);
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+            if !gl.get_program_link_status(program) {
+                log::warn!(
+                    "Failed to link the vignette shader: {}",
+                    gl.get_program_info_log(program)
+                );
+                gl.delete_program(program);
+                return None;
+            }
+            // Core profiles can't draw without a vertex array bound, even an empty one
+            let vao = gl.create_vertex_array().ok();
+            Some(VignetteProgram {
+                program,
+                vao,
+                inner: gl.get_uniform_location(program, "uInner"),
+                feather: gl.get_uniform_location(program, "uFeather"),
+                strength: gl.get_uniform_location(program, "uStrength"),
+            })
+        }
+    }
 }
 
 impl GlClearer {
@@ -36,6 +278,7 @@ impl GlClearer {
         GlClearer {
             fbos,
             should_reverse_winding,
+            vignette_programs: HashMap::new(),
         }
     }
 
@@ -46,6 +289,7 @@ impl GlClearer {
         color: Option<gl::NativeTexture>,
         color_target: u32,
         depth_stencil: Option<gl::NativeTexture>,
+        depth_stencil_target: u32,
     ) -> Option<gl::NativeFramebuffer> {
         let should_reverse_winding = self.should_reverse_winding;
         *self
@@ -62,17 +306,26 @@ impl GlClearer {
                     let fbo = gl.create_framebuffer().ok();
 
                     gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
-                    gl.framebuffer_texture_2d(
-                        gl::FRAMEBUFFER,
-                        gl::COLOR_ATTACHMENT0,
-                        color_target,
-                        color,
-                        0,
-                    );
+                    match color {
+                        ColorAttachment::Texture(texture, target) => gl.framebuffer_texture_2d(
+                            gl::FRAMEBUFFER,
+                            gl::COLOR_ATTACHMENT0,
+                            target,
+                            texture,
+                            0,
+                        ),
+                        #[cfg(feature = "openxr-api")]
+                        ColorAttachment::Renderbuffer(renderbuffer) => gl.framebuffer_renderbuffer(
+                            gl::FRAMEBUFFER,
+                            gl::COLOR_ATTACHMENT0,
+                            gl::RENDERBUFFER,
+                            Some(renderbuffer),
+                        ),
+                    }
                     gl.framebuffer_texture_2d(
                         gl::FRAMEBUFFER,
                         gl::DEPTH_STENCIL_ATTACHMENT,
-                        gl::TEXTURE_2D,
+                        depth_stencil_target,
                         depth_stencil,
                         0,
                     );
@@ -99,15 +352,15 @@ impl GlClearer {
         contexts: &mut dyn GLContexts<SurfmanGL>,
         context_id: ContextId,
         layer_id: LayerId,
-        color: Option<glow::NativeTexture>,
-        color_target: u32,
+        color: ColorAttachment,
         depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil_target: u32,
     ) {
         let gl = match contexts.bindings(device, context_id) {
             None => return,
             Some(gl) => gl,
         };
-        let fbo = self.fbo(gl, layer_id, color, color_target, depth_stencil);
+        let fbo = self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target);
         unsafe {
             // Save the current GL state
             let mut bound_fbos = [0, 0];
@@ -165,6 +418,338 @@ impl GlClearer {
         }
     }
 
+    /// Darken the edges of each viewport, fading in from a clear center, with the
+    /// dark part covering up to `MAX_VIGNETTE` of each side at full `strength`
+    pub(crate) fn vignette(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+        color: ColorAttachment,
+        depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil_target: u32,
+        viewports: &[Rect<i32, Viewport>],
+        strength: f32,
+    ) {
+        let gl = match contexts.bindings(device, context_id) {
+            None => return,
+            Some(gl) => gl,
+        };
+        let program = match *self
+            .vignette_programs
+            .entry(context_id)
+            .or_insert_with(|| VignetteProgram::new(gl))
+        {
+            Some(program) => program,
+            None => return,
+        };
+        let fbo = self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target);
+        // Positions in the view go from -1 to 1, so its sides are 2 apart
+        let inner = 1.0 - 2.0 * MAX_VIGNETTE * strength;
+        unsafe {
+            // Save the current GL state
+            let mut bound_fbos = [0, 0];
+            let mut viewport_box = [0, 0, 0, 0];
+            let bound_program = gl.get_parameter_i32(gl::CURRENT_PROGRAM);
+            let bound_vao = gl.get_parameter_i32(gl::VERTEX_ARRAY_BINDING);
+            let blend_src_rgb = gl.get_parameter_i32(gl::BLEND_SRC_RGB);
+            let blend_dst_rgb = gl.get_parameter_i32(gl::BLEND_DST_RGB);
+            let blend_src_alpha = gl.get_parameter_i32(gl::BLEND_SRC_ALPHA);
+            let blend_dst_alpha = gl.get_parameter_i32(gl::BLEND_DST_ALPHA);
+            let blend_equation_rgb = gl.get_parameter_i32(gl::BLEND_EQUATION_RGB);
+            let blend_equation_alpha = gl.get_parameter_i32(gl::BLEND_EQUATION_ALPHA);
+            let color_mask;
+            let capabilities = [
+                gl::BLEND,
+                gl::CULL_FACE,
+                gl::DEPTH_TEST,
+                gl::STENCIL_TEST,
+                gl::SCISSOR_TEST,
+                gl::RASTERIZER_DISCARD,
+            ];
+            let enabled = capabilities.map(|capability| gl.is_enabled(capability));
+
+            gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+            gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+            gl.get_parameter_i32_slice(gl::VIEWPORT, &mut viewport_box[..]);
+            color_mask = gl.get_parameter_bool_array::<4>(gl::COLOR_WRITEMASK);
+
+            // Blend the vignette over each viewport
+            gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+            for &capability in &capabilities {
+                gl.disable(capability);
+            }
+            gl.enable(gl::BLEND);
+            gl.blend_equation(gl::FUNC_ADD);
+            gl.blend_func_separate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ZERO, gl::ONE);
+            gl.color_mask(true, true, true, true);
+            gl.use_program(Some(program.program));
+            gl.bind_vertex_array(program.vao);
+            gl.uniform_1_f32(program.inner.as_ref(), inner);
+            gl.uniform_1_f32(program.feather.as_ref(), VIGNETTE_FEATHER);
+            gl.uniform_1_f32(program.strength.as_ref(), strength.min(1.0));
+            for viewport in viewports {
+                gl.viewport(
+                    viewport.origin.x,
+                    viewport.origin.y,
+                    viewport.size.width,
+                    viewport.size.height,
+                );
+                gl.draw_arrays(gl::TRIANGLES, 0, 3);
+            }
+
+            // Restore the GL state
+            gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+            gl.use_program(NonZero::new(bound_program as u32).map(gl::NativeProgram));
+            gl.bind_vertex_array(NonZero::new(bound_vao as u32).map(gl::NativeVertexArray));
+            gl.blend_func_separate(
+                blend_src_rgb as _,
+                blend_dst_rgb as _,
+                blend_src_alpha as _,
+                blend_dst_alpha as _,
+            );
+            gl.blend_equation_separate(blend_equation_rgb as _, blend_equation_alpha as _);
+            gl.color_mask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl.viewport(
+                viewport_box[0],
+                viewport_box[1],
+                viewport_box[2],
+                viewport_box[3],
+            );
+            for (&capability, &enabled) in capabilities.iter().zip(&enabled) {
+                if enabled {
+                    gl.enable(capability);
+                } else {
+                    gl.disable(capability);
+                }
+            }
+            debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+        }
+    }
+
+    /// Draw `marker` in each viewport, see `LatencyMarker`
+    pub(crate) fn latency_marker(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+        color: ColorAttachment,
+        depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil_target: u32,
+        viewports: &[Rect<i32, Viewport>],
+        marker: LatencyMarker,
+    ) {
+        let squares: Vec<_> = viewports
+            .iter()
+            .flat_map(|&viewport| marker.squares(viewport))
+            .map(|(square, white)| (square, if white { WHITE } else { BLACK }))
+            .collect();
+        self.fill_rects(
+            device,
+            contexts,
+            context_id,
+            layer_id,
+            color,
+            depth_stencil,
+            depth_stencil_target,
+            &squares,
+        );
+    }
+
+    /// Fill each of `rects` of the color texture with its color
+    fn fill_rects(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+        color: ColorAttachment,
+        depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil_target: u32,
+        rects: &[(Rect<i32, Viewport>, [f32; 4])],
+    ) {
+        let gl = match contexts.bindings(device, context_id) {
+            None => return,
+            Some(gl) => gl,
+        };
+        let fbo = self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target);
+        unsafe {
+            // Save the current GL state
+            let mut bound_fbos = [0, 0];
+            let mut clear_color = [0., 0., 0., 0.];
+            let mut scissor_box = [0, 0, 0, 0];
+            let color_mask;
+            let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+            let rasterizer_enabled = gl.is_enabled(gl::RASTERIZER_DISCARD);
+
+            gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+            gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+            gl.get_parameter_f32_slice(gl::COLOR_CLEAR_VALUE, &mut clear_color[..]);
+            gl.get_parameter_i32_slice(gl::SCISSOR_BOX, &mut scissor_box[..]);
+            color_mask = gl.get_parameter_bool_array::<4>(gl::COLOR_WRITEMASK);
+
+            // Clear each rect to its color
+            gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+            gl.enable(gl::SCISSOR_TEST);
+            gl.disable(gl::RASTERIZER_DISCARD);
+            gl.color_mask(true, true, true, true);
+            for &(rect, [r, g, b, a]) in rects {
+                if rect.size.width > 0 && rect.size.height > 0 {
+                    gl.scissor(
+                        rect.origin.x,
+                        rect.origin.y,
+                        rect.size.width,
+                        rect.size.height,
+                    );
+                    gl.clear_color(r, g, b, a);
+                    gl.clear(gl::COLOR_BUFFER_BIT);
+                }
+            }
+
+            // Restore the GL state
+            gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+            gl.clear_color(
+                clear_color[0],
+                clear_color[1],
+                clear_color[2],
+                clear_color[3],
+            );
+            gl.color_mask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl.scissor(
+                scissor_box[0],
+                scissor_box[1],
+                scissor_box[2],
+                scissor_box[3],
+            );
+            if !scissor_enabled {
+                gl.disable(gl::SCISSOR_TEST);
+            }
+            if rasterizer_enabled {
+                gl.enable(gl::RASTERIZER_DISCARD);
+            }
+            debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+        }
+    }
+
+    /// Read back the contents of a color texture as RGBA8 pixels
+    pub(crate) fn read_pixels(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+        color: ColorAttachment,
+        depth_stencil: Option<glow::NativeTexture>,
+        depth_stencil_target: u32,
+        size: Size2D<i32, Viewport>,
+    ) -> Option<Vec<u8>> {
+        let gl = contexts.bindings(device, context_id)?;
+        let fbo = self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target);
+        let mut pixels = vec![0; size.width as usize * size.height as usize * 4];
+        unsafe {
+            let mut bound_fbo = [0];
+            gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbo[..]);
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, fbo);
+            gl.read_pixels(
+                0,
+                0,
+                size.width,
+                size.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                PixelPackData::Slice(Some(&mut pixels[..])),
+            );
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbo[0] as _));
+            debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+        }
+        Some(pixels)
+    }
+
+    /// The framebuffer of a layer, for content to render into when it can't be given
+    /// the layer's color buffer as a texture
+    #[cfg(feature = "openxr-api")]
+    pub(crate) fn layer_framebuffer(
+        &mut self,
+        gl: &Gl,
+        layer_id: LayerId,
+        color: ColorAttachment,
+        depth_stencil: Option<gl::NativeTexture>,
+        depth_stencil_target: u32,
+    ) -> Option<gl::NativeFramebuffer> {
+        self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target)
+    }
+
+    /// Copy the framebuffer that content rendered a layer into to a single-sampled color
+    /// texture of the same size, resolving it if its color buffer is a multisampled
+    /// renderbuffer, and turning it upside down if `flip` is set. Multisampled buffers
+    /// can't be flipped. The depth/stencil texture is only used to find the framebuffer.
+    #[cfg(feature = "openxr-api")]
+    pub(crate) fn resolve(
+        &mut self,
+        gl: &Gl,
+        layer_id: LayerId,
+        rendered: (ColorAttachment, Option<gl::NativeTexture>),
+        resolved: Option<gl::NativeTexture>,
+        resolved_target: u32,
+        size: Size2D<i32, Viewport>,
+        flip: bool,
+    ) {
+        let (color, depth_stencil) = rendered;
+        let depth_stencil_target = match color {
+            ColorAttachment::Renderbuffer(_) => gl::TEXTURE_2D_MULTISAMPLE,
+            ColorAttachment::Texture(..) => gl::TEXTURE_2D,
+        };
+        let read_fbo = self.fbo(gl, layer_id, color, depth_stencil, depth_stencil_target);
+        let (y0, y1) = if flip {
+            (size.height, 0)
+        } else {
+            (0, size.height)
+        };
+        let draw_fbo = self.fbo(
+            gl,
+            layer_id,
+            ColorAttachment::Texture(resolved, resolved_target),
+            None,
+            gl::TEXTURE_2D,
+        );
+        unsafe {
+            // Save the current GL state
+            let mut bound_fbos = [0, 0];
+            let scissor_enabled = gl.is_enabled(gl::SCISSOR_TEST);
+            gl.get_parameter_i32_slice(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_fbos[0..]);
+            gl.get_parameter_i32_slice(gl::READ_FRAMEBUFFER_BINDING, &mut bound_fbos[1..]);
+
+            // Resolving needs the source and destination rectangles to be the same size
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, read_fbo);
+            gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, draw_fbo);
+            gl.disable(gl::SCISSOR_TEST);
+            gl.blit_framebuffer(
+                0,
+                0,
+                size.width,
+                size.height,
+                0,
+                y0,
+                size.width,
+                y1,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+
+            // Restore the GL state
+            gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffer(bound_fbos[0] as _));
+            gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffer(bound_fbos[1] as _));
+            if scissor_enabled {
+                gl.enable(gl::SCISSOR_TEST);
+            }
+            debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+        }
+    }
+
     pub(crate) fn destroy_layer(
         &mut self,
         device: &mut SurfmanDevice,
@@ -12,6 +12,7 @@ use glow::{self as gl, Context as Gl, HasContext};
 use raw_window_handle::DisplayHandle;
 use std::num::NonZeroU32;
 use std::rc::Rc;
+use std::time::Instant;
 use surfman::chains::{PreserveBuffer, SwapChain, SwapChainAPI, SwapChains, SwapChainsAPI};
 use surfman::{
     Adapter, Connection, Context as SurfmanContext, ContextAttributeFlags, ContextAttributes,
@@ -19,11 +20,12 @@ use surfman::{
 };
 use webxr_api::util::ClipPlanes;
 use webxr_api::{
-    ContextId, DeviceAPI, DiscoveryAPI, Display, Error, Event, EventBuffer, Floor, Frame,
-    InputSource, LayerGrandManager, LayerId, LayerInit, LayerManager, Native, Quitter, Sender,
-    Session, SessionBuilder, SessionInit, SessionMode, SomeEye, View, Viewer, ViewerPose, Viewport,
-    Viewports, Views, CUBE_BACK, CUBE_BOTTOM, CUBE_LEFT, CUBE_RIGHT, CUBE_TOP, LEFT_EYE, RIGHT_EYE,
-    VIEWER,
+    ContextId, DeviceAPI, DeviceDescriptor, DeviceResources, DiscoveryAPI, Display, DwellOptions,
+    DwellSelect, Error, Event, EventBuffer, Feature, Floor, Frame, Handedness, InputFrame, InputId,
+    InputSource, LatencyMarker, LayerGrandManager, LayerId, LayerInit, LayerManager, Native,
+    PendingSession, Quitter, Sender, SessionBuilder, SessionInit, SessionMode, SomeEye,
+    TargetRayMode, View, Viewer, ViewerPose, Viewport, Viewports, Views, CUBE_BACK, CUBE_BOTTOM,
+    CUBE_LEFT, CUBE_RIGHT, CUBE_TOP, LEFT_EYE, RIGHT_EYE, VIEWER,
 };
 
 // How far off the ground are the viewer's eyes?
@@ -40,6 +42,9 @@ const INTER_PUPILLARY_DISTANCE: f32 = 0.06;
 // What is the size of a pixel?
 const PIXELS_PER_METRE: f32 = 6000.0;
 
+// The id of the input source used for the window's gamepad
+const GAMEPAD_INPUT_ID: InputId = InputId(0);
+
 pub trait GlWindow {
     fn get_render_target(
         &self,
@@ -49,10 +54,37 @@ pub trait GlWindow {
     fn get_rotation(&self) -> Rotation3D<f32, UnknownUnit, UnknownUnit>;
     fn get_translation(&self) -> Vector3D<f32, UnknownUnit>;
 
+    /// The orientation of the device the window is on, if it has orientation sensors
+    /// (for example a tablet) and the embedder wants to use them. This is used for the
+    /// viewer's rotation instead of `get_rotation`, so that the window acts as a magic
+    /// window into the scene.
+    ///
+    /// The rotation is from the device's coordinates (+X to the right of the screen,
+    /// +Y to the top, +Z out of the screen) to the earth's (+X east, +Y north, +Z up),
+    /// which is what the Windows and Android sensor APIs report.
+    fn get_sensor_rotation(&self) -> Option<Rotation3D<f32, UnknownUnit, UnknownUnit>> {
+        None
+    }
+
     fn get_mode(&self) -> GlWindowMode {
         GlWindowMode::Blit
     }
     fn display_handle(&self) -> DisplayHandle;
+
+    /// The state of a gamepad attached to the window, if any. This is
+    /// exposed to content as a gaze input source.
+    fn get_gamepad(&self) -> Option<GlWindowGamepad> {
+        None
+    }
+}
+
+/// Gamepad state, laid out as in the WebXR gamepads module
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GlWindowGamepad {
+    pub button_values: Vec<f32>,
+    pub axis_values: Vec<f32>,
+    pub buttons_pressed: u32,
+    pub buttons_touched: u32,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -103,13 +135,14 @@ impl DiscoveryAPI<SurfmanGL> for GlWindowDiscovery {
         mode: SessionMode,
         init: &SessionInit,
         xr: SessionBuilder<SurfmanGL>,
-    ) -> Result<Session, Error> {
+    ) -> Result<PendingSession, Error> {
         if self.supports_session(mode) {
-            let granted_features = init.validate(mode, &["local-floor".into()])?;
+            let granted_features = init.validate(mode, &[Feature::LocalFloor])?;
             let connection = self.connection.clone();
             let adapter = self.adapter.clone();
             let context_attributes = self.context_attributes.clone();
             let window = self.window.clone();
+            let events = xr.event_buffer();
             xr.run_on_main_thread(move |grand_manager| {
                 GlWindowDevice::new(
                     connection,
@@ -117,6 +150,7 @@ impl DiscoveryAPI<SurfmanGL> for GlWindowDiscovery {
                     context_attributes,
                     window,
                     granted_features,
+                    events,
                     grand_manager,
                 )
             })
@@ -128,6 +162,15 @@ impl DiscoveryAPI<SurfmanGL> for GlWindowDiscovery {
     fn supports_session(&self, mode: SessionMode) -> bool {
         mode == SessionMode::ImmersiveVR || mode == SessionMode::ImmersiveAR
     }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        Some(DeviceDescriptor {
+            name: String::from("GL window"),
+            vendor: String::from("webxr"),
+            modes: self.supported_modes(),
+            features: vec![Feature::LocalFloor],
+        })
+    }
 }
 
 pub struct GlWindowDevice {
@@ -142,8 +185,31 @@ pub struct GlWindowDevice {
     read_fbo: Option<gl::NativeFramebuffer>,
     events: EventBuffer,
     clip_planes: ClipPlanes,
-    granted_features: Vec<String>,
+    granted_features: Vec<Feature>,
     shader: Option<GlWindowShader>,
+    gamepad: Option<GlWindowGamepad>,
+    /// Selects with the gamepad's gaze input, which has no select button
+    dwell_select: Option<DwellSelect>,
+    /// Set with `Session::set_mono_rendering`, collapses the eyes into one
+    mono_rendering: bool,
+    /// Turns sensor rotations so that the session starts out facing -Z
+    sensor_heading: Option<Rotation3D<f32, Native, Native>>,
+    /// When the device was created, which frame times are measured from
+    start: Instant,
+    /// When the last frame began, to estimate when the next one is displayed
+    last_frame_start: Option<Instant>,
+}
+
+/// The gaze input that the window's gamepad drives
+fn gamepad_input_source() -> InputSource {
+    InputSource {
+        handedness: Handedness::None,
+        target_ray_mode: TargetRayMode::Gaze,
+        id: GAMEPAD_INPUT_ID,
+        supports_grip: false,
+        hand_support: None,
+        profiles: vec!["generic-button".into()],
+    }
 }
 
 impl DeviceAPI for GlWindowDevice {
@@ -183,26 +249,89 @@ impl DeviceAPI for GlWindowDevice {
             .destroy_layer(context_id, layer_id)
     }
 
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.layer_manager()?
+            .migrate_context(old_context_id, new_context_id)
+    }
+
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
         log::debug!("Begin animation frame for layers {:?}", layers);
         let translation = Vector3D::from_untyped(self.window.get_translation());
         let translation: RigidTransform3D<_, _, Native> =
             RigidTransform3D::from_translation(translation);
-        let rotation = Rotation3D::from_untyped(&self.window.get_rotation());
+        let rotation = match self.window.get_sensor_rotation() {
+            Some(rotation) => self.sensor_rotation(rotation),
+            None => Rotation3D::from_untyped(&self.window.get_rotation()),
+        };
         let rotation = RigidTransform3D::from_rotation(rotation);
         let transform = translation.then(&rotation);
         let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
-        Some(Frame {
+        let gamepad = self.window.get_gamepad();
+        let input_changed = gamepad != self.gamepad;
+        // Gamepads can be plugged in or unplugged while the session runs
+        match (&self.gamepad, &gamepad) {
+            (None, Some(_)) => self
+                .events
+                .callback(Event::AddInput(gamepad_input_source())),
+            (Some(_), None) => self.events.callback(Event::RemoveInput(GAMEPAD_INPUT_ID)),
+            _ => (),
+        }
+        let inputs = gamepad
+            .iter()
+            .cloned()
+            .map(|gamepad| InputFrame {
+                id: GAMEPAD_INPUT_ID,
+                target_ray_origin: Some(transform.cast_unit()),
+                grip_origin: None,
+                linear_velocity: None,
+                angular_velocity: None,
+                pressed: false,
+                hand: None,
+                squeezed: false,
+                select_value: 0.0,
+                squeeze_value: 0.0,
+                button_values: gamepad.button_values,
+                axis_values: gamepad.axis_values,
+                buttons_pressed: gamepad.buttons_pressed,
+                buttons_touched: gamepad.buttons_touched,
+                input_changed,
+            })
+            .collect();
+        self.gamepad = gamepad;
+        // The window's refresh rate isn't known, so assume the frame is displayed
+        // as long after it begins as the last frame took
+        let now = Instant::now();
+        let latency = self
+            .last_frame_start
+            .map(|last| now.duration_since(last))
+            .unwrap_or_default();
+        self.last_frame_start = Some(now);
+        let frame = Frame {
             pose: Some(ViewerPose {
                 transform,
+                linear_velocity: None,
+                angular_velocity: None,
                 views: self.views(transform),
             }),
-            inputs: vec![],
+            inputs,
             events: vec![],
             sub_images,
             hit_test_results: vec![],
-            predicted_display_time: 0.0,
-        })
+            tracked_images: vec![],
+            dom_overlay: None,
+            depth_information: None,
+            predicted_display_time: (now + latency).duration_since(self.start).as_nanos() as f64,
+            predicted_display_period: None,
+            render_budget: None,
+        };
+        if let Some(ref mut dwell_select) = self.dwell_select {
+            dwell_select.update(&frame, &[GAMEPAD_INPUT_ID], &mut self.events);
+        }
+        Some(frame)
     }
 
     fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
@@ -215,27 +344,7 @@ impl DeviceAPI for GlWindowDevice {
         let window_size = self.window_size();
         let viewport_size = self.viewport_size();
 
-        let framebuffer_object = self
-            .device
-            .context_surface_info(&self.context)
-            .unwrap()
-            .map(|info| info.framebuffer_object)
-            .unwrap_or(0);
-        unsafe {
-            self.gl
-                .bind_framebuffer(gl::FRAMEBUFFER, framebuffer(framebuffer_object));
-            debug_assert_eq!(
-                (
-                    self.gl.get_error(),
-                    self.gl.check_framebuffer_status(gl::FRAMEBUFFER)
-                ),
-                (gl::NO_ERROR, gl::FRAMEBUFFER_COMPLETE)
-            );
-
-            self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
-            self.gl.clear(gl::COLOR_BUFFER_BIT);
-            debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
-        }
+        self.clear_window();
 
         for &(_, layer_id) in layers {
             let swap_chain = match self.swap_chains.get(layer_id) {
@@ -276,44 +385,61 @@ impl DeviceAPI for GlWindowDevice {
             swap_chain.recycle_surface(surface);
         }
 
-        match self.target_swap_chain.as_ref() {
-            Some(target_swap_chain) => {
-                // Rendering to a surfman swap chain
-                target_swap_chain
-                    .swap_buffers(&mut self.device, &mut self.context, PreserveBuffer::No)
-                    .unwrap();
-            }
-            None => {
-                // Rendering to a native widget
-                let mut surface = self
-                    .device
-                    .unbind_surface_from_context(&mut self.context)
-                    .unwrap()
-                    .unwrap();
-                self.device
-                    .present_surface(&self.context, &mut surface)
-                    .unwrap();
-                self.device
-                    .bind_surface_to_context(&mut self.context, surface)
-                    .unwrap();
-            }
-        }
+        self.present();
 
         debug_assert_eq!(unsafe { self.gl.get_error() }, gl::NO_ERROR);
     }
 
     fn initial_inputs(&self) -> Vec<InputSource> {
-        vec![]
+        match self.gamepad {
+            Some(_) => vec![gamepad_input_source()],
+            None => vec![],
+        }
     }
 
     fn set_event_dest(&mut self, dest: Sender<Event>) {
         self.events.upgrade(dest)
     }
 
+    fn set_dwell_select(&mut self, options: Option<DwellOptions>) {
+        self.dwell_select = options.map(DwellSelect::new);
+    }
+
+    fn set_mono_rendering(&mut self, mono: bool) {
+        self.mono_rendering = mono;
+    }
+
+    fn resources(&self) -> DeviceResources {
+        DeviceResources {
+            swapchains: self
+                .layer_manager
+                .as_ref()
+                .and_then(LayerManager::swapchain_count),
+            texture_memory: self
+                .layer_manager
+                .as_ref()
+                .and_then(LayerManager::texture_memory),
+            buffered_events: Some(self.events.buffered()),
+        }
+    }
+
     fn quit(&mut self) {
         self.events.callback(Event::SessionEnd);
     }
 
+    fn pause(&mut self) {
+        // Nothing is drawn until the session resumes, so blank the window
+        // rather than leave the last frame frozen on it.
+        self.device.make_context_current(&self.context).unwrap();
+        self.clear_window();
+        self.present();
+        self.last_frame_start = None;
+    }
+
+    fn shutdown(&mut self) {
+        self.layer_manager = None;
+    }
+
     fn set_quitter(&mut self, _: Quitter) {
         // Glwindow currently doesn't have any way to end its own session
         // XXXManishearth add something for this that listens for the window
@@ -324,7 +450,19 @@ impl DeviceAPI for GlWindowDevice {
         self.clip_planes.update(near, far)
     }
 
-    fn granted_features(&self) -> &[String] {
+    fn set_vignette(&mut self, strength: f32) {
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_vignette(strength);
+        }
+    }
+
+    fn set_latency_marker(&mut self, marker: Option<LatencyMarker>) {
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_latency_marker(marker);
+        }
+    }
+
+    fn granted_features(&self) -> &[Feature] {
         &self.granted_features
     }
 }
@@ -346,7 +484,8 @@ impl GlWindowDevice {
         adapter: Adapter,
         context_attributes: ContextAttributes,
         window: Rc<dyn GlWindow>,
-        granted_features: Vec<String>,
+        granted_features: Vec<Feature>,
+        events: EventBuffer,
         grand_manager: LayerGrandManager<SurfmanGL>,
     ) -> Result<GlWindowDevice, Error> {
         let mut device = connection.create_device(&adapter).unwrap();
@@ -410,6 +549,7 @@ impl GlWindowDevice {
         let layer_manager = None;
 
         let shader = GlWindowShader::new(gl.clone(), window.get_mode());
+        let gamepad = window.get_gamepad();
         debug_assert_eq!(unsafe { gl.get_error() }, gl::NO_ERROR);
 
         Ok(GlWindowDevice {
@@ -422,10 +562,16 @@ impl GlWindowDevice {
             target_swap_chain,
             grand_manager,
             layer_manager,
-            events: Default::default(),
+            events,
             clip_planes: Default::default(),
             granted_features,
             shader,
+            gamepad,
+            dwell_select: None,
+            mono_rendering: false,
+            sensor_heading: None,
+            start: Instant::now(),
+            last_frame_start: None,
         })
     }
 
@@ -461,6 +607,71 @@ impl GlWindowDevice {
         }
     }
 
+    fn sensor_rotation(
+        &mut self,
+        rotation: Rotation3D<f32, UnknownUnit, UnknownUnit>,
+    ) -> Rotation3D<f32, Native, Native> {
+        // The earth's up is our +Y, and its north is our -Z
+        let earth_to_native: Rotation3D<f32, UnknownUnit, Native> =
+            Rotation3D::around_x(Angle::degrees(-90.0));
+        let rotation = Rotation3D::from_untyped(&rotation).then(&earth_to_native);
+        let heading = *self.sensor_heading.get_or_insert_with(|| {
+            let forward = rotation.transform_vector3d(Vector3D::new(0.0, 0.0, -1.0));
+            let yaw = Angle::radians((-forward.x).atan2(-forward.z));
+            Rotation3D::around_y(-yaw)
+        });
+        rotation.then(&heading)
+    }
+
+    fn clear_window(&self) {
+        let framebuffer_object = self
+            .device
+            .context_surface_info(&self.context)
+            .unwrap()
+            .map(|info| info.framebuffer_object)
+            .unwrap_or(0);
+        unsafe {
+            self.gl
+                .bind_framebuffer(gl::FRAMEBUFFER, framebuffer(framebuffer_object));
+            debug_assert_eq!(
+                (
+                    self.gl.get_error(),
+                    self.gl.check_framebuffer_status(gl::FRAMEBUFFER)
+                ),
+                (gl::NO_ERROR, gl::FRAMEBUFFER_COMPLETE)
+            );
+
+            self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.gl.clear(gl::COLOR_BUFFER_BIT);
+            debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+        }
+    }
+
+    fn present(&mut self) {
+        match self.target_swap_chain.as_ref() {
+            Some(target_swap_chain) => {
+                // Rendering to a surfman swap chain
+                target_swap_chain
+                    .swap_buffers(&mut self.device, &mut self.context, PreserveBuffer::No)
+                    .unwrap();
+            }
+            None => {
+                // Rendering to a native widget
+                let mut surface = self
+                    .device
+                    .unbind_surface_from_context(&mut self.context)
+                    .unwrap()
+                    .unwrap();
+                self.device
+                    .present_surface(&self.context, &mut surface)
+                    .unwrap();
+                self.device
+                    .bind_surface_to_context(&mut self.context, surface)
+                    .unwrap();
+            }
+        }
+    }
+
     fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
         if let Some(ref mut manager) = self.layer_manager {
             return Ok(manager);
@@ -535,7 +746,9 @@ impl GlWindowDevice {
         eye: SomeEye<Eye>,
     ) -> View<Eye> {
         let projection = self.perspective();
-        let translation = if eye == RIGHT_EYE {
+        let translation = if self.mono_rendering {
+            Vector3D::zero()
+        } else if eye == RIGHT_EYE {
             Vector3D::new(-INTER_PUPILLARY_DISTANCE / 2.0, 0.0, 0.0)
         } else if eye == LEFT_EYE {
             Vector3D::new(INTER_PUPILLARY_DISTANCE / 2.0, 0.0, 0.0)
@@ -572,6 +785,8 @@ impl GlWindowDevice {
         View {
             transform: transform.inverse().then(&viewer),
             projection,
+            recommended_viewport_scale: None,
+            camera: None,
         }
     }
 
@@ -4,21 +4,50 @@
 
 use crate::SurfmanGL;
 use crate::SurfmanLayerManager;
-use euclid::{Point2D, RigidTransform3D};
-use std::sync::{Arc, Mutex};
+use euclid::{Point2D, Point3D, Rect, RigidTransform3D};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, Weak};
 use std::thread;
+use std::time::{Duration, Instant};
 use surfman::chains::SwapChains;
-use webxr_api::util::{self, ClipPlanes, HitTestList};
+use webxr_api::util::{self, ClipPlanes, FeatureUsage, HitTestList, MeshList, ViewportScales};
 use webxr_api::{
-    ApiSpace, BaseSpace, ContextId, DeviceAPI, DiscoveryAPI, Error, Event, EventBuffer, Floor,
-    Frame, FrameUpdateEvent, HitTestId, HitTestResult, HitTestSource, Input, InputFrame, InputId,
-    InputSource, LayerGrandManager, LayerId, LayerInit, LayerManager, MockButton, MockDeviceInit,
-    MockDeviceMsg, MockDiscoveryAPI, MockInputMsg, MockViewInit, MockViewsInit, MockWorld, Native,
-    Quitter, Ray, Receiver, SelectEvent, SelectKind, Sender, Session, SessionBuilder, SessionInit,
-    SessionMode, Space, SubImages, View, Viewer, ViewerPose, Viewports, Views,
+    ApiSpace, BaseSpace, ContextId, DepthInformation, DetectedMesh, DeviceAPI,
+    DeviceChangeNotifier, DeviceDescriptor, DeviceResources, DiscoveryAPI, DomOverlayState,
+    DomOverlayType, DwellOptions, DwellSelect, EntityId, EntityType, Error, Event, EventBuffer,
+    ExtensionEvent, Feature, Floor, Frame, FrameSubscriptions, FrameUpdateEvent, Hand, HitTestId,
+    HitTestLimits, HitTestResult, HitTestSource, Input, InputFrame, InputId, InputSource,
+    JointFrame, LatencyMarker, LayerGrandManager, LayerId, LayerInit, LayerManager, MeshId,
+    MockButton, MockButtonType, MockColocation, MockDeviceInit, MockDeviceMsg, MockDiscoveryAPI,
+    MockHapticPulse, MockInputMsg, MockLayerContents, MockRegion, MockViewInit, MockViewsInit,
+    MockWorld, Native, PendingSession, Quitter, Ray, Receiver, Room, SelectEvent, SelectGesture,
+    SelectKind, Sender, SessionBuilder, SessionInit, SessionMode, Space, SubImages, TargetRayMode,
+    TrackedImage, TransientInputIds, Triangle, TriangleBvh, View, Viewer, ViewerPose, Viewports,
+    Views, Visibility,
 };
 
-pub struct HeadlessMockDiscovery {}
+// The id of the input source used for eye tracking
+const EYE_GAZE_INPUT_ID: InputId = InputId(u32::MAX);
+
+// The frame rates that can be requested with `update_frame_rate`.
+// Frames are not paced until one of these is picked.
+const SUPPORTED_FRAME_RATES: [f32; 4] = [60.0, 72.0, 90.0, 120.0];
+
+pub struct HeadlessMockDiscovery {
+    /// The rooms colocated devices are in, by name
+    rooms: HashMap<String, Weak<Mutex<SharedRoom>>>,
+}
+
+/// What the devices in a room share, see `MockColocation`
+#[derive(Default)]
+struct SharedRoom {
+    /// The world, in the room's space rather than any device's native space
+    world: Option<MockWorld>,
+    /// Bumped whenever `world` changes, so that devices know to pick up the new one
+    version: u64,
+    anchors: HashMap<String, RigidTransform3D<f32, ApiSpace, Room>>,
+}
 
 struct HeadlessDiscovery {
     data: Arc<Mutex<HeadlessDeviceData>>,
@@ -34,13 +63,22 @@ struct InputInfo {
     grip: Option<RigidTransform3D<f32, Input, Native>>,
     clicking: bool,
     buttons: Vec<MockButton>,
+    haptic_pulses: Vec<MockHapticPulse>,
+    hand: Option<Arc<Hand<JointFrame>>>,
 }
 
 struct HeadlessDevice {
     data: Arc<Mutex<HeadlessDeviceData>>,
     id: u32,
     hit_tests: HitTestList,
-    granted_features: Vec<String>,
+    meshes: MeshList,
+    viewport_scales: ViewportScales,
+    feature_usage: FeatureUsage,
+    frame_rate: Option<f32>,
+    frame_subscriptions: FrameSubscriptions,
+    last_frame_time: Option<Instant>,
+    dwell_select: Option<DwellSelect>,
+    granted_features: Vec<Feature>,
     grand_manager: LayerGrandManager<SurfmanGL>,
     layer_manager: Option<LayerManager>,
 }
@@ -52,20 +90,67 @@ struct PerSessionData {
     quitter: Option<Quitter>,
     events: EventBuffer,
     needs_vp_update: bool,
+    needs_bounds_update: bool,
+    needs_mesh_update: bool,
+    /// Whether the session has the `dom-overlay` feature
+    dom_overlay: bool,
+    /// Whether the session has the `secondary-views` feature
+    secondary_views: bool,
+    /// Whether the session has the `depth-sensing` feature
+    depth_sensing: bool,
+    /// Whether content is subscribed to the depth information, which is only copied if so
+    depth_subscribed: bool,
+    /// How many images the session tracks, if it has the `image-tracking` feature
+    tracked_images: usize,
+    /// Events from `MockDeviceMsg::SimulateExtensionEvent` to deliver with the next frame
+    extension_events: Vec<ExtensionEvent>,
+    /// Set with `Session::set_mono_rendering`
+    mono_rendering: bool,
 }
 
 struct HeadlessDeviceData {
     floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
     viewer_origin: Option<RigidTransform3D<f32, Viewer, Native>>,
-    supported_features: Vec<String>,
+    supported_features: Vec<Feature>,
     views: MockViewsInit,
+    /// Set with `MockDeviceMsg::SetSecondaryViewsActive`
+    secondary_views_active: bool,
     needs_floor_update: bool,
     inputs: Vec<InputInfo>,
     sessions: Vec<PerSessionData>,
     disconnected: bool,
     world: Option<MockWorld>,
+    /// The triangles of `world`, for hit tests
+    world_bvh: Option<TriangleBvh>,
     next_id: u32,
     bounds_geometry: Vec<Point2D<f32, Floor>>,
+    export_layer_contents: bool,
+    layer_contents: Vec<MockLayerContents>,
+    eye_gaze: Option<RigidTransform3D<f32, Input, Native>>,
+    /// Set with `MockDeviceMsg::VisibilityChange`, for every session
+    visibility: Visibility,
+    /// Set with `MockDeviceMsg::SetDepthSensingData`
+    depth_sensing_data: Option<DepthInformation>,
+    /// Set with `MockDeviceMsg::SetTrackedImages`
+    tracked_images: Vec<TrackedImage>,
+    transient_input_ids: TransientInputIds,
+    /// The number of frames begun by every session so far
+    frame_count: u64,
+    /// Messages from `MockDeviceMsg::PlayTimeline`, with the frame to handle them in
+    timeline: Vec<(u64, MockDeviceMsg)>,
+    /// When the device was connected, which frame times are measured from
+    start: Instant,
+    /// With `MockDeviceMsg::SetManualFrameClock`, how many more frames can end
+    frames_allowed: Option<u64>,
+    /// Wakes sessions waiting for `frames_allowed` to go up
+    frame_clock: Arc<Condvar>,
+    /// Tells the registry when the device is disconnected
+    device_changes: Option<DeviceChangeNotifier>,
+    /// The room the device is in, from `MockDeviceInit::colocation`
+    room: Option<Arc<Mutex<SharedRoom>>>,
+    room_origin: RigidTransform3D<f32, Native, Room>,
+    /// The version of the room's world that `world` is, if it is up to date
+    room_version: Option<u64>,
 }
 
 impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
@@ -77,19 +162,46 @@ impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
         let viewer_origin = init.viewer_origin.clone();
         let floor_transform = init.floor_origin.map(|f| f.inverse());
         let views = init.views.clone();
-        let data = HeadlessDeviceData {
+        let (room, room_origin) = match init.colocation {
+            Some(MockColocation { room, origin }) => (Some(self.room(room)), origin),
+            None => (None, RigidTransform3D::identity()),
+        };
+        let mut data = HeadlessDeviceData {
             floor_transform,
             viewer_origin,
             supported_features: init.supported_features,
             views,
+            secondary_views_active: true,
             needs_floor_update: false,
             inputs: vec![],
             sessions: vec![],
             disconnected: false,
-            world: init.world,
+            world_bvh: None,
+            world: None,
             next_id: 0,
-            bounds_geometry: vec![],
+            bounds_geometry: init.bounds_geometry,
+            export_layer_contents: init.export_layer_contents,
+            layer_contents: vec![],
+            eye_gaze: None,
+            visibility: Visibility::Visible,
+            depth_sensing_data: None,
+            tracked_images: vec![],
+            transient_input_ids: TransientInputIds::default(),
+            frame_count: 0,
+            timeline: vec![],
+            start: Instant::now(),
+            frames_allowed: None,
+            frame_clock: Arc::new(Condvar::new()),
+            device_changes: None,
+            room,
+            room_origin,
+            room_version: None,
         };
+        // A device joining a room with a world of its own replaces the room's
+        match init.world {
+            Some(world) => data.set_world(Some(world)),
+            None => data.sync_room(),
+        }
         let data = Arc::new(Mutex::new(data));
         let data_ = data.clone();
 
@@ -119,12 +231,22 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
         mode: SessionMode,
         init: &SessionInit,
         xr: SessionBuilder<SurfmanGL>,
-    ) -> Result<Session, Error> {
+    ) -> Result<PendingSession, Error> {
         if !self.supports_session(mode) {
             return Err(Error::NoMatchingDevice);
         }
         let data = self.data.clone();
         let mut d = data.lock().unwrap();
+        let granted_features = init.validate(mode, &d.supported_features)?;
+        let dom_overlay =
+            init.dom_overlay.is_some() && granted_features.contains(&Feature::DomOverlay);
+        let secondary_views = granted_features.contains(&Feature::SecondaryViews);
+        let depth_sensing = granted_features.contains(&Feature::DepthSensing);
+        let tracked_images = if granted_features.contains(&Feature::ImageTracking) {
+            init.tracked_images.len()
+        } else {
+            0
+        };
         let id = d.next_id;
         d.next_id += 1;
         let per_session = PerSessionData {
@@ -132,12 +254,20 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
             mode,
             clip_planes: Default::default(),
             quitter: Default::default(),
-            events: Default::default(),
+            events: xr.event_buffer(),
             needs_vp_update: false,
+            needs_bounds_update: false,
+            needs_mesh_update: true,
+            dom_overlay,
+            secondary_views,
+            depth_sensing,
+            depth_subscribed: true,
+            tracked_images,
+            extension_events: vec![],
+            mono_rendering: false,
         };
         d.sessions.push(per_session);
 
-        let granted_features = init.validate(mode, &d.supported_features)?;
         let layer_manager = None;
         drop(d);
         xr.spawn(move |grand_manager| {
@@ -146,12 +276,39 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
                 id,
                 granted_features,
                 hit_tests: HitTestList::default(),
+                meshes: MeshList::default(),
+                viewport_scales: ViewportScales::default(),
+                feature_usage: FeatureUsage::default(),
+                frame_rate: None,
+                frame_subscriptions: FrameSubscriptions::default(),
+                last_frame_time: None,
+                dwell_select: None,
                 grand_manager,
                 layer_manager,
             })
         })
     }
 
+    fn set_device_change_notifier(&mut self, notifier: DeviceChangeNotifier) {
+        self.data.lock().unwrap().device_changes = Some(notifier);
+    }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        let features = {
+            let data = self.data.lock().unwrap();
+            if data.disconnected {
+                return None;
+            }
+            data.supported_features.clone()
+        };
+        Some(DeviceDescriptor {
+            name: String::from("Headless mock device"),
+            vendor: String::from("webxr"),
+            modes: self.supported_modes(),
+            features,
+        })
+    }
+
     fn supports_session(&self, mode: SessionMode) -> bool {
         if self.data.lock().unwrap().disconnected {
             return false;
@@ -178,6 +335,81 @@ fn view<Eye>(
     View {
         transform: init.transform.inverse().then(&viewer),
         projection,
+        recommended_viewport_scale: None,
+        camera: init.camera,
+    }
+}
+
+/// Move both eyes to halfway between them, so that they see the same thing
+fn collapse_ipd(views: &mut Views) {
+    match views {
+        Views::Stereo(left, right) | Views::StereoSecondary(left, right, _) => {
+            let center = left
+                .transform
+                .translation
+                .lerp(right.transform.translation, 0.5);
+            left.transform.translation = center;
+            right.transform.translation = center;
+        }
+        _ => {}
+    }
+}
+
+fn world_bvh(world: &MockWorld) -> TriangleBvh {
+    // Regions are numbered like their meshes, see `detected_mesh`
+    let regions = world.regions.iter().enumerate();
+    TriangleBvh::new(regions.flat_map(|(i, region)| {
+        region
+            .faces
+            .iter()
+            .map(move |&triangle| (triangle, region.ty, EntityId(i as u32)))
+    }))
+}
+
+/// Move every triangle of `world` by `transform`
+fn transform_world<Src, Dst>(
+    world: &MockWorld,
+    transform: &RigidTransform3D<f32, Src, Dst>,
+) -> MockWorld {
+    let transform = transform.to_transform();
+    let point = |p: Point3D<f32, Native>| {
+        // Rigid transforms always have a result
+        let p = transform.transform_point3d(p.cast_unit()).unwrap();
+        p.cast_unit()
+    };
+    let regions = world
+        .regions
+        .iter()
+        .map(|region| MockRegion {
+            faces: region
+                .faces
+                .iter()
+                .map(|t| Triangle {
+                    first: point(t.first),
+                    second: point(t.second),
+                    third: point(t.third),
+                })
+                .collect(),
+            ty: region.ty,
+        })
+        .collect();
+    MockWorld { regions }
+}
+
+fn detected_mesh(id: MeshId, region: &MockRegion) -> DetectedMesh {
+    let vertices: Arc<[_]> = region
+        .faces
+        .iter()
+        .flat_map(|t| vec![t.first, t.second, t.third])
+        .map(|p| p.cast_unit())
+        .collect();
+    let indices = (0..vertices.len() as u32).collect();
+    DetectedMesh {
+        id,
+        vertices,
+        indices,
+        pose: RigidTransform3D::identity(),
+        last_changed: 0.0,
     }
 }
 
@@ -193,14 +425,53 @@ impl HeadlessDevice {
             .unwrap())
     }
 
+    /// Wait until the next frame can begin: a short while, or with the manual
+    /// frame clock, until a test lets another frame end
+    fn wait_for_frame_clock(&self) {
+        let mut data = self.data.lock().unwrap();
+        if data.frames_allowed.is_none() {
+            drop(data);
+            thread::sleep(Duration::from_millis(20));
+            return;
+        }
+        let frame_clock = data.frame_clock.clone();
+        // Don't keep a disconnected device's sessions from ending
+        while !data.disconnected {
+            match data.frames_allowed {
+                Some(0) => data = frame_clock.wait(data).unwrap(),
+                Some(ref mut frames_allowed) => {
+                    *frames_allowed -= 1;
+                    return;
+                }
+                // Back on the timer, this frame has waited long enough
+                None => return,
+            }
+        }
+    }
+
     fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
         if let Some(ref mut manager) = self.layer_manager {
             return Ok(manager);
         }
         let swap_chains = SwapChains::new();
         let viewports = self.viewports();
+        let data = self.data.clone();
+        let export_layer_contents = data.lock().unwrap().export_layer_contents;
         let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
-            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+            let layer_manager = SurfmanLayerManager::new(viewports, swap_chains);
+            if !export_layer_contents {
+                return Ok(layer_manager);
+            }
+            let callback = move |layer_id, size, pixels| {
+                let mut data = data.lock().unwrap();
+                data.layer_contents.retain(|c| c.layer_id != layer_id);
+                data.layer_contents.push(MockLayerContents {
+                    layer_id,
+                    size,
+                    data: pixels,
+                });
+            };
+            Ok(layer_manager.with_contents_callback(Box::new(callback)))
         })?;
         self.layer_manager = Some(layer_manager);
         Ok(self.layer_manager.as_mut().unwrap())
@@ -212,6 +483,10 @@ impl DeviceAPI for HeadlessDevice {
         self.data.lock().unwrap().floor_transform.clone()
     }
 
+    fn visibility(&self) -> Visibility {
+        self.data.lock().unwrap().visibility
+    }
+
     fn viewports(&self) -> Viewports {
         let d = self.data.lock().unwrap();
         let per_session = d.sessions.iter().find(|s| s.id == self.id).unwrap();
@@ -228,12 +503,39 @@ impl DeviceAPI for HeadlessDevice {
             .destroy_layer(context_id, layer_id)
     }
 
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.layer_manager()?
+            .migrate_context(old_context_id, new_context_id)
+    }
+
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
-        let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+        if let (Some(rate), Some(last_frame_time)) = (self.frame_rate, self.last_frame_time) {
+            let next_frame_time = last_frame_time + Duration::from_secs_f32(1.0 / rate);
+            let now = Instant::now();
+            if next_frame_time > now {
+                thread::sleep(next_frame_time - now);
+            }
+        }
+        self.last_frame_time = Some(Instant::now());
+        self.viewport_scales.commit_scales();
+        let mut sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+        for sub_images in &mut sub_images {
+            for (view, sub_image) in sub_images.view_sub_images.iter_mut().enumerate() {
+                sub_image.viewport = self.viewport_scales.scaled(view, sub_image.viewport);
+            }
+        }
         let mut data = self.data.lock().unwrap();
+        data.play_timeline();
+        data.sync_room();
+        let period = self.frame_rate.map(|rate| 1e9 / rate as f64);
         let mut frame = data.get_frame(
             data.sessions.iter().find(|s| s.id == self.id).unwrap(),
             sub_images,
+            period,
         );
         let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
         if per_session.needs_vp_update {
@@ -242,43 +544,138 @@ impl DeviceAPI for HeadlessDevice {
             let vp = data.viewports(mode);
             frame.events.push(FrameUpdateEvent::UpdateViewports(vp));
         }
-        let events = self.hit_tests.commit_tests();
-        frame.events = events;
+        let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+        if per_session.needs_bounds_update {
+            per_session.needs_bounds_update = false;
+            frame.events.push(FrameUpdateEvent::UpdateBoundsGeometry(
+                data.bounds_geometry.clone(),
+            ));
+        }
+        frame.events.extend(self.hit_tests.commit_tests());
+        let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+        frame.events.extend(
+            per_session
+                .extension_events
+                .drain(..)
+                .map(FrameUpdateEvent::Extension),
+        );
 
-        if let Some(ref world) = data.world {
+        let hit_tests = self.frame_subscriptions.hit_tests;
+        if let Some(bvh) = data.world_bvh.as_ref().filter(|_| hit_tests) {
+            let max_results = self.hit_tests.limits().max_results_per_source;
             for source in self.hit_tests.tests() {
                 let ray = data.native_ray(source.ray, source.space);
                 let ray = if let Some(ray) = ray { ray } else { break };
-                let hits = world
-                    .regions
-                    .iter()
-                    .filter(|region| source.types.is_type(region.ty))
-                    .flat_map(|region| &region.faces)
-                    .filter_map(|triangle| triangle.intersect(ray))
-                    .map(|space| HitTestResult {
-                        space,
+                let hits = bvh
+                    .intersect(ray, source.types)
+                    .into_iter()
+                    .take(max_results)
+                    .map(|hit| HitTestResult {
                         id: source.id,
+                        space: hit.space,
+                        normal: hit.normal,
+                        distance: hit.distance,
+                        entity: hit.entity,
+                        entity_type: hit.entity_type,
                     });
                 frame.hit_test_results.extend(hits);
             }
         }
 
+        if self.frame_subscriptions.meshes
+            && self.granted_features.contains(&Feature::MeshDetection)
+        {
+            let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+            let needs_mesh_update = std::mem::replace(&mut per_session.needs_mesh_update, false);
+            if needs_mesh_update {
+                self.meshes.clear();
+                if let Some(ref world) = data.world {
+                    let regions = world.regions.iter().enumerate();
+                    for (i, region) in regions.filter(|(_, r)| matches!(r.ty, EntityType::Mesh)) {
+                        self.meshes
+                            .update_mesh(detected_mesh(MeshId(i as u32), region));
+                    }
+                }
+            }
+            let events = self.meshes.commit_changes(util::MESH_VERTEX_BUDGET);
+            frame.events.extend(events);
+        }
+
+        if !self.granted_features.contains(&Feature::HandTracking) {
+            for input in &mut frame.inputs {
+                input.hand = None;
+            }
+        } else if frame.inputs.iter().any(|input| input.hand.is_some()) {
+            let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+            self.feature_usage
+                .used(Feature::HandTracking, &mut per_session.events);
+        }
+
+        let focused = data.focused();
+        if self.granted_features.contains(&Feature::EyeTracking) {
+            // Like the other inputs, the gaze isn't reported while the session is blurred
+            let eye_gaze = data.eye_gaze().filter(|_| focused);
+            if eye_gaze.is_some() {
+                let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+                self.feature_usage
+                    .used(Feature::EyeTracking, &mut per_session.events);
+            }
+            frame.inputs.push(InputFrame {
+                id: EYE_GAZE_INPUT_ID,
+                target_ray_origin: eye_gaze,
+                grip_origin: None,
+                linear_velocity: None,
+                angular_velocity: None,
+                pressed: false,
+                squeezed: false,
+                select_value: 0.0,
+                squeeze_value: 0.0,
+                hand: None,
+                button_values: vec![],
+                axis_values: vec![],
+                buttons_pressed: 0,
+                buttons_touched: 0,
+                input_changed: false,
+            });
+        }
+
         if data.needs_floor_update {
             frame.events.push(FrameUpdateEvent::UpdateFloorTransform(
                 data.floor_transform.clone(),
             ));
             data.needs_floor_update = false;
         }
+
+        if let Some(ref mut dwell_select) = self.dwell_select {
+            let gaze_inputs: Vec<_> = data
+                .inputs
+                .iter()
+                .filter(|i| matches!(i.source.target_ray_mode, TargetRayMode::Gaze))
+                .map(|i| i.source.id)
+                .chain(Some(EYE_GAZE_INPUT_ID))
+                .collect();
+            let id = self.id;
+            let per_session = data.sessions.iter_mut().find(|s| s.id == id).unwrap();
+            if focused {
+                dwell_select.update(&frame, &gaze_inputs, &mut per_session.events);
+            } else {
+                dwell_select.reset(&mut per_session.events);
+            }
+        }
         Some(frame)
     }
 
     fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
         let _ = self.layer_manager().unwrap().end_frame(layers);
-        thread::sleep(std::time::Duration::from_millis(20));
+        self.wait_for_frame_clock();
     }
 
     fn initial_inputs(&self) -> Vec<InputSource> {
-        vec![]
+        if self.granted_features.contains(&Feature::EyeTracking) {
+            vec![InputSource::eye_gaze(EYE_GAZE_INPUT_ID)]
+        } else {
+            vec![]
+        }
     }
 
     fn set_event_dest(&mut self, dest: Sender<Event>) {
@@ -289,26 +686,116 @@ impl DeviceAPI for HeadlessDevice {
         self.with_per_session(|s| s.events.callback(Event::SessionEnd))
     }
 
+    fn shutdown(&mut self) {
+        self.layer_manager = None;
+        self.data
+            .lock()
+            .unwrap()
+            .sessions
+            .retain(|s| s.id != self.id);
+    }
+
     fn set_quitter(&mut self, quitter: Quitter) {
         self.with_per_session(|s| s.quitter = Some(quitter))
     }
 
+    fn request_viewport_scale(&mut self, view: usize, scale: f32) {
+        self.viewport_scales.request(view, scale);
+    }
+
+    fn set_vignette(&mut self, strength: f32) {
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_vignette(strength);
+        }
+    }
+
+    fn set_latency_marker(&mut self, marker: Option<LatencyMarker>) {
+        if let Ok(layer_manager) = self.layer_manager() {
+            layer_manager.set_latency_marker(marker);
+        }
+    }
+
     fn update_clip_planes(&mut self, near: f32, far: f32) {
         self.with_per_session(|s| s.clip_planes.update(near, far));
     }
 
-    fn granted_features(&self) -> &[String] {
+    fn set_frame_subscriptions(&mut self, subscriptions: FrameSubscriptions) {
+        self.frame_subscriptions = subscriptions;
+        self.with_per_session(|s| s.depth_subscribed = subscriptions.depth);
+    }
+
+    fn set_dwell_select(&mut self, options: Option<DwellOptions>) {
+        self.dwell_select = options.map(DwellSelect::new);
+    }
+
+    fn set_mono_rendering(&mut self, mono: bool) {
+        self.with_per_session(|s| s.mono_rendering = mono);
+    }
+
+    fn resources(&self) -> DeviceResources {
+        DeviceResources {
+            swapchains: self
+                .layer_manager
+                .as_ref()
+                .and_then(LayerManager::swapchain_count),
+            texture_memory: self
+                .layer_manager
+                .as_ref()
+                .and_then(LayerManager::texture_memory),
+            buffered_events: Some(self.with_per_session(|s| s.events.buffered())),
+        }
+    }
+
+    fn update_frame_rate(&mut self, rate: f32) -> f32 {
+        let rate = SUPPORTED_FRAME_RATES
+            .iter()
+            .cloned()
+            .min_by(|a, b| {
+                let (a, b) = ((a - rate).abs(), (b - rate).abs());
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        if self.frame_rate != Some(rate) {
+            self.frame_rate = Some(rate);
+            self.with_per_session(|s| s.events.callback(Event::FrameRateChanged(rate)));
+        }
+        rate
+    }
+
+    fn supported_frame_rates(&self) -> Vec<f32> {
+        SUPPORTED_FRAME_RATES.to_vec()
+    }
+
+    fn granted_features(&self) -> &[Feature] {
         &self.granted_features
     }
 
+    fn supports_hit_test(&self) -> bool {
+        true
+    }
+
     fn request_hit_test(&mut self, source: HitTestSource) {
         self.hit_tests.request_hit_test(source)
     }
 
+    fn hit_test_limits(&self) -> HitTestLimits {
+        self.hit_tests.limits().clone()
+    }
+
     fn cancel_hit_test(&mut self, id: HitTestId) {
         self.hit_tests.cancel_hit_test(id)
     }
 
+    fn trigger_haptic(&mut self, id: InputId, intensity: f32, duration: f64) {
+        let mut data = self.data.lock().unwrap();
+        if let Some(input) = data.inputs.iter_mut().find(|i| i.source.id == id) {
+            input.haptic_pulses.push(MockHapticPulse {
+                intensity,
+                duration,
+            });
+        }
+    }
+
     fn reference_space_bounds(&self) -> Option<Vec<Point2D<f32, Floor>>> {
         let bounds = self.data.lock().unwrap().bounds_geometry.clone();
         Some(bounds)
@@ -317,7 +804,20 @@ impl DeviceAPI for HeadlessDevice {
 
 impl HeadlessMockDiscovery {
     pub fn new() -> HeadlessMockDiscovery {
-        HeadlessMockDiscovery {}
+        HeadlessMockDiscovery {
+            rooms: HashMap::new(),
+        }
+    }
+
+    /// The room with this name, which is created if no device is in it yet
+    fn room(&mut self, name: String) -> Arc<Mutex<SharedRoom>> {
+        self.rooms.retain(|_, room| room.strong_count() > 0);
+        if let Some(room) = self.rooms.get(&name).and_then(Weak::upgrade) {
+            return room;
+        }
+        let room = Arc::new(Mutex::new(SharedRoom::default()));
+        self.rooms.insert(name, Arc::downgrade(&room));
+        room
     }
 }
 
@@ -330,11 +830,18 @@ macro_rules! with_all_sessions {
 }
 
 impl HeadlessDeviceData {
-    fn get_frame(&self, s: &PerSessionData, sub_images: Vec<SubImages>) -> Frame {
+    /// A frame for session `s`, which is displayed a display `period` from now if it has one.
+    /// Frames sent with events aren't displayed, so they have no period.
+    fn get_frame(
+        &self,
+        s: &PerSessionData,
+        sub_images: Vec<SubImages>,
+        period: Option<f64>,
+    ) -> Frame {
         let views = self.views.clone();
 
         let pose = self.viewer_origin.map(|transform| {
-            let views = if s.mode == SessionMode::Inline {
+            let mut views = if s.mode == SessionMode::Inline {
                 Views::Inline
             } else {
                 match views {
@@ -343,24 +850,64 @@ impl HeadlessDeviceData {
                         view(one, transform, s.clip_planes),
                         view(two, transform, s.clip_planes),
                     ),
+                    MockViewsInit::StereoSecondary(one, two, secondaries) => {
+                        let one = view(one, transform, s.clip_planes);
+                        let two = view(two, transform, s.clip_planes);
+                        if s.secondary_views && self.secondary_views_active {
+                            let secondaries = secondaries
+                                .into_iter()
+                                .map(|secondary| view(secondary, transform, s.clip_planes))
+                                .collect();
+                            Views::StereoSecondary(one, two, secondaries)
+                        } else {
+                            Views::Stereo(one, two)
+                        }
+                    }
+                    MockViewsInit::Custom(views) => Views::Custom(
+                        views
+                            .into_iter()
+                            .map(|one| view(one, transform, s.clip_planes))
+                            .collect(),
+                    ),
                 }
             };
+            if s.mono_rendering {
+                collapse_ipd(&mut views);
+            }
 
-            ViewerPose { transform, views }
+            ViewerPose {
+                transform,
+                linear_velocity: None,
+                angular_velocity: None,
+                views,
+            }
         });
+        // Inputs stay connected while the session is blurred, but aren't tracked
+        let focused = self.focused();
         let inputs = self
             .inputs
             .iter()
             .filter(|i| i.active)
             .map(|i| InputFrame {
                 id: i.source.id,
-                target_ray_origin: i.pointer,
-                grip_origin: i.grip,
+                target_ray_origin: i.pointer.filter(|_| focused),
+                grip_origin: i.grip.filter(|_| focused),
+                linear_velocity: None,
+                angular_velocity: None,
                 pressed: false,
                 squeezed: false,
-                hand: None,
-                button_values: vec![],
-                axis_values: vec![],
+                select_value: 0.0,
+                squeeze_value: 0.0,
+                hand: i.hand.clone().filter(|_| focused),
+                button_values: i.buttons.iter().map(|b| b.pressed_value).collect(),
+                axis_values: i
+                    .buttons
+                    .iter()
+                    .filter(|b| has_axes(&b.button_type))
+                    .flat_map(|b| vec![b.x_value, b.y_value])
+                    .collect(),
+                buttons_pressed: button_mask(&i.buttons, |b| b.pressed),
+                buttons_touched: button_mask(&i.buttons, |b| b.touched),
                 input_changed: false,
             })
             .collect();
@@ -370,10 +917,39 @@ impl HeadlessDeviceData {
             events: vec![],
             sub_images,
             hit_test_results: vec![],
-            predicted_display_time: 0.0,
+            tracked_images: self
+                .tracked_images
+                .iter()
+                .filter(|image| (image.index as usize) < s.tracked_images)
+                .copied()
+                .collect(),
+            dom_overlay: self.dom_overlay(s),
+            depth_information: self
+                .depth_sensing_data
+                .as_ref()
+                .filter(|_| s.depth_sensing && s.depth_subscribed)
+                .cloned(),
+            predicted_display_time: self.start.elapsed().as_nanos() as f64 + period.unwrap_or(0.0),
+            predicted_display_period: period,
+            render_budget: None,
         }
     }
 
+    /// A handheld device's overlay covers the whole screen
+    fn dom_overlay(&self, s: &PerSessionData) -> Option<DomOverlayState> {
+        if !s.dom_overlay {
+            return None;
+        }
+        let viewports = self.viewports(s.mode).viewports;
+        let rect = viewports
+            .iter()
+            .fold(Rect::zero(), |rect, viewport| rect.union(viewport));
+        Some(DomOverlayState {
+            overlay_type: DomOverlayType::Screen,
+            rect: rect.to_f32(),
+        })
+    }
+
     fn viewports(&self, mode: SessionMode) -> Viewports {
         let vec = if mode == SessionMode::Inline {
             vec![]
@@ -381,24 +957,149 @@ impl HeadlessDeviceData {
             match &self.views {
                 MockViewsInit::Mono(one) => vec![one.viewport],
                 MockViewsInit::Stereo(one, two) => vec![one.viewport, two.viewport],
+                MockViewsInit::StereoSecondary(one, two, secondaries) => {
+                    let secondaries = secondaries.iter().map(|secondary| secondary.viewport);
+                    vec![one.viewport, two.viewport]
+                        .into_iter()
+                        .chain(secondaries)
+                        .collect()
+                }
+                MockViewsInit::Custom(views) => views.iter().map(|view| view.viewport).collect(),
             }
         };
         Viewports { viewports: vec }
     }
 
-    fn trigger_select(&mut self, id: InputId, kind: SelectKind, event: SelectEvent) {
+    fn set_visibility(&mut self, visibility: Visibility) {
+        if self.visibility != visibility {
+            self.visibility = visibility;
+            with_all_sessions!(self, |s| s
+                .events
+                .callback(Event::VisibilityChange(visibility)))
+        }
+    }
+
+    /// Like real devices, input is only reported to sessions that are visible and focused
+    fn focused(&self) -> bool {
+        self.visibility == Visibility::Visible
+    }
+
+    fn simulate_screen_tap(&mut self, origin: RigidTransform3D<f32, Input, Native>) {
+        if !self.focused() {
+            return;
+        }
+        let id = self.transient_input_ids.next_id();
+        let source = InputSource::screen_touch(id);
+        let input_frame = InputFrame {
+            id,
+            target_ray_origin: Some(origin),
+            grip_origin: None,
+            linear_velocity: None,
+            angular_velocity: None,
+            pressed: true,
+            squeezed: false,
+            select_value: 1.0,
+            squeeze_value: 0.0,
+            hand: None,
+            button_values: vec![],
+            axis_values: vec![],
+            buttons_pressed: 0,
+            buttons_touched: 0,
+            input_changed: false,
+        };
         for i in 0..self.sessions.len() {
-            let frame = self.get_frame(&self.sessions[i], Vec::new());
+            let mut frame = self.get_frame(&self.sessions[i], Vec::new(), None);
+            frame.inputs.push(input_frame.clone());
+            let frame = Arc::new(frame);
+            let events = &mut self.sessions[i].events;
+            events.callback(Event::TransientInputStart(
+                source.clone(),
+                input_frame.clone(),
+            ));
+            for &event in &[SelectEvent::Start, SelectEvent::Select] {
+                events.callback(Event::Select(
+                    id,
+                    SelectKind::Select,
+                    event,
+                    SelectGesture::ScreenTap,
+                    frame.clone(),
+                ));
+            }
+            events.callback(Event::TransientInputEnd(id));
+        }
+    }
+
+    fn trigger_select(
+        &mut self,
+        id: InputId,
+        kind: SelectKind,
+        event: SelectEvent,
+        gesture: SelectGesture,
+    ) {
+        for i in 0..self.sessions.len() {
+            let frame = Arc::new(self.get_frame(&self.sessions[i], Vec::new(), None));
             self.sessions[i]
                 .events
-                .callback(Event::Select(id, kind, event, frame));
+                .callback(Event::Select(id, kind, event, gesture, frame));
+        }
+    }
+
+    /// Change the world, which changes it for every device in the room if the device is in one
+    fn set_world(&mut self, world: Option<MockWorld>) {
+        if let Some(ref room) = self.room {
+            let mut room = room.lock().unwrap();
+            room.world = world.map(|world| transform_world(&world, &self.room_origin));
+            room.version += 1;
+            drop(room);
+            self.sync_room();
+            return;
+        }
+        self.world_bvh = world.as_ref().map(world_bvh);
+        self.world = world;
+        with_all_sessions!(self, |s| s.needs_mesh_update = true)
+    }
+
+    /// Pick up the room's world if another device in it changed it. Devices only ever
+    /// lock the room while holding their own data, never the other way around.
+    fn sync_room(&mut self) {
+        let room = match self.room {
+            Some(ref room) => room.clone(),
+            None => return,
+        };
+        let room = room.lock().unwrap();
+        if self.room_version == Some(room.version) {
+            return;
+        }
+        self.room_version = Some(room.version);
+        let world = room
+            .world
+            .as_ref()
+            .map(|world| transform_world(world, &self.room_origin.inverse()));
+        drop(room);
+        self.world_bvh = world.as_ref().map(world_bvh);
+        self.world = world;
+        with_all_sessions!(self, |s| s.needs_mesh_update = true)
+    }
+
+    /// Start a frame, handling the timeline messages that are due in it
+    fn play_timeline(&mut self) {
+        self.frame_count += 1;
+        let frame_count = self.frame_count;
+        let due = self
+            .timeline
+            .iter()
+            .take_while(|&&(frame, _)| frame <= frame_count)
+            .count();
+        let due: Vec<_> = self.timeline.drain(..due).collect();
+        for (_, msg) in due {
+            self.handle_msg(msg);
         }
     }
 
     fn handle_msg(&mut self, msg: MockDeviceMsg) -> bool {
         match msg {
-            MockDeviceMsg::SetWorld(w) => self.world = Some(w),
-            MockDeviceMsg::ClearWorld => self.world = None,
+            MockDeviceMsg::SetWorld(w) => self.set_world(Some(w)),
+            MockDeviceMsg::ClearWorld => self.set_world(None),
             MockDeviceMsg::SetViewerOrigin(viewer_origin) => {
                 self.viewer_origin = viewer_origin;
             }
@@ -412,9 +1113,28 @@ impl HeadlessDeviceData {
                     s.needs_vp_update = true;
                 })
             }
+            MockDeviceMsg::SetSecondaryViewsActive(active) => {
+                self.secondary_views_active = active;
+            }
+            MockDeviceMsg::SetSupportedFeatures(features) => {
+                self.supported_features = features;
+            }
+            MockDeviceMsg::SetManualFrameClock(manual) => {
+                self.frames_allowed = if manual { Some(0) } else { None };
+                self.frame_clock.notify_all();
+            }
+            MockDeviceMsg::AdvanceFrame(frames) => {
+                if let Some(ref mut frames_allowed) = self.frames_allowed {
+                    *frames_allowed += frames as u64;
+                    self.frame_clock.notify_all();
+                }
+            }
             MockDeviceMsg::VisibilityChange(v) => {
+                self.visibility = v;
                 with_all_sessions!(self, |s| s.events.callback(Event::VisibilityChange(v)))
             }
+            MockDeviceMsg::Focus => self.set_visibility(Visibility::Visible),
+            MockDeviceMsg::Blur => self.set_visibility(Visibility::VisibleBlurred),
             MockDeviceMsg::AddInputSource(init) => {
                 self.inputs.push(InputInfo {
                     source: init.source.clone(),
@@ -423,6 +1143,8 @@ impl HeadlessDeviceData {
                     active: true,
                     clicking: false,
                     buttons: init.supported_buttons,
+                    haptic_pulses: vec![],
+                    hand: None,
                 });
                 with_all_sessions!(self, |s| s
                     .events
@@ -454,27 +1176,42 @@ impl HeadlessDeviceData {
                         }
                         MockInputMsg::SetPointerOrigin(p) => input.pointer = p,
                         MockInputMsg::SetGripOrigin(p) => input.grip = p,
+                        MockInputMsg::SetHandJoints(hand) => {
+                            let hand_support = hand.map(|joint, _| joint.map(|_| ()));
+                            input.hand = Some(Arc::new(*hand));
+                            if input.source.hand_support.as_ref() != Some(&hand_support) {
+                                input.source.hand_support = Some(hand_support);
+                                with_all_sessions!(self, |s| s
+                                    .events
+                                    .callback(Event::UpdateInput(id, input.source.clone())));
+                            }
+                        }
                         MockInputMsg::TriggerSelect(kind, event) => {
                             if !input.active {
                                 return true;
                             }
+                            let focused = self.visibility == Visibility::Visible;
                             let clicking = input.clicking;
-                            input.clicking = event == SelectEvent::Start;
+                            input.clicking = focused && event == SelectEvent::Start;
+                            let gesture =
+                                SelectGesture::typical(input.source.target_ray_mode, kind);
                             match event {
-                                SelectEvent::Start => {
-                                    self.trigger_select(id, kind, event);
+                                SelectEvent::Start if focused => {
+                                    self.trigger_select(id, kind, event, gesture);
                                 }
                                 SelectEvent::End => {
-                                    if clicking {
-                                        self.trigger_select(id, kind, SelectEvent::Select);
-                                    } else {
-                                        self.trigger_select(id, kind, SelectEvent::End);
+                                    if clicking && focused {
+                                        self.trigger_select(id, kind, SelectEvent::Select, gesture);
+                                    } else if clicking || focused {
+                                        // A select that started before a blur is cancelled
+                                        self.trigger_select(id, kind, SelectEvent::End, gesture);
                                     }
                                 }
-                                SelectEvent::Select => {
-                                    self.trigger_select(id, kind, SelectEvent::Start);
-                                    self.trigger_select(id, kind, SelectEvent::Select);
+                                SelectEvent::Select if focused => {
+                                    self.trigger_select(id, kind, SelectEvent::Start, gesture);
+                                    self.trigger_select(id, kind, SelectEvent::Select, gesture);
                                 }
+                                SelectEvent::Start | SelectEvent::Select => {}
                             }
                         }
                         MockInputMsg::Disconnect => {
@@ -510,18 +1247,49 @@ impl HeadlessDeviceData {
                                 *button = state;
                             }
                         }
+                        MockInputMsg::GetHapticPulses(sender) => {
+                            let _ = sender.send(std::mem::take(&mut input.haptic_pulses));
+                        }
                     }
                 }
             }
             MockDeviceMsg::Disconnect(s) => {
                 self.disconnected = true;
+                self.frame_clock.notify_all();
                 with_all_sessions!(self, |s| s.quitter.as_ref().map(|q| q.quit()));
+                if let Some(ref device_changes) = self.device_changes {
+                    device_changes.notify();
+                }
                 // notify the client that we're done disconnecting
                 let _ = s.send(());
                 return false;
             }
             MockDeviceMsg::SetBoundsGeometry(g) => {
                 self.bounds_geometry = g;
+                with_all_sessions!(self, |s| {
+                    s.needs_bounds_update = true;
+                })
+            }
+            MockDeviceMsg::GetLayerContents(sender) => {
+                let _ = sender.send(self.layer_contents.clone());
+            }
+            MockDeviceMsg::SetEyeGaze(eye_gaze) => {
+                self.eye_gaze = eye_gaze;
+            }
+            MockDeviceMsg::SimulateDomOverlayPointer(pointer) => {
+                for s in &mut self.sessions {
+                    if s.dom_overlay {
+                        s.events.callback(Event::DomOverlayPointer(pointer));
+                    }
+                }
+            }
+            MockDeviceMsg::SimulateExtensionEvent(event) => {
+                for s in &mut self.sessions {
+                    s.extension_events.push(event.clone());
+                }
+            }
+            MockDeviceMsg::SimulateScreenTap(origin) => {
+                self.simulate_screen_tap(origin);
             }
             MockDeviceMsg::SimulateResetPose => {
                 with_all_sessions!(self, |s| s.events.callback(Event::ReferenceSpaceChanged(
@@ -529,16 +1297,87 @@ impl HeadlessDeviceData {
                     RigidTransform3D::identity()
                 )));
             }
+            MockDeviceMsg::SimulateInteractionProfileChange(profiles, buttons) => {
+                let inputs = self
+                    .inputs
+                    .iter_mut()
+                    .filter(|input| input.source.target_ray_mode == TargetRayMode::TrackedPointer);
+                for input in inputs {
+                    input.source.profiles.clone_from(&profiles);
+                    input.buttons.clone_from(&buttons);
+                    if input.active {
+                        let id = input.source.id;
+                        for s in &mut self.sessions {
+                            s.events
+                                .callback(Event::UpdateInput(id, input.source.clone()));
+                        }
+                    }
+                }
+            }
+            MockDeviceMsg::PlayTimeline(entries) => {
+                let start = self.frame_count + 1;
+                self.timeline.extend(
+                    entries
+                        .into_iter()
+                        .map(|entry| (start + entry.frame as u64, entry.msg)),
+                );
+                // This is stable, so messages for the same frame stay in order
+                self.timeline.sort_by_key(|&(frame, _)| frame);
+            }
+            MockDeviceMsg::SetDepthSensingData(data) => {
+                self.depth_sensing_data = data;
+            }
+            MockDeviceMsg::SetTrackedImages(images) => {
+                self.tracked_images = images;
+            }
+            MockDeviceMsg::SetAnchorCreationResult(_) | MockDeviceMsg::MessageAnchor(..) => {
+                // Sessions can't create anchors yet, so there is nothing to apply these to
+            }
+            MockDeviceMsg::SetRoomOrigin(origin) => {
+                if self.room.is_some() {
+                    self.room_origin = origin;
+                    self.room_version = None;
+                    self.sync_room();
+                }
+            }
+            MockDeviceMsg::SetSharedAnchor(name, anchor) => {
+                if let Some(ref room) = self.room {
+                    let mut room = room.lock().unwrap();
+                    match anchor {
+                        Some(anchor) => {
+                            let anchor = anchor.then(&self.room_origin);
+                            room.anchors.insert(name, anchor);
+                        }
+                        None => {
+                            room.anchors.remove(&name);
+                        }
+                    }
+                }
+            }
+            MockDeviceMsg::GetSharedAnchor(name, sender) => {
+                let anchor = self.room.as_ref().and_then(|room| {
+                    let room = room.lock().unwrap();
+                    let anchor = room.anchors.get(&name)?;
+                    Some(anchor.then(&self.room_origin.inverse()))
+                });
+                let _ = sender.send(anchor);
+            }
         }
         true
     }
 
+    fn eye_gaze(&self) -> Option<RigidTransform3D<f32, Input, Native>> {
+        self.eye_gaze
+            .or_else(|| Some(self.viewer_origin?.cast_unit()))
+    }
+
     fn native_ray(&self, ray: Ray<ApiSpace>, space: Space) -> Option<Ray<Native>> {
         let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
             BaseSpace::Local => RigidTransform3D::identity(),
             BaseSpace::Floor => self.floor_transform?.inverse().cast_unit(),
             BaseSpace::Viewer => self.viewer_origin?.cast_unit(),
             BaseSpace::BoundedFloor => self.floor_transform?.inverse().cast_unit(),
+            BaseSpace::TargetRay(EYE_GAZE_INPUT_ID) => self.eye_gaze()?.cast_unit(),
             BaseSpace::TargetRay(id) => self
                 .inputs
                 .iter()
@@ -551,7 +1390,15 @@ impl HeadlessDeviceData {
                 .find(|i| i.source.id == id)?
                 .grip?
                 .cast_unit(),
-            BaseSpace::Joint(..) => panic!("Cannot request mocking backend with hands"),
+            BaseSpace::Joint(id, joint) => self
+                .inputs
+                .iter()
+                .find(|i| i.source.id == id)?
+                .hand
+                .as_ref()?
+                .get(joint)?
+                .pose
+                .cast_unit(),
         };
         let space_origin = space.offset.then(&origin);
 
@@ -562,3 +1409,20 @@ impl HeadlessDeviceData {
         })
     }
 }
+
+fn has_axes(button_type: &MockButtonType) -> bool {
+    match button_type {
+        MockButtonType::Touchpad | MockButtonType::Thumbstick => true,
+        MockButtonType::OptionalThumbstick => true,
+        MockButtonType::Grip | MockButtonType::OptionalButton => false,
+    }
+}
+
+fn button_mask(buttons: &[MockButton], f: impl Fn(&MockButton) -> bool) -> u32 {
+    buttons
+        .iter()
+        .take(32)
+        .enumerate()
+        .filter(|(_, button)| f(button))
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
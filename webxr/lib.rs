@@ -13,10 +13,23 @@ pub mod headless;
 #[cfg(feature = "openxr-api")]
 pub mod openxr;
 
+#[cfg(feature = "replay")]
+pub mod replay;
+
+#[cfg(feature = "simulator")]
+pub mod simulator;
+
+mod backends;
+pub use backends::backends;
+pub use backends::BackendInfo;
+pub use backends::BackendStatus;
+
 pub mod surfman_layer_manager;
+pub use surfman_layer_manager::LayerContentsCallback;
 pub use surfman_layer_manager::SurfmanGL;
 pub use surfman_layer_manager::SurfmanLayerManager;
 pub type MainThreadRegistry = webxr_api::MainThreadRegistry<surfman_layer_manager::SurfmanGL>;
 pub type Discovery = Box<dyn webxr_api::DiscoveryAPI<SurfmanGL>>;
 
 pub(crate) mod gl_utils;
+pub(crate) mod surface_accounting;
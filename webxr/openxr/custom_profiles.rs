@@ -0,0 +1,333 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Interaction profiles for controllers that the built-in list doesn't know about,
+//! loaded from a file provided by the embedder, so that new controllers can be
+//! supported by updating configuration rather than webxr.
+//!
+//! The file has a section for each profile, headed by the profile's path in brackets,
+//! followed by `key = value` lines. Lists are separated by commas, and lines starting
+//! with `#` are comments:
+//!
+//! ```text
+//! [/interaction_profiles/acme/rocket_controller]
+//! # The OpenXR extension the profile needs, if any
+//! extension = XR_ACME_rocket_controller_interaction
+//! # Trigger, grip, touchpad and thumbstick, which can be left empty
+//! buttons = trigger/value, squeeze/value, , thumbstick/click
+//! # Touchpad x and y, and thumbstick x and y
+//! axes = , , thumbstick/x, thumbstick/y
+//! # The two extra buttons on each controller, if it has them
+//! left_buttons = x/click, y/click
+//! right_buttons = a/click, b/click
+//! # Whether the controllers can vibrate, which is false if not given
+//! haptics = true
+//! # The WebXR input profiles, most specific first
+//! profiles = acme-rocket, generic-trigger-squeeze-thumbstick
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use webxr_api::Backend as ApiBackend;
+use webxr_api::Error;
+
+use super::interaction_profiles::{
+    InteractionProfile, InteractionProfileType, INTERACTION_PROFILES,
+};
+
+/// The profiles loaded last, which sessions starting afterwards bind. Reloading replaces
+/// them, and they are freed once nothing that got them before is using them.
+static LOADED_PROFILES: RwLock<Option<Arc<Vec<CustomProfile>>>> = RwLock::new(None);
+
+/// Load the interaction profiles in the file at `path`, replacing the ones loaded before,
+/// and return how many there are. Sessions that start afterwards use the new profiles, so
+/// this can be called again whenever the file changes. If any profile in the file is
+/// invalid, the error says where, and the profiles loaded before are kept.
+pub fn load_interaction_profiles(path: &Path) -> Result<usize, Error> {
+    let error = |line: Option<usize>, message: String| {
+        let location = match line {
+            Some(line) => format!("{}:{}", path.display(), line),
+            None => path.display().to_string(),
+        };
+        Error::BackendSpecific(
+            ApiBackend::OpenXr,
+            format!("Invalid interaction profiles in {}: {}", location, message),
+        )
+    };
+    let data = fs::read_to_string(path).map_err(|e| error(None, e.to_string()))?;
+    let definitions = parse(&data).map_err(|(line, message)| error(Some(line), message))?;
+    let profiles: Vec<_> = definitions
+        .into_iter()
+        .map(ProfileDefinition::into_profile)
+        .collect();
+    let count = profiles.len();
+    *LOADED_PROFILES.write().unwrap() = Some(Arc::new(profiles));
+    Ok(count)
+}
+
+/// The profiles loaded with `load_interaction_profiles`
+pub(crate) fn loaded_profiles() -> Arc<Vec<CustomProfile>> {
+    LOADED_PROFILES.read().unwrap().clone().unwrap_or_default()
+}
+
+/// A profile as it is written in the file
+#[derive(Default)]
+struct ProfileDefinition {
+    path: String,
+    /// The line of the section header, which errors about the whole profile point at
+    line: usize,
+    extension: Option<String>,
+    buttons: Option<Vec<String>>,
+    axes: Option<Vec<String>>,
+    left_buttons: Option<Vec<String>>,
+    right_buttons: Option<Vec<String>>,
+    profiles: Option<Vec<String>>,
+    haptics: Option<bool>,
+}
+
+type ParseError = (usize, String);
+
+fn parse(data: &str) -> Result<Vec<ProfileDefinition>, ParseError> {
+    let mut definitions: Vec<ProfileDefinition> = vec![];
+    let mut paths: HashSet<String> = INTERACTION_PROFILES
+        .iter()
+        .map(|profile| profile.path.to_owned())
+        .collect();
+    for (index, line) in data.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let path = header
+                .strip_suffix(']')
+                .ok_or((number, format!("missing `]` after `{}`", line)))?
+                .trim();
+            validate_profile_path(path).map_err(|message| (number, message))?;
+            if !paths.insert(path.to_owned()) {
+                return Err((number, format!("`{}` is already defined", path)));
+            }
+            if let Some(definition) = definitions.last() {
+                validate(definition)?;
+            }
+            definitions.push(ProfileDefinition {
+                path: path.to_owned(),
+                line: number,
+                ..ProfileDefinition::default()
+            });
+            continue;
+        }
+        let definition = definitions
+            .last_mut()
+            .ok_or((number, "expected a `[path]` header first".to_owned()))?;
+        let (key, value) = line
+            .split_once('=')
+            .ok_or((number, format!("expected `key = value`, found `{}`", line)))?;
+        let key = key.trim();
+        let value = value.trim();
+        let field = match key {
+            "extension" => {
+                if definition.extension.is_some() {
+                    return Err((number, "`extension` is given twice".to_owned()));
+                }
+                validate_extension(value).map_err(|message| (number, message))?;
+                definition.extension = Some(value.to_owned());
+                continue;
+            }
+            "haptics" => {
+                if definition.haptics.is_some() {
+                    return Err((number, "`haptics` is given twice".to_owned()));
+                }
+                let haptics = value.parse().map_err(|_| {
+                    (
+                        number,
+                        format!("`haptics` is `true` or `false`, not `{}`", value),
+                    )
+                })?;
+                definition.haptics = Some(haptics);
+                continue;
+            }
+            "buttons" => &mut definition.buttons,
+            "axes" => &mut definition.axes,
+            "left_buttons" => &mut definition.left_buttons,
+            "right_buttons" => &mut definition.right_buttons,
+            "profiles" => &mut definition.profiles,
+            _ => return Err((number, format!("unknown key `{}`", key))),
+        };
+        if field.is_some() {
+            return Err((number, format!("`{}` is given twice", key)));
+        }
+        let list = if value.is_empty() {
+            vec![]
+        } else {
+            value
+                .split(',')
+                .map(|item| item.trim().to_owned())
+                .collect()
+        };
+        validate_list(key, &list).map_err(|message| (number, message))?;
+        *field = Some(list);
+    }
+    if let Some(definition) = definitions.last() {
+        validate(definition)?;
+    }
+    Ok(definitions)
+}
+
+/// Check that a profile has what it needs to be bound, once all of its lines are read
+fn validate(definition: &ProfileDefinition) -> Result<(), ParseError> {
+    let error = |message: &str| {
+        (
+            definition.line,
+            format!("`{}` {}", definition.path, message),
+        )
+    };
+    match definition.buttons {
+        Some(ref buttons) if buttons.len() == 4 && !buttons[0].is_empty() => (),
+        Some(_) => return Err(error("needs four `buttons`, the first of which selects")),
+        None => return Err(error("has no `buttons`")),
+    }
+    if matches!(definition.axes, Some(ref axes) if axes.len() != 4) {
+        return Err(error("needs four `axes`"));
+    }
+    for buttons in &[&definition.left_buttons, &definition.right_buttons] {
+        if matches!(buttons, Some(buttons) if buttons.len() != 2) {
+            return Err(error(
+                "needs two `left_buttons` and `right_buttons`, if any",
+            ));
+        }
+    }
+    if !matches!(definition.profiles, Some(ref profiles) if !profiles.is_empty()) {
+        return Err(error("has no `profiles`"));
+    }
+    Ok(())
+}
+
+fn validate_profile_path(path: &str) -> Result<(), String> {
+    let components = path
+        .strip_prefix("/interaction_profiles/")
+        .map(|rest| rest.split('/').collect::<Vec<_>>());
+    match components {
+        Some(ref components)
+            if components.len() == 2 && components.iter().all(|c| is_path_component(c)) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "`{}` is not an interaction profile path like `/interaction_profiles/vendor/name`",
+            path
+        )),
+    }
+}
+
+fn validate_extension(extension: &str) -> Result<(), String> {
+    let valid = extension.starts_with("XR_")
+        && extension
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not an OpenXR extension name", extension))
+    }
+}
+
+fn validate_list(key: &str, list: &[String]) -> Result<(), String> {
+    for item in list {
+        let valid = match key {
+            "profiles" => {
+                !item.is_empty()
+                    && item
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            }
+            // Inputs can be left empty, but otherwise are paths under `/user/hand/*/input`
+            _ => item.is_empty() || item.split('/').all(is_path_component),
+        };
+        if !valid {
+            return Err(format!("`{}` is not valid in `{}`", item, key));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `component` can be part of an OpenXR path
+fn is_path_component(component: &str) -> bool {
+    !component.is_empty()
+        && component
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-.".contains(c))
+}
+
+impl ProfileDefinition {
+    /// The profile, once `validate` has checked that it has what it needs
+    fn into_profile(self) -> CustomProfile {
+        let list = |list: Option<Vec<String>>, default_len: usize| {
+            list.unwrap_or_else(|| vec![String::new(); default_len])
+        };
+        CustomProfile {
+            path: self.path,
+            // Extension names are null terminated, like the ones from `openxr::sys`
+            extension: self.extension.map(|extension| format!("{}\0", extension)),
+            lists: [
+                list(self.buttons, 4),
+                list(self.axes, 4),
+                list(self.left_buttons, 0),
+                list(self.right_buttons, 0),
+                list(self.profiles, 0),
+            ],
+            haptics: self.haptics.unwrap_or(false),
+        }
+    }
+}
+
+/// A loaded profile, which `InteractionProfile`s like the built-in ones borrow from
+pub(crate) struct CustomProfile {
+    path: String,
+    extension: Option<String>,
+    /// The buttons, axes, left and right buttons, and WebXR profiles
+    lists: [Vec<String>; 5],
+    haptics: bool,
+}
+
+/// The lists of a `CustomProfile`, as the slices of strings that `InteractionProfile` has
+pub(crate) struct ProfileLists<'a>([Vec<&'a str>; 5]);
+
+impl CustomProfile {
+    pub(crate) fn lists(&self) -> ProfileLists<'_> {
+        let list = |index: usize| self.lists[index].iter().map(String::as_str).collect();
+        ProfileLists([list(0), list(1), list(2), list(3), list(4)])
+    }
+
+    /// The profile like the built-in ones, given `lists` from `CustomProfile::lists`
+    pub(crate) fn interaction_profile<'a>(
+        &'a self,
+        lists: &'a ProfileLists<'a>,
+    ) -> InteractionProfile<'a> {
+        let [buttons, axes, left_buttons, right_buttons, profiles] = &lists.0;
+        InteractionProfile {
+            profile_type: InteractionProfileType::Custom,
+            path: &self.path,
+            required_extension: self
+                .extension
+                .as_ref()
+                .map(|extension| extension.as_bytes()),
+            standard_buttons: buttons,
+            standard_axes: axes,
+            left_buttons,
+            right_buttons,
+            has_haptics: self.haptics,
+            profiles,
+        }
+    }
+
+    /// The OpenXR extension the profile needs, if any, null terminated
+    pub(crate) fn extension(&self) -> Option<&str> {
+        self.extension.as_deref()
+    }
+}
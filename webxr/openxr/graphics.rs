@@ -4,13 +4,16 @@ use surfman::Context as SurfmanContext;
 use surfman::Device as SurfmanDevice;
 use surfman::Error as SurfmanError;
 use surfman::SurfaceTexture;
+use webxr_api::ColorFormat;
 use webxr_api::Error;
 
 pub enum GraphicsProvider {}
 
 pub trait GraphicsProviderMethods<G: Graphics> {
     fn enable_graphics_extensions(exts: &mut ExtensionSet);
-    fn pick_format(formats: &[u32]) -> u32;
+    /// The color format of a native swapchain format, if it can be used for layers
+    fn color_format(format: u32) -> Option<ColorFormat>;
+    fn native_format(format: ColorFormat) -> u32;
     fn create_session(
         device: &SurfmanDevice,
         instance: &Instance,
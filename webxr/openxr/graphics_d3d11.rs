@@ -1,7 +1,6 @@
 use std::{mem, ptr};
 
 use euclid::{Size2D, UnknownUnit};
-use log::warn;
 use openxr::d3d::{Requirements, SessionCreateInfoD3D11, D3D11};
 use openxr::{
     ExtensionSet, FormFactor, FrameStream, FrameWaiter, Graphics, Instance, Session, SystemId,
@@ -11,6 +10,8 @@ use surfman::Context as SurfmanContext;
 use surfman::Device as SurfmanDevice;
 use surfman::Error as SurfmanError;
 use surfman::SurfaceTexture;
+use webxr_api::Backend as ApiBackend;
+use webxr_api::ColorFormat;
 use webxr_api::Error;
 use winapi::shared::winerror::{DXGI_ERROR_NOT_FOUND, S_OK};
 use winapi::shared::{dxgi, dxgiformat};
@@ -28,23 +29,24 @@ impl GraphicsProviderMethods<D3D11> for GraphicsProvider {
         exts.khr_d3d11_enable = true;
     }
 
-    fn pick_format(formats: &[u32]) -> u32 {
+    fn color_format(format: u32) -> Option<ColorFormat> {
         // TODO: extract the format from surfman's device and pick a matching
         // valid format based on that. For now, assume that eglChooseConfig will
-        // gravitate to B8G8R8A8.
-        warn!("Available formats: {:?}", formats);
-        for format in formats {
-            match *format {
-                dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => return *format,
-                dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM => return *format,
-                //dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM => return *format,
-                f => {
-                    warn!("Backend requested unsupported format {:?}", f);
-                }
-            }
+        // gravitate to B8G8R8A8, so only those formats can be shared with surfman.
+        match format {
+            dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => Some(ColorFormat::Bgra8Srgb),
+            dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM => Some(ColorFormat::Bgra8),
+            _ => None,
         }
+    }
 
-        panic!("No formats supported amongst {:?}", formats);
+    fn native_format(format: ColorFormat) -> u32 {
+        match format {
+            ColorFormat::Rgba8 => dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
+            ColorFormat::Rgba8Srgb => dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+            ColorFormat::Bgra8 => dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM,
+            ColorFormat::Bgra8Srgb => dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+        }
     }
 
     fn create_session(
@@ -62,8 +64,9 @@ impl GraphicsProviderMethods<D3D11> for GraphicsProvider {
         //        already created is appropriate. OpenXR returns a validation error
         //        unless we call this method, so we call it and ignore the results
         //        in the short term.
-        let _requirements = D3D11::requirements(&instance, system)
-            .map_err(|e| Error::BackendSpecific(format!("D3D11::requirements {:?}", e)))?;
+        let _requirements = D3D11::requirements(&instance, system).map_err(|e| {
+            Error::BackendSpecific(ApiBackend::OpenXr, format!("D3D11::requirements {:?}", e))
+        })?;
 
         unsafe {
             instance
@@ -73,7 +76,12 @@ impl GraphicsProviderMethods<D3D11> for GraphicsProvider {
                         device: d3d_device as *mut _,
                     },
                 )
-                .map_err(|e| Error::BackendSpecific(format!("Instance::create_session {:?}", e)))
+                .map_err(|e| {
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Instance::create_session {:?}", e),
+                    )
+                })
         }
     }
 
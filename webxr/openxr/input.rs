@@ -1,16 +1,17 @@
 use std::ffi::c_void;
 use std::mem::MaybeUninit;
+use std::sync::Arc;
 
 use euclid::RigidTransform3D;
 use log::debug;
 use openxr::sys::{
     HandJointLocationsEXT, HandJointsLocateInfoEXT, HandTrackingAimStateFB,
-    FB_HAND_TRACKING_AIM_EXTENSION_NAME,
+    SimultaneousHandsAndControllersTrackingResumeInfoMETA, FB_HAND_TRACKING_AIM_EXTENSION_NAME,
 };
 use openxr::{
     self, Action, ActionSet, Binding, FrameState, Graphics, Hand as HandEnum, HandJoint,
-    HandJointLocation, HandTracker, HandTrackingAimFlagsFB, Instance, Path, Posef, Session, Space,
-    SpaceLocationFlags, HAND_JOINT_COUNT,
+    HandJointLocation, HandTracker, HandTrackingAimFlagsFB, Haptic, HapticVibration, Instance,
+    Path, Posef, Session, Space, SpaceLocationFlags, HAND_JOINT_COUNT,
 };
 use webxr_api::Finger;
 use webxr_api::Hand;
@@ -22,19 +23,28 @@ use webxr_api::InputSource;
 use webxr_api::JointFrame;
 use webxr_api::Native;
 use webxr_api::SelectEvent;
+use webxr_api::SelectGesture;
 use webxr_api::TargetRayMode;
 use webxr_api::Viewer;
 
-use super::interaction_profiles::InteractionProfile;
+use super::interaction_profiles::{
+    with_interaction_profiles, InteractionProfile, InteractionProfileType,
+};
+use super::Velocity;
 use super::IDENTITY_POSE;
 
 use crate::ext_string;
-use crate::openxr::interaction_profiles::INTERACTION_PROFILES;
 
 /// Number of frames to wait with the menu gesture before
 /// opening the menu.
 const MENU_GESTURE_SUSTAIN_THRESHOLD: u8 = 60;
 
+/// How far the select and squeeze buttons must be pressed to start a select or squeeze,
+/// and how far they must be let go to end it again, so that a trigger held near the
+/// threshold doesn't start and end selects every frame
+const PRESS_THRESHOLD: f32 = 0.7;
+const RELEASE_THRESHOLD: f32 = 0.3;
+
 /// Helper macro for binding action paths in an interaction profile entry
 macro_rules! bind_inputs {
     ($actions:expr, $paths:expr, $hand:expr, $instance:expr, $ret:expr) => {
@@ -60,27 +70,53 @@ enum ClickState {
     Done,
 }
 
+/// The frames of the input sources of one hand, along with the sources that content
+/// should be told about before the frame, and the ones that go away after it
+pub struct Frames {
+    pub frames: Vec<Frame>,
+    pub added: Vec<InputSource>,
+    /// Sources that changed, such as by switching target ray mode
+    pub updated: Vec<InputSource>,
+    pub removed: Vec<InputId>,
+}
+
 /// All the information on a single input frame
 pub struct Frame {
     pub frame: InputFrame,
     pub select: Option<SelectEvent>,
+    /// What causes `select`, which depends on whether the hand is tracked
+    pub select_gesture: SelectGesture,
     pub squeeze: Option<SelectEvent>,
     pub menu_selected: bool,
 }
 
-impl ClickState {
-    fn update_from_action<G: Graphics>(
-        &mut self,
-        action: &Action<bool>,
-        session: &Session<G>,
-        menu_selected: bool,
-    ) -> (/* is_active */ bool, Option<SelectEvent>) {
-        let click = action.state(session, Path::NULL).unwrap();
-
-        let select_event =
-            self.update_from_value(click.current_state, click.is_active, menu_selected);
+impl Frame {
+    /// Hide this input from content, for use while the session is not focused.
+    /// Any select that was in progress may still be cancelled.
+    pub fn suppress(&mut self) {
+        self.frame.target_ray_origin = None;
+        self.frame.grip_origin = None;
+        self.frame.linear_velocity = None;
+        self.frame.angular_velocity = None;
+        self.frame.hand = None;
+        self.frame.pressed = false;
+        self.frame.squeezed = false;
+        self.frame.select_value = 0.0;
+        self.frame.squeeze_value = 0.0;
+        self.frame.input_changed = false;
+        self.select = self.select.filter(|event| *event == SelectEvent::End);
+        self.squeeze = self.squeeze.filter(|event| *event == SelectEvent::End);
+        self.menu_selected = false;
+    }
+}
 
-        (click.is_active, select_event)
+impl ClickState {
+    /// Whether a button pressed as far as `value` counts as pressed
+    fn is_pressed(&self, value: f32) -> bool {
+        match *self {
+            ClickState::Clicking => value > RELEASE_THRESHOLD,
+            ClickState::Done => value >= PRESS_THRESHOLD,
+        }
     }
 
     fn update_from_value(
@@ -118,15 +154,29 @@ impl ClickState {
 
 pub struct OpenXRInput {
     id: InputId,
+    /// With multimodal input, where hands and controllers are tracked at the same time,
+    /// the hand is its own input source with this id, rather than part of the controller's
+    hand_id: Option<InputId>,
+    /// Whether content has been told about the controller and hand sources. With
+    /// multimodal input, they come and go as controllers are picked up and put down.
+    controller_added: bool,
+    hand_added: bool,
+    profiles: Vec<String>,
+    /// The target ray modes of the sources, which fall back to gaze when a hand
+    /// that is being tracked isn't pointing
+    target_ray_mode: TargetRayMode,
+    hand_target_ray_mode: TargetRayMode,
     action_aim_pose: Action<Posef>,
     action_aim_space: Space,
     action_grip_pose: Action<Posef>,
     action_grip_space: Space,
-    action_click: Action<bool>,
-    action_squeeze: Action<bool>,
+    action_click: Action<f32>,
+    action_squeeze: Action<f32>,
+    action_haptic: Action<Haptic>,
     handedness: Handedness,
     click_state: ClickState,
     squeeze_state: ClickState,
+    hand_click_state: ClickState,
     menu_gesture_sustain: u8,
     #[allow(unused)]
     hand_tracker: Option<HandTracker>,
@@ -148,11 +198,12 @@ fn hand_str(h: Handedness) -> &'static str {
 impl OpenXRInput {
     pub fn new<G: Graphics>(
         id: InputId,
+        hand_id: Option<InputId>,
         handedness: Handedness,
         action_set: &ActionSet,
         session: &Session<G>,
         needs_hands: bool,
-        supported_interaction_profiles: Vec<&'static str>,
+        supported_interaction_profiles: Vec<String>,
     ) -> Self {
         let hand = hand_str(handedness);
         let action_aim_pose: Action<Posef> = action_set
@@ -175,14 +226,14 @@ impl OpenXRInput {
         let action_grip_space = action_grip_pose
             .create_space(session.clone(), Path::NULL, IDENTITY_POSE)
             .unwrap();
-        let action_click: Action<bool> = action_set
+        let action_click: Action<f32> = action_set
             .create_action(
                 &format!("{}_hand_click", hand),
                 &format!("{} hand click", hand),
                 &[],
             )
             .unwrap();
-        let action_squeeze: Action<bool> = action_set
+        let action_squeeze: Action<f32> = action_set
             .create_action(
                 &format!("{}_hand_squeeze", hand),
                 &format!("{} hand squeeze", hand),
@@ -190,6 +241,14 @@ impl OpenXRInput {
             )
             .unwrap();
 
+        let action_haptic: Action<Haptic> = action_set
+            .create_action(
+                &format!("{}_hand_haptic", hand),
+                &format!("{} hand haptic", hand),
+                &[],
+            )
+            .unwrap();
+
         let hand_tracker = if needs_hands {
             let hand = match handedness {
                 Handedness::Left => HandEnum::LEFT,
@@ -282,19 +341,28 @@ impl OpenXRInput {
         };
 
         let use_alternate_input_source = supported_interaction_profiles
-            .contains(&ext_string!(FB_HAND_TRACKING_AIM_EXTENSION_NAME));
+            .iter()
+            .any(|extension| extension == ext_string!(FB_HAND_TRACKING_AIM_EXTENSION_NAME));
 
         Self {
             id,
+            hand_id,
+            controller_added: hand_id.is_none(),
+            hand_added: false,
+            profiles: vec![],
+            target_ray_mode: TargetRayMode::TrackedPointer,
+            hand_target_ray_mode: TargetRayMode::TrackedPointer,
             action_aim_pose,
             action_aim_space,
             action_grip_pose,
             action_grip_space,
             action_click,
             action_squeeze,
+            action_haptic,
             handedness,
             click_state: ClickState::Done,
             squeeze_state: ClickState::Done,
+            hand_click_state: ClickState::Done,
             menu_gesture_sustain: 0,
             hand_tracker,
             action_buttons_common,
@@ -309,11 +377,14 @@ impl OpenXRInput {
         instance: &Instance,
         session: &Session<G>,
         needs_hands: bool,
-        supported_interaction_profiles: Vec<&'static str>,
+        supports_multimodal_input: bool,
+        supported_interaction_profiles: Vec<String>,
     ) -> (ActionSet, Self, Self) {
+        let multimodal = supports_multimodal_input && resume_simultaneous_tracking(session);
         let action_set = instance.create_action_set("hands", "Hands", 0).unwrap();
         let right_hand = OpenXRInput::new(
             InputId(0),
+            Some(InputId(2)).filter(|_| multimodal),
             Handedness::Right,
             &action_set,
             &session,
@@ -322,6 +393,7 @@ impl OpenXRInput {
         );
         let left_hand = OpenXRInput::new(
             InputId(1),
+            Some(InputId(3)).filter(|_| multimodal),
             Handedness::Left,
             &action_set,
             &session,
@@ -329,38 +401,53 @@ impl OpenXRInput {
             supported_interaction_profiles.clone(),
         );
 
-        for profile in INTERACTION_PROFILES {
-            if let Some(extension_name) = profile.required_extension {
-                if !supported_interaction_profiles.contains(&ext_string!(extension_name)) {
-                    continue;
+        with_interaction_profiles(|profiles| {
+            for profile in profiles {
+                if let Some(extension_name) = profile.required_extension {
+                    let extension_name = ext_string!(extension_name);
+                    if !supported_interaction_profiles
+                        .iter()
+                        .any(|extension| extension == extension_name)
+                    {
+                        continue;
+                    }
                 }
-            }
 
-            if profile.path.is_empty() {
-                continue;
-            }
+                if profile.path.is_empty() {
+                    continue;
+                }
 
-            let select = profile.standard_buttons[0];
-            let squeeze = Option::from(profile.standard_buttons[1]).filter(|&s| !s.is_empty());
-            let mut bindings = right_hand.get_bindings(instance, select, squeeze, &profile);
-            bindings.extend(
-                left_hand
-                    .get_bindings(instance, select, squeeze, &profile)
-                    .into_iter(),
-            );
+                // Hands have their own sources, so only controllers are bound to the hand paths
+                let is_hand_profile = matches!(
+                    profile.profile_type,
+                    InteractionProfileType::ExtHandInteraction
+                );
+                if multimodal && is_hand_profile {
+                    continue;
+                }
 
-            let path_controller = instance
-                .string_to_path(profile.path)
-                .expect(format!("Invalid interaction profile path: {}", profile.path).as_str());
-            if let Err(_) =
-                instance.suggest_interaction_profile_bindings(path_controller, &bindings)
-            {
-                debug!(
-                    "Interaction profile path not available for this runtime: {:?}",
-                    profile.path
+                let select = profile.standard_buttons[0];
+                let squeeze = Option::from(profile.standard_buttons[1]).filter(|&s| !s.is_empty());
+                let mut bindings = right_hand.get_bindings(instance, select, squeeze, profile);
+                bindings.extend(
+                    left_hand
+                        .get_bindings(instance, select, squeeze, profile)
+                        .into_iter(),
                 );
+
+                let path_controller = instance
+                    .string_to_path(profile.path)
+                    .expect(format!("Invalid interaction profile path: {}", profile.path).as_str());
+                if let Err(_) =
+                    instance.suggest_interaction_profile_bindings(path_controller, &bindings)
+                {
+                    debug!(
+                        "Interaction profile path not available for this runtime: {:?}",
+                        profile.path
+                    );
+                }
             }
-        }
+        });
 
         session.attach_action_sets(&[&action_set]).unwrap();
 
@@ -409,6 +496,17 @@ impl OpenXRInput {
             ret.push(binding_squeeze);
         }
 
+        // Suggesting a binding for an output the profile doesn't have rejects them all
+        if interaction_profile.has_haptics {
+            let path_haptic = instance
+                .string_to_path(&format!("/user/hand/{}/output/haptic", hand))
+                .expect(&format!(
+                    "Failed to create path for /user/hand/{}/output/haptic",
+                    hand
+                ));
+            ret.push(Binding::new(&self.action_haptic, path_haptic));
+        }
+
         bind_inputs!(
             self.action_buttons_common,
             interaction_profile.standard_buttons,
@@ -452,11 +550,19 @@ impl OpenXRInput {
         frame_state: &FrameState,
         base_space: &Space,
         viewer: &RigidTransform3D<f32, Viewer, Native>,
-    ) -> Frame {
+        hand_joints: bool,
+    ) -> Frames {
         use euclid::Vector3D;
-        let mut target_ray_origin = pose_for(&self.action_aim_space, frame_state, base_space);
+        let (mut target_ray_origin, target_ray_velocity) =
+            pose_for(&self.action_aim_space, frame_state, base_space);
 
-        let grip_origin = pose_for(&self.action_grip_space, frame_state, base_space);
+        let (grip_origin, grip_velocity) =
+            pose_for(&self.action_grip_space, frame_state, base_space);
+        let (linear_velocity, angular_velocity) = if grip_origin.is_some() {
+            grip_velocity
+        } else {
+            target_ray_velocity
+        };
 
         let mut menu_selected = false;
         // Check if the palm is facing up. This is our "menu" gesture.
@@ -544,18 +650,30 @@ impl OpenXRInput {
 
         let input_changed = buttons_changed || axes_changed;
 
-        let (click_is_active, mut click_event) = if !self.use_alternate_input_source {
-            self.click_state
-                .update_from_action(&self.action_click, session, menu_selected)
+        // Whether the buttons are pressed depends on whether they were before
+        let click_pressed = self.click_state.is_pressed(click.current_state);
+        let squeeze_pressed = self.squeeze_state.is_pressed(squeeze.current_state);
+
+        // Without multimodal input, a tracked hand selects by pinching instead of the controller
+        let pinch_selects = self.use_alternate_input_source && self.hand_id.is_none();
+        let (click_is_active, mut click_event) = if !pinch_selects {
+            let click_event =
+                self.click_state
+                    .update_from_value(click_pressed, click.is_active, menu_selected);
+            (click.is_active, click_event)
         } else {
             (true, None)
         };
-        let (squeeze_is_active, squeeze_event) =
+        let squeeze_is_active = squeeze.is_active;
+        let squeeze_event =
             self.squeeze_state
-                .update_from_action(&self.action_squeeze, session, menu_selected);
+                .update_from_value(squeeze_pressed, squeeze_is_active, menu_selected);
 
         let mut aim_state: Option<HandTrackingAimStateFB> = None;
-        let hand = self.hand_tracker.as_ref().and_then(|tracker| {
+        // The alternate input source needs the aim state even if the joints aren't used
+        let locate_hands = hand_joints || self.use_alternate_input_source;
+        let hand_tracker = self.hand_tracker.as_ref().filter(|_| locate_hands);
+        let mut hand = hand_tracker.and_then(|tracker| {
             locate_hand(
                 base_space,
                 tracker,
@@ -566,11 +684,34 @@ impl OpenXRInput {
             )
         });
 
-        let mut pressed = click_is_active && click.current_state;
-        let squeezed = squeeze_is_active && squeeze.current_state;
+        // With multimodal input, the hand is reported as its own source
+        let hand_source = match self.hand_id {
+            Some(hand_id) => Some((hand_id, hand.take(), aim_state.take())),
+            None => None,
+        };
+        if !hand_joints {
+            hand = None;
+        }
+
+        let mut pressed = click_is_active && click_pressed;
+        let squeezed = squeeze_is_active && squeeze_pressed;
+        let mut select_value = if click_is_active {
+            click.current_state
+        } else {
+            0.0
+        };
+        let squeeze_value = if squeeze_is_active {
+            squeeze.current_state
+        } else {
+            0.0
+        };
+        let mut select_gesture = SelectGesture::Trigger;
+        let mut target_ray_mode = TargetRayMode::TrackedPointer;
 
         if let Some(state) = aim_state {
-            target_ray_origin.replace(super::transform(&state.aim_pose));
+            let (mode, origin) = aim_ray(&state, viewer);
+            target_ray_mode = mode;
+            target_ray_origin.replace(origin);
             let index_pinching = state
                 .status
                 .intersects(HandTrackingAimFlagsFB::INDEX_PINCHING);
@@ -578,6 +719,8 @@ impl OpenXRInput {
                 .click_state
                 .update_from_value(index_pinching, true, menu_selected);
             pressed = index_pinching;
+            select_value = state.pinch_strength_index;
+            select_gesture = SelectGesture::Pinch;
         }
 
         let input_frame = InputFrame {
@@ -585,23 +728,172 @@ impl OpenXRInput {
             id: self.id,
             pressed,
             squeezed,
+            select_value,
+            squeeze_value,
             grip_origin,
+            linear_velocity,
+            angular_velocity,
             hand,
+            buttons_pressed: button_mask(&button_values),
+            // There are no touch actions, so report pressed buttons as touched
+            buttons_touched: button_mask(&button_values),
             button_values,
             axis_values,
             input_changed,
         };
 
-        Frame {
+        let controller_frame = Frame {
             frame: input_frame,
             select: click_event,
+            select_gesture,
             squeeze: squeeze_event,
             menu_selected,
+        };
+
+        let mut frames = Frames {
+            frames: vec![],
+            added: vec![],
+            updated: vec![],
+            removed: vec![],
+        };
+        if target_ray_mode != self.target_ray_mode {
+            self.target_ray_mode = target_ray_mode;
+            if self.controller_added {
+                frames.updated.push(self.input_source());
+            }
+        }
+        let (hand_id, hand, aim_state) = match hand_source {
+            Some(hand_source) => hand_source,
+            None => {
+                frames.frames.push(controller_frame);
+                return frames;
+            }
+        };
+        // Runtimes only bind the controller actions to the hand paths while controllers are held
+        let controller_active = self
+            .action_aim_pose
+            .is_active(session, Path::NULL)
+            .unwrap_or(false);
+        if controller_active && !self.controller_added {
+            self.controller_added = true;
+            frames.added.push(self.input_source());
+        }
+        if self.controller_added {
+            frames.frames.push(controller_frame);
         }
+        if !controller_active && self.controller_added {
+            self.controller_added = false;
+            frames.removed.push(self.id);
+        }
+
+        let hand_target_ray_mode = self.hand_target_ray_mode;
+        let hand_frame = match (hand, aim_state) {
+            (Some(hand), Some(state)) => {
+                Some(self.hand_frame(hand_id, hand, state, viewer, hand_joints, menu_selected))
+            }
+            _ => None,
+        };
+        if self.hand_added && self.hand_target_ray_mode != hand_target_ray_mode {
+            frames.updated.push(self.hand_input_source(hand_id));
+        }
+        if hand_frame.is_some() && !self.hand_added {
+            self.hand_added = true;
+            frames.added.push(self.hand_input_source(hand_id));
+        }
+        match hand_frame {
+            Some(hand_frame) => frames.frames.push(hand_frame),
+            None if self.hand_added => {
+                self.hand_added = false;
+                // The hand's last frame ends any pinch it was making
+                let mut lost = Frame {
+                    frame: InputFrame {
+                        id: hand_id,
+                        ..empty_input_frame()
+                    },
+                    select: None,
+                    select_gesture: SelectGesture::Pinch,
+                    squeeze: None,
+                    menu_selected: false,
+                };
+                lost.select = self.hand_click_state.update_from_value(false, false, false);
+                frames.frames.push(lost);
+                frames.removed.push(hand_id);
+            }
+            None => {}
+        }
+        frames
+    }
+
+    /// The frame of the hand's own source, with multimodal input
+    fn hand_frame(
+        &mut self,
+        id: InputId,
+        hand: Arc<Hand<JointFrame>>,
+        state: HandTrackingAimStateFB,
+        viewer: &RigidTransform3D<f32, Viewer, Native>,
+        hand_joints: bool,
+        menu_selected: bool,
+    ) -> Frame {
+        let (target_ray_mode, target_ray_origin) = aim_ray(&state, viewer);
+        self.hand_target_ray_mode = target_ray_mode;
+        let pinching = state
+            .status
+            .intersects(HandTrackingAimFlagsFB::INDEX_PINCHING);
+        let select = self
+            .hand_click_state
+            .update_from_value(pinching, true, menu_selected);
+        Frame {
+            frame: InputFrame {
+                id,
+                target_ray_origin: Some(target_ray_origin),
+                pressed: pinching,
+                select_value: state.pinch_strength_index,
+                hand: Some(hand).filter(|_| hand_joints),
+                ..empty_input_frame()
+            },
+            select,
+            select_gesture: SelectGesture::Pinch,
+            squeeze: None,
+            menu_selected,
+        }
+    }
+
+    pub fn trigger_haptic<G: Graphics>(
+        &self,
+        session: &Session<G>,
+        intensity: f32,
+        duration: f64,
+    ) -> openxr::Result<()> {
+        let vibration = HapticVibration::new()
+            .amplitude(intensity.max(0.0).min(1.0))
+            .frequency(openxr::sys::FREQUENCY_UNSPECIFIED)
+            .duration(openxr::Duration::from_nanos((duration * 1_000_000.) as i64));
+        self.action_haptic
+            .apply_feedback(session, Path::NULL, &vibration)
+    }
+
+    /// Remember the profiles of the controller, for `input_source`
+    pub fn set_profiles(&mut self, profiles: Vec<String>) {
+        self.profiles = profiles;
+    }
+
+    /// The sources that content currently knows about
+    pub fn input_sources(&self) -> Vec<InputSource> {
+        let mut sources: Vec<_> = self.controller_source().into_iter().collect();
+        if let (true, Some(hand_id)) = (self.hand_added, self.hand_id) {
+            sources.push(self.hand_input_source(hand_id));
+        }
+        sources
     }
 
+    /// The controller's source, if content knows about it
+    pub fn controller_source(&self) -> Option<InputSource> {
+        Some(self.input_source()).filter(|_| self.controller_added)
+    }
+
+    /// The controller's source, or the hand's as well without multimodal input
     pub fn input_source(&self) -> InputSource {
-        let hand_support = if self.hand_tracker.is_some() {
+        let hand_support = if self.hand_tracker.is_some() && self.hand_id.is_none() {
             // openxr runtimes must always support all or none joints
             Some(Hand::<()>::default().map(|_, _| Some(())))
         } else {
@@ -610,29 +902,111 @@ impl OpenXRInput {
         InputSource {
             handedness: self.handedness,
             id: self.id,
-            target_ray_mode: TargetRayMode::TrackedPointer,
+            target_ray_mode: self.target_ray_mode,
             supports_grip: true,
-            profiles: vec![],
+            profiles: self.profiles.clone(),
             hand_support,
         }
     }
+
+    fn hand_input_source(&self, id: InputId) -> InputSource {
+        InputSource {
+            handedness: self.handedness,
+            id,
+            target_ray_mode: self.hand_target_ray_mode,
+            supports_grip: false,
+            profiles: vec!["generic-hand-select".into(), "generic-hand".into()],
+            hand_support: Some(Hand::<()>::default().map(|_, _| Some(()))),
+        }
+    }
+}
+
+/// The target ray of a tracked hand, which follows the viewer's gaze
+/// while the hand isn't pointing, for example because it is lowered
+fn aim_ray(
+    state: &HandTrackingAimStateFB,
+    viewer: &RigidTransform3D<f32, Viewer, Native>,
+) -> (TargetRayMode, RigidTransform3D<f32, Input, Native>) {
+    if state.status.intersects(HandTrackingAimFlagsFB::VALID) {
+        (
+            TargetRayMode::TrackedPointer,
+            super::transform(&state.aim_pose),
+        )
+    } else {
+        (TargetRayMode::Gaze, viewer.cast_unit())
+    }
+}
+
+/// Start tracking hands and controllers at the same time, returning whether the runtime did
+fn resume_simultaneous_tracking<G: Graphics>(session: &Session<G>) -> bool {
+    let instance = session.instance();
+    let raw = match instance
+        .exts()
+        .meta_simultaneous_hands_and_controllers
+        .as_ref()
+    {
+        Some(raw) => raw,
+        None => return false,
+    };
+    let info = SimultaneousHandsAndControllersTrackingResumeInfoMETA {
+        ty: SimultaneousHandsAndControllersTrackingResumeInfoMETA::TYPE,
+        next: std::ptr::null(),
+    };
+    let result = unsafe {
+        (raw.resume_simultaneous_hands_and_controllers_tracking)(session.as_raw(), &info)
+    };
+    if result != openxr::sys::Result::SUCCESS {
+        debug!(
+            "Couldn't track hands and controllers together: {:?}",
+            result
+        );
+        return false;
+    }
+    true
+}
+
+/// An input frame where nothing is tracked or pressed
+fn empty_input_frame() -> InputFrame {
+    InputFrame {
+        id: InputId(0),
+        target_ray_origin: None,
+        grip_origin: None,
+        linear_velocity: None,
+        angular_velocity: None,
+        pressed: false,
+        squeezed: false,
+        select_value: 0.0,
+        squeeze_value: 0.0,
+        hand: None,
+        button_values: vec![],
+        axis_values: vec![],
+        buttons_pressed: 0,
+        buttons_touched: 0,
+        input_changed: false,
+    }
 }
 
 fn pose_for(
     action_space: &Space,
     frame_state: &FrameState,
     base_space: &Space,
-) -> Option<RigidTransform3D<f32, Input, Native>> {
-    let location = action_space
-        .locate(base_space, frame_state.predicted_display_time)
+) -> (Option<RigidTransform3D<f32, Input, Native>>, Velocity) {
+    let (location, velocity) = action_space
+        .relate(base_space, frame_state.predicted_display_time)
         .unwrap();
     let pose_valid = location
         .location_flags
         .intersects(SpaceLocationFlags::POSITION_VALID | SpaceLocationFlags::ORIENTATION_VALID);
     if pose_valid {
-        Some(super::transform(&location.pose))
+        super::pose_audit::runtime_pose("input", &location.pose);
+        let velocity = super::velocity(
+            velocity.velocity_flags,
+            &velocity.linear_velocity,
+            &velocity.angular_velocity,
+        );
+        (Some(super::transform(&location.pose)), velocity)
     } else {
-        None
+        (None, (None, None))
     }
 }
 
@@ -643,11 +1017,13 @@ fn locate_hand<G: Graphics>(
     use_alternate_input_source: bool,
     session: &Session<G>,
     aim_state: &mut Option<HandTrackingAimStateFB>,
-) -> Option<Box<Hand<JointFrame>>> {
+) -> Option<Arc<Hand<JointFrame>>> {
     let mut state = HandTrackingAimStateFB::out(std::ptr::null_mut());
     let locations = {
         if !use_alternate_input_source {
-            base_space.locate_hand_joints(tracker, frame_state.predicted_display_time)
+            base_space
+                .relate_hand_joints(tracker, frame_state.predicted_display_time)
+                .map(|related| related.map(|(locations, velocities)| (locations, Some(velocities))))
         } else {
             let locate_info = HandJointsLocateInfoEXT {
                 ty: HandJointsLocateInfoEXT::TYPE,
@@ -677,7 +1053,8 @@ fn locate_hand<G: Graphics>(
                     ) {
                         openxr::sys::Result::SUCCESS if location_info.is_active.into() => {
                             aim_state.replace(state.assume_init());
-                            Some(locations.assume_init())
+                            // Joint velocities are not requested through this path
+                            Some((locations.assume_init(), None))
                         }
                         _ => None,
                     },
@@ -685,55 +1062,69 @@ fn locate_hand<G: Graphics>(
             }
         }
     };
-    let locations = if let Ok(Some(ref locations)) = locations {
+    let locations = if let Ok(Some((ref locations, ref velocities))) = locations {
+        let joint = |joint: HandJoint| {
+            let velocity = velocities.as_ref().map(|velocities| &velocities[joint]);
+            Some((&locations[joint], velocity))
+        };
         Hand {
-            wrist: Some(&locations[HandJoint::WRIST]),
-            thumb_metacarpal: Some(&locations[HandJoint::THUMB_METACARPAL]),
-            thumb_phalanx_proximal: Some(&locations[HandJoint::THUMB_PROXIMAL]),
-            thumb_phalanx_distal: Some(&locations[HandJoint::THUMB_DISTAL]),
-            thumb_phalanx_tip: Some(&locations[HandJoint::THUMB_TIP]),
+            wrist: joint(HandJoint::WRIST),
+            thumb_metacarpal: joint(HandJoint::THUMB_METACARPAL),
+            thumb_phalanx_proximal: joint(HandJoint::THUMB_PROXIMAL),
+            thumb_phalanx_distal: joint(HandJoint::THUMB_DISTAL),
+            thumb_phalanx_tip: joint(HandJoint::THUMB_TIP),
             index: Finger {
-                metacarpal: Some(&locations[HandJoint::INDEX_METACARPAL]),
-                phalanx_proximal: Some(&locations[HandJoint::INDEX_PROXIMAL]),
-                phalanx_intermediate: Some(&locations[HandJoint::INDEX_INTERMEDIATE]),
-                phalanx_distal: Some(&locations[HandJoint::INDEX_DISTAL]),
-                phalanx_tip: Some(&locations[HandJoint::INDEX_TIP]),
+                metacarpal: joint(HandJoint::INDEX_METACARPAL),
+                phalanx_proximal: joint(HandJoint::INDEX_PROXIMAL),
+                phalanx_intermediate: joint(HandJoint::INDEX_INTERMEDIATE),
+                phalanx_distal: joint(HandJoint::INDEX_DISTAL),
+                phalanx_tip: joint(HandJoint::INDEX_TIP),
             },
             middle: Finger {
-                metacarpal: Some(&locations[HandJoint::MIDDLE_METACARPAL]),
-                phalanx_proximal: Some(&locations[HandJoint::MIDDLE_PROXIMAL]),
-                phalanx_intermediate: Some(&locations[HandJoint::MIDDLE_INTERMEDIATE]),
-                phalanx_distal: Some(&locations[HandJoint::MIDDLE_DISTAL]),
-                phalanx_tip: Some(&locations[HandJoint::MIDDLE_TIP]),
+                metacarpal: joint(HandJoint::MIDDLE_METACARPAL),
+                phalanx_proximal: joint(HandJoint::MIDDLE_PROXIMAL),
+                phalanx_intermediate: joint(HandJoint::MIDDLE_INTERMEDIATE),
+                phalanx_distal: joint(HandJoint::MIDDLE_DISTAL),
+                phalanx_tip: joint(HandJoint::MIDDLE_TIP),
             },
             ring: Finger {
-                metacarpal: Some(&locations[HandJoint::RING_METACARPAL]),
-                phalanx_proximal: Some(&locations[HandJoint::RING_PROXIMAL]),
-                phalanx_intermediate: Some(&locations[HandJoint::RING_INTERMEDIATE]),
-                phalanx_distal: Some(&locations[HandJoint::RING_DISTAL]),
-                phalanx_tip: Some(&locations[HandJoint::RING_TIP]),
+                metacarpal: joint(HandJoint::RING_METACARPAL),
+                phalanx_proximal: joint(HandJoint::RING_PROXIMAL),
+                phalanx_intermediate: joint(HandJoint::RING_INTERMEDIATE),
+                phalanx_distal: joint(HandJoint::RING_DISTAL),
+                phalanx_tip: joint(HandJoint::RING_TIP),
             },
             little: Finger {
-                metacarpal: Some(&locations[HandJoint::LITTLE_METACARPAL]),
-                phalanx_proximal: Some(&locations[HandJoint::LITTLE_PROXIMAL]),
-                phalanx_intermediate: Some(&locations[HandJoint::LITTLE_INTERMEDIATE]),
-                phalanx_distal: Some(&locations[HandJoint::LITTLE_DISTAL]),
-                phalanx_tip: Some(&locations[HandJoint::LITTLE_TIP]),
+                metacarpal: joint(HandJoint::LITTLE_METACARPAL),
+                phalanx_proximal: joint(HandJoint::LITTLE_PROXIMAL),
+                phalanx_intermediate: joint(HandJoint::LITTLE_INTERMEDIATE),
+                phalanx_distal: joint(HandJoint::LITTLE_DISTAL),
+                phalanx_tip: joint(HandJoint::LITTLE_TIP),
             },
         }
     } else {
         return None;
     };
 
-    Some(Box::new(locations.map(|loc, _| {
-        loc.and_then(|location| {
+    Some(Arc::new(locations.map(|loc, _| {
+        loc.and_then(|(location, velocity)| {
             let pose_valid = location.location_flags.intersects(
                 SpaceLocationFlags::POSITION_VALID | SpaceLocationFlags::ORIENTATION_VALID,
             );
             if pose_valid {
+                let (linear_velocity, angular_velocity) = match velocity {
+                    Some(velocity) => super::velocity(
+                        velocity.velocity_flags,
+                        &velocity.linear_velocity,
+                        &velocity.angular_velocity,
+                    ),
+                    None => (None, None),
+                };
                 Some(JointFrame {
                     pose: super::transform(&location.pose),
                     radius: location.radius,
+                    linear_velocity,
+                    angular_velocity,
                 })
             } else {
                 None
@@ -741,3 +1132,12 @@ fn locate_hand<G: Graphics>(
         })
     })))
 }
+
+fn button_mask(button_values: &[f32]) -> u32 {
+    button_values
+        .iter()
+        .take(32)
+        .enumerate()
+        .filter(|(_, value)| **value > 0.0)
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
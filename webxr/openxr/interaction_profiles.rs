@@ -11,6 +11,8 @@ use openxr::{
     ExtensionSet,
 };
 
+use super::custom_profiles::{loaded_profiles, CustomProfile};
+
 #[macro_export]
 macro_rules! ext_string {
     ($ext_name:expr) => {
@@ -42,13 +44,15 @@ pub enum InteractionProfileType {
     ValveIndexController,
     ExtHandInteraction,
     FbHandTrackingAim,
+    /// Loaded with `load_interaction_profiles`
+    Custom,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct InteractionProfile<'a> {
     pub profile_type: InteractionProfileType,
     /// The interaction profile path
-    pub path: &'static str,
+    pub path: &'a str,
     /// The OpenXR extension, if any, required to use this profile
     pub required_extension: Option<&'a [u8]>,
     /// Trigger, Grip, Touchpad, Thumbstick
@@ -59,6 +63,8 @@ pub struct InteractionProfile<'a> {
     pub left_buttons: &'a [&'a str],
     /// Any additional buttons on the right controller
     pub right_buttons: &'a [&'a str],
+    /// Whether the controllers have a haptic output that can be bound
+    pub has_haptics: bool,
     /// The corresponding WebXR Input Profile names
     pub profiles: &'a [&'a str],
 }
@@ -71,6 +77,7 @@ pub static KHR_SIMPLE_CONTROLLER_PROFILE: InteractionProfile = InteractionProfil
     standard_axes: &["", "", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: true,
     profiles: &["generic-trigger"],
 };
 
@@ -82,6 +89,7 @@ pub static BYTEDANCE_PICO_NEO3_CONTROLLER_PROFILE: InteractionProfile = Interact
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["pico-neo3", "generic-trigger-squeeze-thumbstick"],
 };
 
@@ -93,6 +101,7 @@ pub static BYTEDANCE_PICO_4_CONTROLLER_PROFILE: InteractionProfile = Interaction
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["pico-4", "generic-trigger-squeeze-thumbstick"],
 };
 
@@ -110,6 +119,7 @@ pub static BYTEDANCE_PICO_G3_CONTROLLER_PROFILE: InteractionProfile = Interactio
     right_buttons: &[],
     // Note: There is no corresponding WebXR Input profile for the Pico G3,
     // but the controller seems identical to the G2, so use that instead.
+    has_haptics: false,
     profiles: &["pico-g2", "generic-trigger-touchpad"],
 };
 
@@ -121,6 +131,7 @@ pub static GOOGLE_DAYDREAM_CONTROLLER_PROFILE: InteractionProfile = InteractionP
     standard_axes: &["trackpad/x", "trackpad/y", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: false,
     profiles: &["google-daydream", "generic-touchpad"],
 };
 
@@ -132,6 +143,7 @@ pub static HP_MIXED_REALITY_MOTION_CONTROLLER_PROFILE: InteractionProfile = Inte
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "hp-mixed-reality",
         "oculus-touch",
@@ -147,6 +159,7 @@ pub static HTC_VIVE_CONTROLLER_PROFILE: InteractionProfile = InteractionProfile
     standard_axes: &["trackpad/x", "trackpad/y", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: true,
     profiles: &["htc-vive", "generic-trigger-squeeze-touchpad"],
 };
 
@@ -158,6 +171,7 @@ pub static HTC_VIVE_COSMOS_CONTROLLER_PROFILE: InteractionProfile = InteractionP
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["htc-vive-cosmos", "generic-trigger-squeeze-thumbstick"],
 };
 
@@ -169,6 +183,7 @@ pub static HTC_VIVE_FOCUS3_CONTROLLER_PROFILE: InteractionProfile = InteractionP
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["htc-vive-focus-3", "generic-trigger-squeeze-thumbstick"],
 };
 
@@ -182,6 +197,7 @@ pub static MAGIC_LEAP_2_CONTROLLER_PROFILE: InteractionProfile = InteractionProf
     right_buttons: &[],
     // Note: There is no corresponding WebXR Input profile for the Magic Leap 2,
     // but the controller seems mostly identical to the 1, so use that instead.
+    has_haptics: true,
     profiles: &["magicleap-one", "generic-trigger-squeeze-touchpad"],
 };
 
@@ -199,6 +215,7 @@ pub static MICROSOFT_MIXED_REALITY_MOTION_CONTROLLER_PROFILE: InteractionProfile
         standard_axes: &["trackpad/x", "trackpad/y", "thumbstick/x", "thumbstick/y"],
         left_buttons: &[],
         right_buttons: &[],
+        has_haptics: true,
         profiles: &[
             "microsoft-mixed-reality",
             "generic-trigger-squeeze-touchpad-thumbstick",
@@ -213,6 +230,7 @@ pub static OCULUS_GO_CONTROLLER_PROFILE: InteractionProfile = InteractionProfile
     standard_axes: &["trackpad/x", "trackpad/y", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: false,
     profiles: &["oculus-go", "generic-trigger-touchpad"],
 };
 
@@ -224,6 +242,7 @@ pub static OCULUS_TOUCH_CONTROLLER_PROFILE: InteractionProfile = InteractionProf
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "oculus-touch-v3",
         "oculus-touch-v2",
@@ -240,6 +259,7 @@ pub static FACEBOOK_TOUCH_CONTROLLER_PRO_PROFILE: InteractionProfile = Interacti
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "meta-quest-touch-pro",
         "oculus-touch-v2",
@@ -256,6 +276,7 @@ pub static META_TOUCH_CONTROLLER_PLUS_PROFILE: InteractionProfile = InteractionP
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "meta-quest-touch-plus",
         "oculus-touch-v3",
@@ -272,6 +293,7 @@ pub static META_TOUCH_CONTROLLER_RIFT_CV1_PROFILE: InteractionProfile = Interact
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["oculus-touch", "generic-trigger-squeeze-thumbstick"],
 };
 
@@ -283,6 +305,7 @@ pub static META_TOUCH_CONTROLLER_QUEST_1_RIFT_S_PROFILE: InteractionProfile = In
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "oculus-touch-v2",
         "oculus-touch",
@@ -298,6 +321,7 @@ pub static META_TOUCH_CONTROLLER_QUEST_2_PROFILE: InteractionProfile = Interacti
     standard_axes: &["", "", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["x/click", "y/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &[
         "oculus-touch-v3",
         "oculus-touch-v2",
@@ -319,6 +343,7 @@ pub static SAMSUNG_ODYSSEY_CONTROLLER_PROFILE: InteractionProfile = InteractionP
     standard_axes: &["trackpad/x", "trackpad/y", "thumbstick/x", "thumbstick/y"],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: true,
     profiles: &[
         "samsung-odyssey",
         "microsoft-mixed-reality",
@@ -334,6 +359,7 @@ pub static VALVE_INDEX_CONTROLLER_PROFILE: InteractionProfile = InteractionProfi
     standard_axes: &["trackpad/x", "trackpad/y", "thumbstick/x", "thumbstick/y"],
     left_buttons: &["a/click", "b/click"],
     right_buttons: &["a/click", "b/click"],
+    has_haptics: true,
     profiles: &["valve-index", "generic-trigger-squeeze-touchpad-thumbstick"],
 };
 
@@ -345,6 +371,7 @@ pub static EXT_HAND_INTERACTION_PROFILE: InteractionProfile = InteractionProfile
     standard_axes: &["", "", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: false,
     profiles: &["generic-hand-select", "generic-hand"],
 };
 
@@ -356,6 +383,7 @@ pub static FB_HAND_TRACKING_AIM_PROFILE: InteractionProfile = InteractionProfile
     standard_axes: &["", "", "", ""],
     left_buttons: &[],
     right_buttons: &[],
+    has_haptics: false,
     profiles: &["generic-hand-select", "generic-hand"],
 };
 
@@ -384,17 +412,36 @@ pub static INTERACTION_PROFILES: [InteractionProfile; 22] = [
     FB_HAND_TRACKING_AIM_PROFILE,
 ];
 
-pub fn get_profiles_from_path(path: String) -> &'static [&'static str] {
-    INTERACTION_PROFILES
-        .iter()
-        .find(|profile| profile.path == path)
-        .map_or(&[], |profile| profile.profiles)
+/// Call `f` with the built-in profiles, followed by the ones loaded with
+/// `load_interaction_profiles`, which only live as long as the call
+pub fn with_interaction_profiles<R>(f: impl FnOnce(&[InteractionProfile]) -> R) -> R {
+    let loaded = loaded_profiles();
+    let lists: Vec<_> = loaded.iter().map(CustomProfile::lists).collect();
+    let mut profiles = INTERACTION_PROFILES.to_vec();
+    profiles.extend(
+        loaded
+            .iter()
+            .zip(&lists)
+            .map(|(profile, lists)| profile.interaction_profile(lists)),
+    );
+    f(&profiles)
+}
+
+pub fn get_profiles_from_path(path: String) -> Vec<String> {
+    with_interaction_profiles(|profiles| {
+        profiles
+            .iter()
+            .find(|profile| profile.path == path)
+            .map_or(vec![], |profile| {
+                profile.profiles.iter().map(|s| s.to_string()).collect()
+            })
+    })
 }
 
 pub fn get_supported_interaction_profiles(
     supported_extensions: &ExtensionSet,
     enabled_extensions: &mut ExtensionSet,
-) -> Vec<&'static str> {
+) -> Vec<String> {
     let mut extensions = Vec::new();
     if supported_extensions.bd_controller_interaction {
         extensions.push(ext_string!(BD_CONTROLLER_INTERACTION_EXTENSION_NAME));
@@ -440,5 +487,20 @@ pub fn get_supported_interaction_profiles(
         extensions.push(ext_string!(FB_HAND_TRACKING_AIM_EXTENSION_NAME));
         enabled_extensions.fb_hand_tracking_aim = true;
     }
+    // The extensions of loaded profiles are ones the openxr crate doesn't know about,
+    // which it lists by name
+    let mut extensions: Vec<String> = extensions.into_iter().map(String::from).collect();
+    for profile in loaded_profiles().iter() {
+        if let Some(extension) = profile.extension() {
+            let name = extension.trim_end_matches('\0');
+            if extensions.iter().any(|other| other == extension) {
+                continue;
+            }
+            if supported_extensions.other.iter().any(|other| other == name) {
+                extensions.push(extension.to_owned());
+                enabled_extensions.other.push(name.to_owned());
+            }
+        }
+    }
     extensions
 }
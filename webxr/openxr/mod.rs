@@ -1,4 +1,6 @@
-use crate::gl_utils::GlClearer;
+use crate::gl_utils::{self, ColorAttachment, GlClearer};
+use crate::surface_accounting::SurfaceAccounting;
+use crate::BackendStatus;
 use crate::SurfmanGL;
 
 use euclid::Box2D;
@@ -9,64 +11,97 @@ use euclid::Rotation3D;
 use euclid::Size2D;
 use euclid::Transform3D;
 use euclid::Vector3D;
-use glow::PixelUnpackData;
 use glow::{self as gl, HasContext};
 use interaction_profiles::{get_profiles_from_path, get_supported_interaction_profiles};
 use log::{error, warn};
-use openxr::sys::CompositionLayerPassthroughFB;
+use openxr::sys::{
+    CompositionLayerPassthroughFB, CompositionLayerSettingsFB, CompositionLayerSettingsFlagsFB,
+};
 use openxr::{
-    self, ActionSet, ActiveActionSet, ApplicationInfo, CompositionLayerBase, CompositionLayerFlags,
-    CompositionLayerProjection, Entry, EnvironmentBlendMode, ExtensionSet, Extent2Di, FormFactor,
-    Fovf, FrameState, FrameStream, FrameWaiter, Graphics, Instance, Passthrough,
-    PassthroughFlagsFB, PassthroughLayer, PassthroughLayerPurposeFB, Posef, Quaternionf,
-    ReferenceSpaceType, SecondaryEndInfo, Session, Space, Swapchain, SwapchainCreateFlags,
+    self, ActionSet, ActiveActionSet, ApplicationInfo, CompositionLayerBase,
+    CompositionLayerCylinderKHR, CompositionLayerEquirect2KHR, CompositionLayerFlags,
+    CompositionLayerProjection, CompositionLayerQuad, Entry, EnvironmentBlendMode, ExtensionSet,
+    Extent2Df, Extent2Di, EyeVisibility, FormFactor, Fovf, FrameState, FrameStream, FrameWaiter,
+    Graphics, Instance, Passthrough, PassthroughFlagsFB, PassthroughLayer,
+    PassthroughLayerPurposeFB, Posef, Quaternionf, ReferenceSpaceType, SecondaryEndInfo, Session,
+    Space, SpaceLocationFlags, SpaceVelocityFlags, Swapchain, SwapchainCreateFlags,
     SwapchainCreateInfo, SwapchainUsageFlags, SystemId, Vector3f, Version, ViewConfigurationType,
+    ViewStateFlags,
 };
 use std::collections::HashMap;
 use std::mem;
 use std::num::NonZeroU32;
 use std::ops::Deref;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use surfman::Context as SurfmanContext;
 use surfman::Device as SurfmanDevice;
 use surfman::Error as SurfmanError;
 use surfman::SurfaceTexture;
 use webxr_api;
-use webxr_api::util::{self, ClipPlanes};
+use webxr_api::util::{self, ClipPlanes, FeatureUsage, ViewportScales};
+use webxr_api::ApiSpace;
+use webxr_api::Backend as ApiBackend;
 use webxr_api::BaseSpace;
-use webxr_api::Capture;
+use webxr_api::CameraIntrinsics;
+use webxr_api::ColorFormat;
 use webxr_api::ContextId;
 use webxr_api::DeviceAPI;
+use webxr_api::DeviceChangeNotifier;
+use webxr_api::DeviceDescriptor;
+use webxr_api::DeviceResources;
 use webxr_api::DiscoveryAPI;
 use webxr_api::Display;
 use webxr_api::Error;
 use webxr_api::Event;
 use webxr_api::EventBuffer;
+use webxr_api::Feature;
 use webxr_api::Floor;
 use webxr_api::Frame;
+use webxr_api::FrameSubscriptions;
 use webxr_api::GLContexts;
+use webxr_api::InputFrame;
 use webxr_api::InputId;
 use webxr_api::InputSource;
+use webxr_api::LatencyMarker;
+use webxr_api::LayerFilter;
+use webxr_api::LayerGeometry;
 use webxr_api::LayerGrandManager;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
+use webxr_api::LayerLayout;
+use webxr_api::LayerLimits;
 use webxr_api::LayerManager;
 use webxr_api::LayerManagerAPI;
+use webxr_api::LayerQuality;
+use webxr_api::LayerType;
 use webxr_api::LeftEye;
 use webxr_api::Native;
+use webxr_api::PendingSession;
 use webxr_api::Quitter;
 use webxr_api::RightEye;
+use webxr_api::Secondary;
+use webxr_api::SelectArbiter;
+use webxr_api::SelectArbitrationOptions;
+use webxr_api::SelectEvent;
+use webxr_api::SelectGesture;
 use webxr_api::SelectKind;
 use webxr_api::Sender;
-use webxr_api::Session as WebXrSession;
 use webxr_api::SessionBuilder;
 use webxr_api::SessionInit;
 use webxr_api::SessionMode;
+use webxr_api::SessionProgress;
+use webxr_api::SessionProgressReporter;
+use webxr_api::Space as WebXrSpace;
 use webxr_api::SubImage;
 use webxr_api::SubImages;
+use webxr_api::TrustedUiFuture;
+use webxr_api::TrustedUiPrompt;
+use webxr_api::TrustedUiProvider;
+use webxr_api::TrustedUiResult;
 use webxr_api::View;
+use webxr_api::Viewer;
 use webxr_api::ViewerPose;
 use webxr_api::Viewport;
 use webxr_api::Viewports;
@@ -75,9 +110,15 @@ use webxr_api::Visibility;
 
 mod input;
 use input::OpenXRInput;
+mod custom_profiles;
 mod graphics;
 mod interaction_profiles;
+mod pose_audit;
+mod recovery;
+pub use custom_profiles::load_interaction_profiles;
 use graphics::{GraphicsProvider, GraphicsProviderMethods};
+pub use recovery::RuntimeRecoveryCallback;
+use recovery::{is_runtime_lost, RunningSession, RuntimeMonitor};
 
 #[cfg(target_os = "windows")]
 mod graphics_d3d11;
@@ -86,6 +127,13 @@ use graphics_d3d11::Backend;
 
 const HEIGHT: f32 = 1.4;
 
+// How long to wait for the runtime to acknowledge a request to exit the session.
+const QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How many frames in a row can fail to end before the runtime is taken to be lost,
+// since some runtimes keep failing frames after a crash without reporting the instance lost
+const MAX_FRAME_ERRORS: u32 = 30;
+
 const IDENTITY_POSE: Posef = Posef {
     orientation: Quaternionf {
         x: 0.,
@@ -117,28 +165,14 @@ const VIEW_INIT: openxr::View = openxr::View {
 // Views > Mixed Reality Capture > Photo and Video Settings).
 const SECONDARY_VIEW_DOWNSCALE: i32 = 2;
 
-/// Provides a way to spawn and interact with context menus
-pub trait ContextMenuProvider: Send {
-    /// Open a context menu, return a way to poll for the result
-    fn open_context_menu(&self) -> Box<dyn ContextMenuFuture>;
-    /// Clone self as a trait object
-    fn clone_object(&self) -> Box<dyn ContextMenuProvider>;
-}
-
-/// A way to poll for the result of the context menu request
-pub trait ContextMenuFuture {
-    fn poll(&self) -> ContextMenuResult;
-}
+// How much the recommended viewport scale changes by per frame
+const VIEWPORT_SCALE_STEP: f32 = 0.05;
 
-/// The result of polling on a context menu request
-pub enum ContextMenuResult {
-    /// Session should exit
-    ExitSession,
-    /// Dialog was dismissed
-    Dismissed,
-    /// User has not acted on dialog
-    Pending,
-}
+/// Called with the viewer's pose every frame, in native coordinates (so before any snap
+/// turns), as soon as the runtime has located it. This is for the embedder's audio engine,
+/// so that spatial audio follows the head without going through content.
+/// It is called on the session's thread, so it shouldn't block.
+pub type AudioListenerCallback = Box<dyn Send + FnMut(&RigidTransform3D<f32, Viewer, Native>)>;
 
 #[derive(Default)]
 pub struct AppInfo {
@@ -188,42 +222,267 @@ impl<Eye> ViewInfo<Eye> {
 
     fn recompute_projection(&mut self, clip_planes: ClipPlanes) {
         self.cached_projection = fov_to_projection_matrix(&self.view.fov, clip_planes);
+        pose_audit::projection(
+            "view projection",
+            &self.view.fov,
+            clip_planes,
+            &self.cached_projection,
+        );
     }
 
-    fn view(&self) -> View<Eye> {
+    fn view(&self, recommended_viewport_scale: f32) -> View<Eye> {
         View {
             transform: transform(&self.view.pose),
             projection: self.cached_projection,
+            recommended_viewport_scale: Some(recommended_viewport_scale),
+            camera: None,
         }
     }
+
+    /// The secondary view is rendered for the camera, so the runtime's field of view
+    /// and image size for it are the camera's. The runtime composes the camera's footage
+    /// with the view at the frame's predicted display time, so there is no offset.
+    fn camera(&self) -> CameraIntrinsics {
+        let fov = &self.view.fov;
+        let size = Size2D::new(self.extent.width, self.extent.height);
+        CameraIntrinsics::from_fov(
+            fov.angle_left,
+            fov.angle_right,
+            fov.angle_up,
+            fov.angle_down,
+            size,
+            0.0,
+        )
+    }
 }
 
 pub struct OpenXrDiscovery {
-    context_menu_provider: Option<Box<dyn ContextMenuProvider>>,
-    app_info: AppInfo,
+    trusted_ui: Option<Box<dyn TrustedUiProvider>>,
+    runtime: RuntimeMonitor,
+    /// Shared with every session, so that it can be set while they are running
+    audio_listener: Arc<Mutex<Option<AudioListenerCallback>>>,
+    /// Starting up the runtime can take seconds, so the instance is kept around
+    /// and shared by every session, rather than created again for each one.
+    /// Sessions create it on their own thread, so that the registry isn't blocked.
+    instance: Arc<InstanceCache>,
+}
+
+/// The instance shared by the sessions of a discovery, see `cached_instance`
+#[derive(Default)]
+struct InstanceCache {
+    state: Mutex<InstanceCacheState>,
+    /// Notified when a thread has finished creating an instance
+    created: Condvar,
+}
+
+#[derive(Default)]
+struct InstanceCacheState {
+    cached: Option<CachedInstance>,
+    /// Whether a thread is creating an instance. The cache isn't locked while it does,
+    /// since that can take seconds, but other threads don't make one of their own.
+    creating: bool,
+}
+
+/// Marks the cache as having an instance created, until dropped
+struct CreatingInstance<'a>(&'a InstanceCache);
+
+impl Drop for CreatingInstance<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().creating = false;
+        self.0.created.notify_all();
+    }
+}
+
+/// An instance, along with the extensions it was asked to enable
+struct CachedInstance {
+    instance: CreatedInstance,
+    needs_hands: bool,
+    needs_secondary: bool,
+    needs_passthrough: bool,
 }
 
 impl OpenXrDiscovery {
-    pub fn new(
-        context_menu_provider: Option<Box<dyn ContextMenuProvider>>,
-        app_info: AppInfo,
-    ) -> Self {
+    pub fn new(trusted_ui: Option<Box<dyn TrustedUiProvider>>, app_info: AppInfo) -> Self {
         Self {
-            context_menu_provider,
-            app_info,
+            trusted_ui,
+            runtime: RuntimeMonitor::new(app_info),
+            audio_listener: Arc::new(Mutex::new(None)),
+            instance: Arc::new(InstanceCache::default()),
+        }
+    }
+
+    /// Set a callback for the viewer's pose every frame, for spatial audio
+    pub fn set_audio_listener(&mut self, callback: Option<AudioListenerCallback>) {
+        *self.audio_listener.lock().unwrap() = callback;
+    }
+
+    /// Set a callback for when the runtime is available again after it was lost,
+    /// for example because it crashed. Sessions end when the runtime is lost.
+    pub fn set_recovery_callback(&mut self, callback: Option<RuntimeRecoveryCallback>) {
+        self.runtime.set_callback(callback);
+    }
+
+    /// The cached instance, for answering the registry. This doesn't wait for an
+    /// instance that a session is creating, failing instead, so that the registry isn't
+    /// blocked for as long as that takes.
+    fn instance(
+        &self,
+        needs_hands: bool,
+        needs_secondary: bool,
+        needs_passthrough: bool,
+    ) -> Result<CreatedInstance, String> {
+        cached_instance(
+            &self.instance,
+            &self.runtime,
+            needs_hands,
+            needs_secondary,
+            needs_passthrough,
+            false,
+        )
+    }
+}
+
+/// Whether the runtime has a headset, or None if a session is creating an instance
+fn headset_available(cache: &InstanceCache, runtime: &RuntimeMonitor) -> Option<bool> {
+    if cache.state.lock().unwrap().creating {
+        return None;
+    }
+    Some(cached_instance(cache, runtime, false, false, false, false).is_ok())
+}
+
+/// Whether the runtime can have sessions of this mode, going by its blend modes
+fn supports_mode(instance: &CreatedInstance, mode: SessionMode) -> bool {
+    let blend_modes = match instance
+        .instance
+        .enumerate_environment_blend_modes(instance.system, ViewConfigurationType::PRIMARY_STEREO)
+    {
+        Ok(blend_modes) => blend_modes,
+        Err(_) => return false,
+    };
+    match mode {
+        SessionMode::ImmersiveAR => {
+            blend_modes.contains(&EnvironmentBlendMode::ADDITIVE)
+                || blend_modes.contains(&EnvironmentBlendMode::ALPHA_BLEND)
+                || instance.supports_passthrough
         }
+        // Immersive VR sessions are not precluded by non-opaque blending
+        SessionMode::ImmersiveVR => !blend_modes.is_empty(),
+        SessionMode::Inline => false,
     }
 }
 
+/// Get an instance with (at least) the requested extensions enabled, reusing the
+/// cached one if it has them all and the runtime is still around. Otherwise a new
+/// instance is created, with the extensions of the old one as well as the new ones.
+/// If another thread is creating an instance, this waits for it if `wait` is set,
+/// and fails otherwise.
+fn cached_instance(
+    cache: &InstanceCache,
+    runtime: &RuntimeMonitor,
+    mut needs_hands: bool,
+    mut needs_secondary: bool,
+    mut needs_passthrough: bool,
+    wait: bool,
+) -> Result<CreatedInstance, String> {
+    let mut state = cache.state.lock().unwrap();
+    while state.creating {
+        if !wait {
+            return Err(String::from("an instance is being created"));
+        }
+        state = cache.created.wait(state).unwrap();
+    }
+    if runtime.take_lost() {
+        state.cached = None;
+    }
+    if let Some(mut old) = state.cached.take() {
+        let covered = (old.needs_hands || !needs_hands)
+            && (old.needs_secondary || !needs_secondary)
+            && (old.needs_passthrough || !needs_passthrough);
+        // If the runtime has restarted or the headset was unplugged, the instance
+        // is lost or the system has changed, so this also checks it is still usable.
+        let system = old
+            .instance
+            .instance
+            .system(FormFactor::HEAD_MOUNTED_DISPLAY);
+        if let Ok(system) = system {
+            // Some runtimes only allow one instance at a time, so an instance that
+            // sessions are still using can't be replaced yet, and the new session makes
+            // do with the extensions it has.
+            if covered || Arc::strong_count(&old.instance.sessions) > 1 {
+                old.instance.system = system;
+                let instance = &old.instance;
+                let instance = instance.only(needs_hands, needs_secondary, needs_passthrough);
+                state.cached = Some(old);
+                return Ok(instance);
+            }
+            needs_hands |= old.needs_hands;
+            needs_secondary |= old.needs_secondary;
+            needs_passthrough |= old.needs_passthrough;
+        }
+        // The old instance is dropped here, before the new one is made. If it was lost,
+        // the sessions still holding on to it are ending, and it goes away with them.
+    }
+    state.creating = true;
+    drop(state);
+
+    let creating = CreatingInstance(cache);
+    let instance = create_instance(
+        needs_hands,
+        needs_secondary,
+        needs_passthrough,
+        runtime.app_info(),
+    )?;
+    cache.state.lock().unwrap().cached = Some(CachedInstance {
+        instance: instance.clone(),
+        needs_hands,
+        needs_secondary,
+        needs_passthrough,
+    });
+    drop(creating);
+    Ok(instance)
+}
+
+#[derive(Clone)]
 pub struct CreatedInstance {
     instance: Instance,
     supports_hands: bool,
+    /// Whether hands and controllers can be tracked at the same time, and so be
+    /// reported as separate input sources
+    supports_multimodal_input: bool,
     supports_secondary: bool,
     system: SystemId,
     supports_mutable_fov: bool,
-    supported_interaction_profiles: Vec<&'static str>,
+    supported_interaction_profiles: Vec<String>,
     supports_passthrough: bool,
     supports_updating_framerate: bool,
+    supports_foveation: bool,
+    supports_layer_settings: bool,
+    supported_layer_types: Vec<LayerType>,
+    /// Held by every session using the instance, so that the cache can tell
+    /// whether it can be replaced
+    sessions: Arc<()>,
+}
+
+impl CreatedInstance {
+    /// This instance, but only supporting the requested extensions,
+    /// so that a session doesn't get features it didn't ask for.
+    fn only(&self, needs_hands: bool, needs_secondary: bool, needs_passthrough: bool) -> Self {
+        CreatedInstance {
+            supports_hands: self.supports_hands && needs_hands,
+            supports_multimodal_input: self.supports_multimodal_input && needs_hands,
+            supports_secondary: self.supports_secondary && needs_secondary,
+            supports_passthrough: self.supports_passthrough && needs_passthrough,
+            ..self.clone()
+        }
+    }
+}
+
+/// Whether the OpenXR loader can be found, for `webxr::backends`
+pub(crate) fn loader_status() -> BackendStatus {
+    match unsafe { Entry::load() } {
+        Ok(_) => BackendStatus::Available,
+        Err(e) => BackendStatus::Unavailable(format!("Entry::load {:?}", e)),
+    }
 }
 
 pub fn create_instance(
@@ -238,11 +497,32 @@ pub fn create_instance(
         .map_err(|e| format!("Entry::enumerate_extensions {:?}", e))?;
     warn!("Available extensions:\n{:?}", supported);
     let mut supports_hands = needs_hands && supported.ext_hand_tracking;
+    // Separate hand sources need the aim state for their target rays
+    let supports_multimodal_input = supports_hands
+        && supported.meta_simultaneous_hands_and_controllers
+        && supported.fb_hand_tracking_aim;
     let supports_passthrough = needs_passthrough && supported.fb_passthrough;
     let supports_secondary = needs_secondary
         && supported.msft_secondary_view_configuration
         && supported.msft_first_person_observer;
     let supports_updating_framerate = supported.fb_display_refresh_rate;
+    let supports_foveation = supported.fb_foveation
+        && supported.fb_foveation_configuration
+        && supported.fb_swapchain_update_state;
+    let supports_layer_settings = supported.fb_composition_layer_settings;
+    // Quad layers are part of the core spec, the other shapes are extensions.
+    // Cube layers can't be supported, since surfman can't share cube map images.
+    let mut supported_layer_types = vec![
+        LayerType::WebGLLayer,
+        LayerType::ProjectionLayer,
+        LayerType::QuadLayer,
+    ];
+    if supported.khr_composition_layer_cylinder {
+        supported_layer_types.push(LayerType::CylinderLayer);
+    }
+    if supported.khr_composition_layer_equirect2 {
+        supported_layer_types.push(LayerType::EquirectLayer);
+    }
 
     let app_info = ApplicationInfo {
         application_name: &app_info.application_name,
@@ -258,6 +538,10 @@ pub fn create_instance(
         exts.ext_hand_tracking = true;
     }
 
+    if supports_multimodal_input {
+        exts.meta_simultaneous_hands_and_controllers = true;
+    }
+
     if supports_secondary {
         exts.msft_secondary_view_configuration = true;
         exts.msft_first_person_observer = true;
@@ -271,6 +555,16 @@ pub fn create_instance(
         exts.fb_display_refresh_rate = true;
     }
 
+    if supports_foveation {
+        exts.fb_foveation = true;
+        exts.fb_foveation_configuration = true;
+        exts.fb_swapchain_update_state = true;
+    }
+
+    exts.khr_composition_layer_cylinder = supported.khr_composition_layer_cylinder;
+    exts.khr_composition_layer_equirect2 = supported.khr_composition_layer_equirect2;
+    exts.fb_composition_layer_settings = supports_layer_settings;
+
     let supported_interaction_profiles = get_supported_interaction_profiles(&supported, &mut exts);
 
     let instance = entry
@@ -300,12 +594,17 @@ pub fn create_instance(
     Ok(CreatedInstance {
         instance,
         supports_hands,
+        supports_multimodal_input,
         supports_secondary,
         system,
         supports_mutable_fov,
         supported_interaction_profiles,
         supports_passthrough,
         supports_updating_framerate,
+        supports_foveation,
+        supports_layer_settings,
+        supported_layer_types,
+        sessions: Arc::new(()),
     })
 }
 
@@ -315,65 +614,119 @@ impl DiscoveryAPI<SurfmanGL> for OpenXrDiscovery {
         mode: SessionMode,
         init: &SessionInit,
         xr: SessionBuilder<SurfmanGL>,
-    ) -> Result<WebXrSession, Error> {
-        if self.supports_session(mode) {
-            let needs_hands = init.feature_requested("hand-tracking");
-            let needs_secondary =
-                init.feature_requested("secondary-views") && init.first_person_observer_view;
-            let needs_passthrough = mode == SessionMode::ImmersiveAR;
-            let instance = create_instance(
+    ) -> Result<PendingSession, Error> {
+        if mode == SessionMode::Inline {
+            return Err(Error::NoMatchingDevice);
+        }
+        let needs_hands = init.feature_requested(&Feature::HandTracking);
+        let needs_secondary =
+            init.feature_requested(&Feature::SecondaryViews) && init.first_person_observer_view;
+        let needs_passthrough = mode == SessionMode::ImmersiveAR;
+        let init = init.clone();
+        let progress = xr.progress_reporter();
+        let cancel = xr.cancellation();
+        let events = xr.event_buffer();
+        let cache = self.instance.clone();
+        // Every session needs the provider, for the exit menu and permission prompts
+        let trusted_ui = self.trusted_ui.as_ref().map(|ui| ui.clone_object());
+        let runtime = self.runtime.clone();
+        let audio_listener = self.audio_listener.clone();
+        // Starting up the runtime can take seconds, so the instance is created on the
+        // session's thread rather than the registry's. If the runtime can't have this kind
+        // of session after all, the registry moves on to the next device.
+        xr.spawn(move |grand_manager| {
+            let instance = cached_instance(
+                &cache,
+                &runtime,
                 needs_hands,
                 needs_secondary,
                 needs_passthrough,
-                &self.app_info,
+                true,
             )
-            .map_err(|e| Error::BackendSpecific(e))?;
+            .map_err(|e| Error::RuntimeUnavailable(ApiBackend::OpenXr, e))?;
+            if !supports_mode(&instance, mode) {
+                return Err(Error::NoMatchingDevice);
+            }
+            progress.report(SessionProgress::InstanceCreated);
+            // The instance is kept for the next request, but there's no need to start a session
+            if cancel.is_cancelled() {
+                return Err(Error::RequestCancelled);
+            }
 
-            let mut supported_features = vec!["local-floor".into(), "bounded-floor".into()];
-            if instance.supports_hands {
-                supported_features.push("hand-tracking".into());
+            let mut supported_features = vec![Feature::LocalFloor, Feature::BoundedFloor];
+            // Content only gets hand joints once the user allows it with a trusted prompt
+            if instance.supports_hands && trusted_ui.is_some() {
+                supported_features.push(Feature::HandTracking);
             }
             if instance.supports_secondary && init.first_person_observer_view {
-                supported_features.push("secondary-views".into());
+                supported_features.push(Feature::SecondaryViews);
             }
             let granted_features = init.validate(mode, &supported_features)?;
-            let context_menu_provider = self.context_menu_provider.take();
-            xr.spawn(move |grand_manager| {
-                OpenXrDevice::new(
-                    instance,
-                    granted_features,
-                    context_menu_provider,
-                    grand_manager,
-                )
-            })
-        } else {
-            Err(Error::NoMatchingDevice)
-        }
+            OpenXrDevice::new(
+                instance,
+                granted_features,
+                events,
+                trusted_ui,
+                runtime,
+                audio_listener,
+                grand_manager,
+                progress,
+            )
+        })
     }
 
     fn supports_session(&self, mode: SessionMode) -> bool {
-        let mut supports = false;
         // Determining AR support requires enumerating environment blend modes,
         // but this requires an already created XrInstance and SystemId.
-        // We'll make a "default" instance here to check the blend modes,
-        // then a proper one in request_session with hands/secondary support if needed.
+        // We'll get a "default" instance here to check the blend modes,
+        // which request_session can reuse, adding hands/secondary support if needed.
+        // While a session is creating an instance, this answers false rather than wait.
         let needs_passthrough = mode == SessionMode::ImmersiveAR;
-        if let Ok(instance) = create_instance(false, false, needs_passthrough, &self.app_info) {
-            if let Ok(blend_modes) = instance.instance.enumerate_environment_blend_modes(
-                instance.system,
-                ViewConfigurationType::PRIMARY_STEREO,
-            ) {
-                if mode == SessionMode::ImmersiveAR {
-                    supports = blend_modes.contains(&EnvironmentBlendMode::ADDITIVE)
-                        || blend_modes.contains(&EnvironmentBlendMode::ALPHA_BLEND)
-                        || instance.supports_passthrough;
-                } else if mode == SessionMode::ImmersiveVR {
-                    // Immersive VR sessions are not precluded by non-opaque blending
-                    supports = blend_modes.len() > 0;
-                }
-            }
+        match self.instance(false, false, needs_passthrough) {
+            Ok(instance) => supports_mode(&instance, mode),
+            Err(_) => false,
         }
-        supports
+    }
+
+    fn spawns_sessions(&self) -> bool {
+        true
+    }
+
+    fn set_device_change_notifier(&mut self, notifier: DeviceChangeNotifier) {
+        self.runtime.set_device_change_notifier(notifier);
+        // Only asking the runtime finds out about headsets being plugged in or unplugged
+        let cache = Arc::downgrade(&self.instance);
+        self.runtime
+            .watch_availability(move |runtime| headset_available(&cache.upgrade()?, runtime));
+    }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        let modes = self.supported_modes();
+        if modes.is_empty() {
+            return None;
+        }
+        // Asking for hands and secondary views finds out whether the runtime has them,
+        // and request_session can reuse the instance with them turned off if need be
+        let instance = self.instance(true, true, false).ok()?;
+        let name = instance
+            .instance
+            .system_properties(instance.system)
+            .ok()?
+            .system_name;
+        let vendor = instance.instance.properties().ok()?.runtime_name;
+        let mut features = vec![Feature::LocalFloor, Feature::BoundedFloor];
+        if instance.supports_hands {
+            features.push(Feature::HandTracking);
+        }
+        if instance.supports_secondary {
+            features.push(Feature::SecondaryViews);
+        }
+        Some(DeviceDescriptor {
+            name,
+            vendor,
+            modes,
+            features,
+        })
     }
 }
 
@@ -389,14 +742,76 @@ struct OpenXrDevice {
     supports_secondary: bool,
     supports_mutable_fov: bool,
     supports_updating_framerate: bool,
+    layer_limits: LayerLimits,
+    visibility: Visibility,
+    frame_start: Option<Instant>,
+    /// Whether the runtime wants the current frame to be rendered
+    should_render: bool,
+    frame_subscriptions: FrameSubscriptions,
+    feature_usage: FeatureUsage,
+    /// Set with `Session::set_mono_rendering`
+    mono_rendering: bool,
 
     // input
     action_set: ActionSet,
     right_hand: OpenXRInput,
     left_hand: OpenXRInput,
-    granted_features: Vec<String>,
-    context_menu_provider: Option<Box<dyn ContextMenuProvider>>,
-    context_menu_future: Option<Box<dyn ContextMenuFuture>>,
+    /// Set with `Session::set_select_arbitration`
+    select_arbitration: SelectArbitrationOptions,
+    /// The arbiters of the inputs that have select arbitration
+    select_arbiters: Vec<(InputId, SelectArbiter)>,
+    /// The inputs whose arbitration changed mid-gesture, whose arbiters are replaced
+    /// once the gesture is over
+    stale_select_arbiters: Vec<InputId>,
+    granted_features: Vec<Feature>,
+    trusted_ui: Option<Box<dyn TrustedUiProvider>>,
+    context_menu_future: Option<Box<dyn TrustedUiFuture>>,
+    /// Whether the user let content have hand joints, which is asked the first time there are any
+    hand_permission: Permission,
+    /// Where to report the runtime going away
+    runtime: RuntimeMonitor,
+    /// How many frames in a row have failed to end
+    frame_errors: u32,
+    /// Stops the discovery checking for a headset while this session has it
+    _running: RunningSession,
+    audio_listener: Arc<Mutex<Option<AudioListenerCallback>>>,
+    /// Set with `set_reorientation`, which the audio listener is turned by like content is
+    reorientation: RigidTransform3D<f32, Native, Native>,
+    /// Keeps the instance from being replaced while this session uses it
+    _instance_sessions: Arc<()>,
+}
+
+/// Whether the user lets content use a sensitive feature, asked with a trusted UI prompt
+enum Permission {
+    Unasked,
+    Pending(Box<dyn TrustedUiFuture>),
+    Granted,
+    Refused,
+}
+
+impl Permission {
+    /// Whether the feature's data can be given to content, prompting the user the first time.
+    /// Without a trusted UI to prompt with, the user can't allow it, so it is refused.
+    fn check(&mut self, feature: Feature, trusted_ui: Option<&dyn TrustedUiProvider>) -> bool {
+        if let Permission::Unasked = *self {
+            *self = match trusted_ui {
+                Some(trusted_ui) => Permission::Pending(
+                    trusted_ui.open_prompt(TrustedUiPrompt::Permission(feature)),
+                ),
+                None => Permission::Refused,
+            };
+        }
+        if let Permission::Pending(ref future) = *self {
+            match future.poll() {
+                TrustedUiResult::Granted => *self = Permission::Granted,
+                TrustedUiResult::Dismissed | TrustedUiResult::ExitSession => {
+                    *self = Permission::Refused
+                }
+                TrustedUiResult::Pending => (),
+            }
+        }
+        matches!(*self, Permission::Granted)
+    }
 }
 
 /// Data that is shared between the openxr thread and the
@@ -404,13 +819,18 @@ struct OpenXrDevice {
 struct SharedData {
     left: ViewInfo<LeftEye>,
     right: ViewInfo<RightEye>,
-    secondary: Option<ViewInfo<Capture>>,
+    secondary: Option<ViewInfo<Secondary>>,
     secondary_active: bool,
     primary_blend_mode: EnvironmentBlendMode,
     secondary_blend_mode: Option<EnvironmentBlendMode>,
     frame_state: Option<FrameState>,
     space: Space,
     swapchain_sample_count: u32,
+    viewport_scales: ViewportScales,
+    recommended_viewport_scale: f32,
+    /// Where the inputs are this frame, including while they are hidden from content,
+    /// which layers attached to them are placed relative to
+    inputs: Vec<InputFrame>,
 }
 
 struct OpenXrLayerManager {
@@ -420,17 +840,51 @@ struct OpenXrLayerManager {
     layers: Vec<(ContextId, LayerId)>,
     openxr_layers: HashMap<LayerId, OpenXrLayer>,
     clearer: GlClearer,
+    accounting: SurfaceAccounting,
+    supports_foveation: bool,
+    supports_layer_settings: bool,
+    vignette: f32,
+    latency_marker: Option<LatencyMarker>,
     _passthrough: Option<Passthrough>,
     passthrough_layer: Option<PassthroughLayer>,
+    /// The space that head-locked quad, cylinder and equirect layers are placed in
+    viewer_space: Space,
+    /// Reports that the swapchains are ready, once the first layer has them
+    progress: Option<SessionProgressReporter>,
+}
+
+/// Where a quad, cylinder or equirect layer is placed in the world
+struct LayerPlacement {
+    space: WebXrSpace,
+    view_pixel_size: Size2D<i32, Viewport>,
+    layout: LayerLayout,
+    geometry: LayerGeometry,
 }
 
 struct OpenXrLayer {
     swapchain: Swapchain<Backend>,
     depth_stencil_texture: Option<gl::NativeTexture>,
+    /// The renderbuffer that content renders into if the layer is multisampled,
+    /// which is resolved into the swapchain image before it is released
+    multisampled_renderbuffer: Option<gl::NativeRenderbuffer>,
+    /// On Windows, the texture that content renders placed layers into, which is copied
+    /// upside down into the swapchain image before it is released. D3D images start at the
+    /// top, and unlike the views, placed layers can't be flipped by their field of view.
+    flipped_texture: Option<gl::NativeTexture>,
     size: Size2D<i32, Viewport>,
+    /// The number of samples per pixel of the textures that content renders into.
+    /// The swapchain images are always single-sampled, since surfman only wraps 2D textures.
+    sample_count: u32,
+    color_format: ColorFormat,
     images: Vec<<Backend as Graphics>::SwapchainImage>,
     surface_textures: Vec<Option<SurfaceTexture>>,
+    /// The index of the image acquired for the current frame
+    image: usize,
     waited: bool,
+    /// None for projection layers, which cover the views rather than being placed in the world
+    placement: Option<LayerPlacement>,
+    /// Set with `LayerManagerAPI::set_layer_quality`, None leaves the filtering to the runtime
+    quality: Option<LayerQuality>,
 }
 
 impl OpenXrLayerManager {
@@ -439,12 +893,17 @@ impl OpenXrLayerManager {
         shared_data: Arc<Mutex<Option<SharedData>>>,
         frame_stream: FrameStream<Backend>,
         should_reverse_winding: bool,
+        supports_foveation: bool,
+        supports_layer_settings: bool,
         _passthrough: Option<Passthrough>,
         passthrough_layer: Option<PassthroughLayer>,
+        viewer_space: Space,
+        progress: SessionProgressReporter,
     ) -> OpenXrLayerManager {
         let layers = Vec::new();
         let openxr_layers = HashMap::new();
         let clearer = GlClearer::new(should_reverse_winding);
+        let accounting = SurfaceAccounting::default();
         OpenXrLayerManager {
             session,
             shared_data,
@@ -452,8 +911,15 @@ impl OpenXrLayerManager {
             layers,
             openxr_layers,
             clearer,
+            accounting,
+            supports_foveation,
+            supports_layer_settings,
+            vignette: 0.0,
+            latency_marker: None,
             _passthrough,
             passthrough_layer,
+            viewer_space,
+            progress: Some(progress),
         }
     }
 }
@@ -462,24 +928,61 @@ impl OpenXrLayer {
     fn new(
         swapchain: Swapchain<Backend>,
         depth_stencil_texture: Option<gl::NativeTexture>,
+        multisampled_renderbuffer: Option<gl::NativeRenderbuffer>,
+        flipped_texture: Option<gl::NativeTexture>,
         size: Size2D<i32, Viewport>,
+        sample_count: u32,
+        color_format: ColorFormat,
+        placement: Option<LayerPlacement>,
     ) -> Result<OpenXrLayer, Error> {
-        let images = swapchain
-            .enumerate_images()
-            .map_err(|e| Error::BackendSpecific(format!("Session::enumerate_images {:?}", e)))?;
+        let images = swapchain.enumerate_images().map_err(|e| {
+            Error::BackendSpecific(
+                ApiBackend::OpenXr,
+                format!("Session::enumerate_images {:?}", e),
+            )
+        })?;
         let waited = false;
         let mut surface_textures = Vec::new();
         surface_textures.resize_with(images.len(), || None);
         Ok(OpenXrLayer {
             swapchain,
             depth_stencil_texture,
+            multisampled_renderbuffer,
+            flipped_texture,
             size,
+            sample_count,
+            color_format,
             images,
             surface_textures,
+            image: 0,
             waited,
+            placement,
+            quality: None,
         })
     }
 
+    /// The texture wrapping the swapchain image acquired for this frame, and its texture target
+    fn image_texture(&self, device: &SurfmanDevice) -> Option<(gl::NativeTexture, u32)> {
+        let surface_texture = self.surface_textures.get(self.image)?.as_ref()?;
+        let texture = NonZeroU32::new(device.surface_texture_object(surface_texture))?;
+        Some((
+            gl::NativeTexture(texture),
+            device.surface_gl_texture_target(),
+        ))
+    }
+
+    /// The color buffer that content renders into this frame
+    fn color_attachment(&self, device: &SurfmanDevice) -> Option<ColorAttachment> {
+        if let Some(renderbuffer) = self.multisampled_renderbuffer {
+            return Some(ColorAttachment::Renderbuffer(renderbuffer));
+        }
+        if let Some(texture) = self.flipped_texture {
+            return Some(ColorAttachment::Texture(Some(texture), gl::TEXTURE_2D));
+        }
+        let (texture, target) = self.image_texture(device)?;
+        Some(ColorAttachment::Texture(Some(texture), target))
+    }
+
     fn get_surface_texture(
         &mut self,
         device: &mut SurfmanDevice,
@@ -515,20 +1018,64 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         let guard = self.shared_data.lock().unwrap();
         let data = guard.as_ref().unwrap();
 
+        let placement = match init {
+            LayerInit::CompositionLayer {
+                space,
+                view_pixel_size,
+                layout,
+                geometry,
+                ..
+            } => {
+                match space.base {
+                    BaseSpace::Local
+                    | BaseSpace::Floor
+                    | BaseSpace::BoundedFloor
+                    | BaseSpace::Viewer => (),
+                    // Only trusted overlays can follow inputs, see `attach_layer`
+                    base => {
+                        return Err(Error::UnsupportedFeature(format!(
+                            "layers in {:?} space",
+                            base
+                        )))
+                    }
+                }
+                Some(LayerPlacement {
+                    space,
+                    view_pixel_size,
+                    layout,
+                    geometry,
+                })
+            }
+            LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. } => None,
+        };
+
         // XXXManishearth should we be doing this, or letting Servo set the format?
         let formats = self.session.enumerate_swapchain_formats().map_err(|e| {
-            Error::BackendSpecific(format!("Session::enumerate_swapchain_formats {:?}", e))
+            Error::SwapchainCreationFailed(
+                ApiBackend::OpenXr,
+                format!("Session::enumerate_swapchain_formats {:?}", e),
+            )
         })?;
-        let format = GraphicsProvider::pick_format(&formats);
+        let supported_formats: Vec<_> = formats
+            .iter()
+            .filter_map(|&format| GraphicsProvider::color_format(format))
+            .collect();
+        let color_format = init.pick_color_format(&supported_formats).ok_or_else(|| {
+            Error::SwapchainCreationFailed(
+                ApiBackend::OpenXr,
+                format!("no usable color format amongst {:?}", formats),
+            )
+        })?;
+        let format = GraphicsProvider::native_format(color_format);
         let texture_size = init.texture_size(&data.viewports());
-        let sample_count = data.swapchain_sample_count;
+        let sample_count = init.sample_count(data.swapchain_sample_count);
         let swapchain_create_info = SwapchainCreateInfo {
             create_flags: SwapchainCreateFlags::EMPTY,
             usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT | SwapchainUsageFlags::SAMPLED,
             width: texture_size.width as u32,
             height: texture_size.height as u32,
             format,
-            sample_count,
+            sample_count: 1,
             face_count: 1,
             array_size: 1,
             mip_count: 1,
@@ -536,42 +1083,73 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         let swapchain = self
             .session
             .create_swapchain(&swapchain_create_info)
-            .map_err(|e| Error::BackendSpecific(format!("Session::create_swapchain {:?}", e)))?;
+            .map_err(|e| {
+                Error::SwapchainCreationFailed(
+                    ApiBackend::OpenXr,
+                    format!("Session::create_swapchain {:?}", e),
+                )
+            })?;
 
         // TODO: Treat depth and stencil separately?
-        // TODO: Use the openxr API for depth/stencil swap chains?
+        // Depth stays in a texture of our own rather than a depth swapchain, so it isn't given
+        // to the compositor with XR_KHR_composition_layer_depth. That needs the swapchain's
+        // images as GL textures, and ANGLE can only wrap D3D11 color textures, not depth ones.
         let has_depth_stencil = match init {
             LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
             LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
+            LayerInit::CompositionLayer { .. } => false,
+        };
+        let gl = contexts
+            .bindings(device, context_id)
+            .ok_or(Error::NoMatchingDevice)?;
+        let sample_count = if sample_count > 1
+            && gl_utils::multisampled_color_format(gl, color_format).is_none()
+        {
+            warn!(
+                "No multisampled format to resolve into {:?}, not antialiasing",
+                color_format
+            );
+            1
+        } else {
+            sample_count
         };
         let depth_stencil_texture = if has_depth_stencil {
-            let gl = contexts
-                .bindings(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
-            unsafe {
-                let depth_stencil_texture = gl.create_texture().ok();
-                gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
-                gl.tex_image_2d(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::DEPTH24_STENCIL8 as _,
-                    texture_size.width,
-                    texture_size.height,
-                    0,
-                    gl::DEPTH_STENCIL,
-                    gl::UNSIGNED_INT_24_8,
-                    PixelUnpackData::Slice(None),
-                );
-                depth_stencil_texture
-            }
+            gl_utils::create_depth_stencil_texture(gl, texture_size, sample_count)
+        } else {
+            None
+        };
+        let multisampled_renderbuffer = if sample_count > 1 {
+            gl_utils::create_multisampled_color_renderbuffer(
+                gl,
+                texture_size,
+                sample_count,
+                color_format,
+            )
+        } else {
+            None
+        };
+        let flipped_texture = if placement.is_some() && cfg!(target_os = "windows") {
+            gl_utils::create_color_texture(gl, texture_size, color_format)
         } else {
             None
         };
 
         let layer_id = LayerId::new();
-        let openxr_layer = OpenXrLayer::new(swapchain, depth_stencil_texture, texture_size)?;
+        let openxr_layer = OpenXrLayer::new(
+            swapchain,
+            depth_stencil_texture,
+            multisampled_renderbuffer,
+            flipped_texture,
+            texture_size,
+            sample_count,
+            color_format,
+            placement,
+        )?;
         self.layers.push((context_id, layer_id));
         self.openxr_layers.insert(layer_id, openxr_layer);
+        if let Some(progress) = self.progress.take() {
+            progress.report(SessionProgress::SwapchainReady);
+        }
         Ok(layer_id)
     }
 
@@ -586,9 +1164,15 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             .destroy_layer(device, contexts, context_id, layer_id);
         self.layers.retain(|&ids| ids != (context_id, layer_id));
         if let Some(mut layer) = self.openxr_layers.remove(&layer_id) {
-            if let Some(depth_stencil_texture) = layer.depth_stencil_texture {
-                let gl = contexts.bindings(device, context_id).unwrap();
-                unsafe { gl.delete_texture(depth_stencil_texture) };
+            let gl = contexts.bindings(device, context_id).unwrap();
+            unsafe {
+                let textures = [layer.depth_stencil_texture, layer.flipped_texture];
+                for texture in textures.iter().flatten() {
+                    gl.delete_texture(*texture);
+                }
+                if let Some(renderbuffer) = layer.multisampled_renderbuffer {
+                    gl.delete_renderbuffer(renderbuffer);
+                }
             }
             let mut context = contexts
                 .context(device, context_id)
@@ -599,30 +1183,344 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                         .destroy_surface_texture(&mut context, surface_texture)
                         .unwrap();
                     device.destroy_surface(&mut context, &mut surface).unwrap();
+                    self.accounting.destroyed(layer_id);
+                }
+            }
+        }
+        self.accounting.layer_destroyed(layer_id);
+    }
+
+    fn migrate_context(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        // The OpenXR swapchains don't belong to a GL context, so only the surface
+        // textures wrapping their images and the depth/stencil textures need recreating.
+        for (context_id, layer_id) in &mut self.layers {
+            if *context_id != old_context_id {
+                continue;
+            }
+            self.clearer
+                .destroy_layer(device, contexts, old_context_id, *layer_id);
+            let layer = self
+                .openxr_layers
+                .get_mut(layer_id)
+                .ok_or(Error::NoMatchingDevice)?;
+            let surface_textures = mem::replace(&mut layer.surface_textures, vec![]);
+            layer
+                .surface_textures
+                .resize_with(surface_textures.len(), || None);
+            match contexts.context(device, old_context_id) {
+                Some(mut context) => {
+                    for surface_texture in surface_textures.into_iter().flatten() {
+                        let mut surface = device
+                            .destroy_surface_texture(&mut context, surface_texture)
+                            .unwrap();
+                        device.destroy_surface(&mut context, &mut surface).unwrap();
+                        self.accounting.destroyed(*layer_id);
+                    }
+                    let gl = contexts.bindings(device, old_context_id).unwrap();
+                    unsafe {
+                        let textures = [layer.depth_stencil_texture, layer.flipped_texture];
+                        for texture in textures.iter().flatten() {
+                            gl.delete_texture(*texture);
+                        }
+                        if let Some(renderbuffer) = layer.multisampled_renderbuffer {
+                            gl.delete_renderbuffer(renderbuffer);
+                        }
+                    }
+                }
+                None => {
+                    // The old context is gone, and its GL resources went with it.
+                    warn!("Context {:?} lost, leaking its surfaces", old_context_id);
+                    for surface_texture in surface_textures.into_iter().flatten() {
+                        mem::forget(surface_texture);
+                        self.accounting.destroyed(*layer_id);
+                    }
                 }
             }
+            let gl = contexts
+                .bindings(device, new_context_id)
+                .ok_or(Error::NoMatchingDevice)?;
+            if layer.depth_stencil_texture.is_some() {
+                layer.depth_stencil_texture =
+                    gl_utils::create_depth_stencil_texture(gl, layer.size, layer.sample_count);
+            }
+            if layer.multisampled_renderbuffer.is_some() {
+                layer.multisampled_renderbuffer = gl_utils::create_multisampled_color_renderbuffer(
+                    gl,
+                    layer.size,
+                    layer.sample_count,
+                    layer.color_format,
+                );
+            }
+            if layer.flipped_texture.is_some() {
+                layer.flipped_texture =
+                    gl_utils::create_color_texture(gl, layer.size, layer.color_format);
+            }
+            *context_id = new_context_id;
         }
+        Ok(())
     }
 
     fn layers(&self) -> &[(ContextId, LayerId)] {
         &self.layers[..]
     }
 
-    fn end_frame(
+    fn swapchain_count(&self) -> Option<usize> {
+        Some(self.openxr_layers.len())
+    }
+
+    fn texture_memory(&self) -> Option<u64> {
+        let memory = self
+            .openxr_layers
+            .values()
+            .map(|layer| {
+                let image = gl_utils::texture_memory(layer.size, 1);
+                let multisampled = gl_utils::texture_memory(layer.size, layer.sample_count);
+                let mut memory = image * layer.images.len() as u64;
+                if layer.depth_stencil_texture.is_some() {
+                    memory += multisampled;
+                }
+                if layer.multisampled_renderbuffer.is_some() {
+                    memory += multisampled;
+                }
+                if layer.flipped_texture.is_some() {
+                    memory += image;
+                }
+                memory
+            })
+            .sum();
+        Some(memory)
+    }
+
+    fn set_foveation_level(
         &mut self,
         _device: &mut SurfmanDevice,
         _contexts: &mut dyn GLContexts<SurfmanGL>,
+        _context_id: ContextId,
+        layer_id: LayerId,
+        level: f32,
+    ) {
+        if !self.supports_foveation {
+            return;
+        }
+        let openxr_layer = match self.openxr_layers.get(&layer_id) {
+            Some(openxr_layer) => openxr_layer,
+            None => return,
+        };
+        if let Err(e) = set_swapchain_foveation(&self.session, &openxr_layer.swapchain, level) {
+            warn!("Failed to set foveation level: {:?}", e);
+        }
+    }
+
+    fn set_layer_quality(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        _context_id: ContextId,
+        layer_id: LayerId,
+        quality: Option<LayerQuality>,
+    ) {
+        if let Some(openxr_layer) = self.openxr_layers.get_mut(&layer_id) {
+            openxr_layer.quality = quality;
+        }
+    }
+
+    fn set_layer_geometry(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        _context_id: ContextId,
+        layer_id: LayerId,
+        geometry: LayerGeometry,
+    ) {
+        let placement = self
+            .openxr_layers
+            .get_mut(&layer_id)
+            .and_then(|openxr_layer| openxr_layer.placement.as_mut());
+        match placement {
+            Some(placement) if placement.geometry.layer_type() == geometry.layer_type() => {
+                placement.geometry = geometry
+            }
+            _ => warn!(
+                "Can't set the geometry of layer {:?} to {:?}",
+                layer_id, geometry
+            ),
+        }
+    }
+
+    fn attach_layer(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        _context_id: ContextId,
+        layer_id: LayerId,
+        space: WebXrSpace,
+    ) {
+        let placement = self
+            .openxr_layers
+            .get_mut(&layer_id)
+            .and_then(|openxr_layer| openxr_layer.placement.as_mut());
+        match placement {
+            Some(placement) => placement.space = space,
+            None => warn!("Can't attach layer {:?} to {:?}", layer_id, space),
+        }
+    }
+
+    fn set_vignette(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        strength: f32,
+    ) {
+        self.vignette = strength;
+    }
+
+    fn set_latency_marker(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        marker: Option<LatencyMarker>,
+    ) {
+        self.latency_marker = marker;
+    }
+
+    fn release_surfaces(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+    ) {
+        // The surface textures wrapping the swapchain images are recreated lazily
+        // by `begin_frame`, so they can go while paused. The swapchains themselves
+        // stay, since the layers' ids and sizes have to survive a resume.
+        for &(context_id, layer_id) in &self.layers {
+            self.clearer
+                .destroy_layer(device, contexts, context_id, layer_id);
+            let layer = match self.openxr_layers.get_mut(&layer_id) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let mut context = match contexts.context(device, context_id) {
+                Some(context) => context,
+                None => continue,
+            };
+            for surface_texture in layer.surface_textures.iter_mut() {
+                if let Some(surface_texture) = surface_texture.take() {
+                    let mut surface = device
+                        .destroy_surface_texture(&mut context, surface_texture)
+                        .unwrap();
+                    device.destroy_surface(&mut context, &mut surface).unwrap();
+                    self.accounting.destroyed(layer_id);
+                }
+            }
+        }
+    }
+
+    fn end_frame(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error> {
         let guard = self.shared_data.lock().unwrap();
         let data = guard.as_ref().unwrap();
 
+        if self.vignette > 0.0 || self.latency_marker.is_some() {
+            let viewports = data.scaled_viewports();
+            for &(context_id, layer_id) in layers {
+                // These are only drawn over the views, not layers placed in the world
+                let openxr_layer = match self.openxr_layers.get(&layer_id) {
+                    Some(openxr_layer)
+                        if openxr_layer.waited && openxr_layer.placement.is_none() =>
+                    {
+                        openxr_layer
+                    }
+                    _ => continue,
+                };
+                let color = match openxr_layer.color_attachment(device) {
+                    Some(color) => color,
+                    None => continue,
+                };
+                if self.vignette > 0.0 {
+                    self.clearer.vignette(
+                        device,
+                        contexts,
+                        context_id,
+                        layer_id,
+                        color,
+                        openxr_layer.depth_stencil_texture,
+                        gl_utils::texture_target(openxr_layer.sample_count),
+                        &viewports.viewports,
+                        self.vignette,
+                    );
+                }
+                if let Some(marker) = self.latency_marker {
+                    self.clearer.latency_marker(
+                        device,
+                        contexts,
+                        context_id,
+                        layer_id,
+                        color,
+                        openxr_layer.depth_stencil_texture,
+                        gl_utils::texture_target(openxr_layer.sample_count),
+                        &viewports.viewports,
+                        marker,
+                    );
+                }
+            }
+        }
+
+        // Multisampled and flipped layers were rendered into their own buffers, which need
+        // copying into the swapchain images before those are released
+        for &(context_id, layer_id) in &self.layers {
+            let openxr_layer = match self.openxr_layers.get(&layer_id) {
+                Some(openxr_layer) if openxr_layer.waited => openxr_layer,
+                _ => continue,
+            };
+            let (rendered, flip) = match (
+                openxr_layer.multisampled_renderbuffer,
+                openxr_layer.flipped_texture,
+            ) {
+                (Some(renderbuffer), _) => (ColorAttachment::Renderbuffer(renderbuffer), false),
+                (None, Some(texture)) => (
+                    ColorAttachment::Texture(Some(texture), gl::TEXTURE_2D),
+                    true,
+                ),
+                (None, None) => continue,
+            };
+            let resolved_texture = match openxr_layer.surface_textures.get(openxr_layer.image) {
+                Some(Some(surface_texture)) => device.surface_texture_object(surface_texture),
+                _ => continue,
+            };
+            let resolved_target = device.surface_gl_texture_target();
+            let gl = match contexts.bindings(device, context_id) {
+                Some(gl) => gl,
+                None => continue,
+            };
+            self.clearer.resolve(
+                gl,
+                layer_id,
+                (rendered, openxr_layer.depth_stencil_texture),
+                NonZeroU32::new(resolved_texture).map(glow::NativeTexture),
+                resolved_target,
+                openxr_layer.size,
+                flip,
+            );
+        }
+
         // At this point the frame contents have been rendered, so we can release access to the texture
         // in preparation for displaying it.
         for (_, openxr_layer) in &mut self.openxr_layers {
             if openxr_layer.waited {
                 openxr_layer.swapchain.release_image().map_err(|e| {
-                    Error::BackendSpecific(format!("Session::release_image {:?}", e))
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Session::release_image {:?}", e),
+                    )
                 })?;
                 openxr_layer.waited = false;
             }
@@ -639,12 +1537,30 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             std::mem::swap(&mut l_fov.angle_up, &mut r_fov.angle_down);
             std::mem::swap(&mut r_fov.angle_up, &mut l_fov.angle_down);
         }
+        pose_audit::submitted_view("left view", &data.left.view.pose, &l_fov);
+        pose_audit::submitted_view("right view", &data.right.view.pose, &r_fov);
 
-        let viewports = data.viewports();
-        let primary_views = layers
+        let viewports = data.scaled_viewports();
+        let openxr_layers = layers
+            .iter()
+            .filter_map(|&(_, layer_id)| openxr_layers.get(&layer_id))
+            .collect::<Vec<_>>();
+        // The filtering settings of each layer, which have to outlive the layers that point to them
+        let layer_settings = openxr_layers
             .iter()
-            .filter_map(|&(_, layer_id)| {
-                let openxr_layer = openxr_layers.get(&layer_id)?;
+            .map(|openxr_layer| {
+                openxr_layer
+                    .quality
+                    .filter(|_| self.supports_layer_settings)
+                    .map(layer_settings)
+            })
+            .collect::<Vec<_>>();
+        let primary_views = openxr_layers
+            .iter()
+            .map(|openxr_layer| {
+                if openxr_layer.placement.is_some() {
+                    return None;
+                }
                 Some([
                     openxr::CompositionLayerProjectionView::new()
                         .pose(data.left.view.pose)
@@ -668,22 +1584,54 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             })
             .collect::<Vec<_>>();
 
-        let primary_layers = primary_views
+        // Layers placed in the world are shown in every view, so they are shared
+        // between the primary and secondary views
+        let placed_layers = openxr_layers
             .iter()
-            .map(|views| {
-                CompositionLayerProjection::new()
-                    .space(&data.space)
-                    .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
-                    .views(&views[..])
+            .zip(&layer_settings)
+            .map(|(openxr_layer, settings)| match openxr_layer.placement {
+                Some(ref placement) => {
+                    placed_composition_layers(openxr_layer, placement, data, &self.viewer_space)
+                        .into_iter()
+                        .map(|layer| layer.with_settings(settings.as_ref()))
+                        .collect()
+                }
+                None => vec![],
             })
             .collect::<Vec<_>>();
 
-        let mut primary_layers = primary_layers
+        let primary_projections = primary_views
             .iter()
-            .map(|layer| layer.deref())
+            .zip(&layer_settings)
+            .map(|(views, settings)| {
+                views.as_ref().map(|views| {
+                    projection_with_settings(
+                        CompositionLayerProjection::new()
+                            .space(&data.space)
+                            .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
+                            .views(&views[..]),
+                        settings.as_ref(),
+                    )
+                })
+            })
             .collect::<Vec<_>>();
 
-        if let Some(passthrough_layer) = &self.passthrough_layer {
+        let mut primary_layers = primary_projections
+            .iter()
+            .zip(&placed_layers)
+            .flat_map(|(projection, placed)| {
+                projection
+                    .iter()
+                    .map(|layer| layer.deref())
+                    .chain(placed.iter().map(PlacedLayer::base))
+            })
+            .collect::<Vec<_>>();
+
+        let should_render = data
+            .frame_state
+            .as_ref()
+            .map_or(true, |frame_state| frame_state.should_render);
+        if let (Some(passthrough_layer), true) = (&self.passthrough_layer, should_render) {
             let clp = CompositionLayerPassthroughFB {
                 ty: CompositionLayerPassthroughFB::TYPE,
                 next: std::ptr::null(),
@@ -697,13 +1645,18 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             }
         }
 
+        // The only secondary view configuration that runtimes are asked for is the
+        // first-person observer, which is a single view right after the eyes.
         if let (Some(secondary), true) = (data.secondary.as_ref(), data.secondary_active) {
             let mut s_fov = secondary.view.fov;
             std::mem::swap(&mut s_fov.angle_up, &mut s_fov.angle_down);
-            let secondary_views = layers
+            pose_audit::submitted_view("secondary view", &secondary.view.pose, &s_fov);
+            let secondary_views = openxr_layers
                 .iter()
-                .filter_map(|&(_, layer_id)| {
-                    let openxr_layer = openxr_layers.get(&layer_id)?;
+                .map(|openxr_layer| {
+                    if openxr_layer.placement.is_some() {
+                        return None;
+                    }
                     Some([openxr::CompositionLayerProjectionView::new()
                         .pose(secondary.view.pose)
                         .fov(s_fov)
@@ -716,19 +1669,31 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                 })
                 .collect::<Vec<_>>();
 
-            let secondary_layers = secondary_views
+            let secondary_projections = secondary_views
                 .iter()
-                .map(|views| {
-                    CompositionLayerProjection::new()
-                        .space(&data.space)
-                        .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
-                        .views(&views[..])
+                .zip(&layer_settings)
+                .map(|(views, settings)| {
+                    views.as_ref().map(|views| {
+                        projection_with_settings(
+                            CompositionLayerProjection::new()
+                                .space(&data.space)
+                                .layer_flags(CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA)
+                                .views(&views[..]),
+                            settings.as_ref(),
+                        )
+                    })
                 })
                 .collect::<Vec<_>>();
 
-            let secondary_layers = secondary_layers
+            let secondary_layers = secondary_projections
                 .iter()
-                .map(|layer| layer.deref())
+                .zip(&placed_layers)
+                .flat_map(|(projection, placed)| {
+                    projection
+                        .iter()
+                        .map(|layer| layer.deref())
+                        .chain(placed.iter().map(PlacedLayer::base))
+                })
                 .collect::<Vec<_>>();
 
             self.frame_stream
@@ -746,9 +1711,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                         layers: &secondary_layers[..],
                     },
                 )
-                .map_err(|e| {
-                    Error::BackendSpecific(format!("FrameStream::end_secondary {:?}", e))
-                })?;
+                .map_err(|e| frame_stream_error("FrameStream::end_secondary", e))?;
         } else {
             self.frame_stream
                 .end(
@@ -756,7 +1719,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                     data.primary_blend_mode,
                     &primary_layers[..],
                 )
-                .map_err(|e| Error::BackendSpecific(format!("FrameStream::end {:?}", e)))?;
+                .map_err(|e| frame_stream_error("FrameStream::end", e))?;
         }
         Ok(())
     }
@@ -771,9 +1734,10 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         let data = data_guard.as_ref().unwrap();
         let openxr_layers = &mut self.openxr_layers;
         let clearer = &mut self.clearer;
+        let accounting = &mut self.accounting;
         self.frame_stream
             .begin()
-            .map_err(|e| Error::BackendSpecific(format!("FrameStream::begin {:?}", e)))?;
+            .map_err(|e| frame_stream_error("FrameStream::begin", e))?;
         layers
             .iter()
             .map(|&(context_id, layer_id)| {
@@ -785,23 +1749,41 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                     .ok_or(Error::NoMatchingDevice)?;
 
                 let image = openxr_layer.swapchain.acquire_image().map_err(|e| {
-                    Error::BackendSpecific(format!("Swapchain::acquire_image {:?}", e))
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Swapchain::acquire_image {:?}", e),
+                    )
                 })?;
                 openxr_layer
                     .swapchain
                     .wait_image(openxr::Duration::INFINITE)
                     .map_err(|e| {
-                        Error::BackendSpecific(format!("Swapchain::wait_image {:?}", e))
+                        Error::BackendSpecific(
+                            ApiBackend::OpenXr,
+                            format!("Swapchain::wait_image {:?}", e),
+                        )
                     })?;
+                openxr_layer.image = image as usize;
                 openxr_layer.waited = true;
 
-                let color_surface_texture = openxr_layer
+                // Surface textures are created lazily, the first time each image is used
+                if let Some(None) = openxr_layer.surface_textures.get(image as usize) {
+                    accounting.created(layer_id);
+                }
+                openxr_layer
                     .get_surface_texture(device, context, image as usize)
                     .map_err(|e| {
-                        Error::BackendSpecific(format!("Layer::get_surface_texture {:?}", e))
+                        Error::BackendSpecific(
+                            ApiBackend::OpenXr,
+                            format!("Layer::get_surface_texture {:?}", e),
+                        )
                     })?;
-                let color_texture = device.surface_texture_object(color_surface_texture);
-                let color_target = device.surface_gl_texture_target();
+                let (color_texture, _) = openxr_layer
+                    .image_texture(device)
+                    .ok_or(Error::NoMatchingDevice)?;
+                let color = openxr_layer
+                    .color_attachment(device)
+                    .ok_or(Error::NoMatchingDevice)?;
                 let depth_stencil_texture = openxr_layer
                     .depth_stencil_texture
                     .map(|texture| texture.0.get());
@@ -809,17 +1791,22 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                 let origin = Point2D::new(0, 0);
                 let texture_size = openxr_layer.size;
                 let sub_image = Some(SubImage {
-                    color_texture,
+                    color_texture: color_texture.0.get(),
                     depth_stencil_texture,
                     texture_array_index,
                     viewport: Rect::new(origin, texture_size),
                 });
-                let view_sub_images = data
-                    .viewports()
-                    .viewports
+                let viewports = match openxr_layer.placement {
+                    Some(ref placement) => placement
+                        .layout
+                        .eye_rects(placement.view_pixel_size)
+                        .to_vec(),
+                    None => data.scaled_viewports().viewports,
+                };
+                let view_sub_images = viewports
                     .iter()
                     .map(|&viewport| SubImage {
-                        color_texture,
+                        color_texture: color_texture.0.get(),
                         depth_stencil_texture,
                         texture_array_index,
                         viewport,
@@ -830,12 +1817,33 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
                     contexts,
                     context_id,
                     layer_id,
-                    NonZeroU32::new(color_texture).map(glow::NativeTexture),
-                    color_target,
+                    color,
                     openxr_layer.depth_stencil_texture,
+                    gl_utils::texture_target(openxr_layer.sample_count),
                 );
+                // Renderbuffers can't be handed to content as textures, so it gets their framebuffer
+                let framebuffer = match color {
+                    ColorAttachment::Renderbuffer(_) => {
+                        let gl = contexts
+                            .bindings(device, context_id)
+                            .ok_or(Error::NoMatchingDevice)?;
+                        clearer
+                            .layer_framebuffer(
+                                gl,
+                                layer_id,
+                                color,
+                                openxr_layer.depth_stencil_texture,
+                                gl_utils::texture_target(openxr_layer.sample_count),
+                            )
+                            .map(|framebuffer| framebuffer.0.get())
+                    }
+                    ColorAttachment::Texture(..) => None,
+                };
                 Ok(SubImages {
                     layer_id,
+                    sample_count: openxr_layer.sample_count,
+                    color_format: openxr_layer.color_format,
+                    framebuffer,
                     sub_image,
                     view_sub_images,
                 })
@@ -844,6 +1852,69 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
     }
 }
 
+/// Applies a fixed foveation profile to a swapchain, using the FB foveation extensions
+fn set_swapchain_foveation(
+    session: &Session<Backend>,
+    swapchain: &Swapchain<Backend>,
+    level: f32,
+) -> Result<(), openxr::sys::Result> {
+    let exts = session.instance().exts();
+    let (foveation, update_state) = match (&exts.fb_foveation, &exts.fb_swapchain_update_state) {
+        (Some(foveation), Some(update_state)) => (foveation, update_state),
+        _ => return Ok(()),
+    };
+    let level = if level <= 0.0 {
+        openxr::sys::FoveationLevelFB::NONE
+    } else if level < 1.0 / 3.0 {
+        openxr::sys::FoveationLevelFB::LOW
+    } else if level < 2.0 / 3.0 {
+        openxr::sys::FoveationLevelFB::MEDIUM
+    } else {
+        openxr::sys::FoveationLevelFB::HIGH
+    };
+    let mut level_info = openxr::sys::FoveationLevelProfileCreateInfoFB {
+        ty: openxr::sys::FoveationLevelProfileCreateInfoFB::TYPE,
+        next: std::ptr::null_mut(),
+        level,
+        vertical_offset: 0.0,
+        dynamic: openxr::sys::FoveationDynamicFB::DISABLED,
+    };
+    let create_info = openxr::sys::FoveationProfileCreateInfoFB {
+        ty: openxr::sys::FoveationProfileCreateInfoFB::TYPE,
+        next: &mut level_info as *mut _ as *mut _,
+    };
+    let mut profile = openxr::sys::FoveationProfileFB::NULL;
+    unsafe {
+        let result =
+            (foveation.create_foveation_profile)(session.as_raw(), &create_info, &mut profile);
+        if result != openxr::sys::Result::SUCCESS {
+            return Err(result);
+        }
+        let state = openxr::sys::SwapchainStateFoveationFB {
+            ty: openxr::sys::SwapchainStateFoveationFB::TYPE,
+            next: std::ptr::null_mut(),
+            flags: openxr::sys::SwapchainStateFoveationFlagsFB::EMPTY,
+            profile,
+        };
+        let result = (update_state.update_swapchain)(swapchain.as_raw(), &state as *const _ as _);
+        // The swapchain keeps its own reference to the profile
+        (foveation.destroy_foveation_profile)(profile);
+        if result != openxr::sys::Result::SUCCESS {
+            return Err(result);
+        }
+    }
+    Ok(())
+}
+
+/// Losing the session while rendering usually means the GPU was reset or removed
+fn frame_stream_error(call: &str, e: openxr::sys::Result) -> Error {
+    if e == openxr::sys::Result::ERROR_SESSION_LOST {
+        Error::GraphicsDeviceLost(ApiBackend::OpenXr)
+    } else {
+        Error::BackendSpecific(ApiBackend::OpenXr, format!("{} {:?}", call, e))
+    }
+}
+
 fn image_rect(viewport: Rect<i32, Viewport>) -> openxr::Rect2Di {
     openxr::Rect2Di {
         extent: openxr::Extent2Di {
@@ -857,22 +1928,69 @@ fn image_rect(viewport: Rect<i32, Viewport>) -> openxr::Rect2Di {
     }
 }
 
+/// Describes the filtering of a layer, using the FB composition layer settings extension
+fn layer_settings(quality: LayerQuality) -> CompositionLayerSettingsFB {
+    let mut layer_flags = CompositionLayerSettingsFlagsFB::EMPTY;
+    match quality.supersampling {
+        LayerFilter::Off => {}
+        LayerFilter::Normal => {
+            layer_flags |= CompositionLayerSettingsFlagsFB::NORMAL_SUPER_SAMPLING
+        }
+        LayerFilter::Quality => {
+            layer_flags |= CompositionLayerSettingsFlagsFB::QUALITY_SUPER_SAMPLING
+        }
+    }
+    match quality.sharpening {
+        LayerFilter::Off => {}
+        LayerFilter::Normal => layer_flags |= CompositionLayerSettingsFlagsFB::NORMAL_SHARPENING,
+        LayerFilter::Quality => layer_flags |= CompositionLayerSettingsFlagsFB::QUALITY_SHARPENING,
+    }
+    CompositionLayerSettingsFB {
+        ty: CompositionLayerSettingsFB::TYPE,
+        next: std::ptr::null(),
+        layer_flags,
+    }
+}
+
+/// Chains the filtering settings of a layer onto its projection
+fn projection_with_settings<'a>(
+    projection: CompositionLayerProjection<'a, Backend>,
+    settings: Option<&'a CompositionLayerSettingsFB>,
+) -> CompositionLayerProjection<'a, Backend> {
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return projection,
+    };
+    let mut projection = projection.into_raw();
+    projection.next = settings as *const _ as *const _;
+    unsafe { CompositionLayerProjection::from_raw(projection) }
+}
+
 impl OpenXrDevice {
     fn new(
         instance: CreatedInstance,
-        granted_features: Vec<String>,
-        context_menu_provider: Option<Box<dyn ContextMenuProvider>>,
+        granted_features: Vec<Feature>,
+        events: EventBuffer,
+        trusted_ui: Option<Box<dyn TrustedUiProvider>>,
+        runtime: RuntimeMonitor,
+        audio_listener: Arc<Mutex<Option<AudioListenerCallback>>>,
         grand_manager: LayerGrandManager<SurfmanGL>,
+        progress: SessionProgressReporter,
     ) -> Result<OpenXrDevice, Error> {
         let CreatedInstance {
             instance,
             supports_hands,
+            supports_multimodal_input,
             supports_secondary,
             system,
             supports_mutable_fov,
             supported_interaction_profiles,
             supports_passthrough,
             supports_updating_framerate,
+            supports_foveation,
+            supports_layer_settings,
+            supported_layer_types,
+            sessions,
         } = instance;
 
         let (init_tx, init_rx) = crossbeam_channel::unbounded();
@@ -882,6 +2000,7 @@ impl OpenXrDevice {
         let shared_data_clone = shared_data.clone();
         let mut data = shared_data.lock().unwrap();
 
+        let layer_manager_progress = progress.clone();
         let layer_manager = grand_manager.create_layer_manager(move |device, _| {
             let (session, frame_waiter, frame_stream) =
                 GraphicsProvider::create_session(device, &instance_clone, system)?;
@@ -898,6 +2017,14 @@ impl OpenXrDevice {
             } else {
                 (None, None)
             };
+            let viewer_space = session
+                .create_reference_space(ReferenceSpaceType::VIEW, IDENTITY_POSE)
+                .map_err(|e| {
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Session::create_reference_space {:?}", e),
+                    )
+                })?;
             let session = Arc::new(session);
             init_tx
                 .send((session.clone(), frame_waiter))
@@ -907,8 +2034,12 @@ impl OpenXrDevice {
                 shared_data_clone,
                 frame_stream,
                 !supports_mutable_fov,
+                supports_foveation,
+                supports_layer_settings,
                 passthrough,
                 passthrough_layer,
+                viewer_space,
+                layer_manager_progress,
             ))
         })?;
 
@@ -923,13 +2054,19 @@ impl OpenXrDevice {
                     &[ViewConfigurationType::SECONDARY_MONO_FIRST_PERSON_OBSERVER_MSFT],
                 )
                 .map_err(|e| {
-                    Error::BackendSpecific(format!("Session::begin_with_secondary {:?}", e))
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Session::begin_with_secondary {:?}", e),
+                    )
                 })?;
         } else {
             session
                 .begin(ViewConfigurationType::PRIMARY_STEREO)
-                .map_err(|e| Error::BackendSpecific(format!("Session::begin {:?}", e)))?;
+                .map_err(|e| {
+                    Error::BackendSpecific(ApiBackend::OpenXr, format!("Session::begin {:?}", e))
+                })?;
         }
+        progress.report(SessionProgress::SessionBegun);
 
         let pose = Posef {
             orientation: Quaternionf {
@@ -947,23 +2084,29 @@ impl OpenXrDevice {
         let space = session
             .create_reference_space(ReferenceSpaceType::LOCAL, pose)
             .map_err(|e| {
-                Error::BackendSpecific(format!("Session::create_reference_space {:?}", e))
+                Error::BackendSpecific(
+                    ApiBackend::OpenXr,
+                    format!("Session::create_reference_space {:?}", e),
+                )
             })?;
 
         let viewer_space = session
             .create_reference_space(ReferenceSpaceType::VIEW, pose)
             .map_err(|e| {
-                Error::BackendSpecific(format!("Session::create_reference_space {:?}", e))
+                Error::BackendSpecific(
+                    ApiBackend::OpenXr,
+                    format!("Session::create_reference_space {:?}", e),
+                )
             })?;
 
         let view_configuration_type = ViewConfigurationType::PRIMARY_STEREO;
         let view_configurations = instance
             .enumerate_view_configuration_views(system, view_configuration_type)
             .map_err(|e| {
-                Error::BackendSpecific(format!(
-                    "Session::enumerate_view_configuration_views {:?}",
-                    e
-                ))
+                Error::BackendSpecific(
+                    ApiBackend::OpenXr,
+                    format!("Session::enumerate_view_configuration_views {:?}", e),
+                )
             })?;
 
         let left_view_configuration = view_configurations[0];
@@ -992,10 +2135,10 @@ impl OpenXrDevice {
                     ViewConfigurationType::SECONDARY_MONO_FIRST_PERSON_OBSERVER_MSFT,
                 )
                 .map_err(|e| {
-                    Error::BackendSpecific(format!(
-                        "Session::enumerate_view_configuration_views {:?}",
-                        e
-                    ))
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Session::enumerate_view_configuration_views {:?}", e),
+                    )
                 })?
                 .get(0)
                 .expect(
@@ -1008,10 +2151,10 @@ impl OpenXrDevice {
                     ViewConfigurationType::SECONDARY_MONO_FIRST_PERSON_OBSERVER_MSFT,
                 )
                 .map_err(|e| {
-                    Error::BackendSpecific(format!(
-                        "Instance::enumerate_environment_blend_modes {:?}",
-                        e
-                    ))
+                    Error::BackendSpecific(
+                        ApiBackend::OpenXr,
+                        format!("Instance::enumerate_environment_blend_modes {:?}", e),
+                    )
                 })?[0];
 
             let secondary_extent = Extent2Di {
@@ -1033,10 +2176,10 @@ impl OpenXrDevice {
         let primary_blend_mode = instance
             .enumerate_environment_blend_modes(system, view_configuration_type)
             .map_err(|e| {
-                Error::BackendSpecific(format!(
-                    "Instance::enumerate_environment_blend_modes {:?}",
-                    e
-                ))
+                Error::BackendSpecific(
+                    ApiBackend::OpenXr,
+                    format!("Instance::enumerate_environment_blend_modes {:?}", e),
+                )
             })?[0];
 
         let left = ViewInfo {
@@ -1059,19 +2202,41 @@ impl OpenXrDevice {
             primary_blend_mode,
             secondary_blend_mode,
             swapchain_sample_count,
+            viewport_scales: ViewportScales::default(),
+            recommended_viewport_scale: 1.0,
+            inputs: vec![],
         });
         drop(data);
 
+        let graphics_properties = instance
+            .system_properties(system)
+            .map_err(|e| {
+                Error::BackendSpecific(
+                    ApiBackend::OpenXr,
+                    format!("Instance::system_properties {:?}", e),
+                )
+            })?
+            .graphics_properties;
+        let layer_limits = LayerLimits {
+            max_layers: graphics_properties.max_layer_count as usize,
+            max_texture_size: Size2D::new(
+                graphics_properties.max_swapchain_image_width as i32,
+                graphics_properties.max_swapchain_image_height as i32,
+            ),
+            supported_layer_types,
+        };
+
         let (action_set, right_hand, left_hand) = OpenXRInput::setup_inputs(
             &instance,
             &session,
             supports_hands,
+            supports_multimodal_input,
             supported_interaction_profiles,
         );
 
         Ok(OpenXrDevice {
             instance,
-            events: Default::default(),
+            events,
             session,
             frame_waiter,
             viewer_space,
@@ -1079,18 +2244,122 @@ impl OpenXrDevice {
             supports_secondary,
             supports_mutable_fov,
             supports_updating_framerate,
+            layer_limits,
+            visibility: Visibility::Hidden,
+            frame_start: None,
+            should_render: true,
+            frame_subscriptions: FrameSubscriptions::default(),
+            feature_usage: FeatureUsage::default(),
+            mono_rendering: false,
             layer_manager,
             shared_data,
 
             action_set,
             right_hand,
             left_hand,
+            select_arbitration: SelectArbitrationOptions::default(),
+            select_arbiters: vec![],
+            stale_select_arbiters: vec![],
             granted_features,
-            context_menu_provider,
+            trusted_ui,
             context_menu_future: None,
+            hand_permission: Permission::Unasked,
+            _running: runtime.session_running(),
+            runtime,
+            frame_errors: 0,
+            audio_listener,
+            reorientation: RigidTransform3D::identity(),
+            _instance_sessions: sessions,
         })
     }
 
+    fn set_visibility(&mut self, visibility: Visibility) {
+        if self.visibility != visibility {
+            self.visibility = visibility;
+            self.events.callback(Event::VisibilityChange(visibility));
+        }
+    }
+
+    /// The select events of an input for this frame, arbitrated if its profiles have
+    /// select arbitration
+    fn arbitrate_selects(
+        &mut self,
+        input: &input::Frame,
+        time: f64,
+    ) -> Vec<(SelectKind, SelectEvent)> {
+        let id = input.frame.id;
+        let has_arbiter = self
+            .select_arbiters
+            .iter()
+            .any(|&(arbiter_id, _)| arbiter_id == id);
+        if !has_arbiter && (input.select.is_some() || input.squeeze.is_some()) {
+            let profiles = self
+                .right_hand
+                .input_sources()
+                .into_iter()
+                .chain(self.left_hand.input_sources())
+                .find(|source| source.id == id)
+                .map(|source| source.profiles)
+                .unwrap_or_default();
+            if let Some(arbitration) = self.select_arbitration.for_profiles(&profiles) {
+                self.select_arbiters
+                    .push((id, SelectArbiter::new(arbitration)));
+            }
+        }
+        match self
+            .select_arbiters
+            .iter()
+            .position(|(arbiter_id, _)| *arbiter_id == id)
+        {
+            Some(index) => {
+                let arbiter = &mut self.select_arbiters[index].1;
+                let events = arbiter.arbitrate(input.select, input.squeeze, time);
+                if arbiter.is_idle() && self.stale_select_arbiters.contains(&id) {
+                    self.select_arbiters.remove(index);
+                    self.stale_select_arbiters
+                        .retain(|&stale_id| stale_id != id);
+                }
+                events
+            }
+            None => [
+                (SelectKind::Select, input.select),
+                (SelectKind::Squeeze, input.squeeze),
+            ]
+            .iter()
+            .filter_map(|&(kind, event)| Some((kind, event?)))
+            .collect(),
+        }
+    }
+
+    /// Stop using the arbiters of the inputs that `replace` picks, because their arbitration
+    /// changed. Arbiters in the middle of a gesture are kept until it is over, so that
+    /// the ends of the events they let through aren't lost.
+    fn replace_select_arbiters(&mut self, replace: impl Fn(InputId) -> bool) {
+        let stale = &mut self.stale_select_arbiters;
+        self.select_arbiters.retain(|(id, arbiter)| {
+            if !replace(*id) {
+                return true;
+            }
+            if arbiter.is_idle() {
+                return false;
+            }
+            if !stale.contains(id) {
+                stale.push(*id);
+            }
+            true
+        });
+    }
+
+    /// Count a failed frame. If the runtime has gone away, or frames keep failing, end
+    /// the session, and have the discovery start waiting for the runtime to come back.
+    fn frame_error(&mut self, lost: bool) {
+        self.frame_errors += 1;
+        if lost || self.frame_errors == MAX_FRAME_ERRORS {
+            self.runtime.runtime_lost();
+            self.events.callback(Event::SessionEnd);
+        }
+    }
+
     fn handle_openxr_events(&mut self) -> bool {
         use openxr::Event::*;
         let mut stopped = false;
@@ -1100,6 +2369,10 @@ impl OpenXrDevice {
                 Ok(event) => event,
                 Err(e) => {
                     error!("Error polling events: {:?}", e);
+                    if is_runtime_lost(e) {
+                        self.runtime.runtime_lost();
+                        self.events.callback(Event::SessionEnd);
+                    }
                     return false;
                 }
             };
@@ -1110,34 +2383,36 @@ impl OpenXrDevice {
                         return false;
                     }
                     openxr::SessionState::STOPPING => {
-                        self.events
-                            .callback(Event::VisibilityChange(Visibility::Hidden));
+                        self.set_visibility(Visibility::Hidden);
                         if let Err(e) = self.session.end() {
                             error!("Session failed to end on STOPPING: {:?}", e);
                         }
                         stopped = true;
                     }
                     openxr::SessionState::READY if stopped => {
-                        self.events
-                            .callback(Event::VisibilityChange(Visibility::Visible));
+                        // The session becomes visible again once the runtime
+                        // moves it to VISIBLE or FOCUSED
                         if let Err(e) = self.session.begin(ViewConfigurationType::PRIMARY_STEREO) {
                             error!("Session failed to begin on READY: {:?}", e);
                         }
                         stopped = false;
                     }
+                    openxr::SessionState::SYNCHRONIZED => {
+                        self.set_visibility(Visibility::Hidden);
+                    }
                     openxr::SessionState::FOCUSED => {
-                        self.events
-                            .callback(Event::VisibilityChange(Visibility::Visible));
+                        self.set_visibility(Visibility::Visible);
                     }
                     openxr::SessionState::VISIBLE => {
-                        self.events
-                            .callback(Event::VisibilityChange(Visibility::VisibleBlurred));
+                        // A system overlay (e.g. the runtime dashboard) has focus
+                        self.set_visibility(Visibility::VisibleBlurred);
                     }
                     _ => {
                         // FIXME: Handle other states
                     }
                 },
                 Some(InstanceLossPending(_)) => {
+                    self.runtime.runtime_lost();
                     self.events.callback(Event::SessionEnd);
                     return false;
                 }
@@ -1148,20 +2423,16 @@ impl OpenXrDevice {
 
                     match profile {
                         Ok(profile) => {
-                            let profiles = get_profiles_from_path(profile)
-                                .iter()
-                                .map(|s| s.to_string())
-                                .collect();
-
-                            let mut new_left = self.left_hand.input_source();
-                            new_left.profiles.clone_from(&profiles);
-                            self.events
-                                .callback(Event::UpdateInput(new_left.id, new_left));
-
-                            let mut new_right = self.right_hand.input_source();
-                            new_right.profiles.clone_from(&profiles);
-                            self.events
-                                .callback(Event::UpdateInput(new_right.id, new_right));
+                            let profiles = get_profiles_from_path(profile);
+
+                            for hand in &mut [&mut self.left_hand, &mut self.right_hand] {
+                                hand.set_profiles(profiles.clone());
+                                // With multimodal input, controllers that aren't held get
+                                // their profiles when they are picked up
+                                if let Some(source) = hand.controller_source() {
+                                    self.events.callback(Event::UpdateInput(source.id, source));
+                                }
+                            }
                         }
                         Err(e) => {
                             error!("Failed to get interaction profile: {:?}", e);
@@ -1174,14 +2445,21 @@ impl OpenXrDevice {
                         ReferenceSpaceType::LOCAL => BaseSpace::Local,
                         ReferenceSpaceType::LOCAL_FLOOR => BaseSpace::Floor,
                         ReferenceSpaceType::STAGE => BaseSpace::BoundedFloor,
-                        _ => unreachable!(
-                            "Should not be receiving change events for unsupported space types"
-                        ),
+                        // Runtimes report changes to every space they have, even ones
+                        // that were never created (such as unbounded spaces)
+                        other => {
+                            warn!("Ignoring change to unsupported reference space {:?}", other);
+                            continue;
+                        }
                     };
                     let transform = transform(&e.pose_in_previous_space());
                     self.events
                         .callback(Event::ReferenceSpaceChanged(base_space, transform));
                 }
+                Some(DisplayRefreshRateChangedFB(e)) => {
+                    let rate = e.to_display_refresh_rate();
+                    self.events.callback(Event::FrameRateChanged(rate));
+                }
                 Some(_) => {
                     // FIXME: Handle other events
                 }
@@ -1201,36 +2479,53 @@ impl OpenXrDevice {
 
 impl SharedData {
     fn views(&self) -> Views {
-        let left_view = self.left.view();
-        let right_view = self.right.view();
+        let scale = self.recommended_viewport_scale;
+        let left_view = self.left.view(scale);
+        let right_view = self.right.view(scale);
         if let (Some(secondary), true) = (self.secondary.as_ref(), self.secondary_active) {
             // Note: we report the secondary view only when it is active
-            let third_eye = secondary.view();
-            return Views::StereoCapture(left_view, right_view, third_eye);
+            let mut third_eye = secondary.view(scale);
+            third_eye.camera = Some(secondary.camera());
+            return Views::StereoSecondary(left_view, right_view, vec![third_eye]);
         }
         Views::Stereo(left_view, right_view)
     }
 
     fn viewports(&self) -> Viewports {
-        let left_vp = Rect::new(
-            Point2D::zero(),
-            Size2D::new(self.left.extent.width, self.left.extent.height),
-        );
-        let right_vp = Rect::new(
-            Point2D::new(self.left.extent.width, 0),
-            Size2D::new(self.right.extent.width, self.right.extent.height),
-        );
-        let mut viewports = vec![left_vp, right_vp];
+        let left_size = Size2D::new(self.left.extent.width, self.left.extent.height);
+        let right_size = Size2D::new(self.right.extent.width, self.right.extent.height);
         // Note: we report the secondary viewport even when it is inactive
-        if let Some(ref secondary) = self.secondary {
-            let secondary_vp = Rect::new(
-                Point2D::new(self.left.extent.width + self.right.extent.width, 0),
-                Size2D::new(secondary.extent.width, secondary.extent.height)
-                    / SECONDARY_VIEW_DOWNSCALE,
-            );
-            viewports.push(secondary_vp)
-        }
-        Viewports { viewports }
+        let secondary_size = self.secondary.as_ref().map(|secondary| {
+            Size2D::new(secondary.extent.width, secondary.extent.height) / SECONDARY_VIEW_DOWNSCALE
+        });
+        Viewports::side_by_side(
+            vec![left_size, right_size]
+                .into_iter()
+                .chain(secondary_size),
+        )
+    }
+
+    /// The viewports that are rendered into, after applying the scales requested by content
+    fn scaled_viewports(&self) -> Viewports {
+        self.viewport_scales.scaled_viewports(&self.viewports())
+    }
+
+    /// Adjusts the recommended viewport scale based on how much of the display period
+    /// the last frame took to render
+    fn update_recommended_viewport_scale(&mut self, frame_time: Duration) {
+        let period = match self.frame_state {
+            Some(ref frame_state) => frame_state.predicted_display_period.as_nanos() as f32,
+            None => return,
+        };
+        let load = frame_time.as_nanos() as f32 / period;
+        let scale = if load > 0.9 {
+            self.recommended_viewport_scale - VIEWPORT_SCALE_STEP
+        } else if load < 0.7 {
+            self.recommended_viewport_scale + VIEWPORT_SCALE_STEP
+        } else {
+            self.recommended_viewport_scale
+        };
+        self.recommended_viewport_scale = scale.max(util::MIN_VIEWPORT_SCALE).min(1.0);
     }
 }
 
@@ -1257,6 +2552,73 @@ impl DeviceAPI for OpenXrDevice {
         self.layer_manager.destroy_layer(context_id, layer_id)
     }
 
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.layer_manager
+            .migrate_context(old_context_id, new_context_id)
+    }
+
+    fn set_vignette(&mut self, strength: f32) {
+        self.layer_manager.set_vignette(strength);
+    }
+
+    fn set_latency_marker(&mut self, marker: Option<LatencyMarker>) {
+        self.layer_manager.set_latency_marker(marker);
+    }
+
+    fn set_reorientation(&mut self, reorientation: RigidTransform3D<f32, Native, Native>) {
+        self.reorientation = reorientation;
+    }
+
+    fn set_mono_rendering(&mut self, mono: bool) {
+        self.mono_rendering = mono;
+    }
+
+    fn set_select_arbitration(&mut self, options: SelectArbitrationOptions) {
+        self.select_arbitration = options;
+        self.replace_select_arbiters(|_| true);
+    }
+
+    fn resources(&self) -> DeviceResources {
+        DeviceResources {
+            swapchains: self.layer_manager.swapchain_count(),
+            texture_memory: self.layer_manager.texture_memory(),
+            buffered_events: Some(self.events.buffered()),
+        }
+    }
+
+    fn set_foveation_level(&mut self, context_id: ContextId, layer_id: LayerId, level: f32) {
+        self.layer_manager
+            .set_foveation_level(context_id, layer_id, level)
+    }
+
+    fn set_layer_quality(
+        &mut self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        quality: Option<LayerQuality>,
+    ) {
+        self.layer_manager
+            .set_layer_quality(context_id, layer_id, quality)
+    }
+
+    fn set_layer_geometry(
+        &mut self,
+        context_id: ContextId,
+        layer_id: LayerId,
+        geometry: LayerGeometry,
+    ) {
+        self.layer_manager
+            .set_layer_geometry(context_id, layer_id, geometry)
+    }
+
+    fn attach_layer(&mut self, context_id: ContextId, layer_id: LayerId, space: WebXrSpace) {
+        self.layer_manager.attach_layer(context_id, layer_id, space)
+    }
+
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
         if !self.handle_openxr_events() {
             warn!("no frame, session isn't running");
@@ -1265,12 +2627,14 @@ impl DeviceAPI for OpenXrDevice {
         }
         if let Some(ref context_menu_future) = self.context_menu_future {
             match context_menu_future.poll() {
-                ContextMenuResult::ExitSession => {
+                TrustedUiResult::ExitSession => {
                     self.quit();
                     return None;
                 }
-                ContextMenuResult::Dismissed => self.context_menu_future = None,
-                ContextMenuResult::Pending => (),
+                TrustedUiResult::Dismissed | TrustedUiResult::Granted => {
+                    self.context_menu_future = None
+                }
+                TrustedUiResult::Pending => (),
             }
         }
 
@@ -1279,6 +2643,7 @@ impl DeviceAPI for OpenXrDevice {
                 Ok(frame_state) => frame_state,
                 Err(e) => {
                     error!("Error waiting on frame: {:?}", e);
+                    self.frame_error(is_runtime_lost(e));
                     return None;
                 }
             };
@@ -1293,11 +2658,23 @@ impl DeviceAPI for OpenXrDevice {
                 Ok(frame_state) => (frame_state, None),
                 Err(e) => {
                     error!("Error waiting on frame: {:?}", e);
+                    self.frame_error(is_runtime_lost(e));
                     return None;
                 }
             }
         };
 
+        // When the runtime doesn't want us to render (for example while it is showing its
+        // own UI), content still gets poses, but no images are acquired or submitted.
+        self.should_render = frame_state.should_render;
+        let layers = if self.should_render { layers } else { &[] };
+
+        if let Some(data) = self.shared_data.lock().unwrap().as_mut() {
+            // Scales requested since the last frame apply from this one on, so that the
+            // layer manager uses the same viewports for all of it
+            data.viewport_scales.commit_scales();
+        }
+
         // We get the subimages before grabbing the lock,
         // since otherwise we'll deadlock
         let sub_images = self.layer_manager.begin_frame(layers).ok()?;
@@ -1305,8 +2682,7 @@ impl DeviceAPI for OpenXrDevice {
         let mut guard = self.shared_data.lock().unwrap();
         let data = guard.as_mut().unwrap();
 
-        // XXXManishearth should we check frame_state.should_render?
-        let (_view_flags, mut views) = match self.session.locate_views(
+        let (view_flags, mut views) = match self.session.locate_views(
             ViewConfigurationType::PRIMARY_STEREO,
             frame_state.predicted_display_time,
             &data.space,
@@ -1317,24 +2693,55 @@ impl DeviceAPI for OpenXrDevice {
                 return None;
             }
         };
+        if view_flags.contains(ViewStateFlags::ORIENTATION_VALID) {
+            for view in &views {
+                pose_audit::runtime_pose("view", &view.pose);
+            }
+        }
         if !self.supports_mutable_fov {
             views.iter_mut().for_each(|v| {
                 std::mem::swap(&mut v.fov.angle_up, &mut v.fov.angle_down);
             });
         }
+        if self.mono_rendering {
+            // The views keep their own orientation and field of view, but are rendered
+            // and submitted from the same place, so both eyes see the same thing
+            let (left, right) = (views[0].pose.position, views[1].pose.position);
+            let center = Vector3f {
+                x: (left.x + right.x) / 2.,
+                y: (left.y + right.y) / 2.,
+                z: (left.z + right.z) / 2.,
+            };
+            views[0].pose.position = center;
+            views[1].pose.position = center;
+        }
         data.left.set_view(views[0], self.clip_planes);
         data.right.set_view(views[1], self.clip_planes);
-        let pose = match self
+        let (pose, pose_velocity) = match self
             .viewer_space
-            .locate(&data.space, frame_state.predicted_display_time)
+            .relate(&data.space, frame_state.predicted_display_time)
         {
-            Ok(pose) => pose,
+            Ok(located) => located,
             Err(e) => {
                 error!("Error locating viewer space: {:?}", e);
                 return None;
             }
         };
+        if pose
+            .location_flags
+            .contains(SpaceLocationFlags::ORIENTATION_VALID)
+        {
+            pose_audit::runtime_pose("viewer", &pose.pose);
+        }
         let transform = transform(&pose.pose);
+        if let Some(ref mut audio_listener) = *self.audio_listener.lock().unwrap() {
+            audio_listener(&transform.then(&self.reorientation));
+        }
+        let (linear_velocity, angular_velocity) = velocity(
+            pose_velocity.velocity_flags,
+            &pose_velocity.linear_velocity,
+            &pose_velocity.angular_velocity,
+        );
 
         if let Some(secondary_state) = secondary_state.as_ref() {
             data.secondary_active = secondary_state.active;
@@ -1361,86 +2768,124 @@ impl DeviceAPI for OpenXrDevice {
             return None;
         }
 
-        let mut right = self
-            .right_hand
-            .frame(&self.session, &frame_state, &data.space, &transform);
-        let mut left = self
-            .left_hand
-            .frame(&self.session, &frame_state, &data.space, &transform);
+        let right = self.right_hand.frame(
+            &self.session,
+            &frame_state,
+            &data.space,
+            &transform,
+            self.frame_subscriptions.hand_joints,
+        );
+        let left = self.left_hand.frame(
+            &self.session,
+            &frame_state,
+            &data.space,
+            &transform,
+            self.frame_subscriptions.hand_joints,
+        );
+        let mut inputs: Vec<_> = right.frames.into_iter().chain(left.frames).collect();
 
         data.frame_state = Some(frame_state);
+        data.inputs = inputs.iter().map(|input| input.frame.clone()).collect();
         let views = data.views();
 
-        if let Some(ref context_menu_provider) = self.context_menu_provider {
-            if (left.menu_selected || right.menu_selected) && self.context_menu_future.is_none() {
-                self.context_menu_future = Some(context_menu_provider.open_context_menu());
+        if let Some(ref trusted_ui) = self.trusted_ui {
+            let menu_selected = inputs.iter().any(|input| input.menu_selected);
+            if menu_selected && self.context_menu_future.is_none() {
+                self.context_menu_future = Some(trusted_ui.open_prompt(TrustedUiPrompt::ExitMenu));
             } else if self.context_menu_future.is_some() {
                 // Do not surface input info whilst the context menu is open
                 // We don't do this for the first frame after the context menu is opened
                 // so that the appropriate select cancel events may fire
-                right.frame.target_ray_origin = None;
-                right.frame.grip_origin = None;
-                left.frame.target_ray_origin = None;
-                left.frame.grip_origin = None;
-                right.select = None;
-                right.squeeze = None;
-                left.select = None;
-                left.squeeze = None;
+                for input in &mut inputs {
+                    input.frame.target_ray_origin = None;
+                    input.frame.grip_origin = None;
+                    input.select = None;
+                    input.squeeze = None;
+                }
             }
         }
 
-        let left_input_changed = left.frame.input_changed;
-        let right_input_changed = right.frame.input_changed;
+        if self.visibility != Visibility::Visible {
+            // Content must not receive input while it doesn't have focus
+            for input in &mut inputs {
+                input.suppress();
+            }
+        }
+
+        let has_hands = inputs.iter().any(|input| input.frame.hand.is_some());
+        if has_hands
+            && !self
+                .hand_permission
+                .check(Feature::HandTracking, self.trusted_ui.as_deref())
+        {
+            for input in &mut inputs {
+                input.frame.hand = None;
+            }
+        }
+
+        if inputs.iter().any(|input| input.frame.hand.is_some()) {
+            self.feature_usage
+                .used(Feature::HandTracking, &mut self.events);
+        }
+
+        for source in right.added.into_iter().chain(left.added) {
+            self.events.callback(Event::AddInput(source));
+        }
+        for source in right.updated.into_iter().chain(left.updated) {
+            // The source may have new profiles, with different arbitration
+            self.replace_select_arbiters(|id| id == source.id);
+            self.events.callback(Event::UpdateInput(source.id, source));
+        }
 
         let frame = Frame {
-            pose: Some(ViewerPose { transform, views }),
-            inputs: vec![right.frame, left.frame],
+            pose: Some(ViewerPose {
+                transform,
+                linear_velocity,
+                angular_velocity,
+                views,
+            }),
+            inputs: inputs.iter().map(|input| input.frame.clone()).collect(),
             events: vec![],
             sub_images,
             hit_test_results: vec![],
+            tracked_images: vec![],
+            dom_overlay: None,
+            depth_information: None,
             predicted_display_time: frame_state.predicted_display_time.as_nanos() as f64,
+            predicted_display_period: Some(frame_state.predicted_display_period.as_nanos() as f64),
+            render_budget: None,
         };
 
-        if let Some(right_select) = right.select {
-            self.events.callback(Event::Select(
-                InputId(0),
-                SelectKind::Select,
-                right_select,
-                frame.clone(),
-            ));
-        }
-        if let Some(right_squeeze) = right.squeeze {
-            self.events.callback(Event::Select(
-                InputId(0),
-                SelectKind::Squeeze,
-                right_squeeze,
-                frame.clone(),
-            ));
-        }
-        if let Some(left_select) = left.select {
-            self.events.callback(Event::Select(
-                InputId(1),
-                SelectKind::Select,
-                left_select,
-                frame.clone(),
-            ));
-        }
-        if let Some(left_squeeze) = left.squeeze {
-            self.events.callback(Event::Select(
-                InputId(1),
-                SelectKind::Squeeze,
-                left_squeeze,
-                frame.clone(),
-            ));
-        }
-        if left_input_changed {
-            self.events
-                .callback(Event::InputChanged(InputId(1), frame.inputs[1].clone()))
-        }
-        if right_input_changed {
-            self.events
-                .callback(Event::InputChanged(InputId(0), frame.inputs[0].clone()))
+        let mut select_frame = None;
+        for input in &inputs {
+            let selects = self.arbitrate_selects(input, frame.predicted_display_time);
+            for (kind, event) in selects {
+                let gesture = match kind {
+                    SelectKind::Select => input.select_gesture,
+                    SelectKind::Squeeze => SelectGesture::Grip,
+                };
+                let select_frame = select_frame
+                    .get_or_insert_with(|| Arc::new(frame.for_event()))
+                    .clone();
+                let id = input.frame.id;
+                self.events
+                    .callback(Event::Select(id, kind, event, gesture, select_frame));
+            }
+        }
+        for input in inputs {
+            if input.frame.input_changed {
+                self.events
+                    .callback(Event::InputChanged(input.frame.id, input.frame))
+            }
+        }
+        for id in right.removed.into_iter().chain(left.removed) {
+            self.select_arbiters
+                .retain(|&(arbiter_id, _)| arbiter_id != id);
+            self.stale_select_arbiters
+                .retain(|&stale_id| stale_id != id);
+            self.events.callback(Event::RemoveInput(id));
         }
+        self.frame_start = Some(Instant::now());
         Some(frame)
     }
 
@@ -1449,14 +2894,31 @@ impl DeviceAPI for OpenXrDevice {
         // Due to threading issues we can't call D3D11 APIs on the openxr thread as the
         // WebGL thread might be using the device simultaneously, so this method delegates
         // everything to the layer manager.
-        let _ = self.layer_manager.end_frame(layers);
+        if let Some(frame_start) = self.frame_start.take() {
+            if let Some(data) = self.shared_data.lock().unwrap().as_mut() {
+                data.update_recommended_viewport_scale(frame_start.elapsed());
+            }
+        }
+        let layers = if self.should_render { layers } else { &[] };
+        match self.layer_manager.end_frame(layers) {
+            Ok(()) => self.frame_errors = 0,
+            Err(e) => {
+                error!("Error ending frame: {:?}", e);
+                self.frame_error(false);
+            }
+        }
+    }
+
+    fn request_viewport_scale(&mut self, view: usize, scale: f32) {
+        if let Some(data) = self.shared_data.lock().unwrap().as_mut() {
+            data.viewport_scales.request(view, scale);
+        }
     }
 
     fn initial_inputs(&self) -> Vec<InputSource> {
-        vec![
-            self.right_hand.input_source(),
-            self.left_hand.input_source(),
-        ]
+        let mut inputs = self.right_hand.input_sources();
+        inputs.extend(self.left_hand.input_sources());
+        inputs
     }
 
     fn set_event_dest(&mut self, dest: Sender<Event>) {
@@ -1465,7 +2927,12 @@ impl DeviceAPI for OpenXrDevice {
 
     fn quit(&mut self) {
         self.session.request_exit().unwrap();
+        let deadline = Instant::now() + QUIT_TIMEOUT;
         loop {
+            if Instant::now() > deadline {
+                warn!("Timed out waiting for the session to exit");
+                break;
+            }
             let mut buffer = openxr::EventDataBuffer::new();
             let event = match self.instance.poll_event(&mut buffer) {
                 Ok(e) => e,
@@ -1498,6 +2965,17 @@ impl DeviceAPI for OpenXrDevice {
         *self.shared_data.lock().unwrap() = None;
     }
 
+    fn pause(&mut self) {
+        // No frames are waited for or submitted while paused, so the runtime
+        // shows its own content. What the layers hold for rendering can go.
+        self.layer_manager.release_surfaces();
+    }
+
+    fn shutdown(&mut self) {
+        self.context_menu_future = None;
+        *self.shared_data.lock().unwrap() = None;
+    }
+
     fn set_quitter(&mut self, _: Quitter) {
         // the quitter is only needed if we have anything from outside the render
         // thread that can signal a quit. We don't.
@@ -1507,6 +2985,10 @@ impl DeviceAPI for OpenXrDevice {
         self.clip_planes.update(near, far);
     }
 
+    fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
     fn environment_blend_mode(&self) -> webxr_api::EnvironmentBlendMode {
         match self
             .shared_data
@@ -1523,12 +3005,17 @@ impl DeviceAPI for OpenXrDevice {
         }
     }
 
-    fn granted_features(&self) -> &[String] {
+    fn granted_features(&self) -> &[Feature] {
         &self.granted_features
     }
 
+    fn set_frame_subscriptions(&mut self, subscriptions: FrameSubscriptions) {
+        self.frame_subscriptions = subscriptions;
+    }
+
     fn update_frame_rate(&mut self, rate: f32) -> f32 {
         if self.supports_updating_framerate {
+            // The runtime reports the change with a DisplayRefreshRateChangedFB event
             self.session
                 .request_display_refresh_rate(rate)
                 .expect("Failed to request display refresh rate");
@@ -1540,6 +3027,21 @@ impl DeviceAPI for OpenXrDevice {
         }
     }
 
+    fn trigger_haptic(&mut self, id: InputId, intensity: f32, duration: f64) {
+        let hand = match id {
+            InputId(0) => &self.right_hand,
+            InputId(1) => &self.left_hand,
+            _ => return,
+        };
+        if let Err(e) = hand.trigger_haptic(&self.session, intensity, duration) {
+            warn!("Failed to apply haptic feedback: {:?}", e);
+        }
+    }
+
+    fn layer_limits(&self) -> LayerLimits {
+        self.layer_limits.clone()
+    }
+
     fn supported_frame_rates(&self) -> Vec<f32> {
         if self.supports_updating_framerate {
             self.session
@@ -1571,6 +3073,179 @@ impl DeviceAPI for OpenXrDevice {
     }
 }
 
+/// The composition layer of a layer placed in the world, which is one of several types
+enum PlacedLayer<'a> {
+    Quad(CompositionLayerQuad<'a, Backend>),
+    Cylinder(CompositionLayerCylinderKHR<'a, Backend>),
+    Equirect(CompositionLayerEquirect2KHR<'a, Backend>),
+}
+
+impl<'a> PlacedLayer<'a> {
+    fn base(&self) -> &CompositionLayerBase<'a, Backend> {
+        match self {
+            PlacedLayer::Quad(layer) => layer.deref(),
+            PlacedLayer::Cylinder(layer) => layer.deref(),
+            PlacedLayer::Equirect(layer) => layer.deref(),
+        }
+    }
+
+    /// Chains the filtering settings of the layer onto it, like `projection_with_settings`
+    fn with_settings(self, settings: Option<&'a CompositionLayerSettingsFB>) -> Self {
+        let next = match settings {
+            Some(settings) => settings as *const _ as *const _,
+            None => return self,
+        };
+        unsafe {
+            match self {
+                PlacedLayer::Quad(layer) => {
+                    let mut layer = layer.into_raw();
+                    layer.next = next;
+                    PlacedLayer::Quad(CompositionLayerQuad::from_raw(layer))
+                }
+                PlacedLayer::Cylinder(layer) => {
+                    let mut layer = layer.into_raw();
+                    layer.next = next;
+                    PlacedLayer::Cylinder(CompositionLayerCylinderKHR::from_raw(layer))
+                }
+                PlacedLayer::Equirect(layer) => {
+                    let mut layer = layer.into_raw();
+                    layer.next = next;
+                    PlacedLayer::Equirect(CompositionLayerEquirect2KHR::from_raw(layer))
+                }
+            }
+        }
+    }
+}
+
+/// The composition layers that show a quad, cylinder or equirect layer, one for
+/// both eyes if its layout is mono, or one for each eye if it is stereo. Layers attached
+/// to an input that isn't tracked this frame aren't shown.
+fn placed_composition_layers<'a>(
+    openxr_layer: &'a OpenXrLayer,
+    placement: &LayerPlacement,
+    data: &'a SharedData,
+    viewer_space: &'a Space,
+) -> Vec<PlacedLayer<'a>> {
+    let input = |id: InputId| data.inputs.iter().find(|input| input.id == id);
+    let input_origin: Option<RigidTransform3D<f32, ApiSpace, Native>> = match placement.space.base {
+        BaseSpace::TargetRay(id) => input(id)
+            .and_then(|input| input.target_ray_origin)
+            .map(|origin| origin.cast_unit()),
+        BaseSpace::Grip(id) => input(id)
+            .and_then(|input| input.grip_origin)
+            .map(|origin| origin.cast_unit()),
+        BaseSpace::Joint(id, joint) => input(id)
+            .and_then(|input| input.hand.as_ref()?.get(joint))
+            .map(|joint| joint.pose.cast_unit()),
+        _ => None,
+    };
+    let (space, base): (_, RigidTransform3D<f32, ApiSpace, Native>) = match placement.space.base {
+        BaseSpace::Viewer => (viewer_space, RigidTransform3D::identity()),
+        BaseSpace::Floor | BaseSpace::BoundedFloor => {
+            let floor_origin = Vector3D::new(0.0, -HEIGHT, 0.0);
+            (
+                &data.space,
+                RigidTransform3D::from_translation(floor_origin),
+            )
+        }
+        BaseSpace::Local => (&data.space, RigidTransform3D::identity()),
+        BaseSpace::TargetRay(..) | BaseSpace::Grip(..) | BaseSpace::Joint(..) => match input_origin
+        {
+            Some(origin) => (&data.space, origin),
+            None => return vec![],
+        },
+    };
+    let space_origin = placement.space.offset.then(&base);
+    let [left, right] = placement.layout.eye_rects(placement.view_pixel_size);
+    let eyes = match placement.layout {
+        LayerLayout::Mono => vec![(EyeVisibility::BOTH, left)],
+        LayerLayout::StereoLeftRight | LayerLayout::StereoTopBottom => {
+            vec![(EyeVisibility::LEFT, left), (EyeVisibility::RIGHT, right)]
+        }
+    };
+    eyes.into_iter()
+        .filter_map(|(eye, mut rect)| {
+            // The image was flipped when it was copied into the swapchain, see `flipped_texture`
+            if openxr_layer.flipped_texture.is_some() {
+                rect.origin.y = openxr_layer.size.height - rect.max_y();
+            }
+            let sub_image = openxr::SwapchainSubImage::new()
+                .swapchain(&openxr_layer.swapchain)
+                .image_array_index(0)
+                .image_rect(image_rect(rect));
+            let flags = CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA;
+            let layer = match placement.geometry {
+                LayerGeometry::Quad {
+                    transform,
+                    width,
+                    height,
+                } => PlacedLayer::Quad(
+                    CompositionLayerQuad::new()
+                        .layer_flags(flags)
+                        .space(space)
+                        .eye_visibility(eye)
+                        .sub_image(sub_image)
+                        .pose(pose(&transform.then(&space_origin)))
+                        .size(Extent2Df { width, height }),
+                ),
+                LayerGeometry::Cylinder {
+                    transform,
+                    radius,
+                    central_angle,
+                    aspect_ratio,
+                } => PlacedLayer::Cylinder(
+                    CompositionLayerCylinderKHR::new()
+                        .layer_flags(flags)
+                        .space(space)
+                        .eye_visibility(eye)
+                        .sub_image(sub_image)
+                        .pose(pose(&transform.then(&space_origin)))
+                        .radius(radius)
+                        .central_angle(central_angle)
+                        .aspect_ratio(aspect_ratio),
+                ),
+                LayerGeometry::Equirect {
+                    transform,
+                    radius,
+                    central_horizontal_angle,
+                    upper_vertical_angle,
+                    lower_vertical_angle,
+                } => PlacedLayer::Equirect(
+                    CompositionLayerEquirect2KHR::new()
+                        .layer_flags(flags)
+                        .space(space)
+                        .eye_visibility(eye)
+                        .sub_image(sub_image)
+                        .pose(pose(&transform.then(&space_origin)))
+                        .radius(radius)
+                        .central_horizontal_angle(central_horizontal_angle)
+                        .upper_vertical_angle(upper_vertical_angle)
+                        .lower_vertical_angle(lower_vertical_angle),
+                ),
+                // Cube layers aren't supported, see `create_instance`
+                LayerGeometry::Cube { .. } => return None,
+            };
+            Some(layer)
+        })
+        .collect()
+}
+
+fn pose<Src, Dst>(transform: &RigidTransform3D<f32, Src, Dst>) -> Posef {
+    Posef {
+        orientation: Quaternionf {
+            x: transform.rotation.i,
+            y: transform.rotation.j,
+            z: transform.rotation.k,
+            w: transform.rotation.r,
+        },
+        position: Vector3f {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            z: transform.translation.z,
+        },
+    }
+}
+
 fn transform<Src, Dst>(pose: &Posef) -> RigidTransform3D<f32, Src, Dst> {
     let rotation = Rotation3D::quaternion(
         pose.orientation.x,
@@ -1582,6 +3257,18 @@ fn transform<Src, Dst>(pose: &Posef) -> RigidTransform3D<f32, Src, Dst> {
     RigidTransform3D::new(rotation, translation)
 }
 
+type Velocity = (Option<Vector3D<f32, Native>>, Option<Vector3D<f32, Native>>);
+
+/// Drops whichever of the velocities the runtime did not report as valid
+fn velocity(flags: SpaceVelocityFlags, linear: &Vector3f, angular: &Vector3f) -> Velocity {
+    let linear = Vector3D::new(linear.x, linear.y, linear.z);
+    let angular = Vector3D::new(angular.x, angular.y, angular.z);
+    (
+        Some(linear).filter(|_| flags.contains(SpaceVelocityFlags::LINEAR_VALID)),
+        Some(angular).filter(|_| flags.contains(SpaceVelocityFlags::ANGULAR_VALID)),
+    )
+}
+
 #[inline]
 fn fov_to_projection_matrix<T, U>(fov: &Fovf, clip_planes: ClipPlanes) -> Transform3D<f32, T, U> {
     util::fov_to_projection_matrix(
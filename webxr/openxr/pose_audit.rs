@@ -0,0 +1,119 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Debug checks of the coordinate conventions used when talking to the OpenXR runtime.
+//!
+//! OpenXR and WebXR both use right-handed coordinates with -Z forward and +Y up, and euclid
+//! matrices are applied to row vectors, so a transposed matrix or a flipped axis can slip in
+//! unnoticed when converting poses and fields of view. With the `openxr-pose-audit` feature
+//! enabled, poses coming from the runtime, the projection matrices built from them and the
+//! views submitted back are checked against these conventions. Violations panic in debug
+//! builds and are logged otherwise. Without the feature this is a no-op.
+
+use euclid::Transform3D;
+use openxr::{Fovf, Posef};
+use webxr_api::util::ClipPlanes;
+use webxr_api::Display;
+
+#[cfg(feature = "openxr-pose-audit")]
+use euclid::{Point3D, RigidTransform3D, UnknownUnit, Vector3D};
+
+#[cfg(feature = "openxr-pose-audit")]
+const EPSILON: f32 = 1e-3;
+
+/// Checks a pose located by the runtime, and its conversion to a euclid transform
+#[cfg(feature = "openxr-pose-audit")]
+pub(crate) fn runtime_pose(what: &str, pose: &Posef) {
+    check_pose(what, pose);
+    let transform: RigidTransform3D<f32, UnknownUnit, UnknownUnit> = super::transform(pose);
+    let matrix = transform.to_transform();
+    if (matrix.determinant() - 1.).abs() > EPSILON {
+        violation(what, "converted pose is not a right-handed rotation");
+    }
+    // The forward vector rotated by the quaternion, worked out by hand
+    let o = &pose.orientation;
+    let expected = Vector3D::new(
+        -2. * (o.x * o.z + o.w * o.y),
+        -2. * (o.y * o.z - o.w * o.x),
+        -1. + 2. * (o.x * o.x + o.y * o.y),
+    );
+    let forward = matrix.transform_vector3d(Vector3D::new(0., 0., -1.));
+    if (forward - expected).length() > EPSILON {
+        violation(
+            what,
+            "converted pose rotates the wrong way, is it transposed?",
+        );
+    }
+}
+
+/// Checks a projection matrix built from a field of view reported by the runtime
+#[cfg(feature = "openxr-pose-audit")]
+pub(crate) fn projection<Eye>(
+    what: &str,
+    fov: &Fovf,
+    clip_planes: ClipPlanes,
+    projection: &Transform3D<f32, Eye, Display>,
+) {
+    if fov.angle_left >= fov.angle_right {
+        violation(what, "field of view is mirrored horizontally");
+    }
+    let depth = |z: f32| {
+        let clip = projection.transform_point3d_homogeneous(Point3D::new(0., 0., z));
+        (clip.w, clip.z / clip.w)
+    };
+    let (near_w, near_depth) = depth(-clip_planes.near);
+    let (far_w, far_depth) = depth(-clip_planes.far);
+    if near_w <= 0. || far_w <= 0. {
+        violation(what, "projection does not look down -Z, is it transposed?");
+    } else if (near_depth + 1.).abs() > EPSILON || (far_depth - 1.).abs() > EPSILON {
+        violation(what, "projection does not map the clip planes to -1 and 1");
+    }
+}
+
+/// Checks a view that is about to be submitted in a composition layer. The up and down
+/// angles are deliberately swapped on some runtimes, so only the horizontal ones are checked.
+#[cfg(feature = "openxr-pose-audit")]
+pub(crate) fn submitted_view(what: &str, pose: &Posef, fov: &Fovf) {
+    check_pose(what, pose);
+    if fov.angle_left >= fov.angle_right {
+        violation(what, "submitted field of view is mirrored horizontally");
+    }
+}
+
+#[cfg(feature = "openxr-pose-audit")]
+fn check_pose(what: &str, pose: &Posef) {
+    let p = &pose.position;
+    if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) {
+        violation(what, "position is not finite");
+    }
+    let o = &pose.orientation;
+    let length = (o.x * o.x + o.y * o.y + o.z * o.z + o.w * o.w).sqrt();
+    if (length - 1.).abs() > EPSILON {
+        violation(what, "orientation is not a unit quaternion");
+    }
+}
+
+#[cfg(feature = "openxr-pose-audit")]
+fn violation(what: &str, problem: &str) {
+    if cfg!(debug_assertions) {
+        panic!("OpenXR pose audit: {}: {}", what, problem);
+    } else {
+        log::error!("OpenXR pose audit: {}: {}", what, problem);
+    }
+}
+
+#[cfg(not(feature = "openxr-pose-audit"))]
+pub(crate) fn runtime_pose(_what: &str, _pose: &Posef) {}
+
+#[cfg(not(feature = "openxr-pose-audit"))]
+pub(crate) fn projection<Eye>(
+    _what: &str,
+    _fov: &Fovf,
+    _clip_planes: ClipPlanes,
+    _projection: &Transform3D<f32, Eye, Display>,
+) {
+}
+
+#[cfg(not(feature = "openxr-pose-audit"))]
+pub(crate) fn submitted_view(_what: &str, _pose: &Posef, _fov: &Fovf) {}
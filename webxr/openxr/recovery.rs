@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Recovering from the OpenXR runtime going away, for example when SteamVR crashes.
+//! Sessions report the runtime as lost when it says so or their frames keep failing,
+//! and the runtime is then polled until it is back.
+
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use log::warn;
+use webxr_api::DeviceChangeNotifier;
+
+use super::{create_instance, AppInfo};
+
+/// Called on a background thread once the OpenXR runtime is available again after it
+/// was lost, so that the embedder can re-create its `OpenXrDiscovery`
+pub type RuntimeRecoveryCallback = Box<dyn Send + FnMut()>;
+
+// How often to check whether a lost runtime has come back
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// How often to check for a headset being plugged in or unplugged while no session is running
+const AVAILABILITY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether the runtime a discovery uses has been lost, shared with its sessions.
+/// Its threads stop once every handle to it has been dropped.
+#[derive(Clone)]
+pub(crate) struct RuntimeMonitor {
+    state: Arc<Mutex<MonitorState>>,
+    app_info: Arc<AppInfo>,
+}
+
+struct MonitorState {
+    /// Whether the runtime was lost since the discovery last checked
+    lost: bool,
+    polling: bool,
+    callback: Option<RuntimeRecoveryCallback>,
+    /// Tells the registry when the runtime goes away and comes back
+    device_changes: Option<DeviceChangeNotifier>,
+    /// How many sessions are running, see `RunningSession`
+    sessions: usize,
+    /// Whether a thread is checking for a headset, see `watch_availability`
+    watching: bool,
+}
+
+/// Held by running sessions, while which the runtime isn't checked for a headset
+pub(crate) struct RunningSession(RuntimeMonitor);
+
+impl Drop for RunningSession {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().sessions -= 1;
+    }
+}
+
+impl RuntimeMonitor {
+    pub(crate) fn new(app_info: AppInfo) -> RuntimeMonitor {
+        RuntimeMonitor {
+            state: Arc::new(Mutex::new(MonitorState {
+                lost: false,
+                polling: false,
+                callback: None,
+                device_changes: None,
+                sessions: 0,
+                watching: false,
+            })),
+            app_info: Arc::new(app_info),
+        }
+    }
+
+    /// The monitor that `state` belongs to, unless every handle to it has been dropped
+    fn upgrade(
+        state: &Weak<Mutex<MonitorState>>,
+        app_info: &Arc<AppInfo>,
+    ) -> Option<RuntimeMonitor> {
+        Some(RuntimeMonitor {
+            state: state.upgrade()?,
+            app_info: app_info.clone(),
+        })
+    }
+
+    pub(crate) fn app_info(&self) -> &AppInfo {
+        &self.app_info
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<RuntimeRecoveryCallback>) {
+        self.state.lock().unwrap().callback = callback;
+    }
+
+    pub(crate) fn set_device_change_notifier(&self, notifier: DeviceChangeNotifier) {
+        self.state.lock().unwrap().device_changes = Some(notifier);
+    }
+
+    /// Note that a session is running, until the returned value is dropped
+    pub(crate) fn session_running(&self) -> RunningSession {
+        self.state.lock().unwrap().sessions += 1;
+        RunningSession(self.clone())
+    }
+
+    /// Check for a headset every so often while no session is running and the runtime
+    /// isn't lost, and tell the registry when it comes or goes. `available` says whether
+    /// there is one, or None if it can't tell right now.
+    pub(crate) fn watch_availability<F>(&self, available: F)
+    where
+        F: 'static + Send + Fn(&RuntimeMonitor) -> Option<bool>,
+    {
+        let mut state = self.state.lock().unwrap();
+        if state.watching {
+            return;
+        }
+        state.watching = true;
+        let state = Arc::downgrade(&self.state);
+        let app_info = self.app_info.clone();
+        thread::spawn(move || {
+            let mut was_available = None;
+            loop {
+                thread::sleep(AVAILABILITY_INTERVAL);
+                let monitor = match RuntimeMonitor::upgrade(&state, &app_info) {
+                    Some(monitor) => monitor,
+                    None => return,
+                };
+                {
+                    // Running sessions and `runtime_lost` already say whether there is one
+                    let state = monitor.state.lock().unwrap();
+                    if state.sessions > 0 || state.polling {
+                        was_available = Some(!state.polling);
+                        continue;
+                    }
+                }
+                let available = match available(&monitor) {
+                    Some(available) => available,
+                    None => continue,
+                };
+                if was_available.is_some_and(|was_available| was_available != available) {
+                    if let Some(ref device_changes) = monitor.state.lock().unwrap().device_changes {
+                        device_changes.notify();
+                    }
+                }
+                was_available = Some(available);
+            }
+        });
+    }
+
+    /// Whether the runtime was lost since this was last called,
+    /// in which case any instance of it can't be used any more
+    pub(crate) fn take_lost(&self) -> bool {
+        std::mem::replace(&mut self.state.lock().unwrap().lost, false)
+    }
+
+    /// Report the runtime as lost, and start waiting for it to come back
+    pub(crate) fn runtime_lost(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.lost = true;
+        if state.polling {
+            return;
+        }
+        state.polling = true;
+        warn!("OpenXR runtime lost, waiting for it to come back");
+        if let Some(ref device_changes) = state.device_changes {
+            device_changes.notify();
+        }
+        let state = Arc::downgrade(&self.state);
+        let app_info = self.app_info.clone();
+        thread::spawn(move || RuntimeMonitor::poll(state, app_info));
+    }
+
+    fn poll(state: Weak<Mutex<MonitorState>>, app_info: Arc<AppInfo>) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if state.strong_count() == 0 {
+                return;
+            }
+            if create_instance(false, false, false, &app_info).is_ok() {
+                break;
+            }
+        }
+        let monitor = match RuntimeMonitor::upgrade(&state, &app_info) {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        let callback = {
+            let mut state = monitor.state.lock().unwrap();
+            state.polling = false;
+            if let Some(ref device_changes) = state.device_changes {
+                device_changes.notify();
+            }
+            state.callback.take()
+        };
+        // The lock isn't held while calling back, in case the embedder uses the discovery
+        if let Some(mut callback) = callback {
+            callback();
+            let mut state = monitor.state.lock().unwrap();
+            if state.callback.is_none() {
+                state.callback = Some(callback);
+            }
+        }
+    }
+}
+
+/// Whether an error from the runtime means that it has gone away
+pub(crate) fn is_runtime_lost(e: openxr::sys::Result) -> bool {
+    e == openxr::sys::Result::ERROR_INSTANCE_LOST
+        || e == openxr::sys::Result::ERROR_RUNTIME_FAILURE
+        || e == openxr::sys::Result::ERROR_RUNTIME_UNAVAILABLE
+}
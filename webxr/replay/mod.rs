@@ -0,0 +1,230 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A device that plays back a session recorded with `webxr_api::SessionRecorder`,
+//! sending content the recorded frames and events at the pace they were recorded at.
+//! Content renders to offscreen surfaces, like with the headless device.
+
+use crate::SurfmanGL;
+use crate::SurfmanLayerManager;
+use euclid::{Point2D, RigidTransform3D};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use surfman::chains::SwapChains;
+use webxr_api::{
+    ContextId, DeviceAPI, DeviceDescriptor, DiscoveryAPI, EnvironmentBlendMode, Error, Event,
+    EventBuffer, Feature, Floor, Frame, InputSource, LayerGrandManager, LayerId, LayerInit,
+    LayerManager, Native, PendingSession, Quitter, RecordedMsg, Recording, Sender, SessionBuilder,
+    SessionInit, SessionMode, Viewports,
+};
+
+pub struct ReplayDiscovery {
+    recording: Arc<Recording>,
+}
+
+impl ReplayDiscovery {
+    pub fn new(recording: Recording) -> ReplayDiscovery {
+        ReplayDiscovery {
+            recording: Arc::new(recording),
+        }
+    }
+}
+
+impl ReplayDiscovery {
+    fn supported_features(&self) -> Vec<Feature> {
+        // The results of the hit tests content made while recording are in the frames,
+        // but new hit tests can't be answered
+        self.recording
+            .info
+            .granted_features
+            .iter()
+            .filter(|&feature| *feature != Feature::HitTest)
+            .cloned()
+            .collect()
+    }
+}
+
+impl DiscoveryAPI<SurfmanGL> for ReplayDiscovery {
+    fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+        xr: SessionBuilder<SurfmanGL>,
+    ) -> Result<PendingSession, Error> {
+        if !self.supports_session(mode) {
+            return Err(Error::NoMatchingDevice);
+        }
+        let granted_features = init.validate(mode, &self.supported_features())?;
+        let recording = self.recording.clone();
+        let events = xr.event_buffer();
+        xr.spawn(move |grand_manager| {
+            Ok(ReplayDevice {
+                recording,
+                next_entry: 0,
+                start: None,
+                last_frame: None,
+                granted_features,
+                events,
+                quitter: None,
+                grand_manager,
+                layer_manager: None,
+            })
+        })
+    }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        Some(DeviceDescriptor {
+            name: String::from("Session replay"),
+            vendor: String::from("webxr"),
+            modes: self.supported_modes(),
+            features: self.supported_features(),
+        })
+    }
+
+    fn supports_session(&self, mode: SessionMode) -> bool {
+        // Recordings are of immersive sessions, and only AR sessions blend with the world
+        match self.recording.info.environment_blend_mode {
+            EnvironmentBlendMode::Opaque => mode == SessionMode::ImmersiveVR,
+            _ => mode == SessionMode::ImmersiveAR,
+        }
+    }
+}
+
+struct ReplayDevice {
+    recording: Arc<Recording>,
+    /// The index of the next entry of the recording to play
+    next_entry: usize,
+    /// When the first frame was played, which the times of entries are measured from
+    start: Option<Instant>,
+    /// The last frame played, which is repeated while the session ends
+    last_frame: Option<Frame>,
+    granted_features: Vec<Feature>,
+    events: EventBuffer,
+    quitter: Option<Quitter>,
+    grand_manager: LayerGrandManager<SurfmanGL>,
+    layer_manager: Option<LayerManager>,
+}
+
+impl ReplayDevice {
+    fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
+        if let Some(ref mut manager) = self.layer_manager {
+            return Ok(manager);
+        }
+        let swap_chains = SwapChains::new();
+        let viewports = self.viewports();
+        let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
+            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+        })?;
+        self.layer_manager = Some(layer_manager);
+        Ok(self.layer_manager.as_mut().unwrap())
+    }
+
+    /// Play the recording up to its next frame, sending the events before it on the way
+    fn next_frame(&mut self) -> Option<Frame> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        while let Some(entry) = self.recording.entries.get(self.next_entry) {
+            self.next_entry += 1;
+            match entry.msg {
+                // The session ends once the recording runs out
+                RecordedMsg::Event(ref event) => match **event {
+                    Event::SessionEnd => {}
+                    ref event => self.events.callback(event.clone()),
+                },
+                RecordedMsg::Frame(ref frame) => {
+                    let due = start + Duration::from_secs_f64(entry.time / 1000.0);
+                    let now = Instant::now();
+                    if due > now {
+                        thread::sleep(due - now);
+                    }
+                    self.last_frame = Some((**frame).clone());
+                    return self.last_frame.clone();
+                }
+            }
+        }
+        if let Some(quitter) = self.quitter.take() {
+            quitter.quit();
+        }
+        self.last_frame.clone()
+    }
+}
+
+impl DeviceAPI for ReplayDevice {
+    fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
+        self.recording.info.floor_transform
+    }
+
+    fn viewports(&self) -> Viewports {
+        self.recording.info.viewports.clone()
+    }
+
+    fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.layer_manager()?.create_layer(context_id, init)
+    }
+
+    fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.layer_manager()
+            .unwrap()
+            .destroy_layer(context_id, layer_id)
+    }
+
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.layer_manager()?
+            .migrate_context(old_context_id, new_context_id)
+    }
+
+    fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
+        let mut frame = self.next_frame()?;
+        frame.sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+        Some(frame)
+    }
+
+    fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
+        let _ = self.layer_manager().unwrap().end_frame(layers);
+    }
+
+    fn initial_inputs(&self) -> Vec<InputSource> {
+        self.recording.info.initial_inputs.clone()
+    }
+
+    fn set_event_dest(&mut self, dest: Sender<Event>) {
+        self.events.upgrade(dest)
+    }
+
+    fn quit(&mut self) {
+        self.events.callback(Event::SessionEnd);
+    }
+
+    fn shutdown(&mut self) {
+        self.layer_manager = None;
+    }
+
+    fn set_quitter(&mut self, quitter: Quitter) {
+        self.quitter = Some(quitter);
+    }
+
+    fn update_clip_planes(&mut self, _: f32, _: f32) {
+        // The projections are the recorded ones
+    }
+
+    fn environment_blend_mode(&self) -> EnvironmentBlendMode {
+        self.recording.info.environment_blend_mode
+    }
+
+    fn granted_features(&self) -> &[Feature] {
+        &self.granted_features
+    }
+
+    fn supported_frame_rates(&self) -> Vec<f32> {
+        self.recording.info.supported_frame_rates.clone()
+    }
+
+    fn reference_space_bounds(&self) -> Option<Vec<Point2D<f32, Floor>>> {
+        self.recording.info.reference_space_bounds.clone()
+    }
+}
@@ -0,0 +1,322 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A simulated stereo headset, for smoke-testing immersive sessions end to end in CI.
+//!
+//! Unlike the headless mock device, nothing drives it: the viewer orbits a point in front
+//! of where it started, and a controller in its right hand pulls its trigger every couple
+//! of seconds. Content renders to offscreen surfaces, which nothing displays.
+
+use crate::SurfmanGL;
+use crate::SurfmanLayerManager;
+use euclid::{Angle, Point2D, Rect, RigidTransform3D, Rotation3D, Size2D, Vector3D};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use surfman::chains::SwapChains;
+use webxr_api::util::{self, ClipPlanes};
+use webxr_api::{
+    ContextId, DeviceAPI, DeviceDescriptor, DiscoveryAPI, Error, Event, EventBuffer, Feature,
+    Floor, Frame, Handedness, Input, InputFrame, InputId, InputSource, LayerGrandManager, LayerId,
+    LayerInit, LayerManager, Native, PendingSession, Quitter, SelectEvent, SelectGesture,
+    SelectKind, Sender, SessionBuilder, SessionInit, SessionMode, TargetRayMode, View, Viewer,
+    ViewerPose, Viewport, Viewports, Views,
+};
+
+// The features sessions on the simulator can have
+const SUPPORTED_FEATURES: [Feature; 3] = [Feature::Viewer, Feature::Local, Feature::LocalFloor];
+
+// How often the simulator produces frames
+const FRAME_RATE: f32 = 90.0;
+
+// The size of each eye's viewport, in pixels
+const VIEWPORT_SIZE: Size2D<i32, Viewport> = Size2D::new(800, 800);
+
+// Half of the field of view of each eye, in radians
+const HALF_FOV: f32 = std::f32::consts::FRAC_PI_4;
+
+// The distance between the viewer's eyes, in metres
+const INTER_PUPILLARY_DISTANCE: f32 = 0.064;
+
+// How far above the floor the viewer's eyes are, in metres
+const HEIGHT: f32 = 1.6;
+
+// How far the viewer is from the point it orbits, in metres
+const ORBIT_RADIUS: f32 = 1.0;
+
+// How long each orbit takes, in seconds
+const ORBIT_PERIOD: f32 = 20.0;
+
+// Where the controller is held, from the viewer
+const CONTROLLER_OFFSET: Vector3D<f32, Viewer> = Vector3D::new(0.2, -0.3, -0.4);
+
+// How often the controller's trigger is pulled, and for how long, in seconds
+const SELECT_INTERVAL: f32 = 2.0;
+const SELECT_DURATION: f32 = 0.2;
+
+// The id of the controller
+const CONTROLLER_INPUT_ID: InputId = InputId(0);
+
+pub struct SimulatorDiscovery {}
+
+impl SimulatorDiscovery {
+    pub fn new() -> SimulatorDiscovery {
+        SimulatorDiscovery {}
+    }
+}
+
+impl Default for SimulatorDiscovery {
+    fn default() -> SimulatorDiscovery {
+        SimulatorDiscovery::new()
+    }
+}
+
+impl DiscoveryAPI<SurfmanGL> for SimulatorDiscovery {
+    fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+        xr: SessionBuilder<SurfmanGL>,
+    ) -> Result<PendingSession, Error> {
+        if !self.supports_session(mode) {
+            return Err(Error::NoMatchingDevice);
+        }
+        let granted_features = init.validate(mode, &SUPPORTED_FEATURES)?;
+        let events = xr.event_buffer();
+        xr.spawn(move |grand_manager| {
+            Ok(SimulatorDevice {
+                granted_features,
+                clip_planes: ClipPlanes::default(),
+                events,
+                pressed: false,
+                start: Instant::now(),
+                next_frame_time: None,
+                grand_manager,
+                layer_manager: None,
+            })
+        })
+    }
+
+    fn supports_session(&self, mode: SessionMode) -> bool {
+        mode == SessionMode::ImmersiveVR
+    }
+
+    fn device_descriptor(&self) -> Option<DeviceDescriptor> {
+        Some(DeviceDescriptor {
+            name: String::from("Simulated headset"),
+            vendor: String::from("webxr"),
+            modes: self.supported_modes(),
+            features: SUPPORTED_FEATURES.to_vec(),
+        })
+    }
+}
+
+struct SimulatorDevice {
+    granted_features: Vec<Feature>,
+    clip_planes: ClipPlanes,
+    events: EventBuffer,
+    /// Whether the controller's trigger is pulled
+    pressed: bool,
+    /// When the device was created, which the script and frame times are measured from
+    start: Instant,
+    /// When the next frame is due, once the first one has begun
+    next_frame_time: Option<Instant>,
+    grand_manager: LayerGrandManager<SurfmanGL>,
+    layer_manager: Option<LayerManager>,
+}
+
+impl SimulatorDevice {
+    fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
+        if let Some(ref mut manager) = self.layer_manager {
+            return Ok(manager);
+        }
+        let swap_chains = SwapChains::new();
+        let viewports = self.viewports();
+        let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
+            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+        })?;
+        self.layer_manager = Some(layer_manager);
+        Ok(self.layer_manager.as_mut().unwrap())
+    }
+
+    /// The viewer, `time` seconds into the script. It orbits a point in front of where
+    /// it starts, always facing it.
+    fn viewer(&self, time: f32) -> RigidTransform3D<f32, Viewer, Native> {
+        let angle = time / ORBIT_PERIOD * std::f32::consts::TAU;
+        let center = Vector3D::new(0.0, 0.0, -ORBIT_RADIUS);
+        let offset = Vector3D::new(angle.sin(), 0.0, angle.cos()) * ORBIT_RADIUS;
+        RigidTransform3D::new(Rotation3D::around_y(Angle::radians(angle)), center + offset)
+    }
+
+    fn eye<Eye>(&self, x: f32, viewer: &RigidTransform3D<f32, Viewer, Native>) -> View<Eye> {
+        let transform = RigidTransform3D::from_translation(Vector3D::new(x, 0.0, 0.0));
+        View {
+            transform: transform.then(viewer),
+            projection: util::fov_to_projection_matrix(
+                -HALF_FOV,
+                HALF_FOV,
+                HALF_FOV,
+                -HALF_FOV,
+                self.clip_planes,
+            ),
+            recommended_viewport_scale: None,
+            camera: None,
+        }
+    }
+}
+
+impl DeviceAPI for SimulatorDevice {
+    fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
+        Some(RigidTransform3D::from_translation(Vector3D::new(
+            0.0, HEIGHT, 0.0,
+        )))
+    }
+
+    fn viewports(&self) -> Viewports {
+        Viewports {
+            viewports: vec![
+                Rect::new(Point2D::zero(), VIEWPORT_SIZE),
+                Rect::new(Point2D::new(VIEWPORT_SIZE.width, 0), VIEWPORT_SIZE),
+            ],
+        }
+    }
+
+    fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.layer_manager()?.create_layer(context_id, init)
+    }
+
+    fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.layer_manager()
+            .unwrap()
+            .destroy_layer(context_id, layer_id)
+    }
+
+    fn migrate_context(
+        &mut self,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        self.layer_manager()?
+            .migrate_context(old_context_id, new_context_id)
+    }
+
+    fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
+        let period = Duration::from_secs_f32(1.0 / FRAME_RATE);
+        let now = Instant::now();
+        let frame_time = match self.next_frame_time {
+            Some(next_frame_time) if next_frame_time > now => {
+                thread::sleep(next_frame_time - now);
+                next_frame_time
+            }
+            _ => now,
+        };
+        self.next_frame_time = Some(frame_time + period);
+        let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+
+        let time = frame_time.duration_since(self.start).as_secs_f32();
+        let viewer = self.viewer(time);
+        let views = Views::Stereo(
+            self.eye(-INTER_PUPILLARY_DISTANCE / 2.0, &viewer),
+            self.eye(INTER_PUPILLARY_DISTANCE / 2.0, &viewer),
+        );
+        let controller: RigidTransform3D<f32, Input, Viewer> =
+            RigidTransform3D::from_translation(CONTROLLER_OFFSET);
+        let controller = controller.then(&viewer);
+        let pressed = time % SELECT_INTERVAL < SELECT_DURATION;
+        let input_changed = pressed != self.pressed;
+        let frame = Frame {
+            pose: Some(ViewerPose {
+                transform: viewer,
+                linear_velocity: None,
+                angular_velocity: None,
+                views,
+            }),
+            inputs: vec![InputFrame {
+                id: CONTROLLER_INPUT_ID,
+                target_ray_origin: Some(controller),
+                grip_origin: Some(controller),
+                linear_velocity: None,
+                angular_velocity: None,
+                pressed,
+                squeezed: false,
+                select_value: if pressed { 1.0 } else { 0.0 },
+                squeeze_value: 0.0,
+                hand: None,
+                button_values: vec![if pressed { 1.0 } else { 0.0 }],
+                axis_values: vec![],
+                buttons_pressed: pressed as u32,
+                buttons_touched: pressed as u32,
+                input_changed,
+            }],
+            events: vec![],
+            sub_images,
+            hit_test_results: vec![],
+            tracked_images: vec![],
+            dom_overlay: None,
+            depth_information: None,
+            predicted_display_time: frame_time.duration_since(self.start).as_nanos() as f64,
+            predicted_display_period: Some(1e9 / FRAME_RATE as f64),
+            render_budget: None,
+        };
+        if input_changed {
+            self.pressed = pressed;
+            let event = if pressed {
+                SelectEvent::Start
+            } else {
+                SelectEvent::Select
+            };
+            self.events.callback(Event::Select(
+                CONTROLLER_INPUT_ID,
+                SelectKind::Select,
+                event,
+                SelectGesture::Trigger,
+                Arc::new(frame.for_event()),
+            ));
+        }
+        Some(frame)
+    }
+
+    fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
+        let _ = self.layer_manager().unwrap().end_frame(layers);
+    }
+
+    fn initial_inputs(&self) -> Vec<InputSource> {
+        vec![InputSource {
+            handedness: Handedness::Right,
+            target_ray_mode: TargetRayMode::TrackedPointer,
+            id: CONTROLLER_INPUT_ID,
+            supports_grip: true,
+            hand_support: None,
+            profiles: vec!["generic-trigger".into()],
+        }]
+    }
+
+    fn set_event_dest(&mut self, dest: Sender<Event>) {
+        self.events.upgrade(dest)
+    }
+
+    fn quit(&mut self) {
+        self.events.callback(Event::SessionEnd);
+    }
+
+    fn shutdown(&mut self) {
+        self.layer_manager = None;
+    }
+
+    fn set_quitter(&mut self, _: Quitter) {
+        // The simulator never ends sessions itself
+    }
+
+    fn update_clip_planes(&mut self, near: f32, far: f32) {
+        self.clip_planes.update(near, far);
+    }
+
+    fn granted_features(&self) -> &[Feature] {
+        &self.granted_features
+    }
+
+    fn supported_frame_rates(&self) -> Vec<f32> {
+        vec![FRAME_RATE]
+    }
+}
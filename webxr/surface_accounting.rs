@@ -0,0 +1,80 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Debug accounting of the surface textures handed out by layer managers.
+//!
+//! With the `surface-accounting` feature enabled, layer managers count the surface textures
+//! they create and destroy for each layer, and report any imbalance when a layer or the
+//! layer manager itself is destroyed. Without the feature this is a no-op.
+
+use webxr_api::LayerId;
+
+#[cfg(feature = "surface-accounting")]
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub(crate) struct SurfaceAccounting {
+    #[cfg(feature = "surface-accounting")]
+    layers: HashMap<LayerId, SurfaceCounts>,
+}
+
+#[cfg(feature = "surface-accounting")]
+#[derive(Clone, Copy, Debug, Default)]
+struct SurfaceCounts {
+    created: usize,
+    destroyed: usize,
+}
+
+#[cfg(feature = "surface-accounting")]
+impl SurfaceAccounting {
+    pub(crate) fn created(&mut self, layer_id: LayerId) {
+        self.layers.entry(layer_id).or_default().created += 1;
+    }
+
+    pub(crate) fn destroyed(&mut self, layer_id: LayerId) {
+        self.layers.entry(layer_id).or_default().destroyed += 1;
+    }
+
+    pub(crate) fn layer_destroyed(&mut self, layer_id: LayerId) {
+        if let Some(counts) = self.layers.remove(&layer_id) {
+            report(layer_id, counts);
+        }
+    }
+}
+
+#[cfg(feature = "surface-accounting")]
+impl Drop for SurfaceAccounting {
+    fn drop(&mut self) {
+        for (layer_id, counts) in self.layers.drain() {
+            report(layer_id, counts);
+        }
+    }
+}
+
+#[cfg(feature = "surface-accounting")]
+fn report(layer_id: LayerId, counts: SurfaceCounts) {
+    if counts.created == counts.destroyed {
+        log::debug!(
+            "{:?}: {} surface textures released",
+            layer_id,
+            counts.created
+        );
+    } else {
+        log::warn!(
+            "{:?}: {} surface textures created but {} destroyed",
+            layer_id,
+            counts.created,
+            counts.destroyed
+        );
+    }
+}
+
+#[cfg(not(feature = "surface-accounting"))]
+impl SurfaceAccounting {
+    pub(crate) fn created(&mut self, _: LayerId) {}
+
+    pub(crate) fn destroyed(&mut self, _: LayerId) {}
+
+    pub(crate) fn layer_destroyed(&mut self, _: LayerId) {}
+}
@@ -4,18 +4,28 @@
 
 //! An implementation of layer management using surfman
 
-use crate::gl_utils::GlClearer;
+use crate::gl_utils::{self, ColorAttachment, GlClearer};
+use crate::surface_accounting::SurfaceAccounting;
 use euclid::{Point2D, Rect, Size2D};
-use glow::{self as gl, Context as Gl, HasContext, PixelUnpackData};
+use glow::{self as gl, Context as Gl, HasContext};
+use log::warn;
 use std::collections::HashMap;
+use std::mem;
 use std::num::NonZeroU32;
 use surfman::chains::{PreserveBuffer, SwapChains, SwapChainsAPI};
 use surfman::{Context as SurfmanContext, Device as SurfmanDevice, SurfaceAccess, SurfaceTexture};
 use webxr_api::{
-    ContextId, Error, GLContexts, GLTypes, LayerId, LayerInit, LayerManagerAPI, SubImage,
-    SubImages, Viewports,
+    Backend, ColorFormat, ContextId, Error, GLContexts, GLTypes, LatencyMarker, LayerId, LayerInit,
+    LayerManagerAPI, SubImage, SubImages, Viewport, Viewports,
 };
 
+// The number of surfaces a swap chain holds on to for most of a frame,
+// the one content renders to and the one that was last presented
+const SURFACES_PER_SWAP_CHAIN: u64 = 2;
+
+/// A callback that is given the RGBA8 contents of each layer at the end of a frame
+pub type LayerContentsCallback = Box<dyn Send + FnMut(LayerId, Size2D<i32, Viewport>, Vec<u8>)>;
+
 #[derive(Copy, Clone, Debug)]
 pub enum SurfmanGL {}
 
@@ -32,6 +42,10 @@ pub struct SurfmanLayerManager {
     depth_stencil_textures: HashMap<LayerId, Option<gl::NativeTexture>>,
     viewports: Viewports,
     clearer: GlClearer,
+    accounting: SurfaceAccounting,
+    contents_callback: Option<LayerContentsCallback>,
+    vignette: f32,
+    latency_marker: Option<LatencyMarker>,
 }
 
 impl SurfmanLayerManager {
@@ -43,6 +57,8 @@ impl SurfmanLayerManager {
         let surface_textures = HashMap::new();
         let depth_stencil_textures = HashMap::new();
         let clearer = GlClearer::new(false);
+        let accounting = SurfaceAccounting::default();
+        let contents_callback = None;
         SurfmanLayerManager {
             layers,
             swap_chains,
@@ -50,46 +66,156 @@ impl SurfmanLayerManager {
             depth_stencil_textures,
             viewports,
             clearer,
+            accounting,
+            contents_callback,
+            vignette: 0.0,
+            latency_marker: None,
         }
     }
-}
 
-impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
-    fn create_layer(
+    /// Read back the contents of every layer at the end of each frame.
+    /// This is slow, and is intended for testing.
+    pub fn with_contents_callback(mut self, callback: LayerContentsCallback) -> Self {
+        self.contents_callback = Some(callback);
+        self
+    }
+
+    fn draw_vignette(
         &mut self,
         device: &mut SurfmanDevice,
         contexts: &mut dyn GLContexts<SurfmanGL>,
         context_id: ContextId,
-        init: LayerInit,
-    ) -> Result<LayerId, Error> {
-        let texture_size = init.texture_size(&self.viewports);
-        let layer_id = LayerId::new();
-        let access = SurfaceAccess::GPUOnly;
-        let size = texture_size.to_untyped();
-        // TODO: Treat depth and stencil separately?
-        let has_depth_stencil = match init {
-            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
-            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
+        layer_id: LayerId,
+    ) {
+        if self.vignette <= 0.0 {
+            return;
+        }
+        let surface_texture = match self.surface_textures.get(&layer_id) {
+            Some(surface_texture) => surface_texture,
+            None => return,
+        };
+        let color_texture = device.surface_texture_object(surface_texture);
+        let color_target = device.surface_gl_texture_target();
+        let depth_stencil_texture = self
+            .depth_stencil_textures
+            .get(&layer_id)
+            .cloned()
+            .flatten();
+        self.clearer.vignette(
+            device,
+            contexts,
+            context_id,
+            layer_id,
+            ColorAttachment::Texture(
+                NonZeroU32::new(color_texture).map(gl::NativeTexture),
+                color_target,
+            ),
+            depth_stencil_texture,
+            gl::TEXTURE_2D,
+            &self.viewports.viewports,
+            self.vignette,
+        );
+    }
+
+    fn draw_latency_marker(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+    ) {
+        let marker = match self.latency_marker {
+            Some(marker) => marker,
+            None => return,
+        };
+        let surface_texture = match self.surface_textures.get(&layer_id) {
+            Some(surface_texture) => surface_texture,
+            None => return,
         };
+        let color_texture = device.surface_texture_object(surface_texture);
+        let color_target = device.surface_gl_texture_target();
+        let depth_stencil_texture = self
+            .depth_stencil_textures
+            .get(&layer_id)
+            .cloned()
+            .flatten();
+        self.clearer.latency_marker(
+            device,
+            contexts,
+            context_id,
+            layer_id,
+            ColorAttachment::Texture(
+                NonZeroU32::new(color_texture).map(gl::NativeTexture),
+                color_target,
+            ),
+            depth_stencil_texture,
+            gl::TEXTURE_2D,
+            &self.viewports.viewports,
+            marker,
+        );
+    }
+
+    fn export_contents(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+    ) {
+        let callback = match self.contents_callback {
+            Some(ref mut callback) => callback,
+            None => return,
+        };
+        let (surface_texture, swap_chain) = match (
+            self.surface_textures.get(&layer_id),
+            self.swap_chains.get(layer_id),
+        ) {
+            (Some(surface_texture), Some(swap_chain)) => (surface_texture, swap_chain),
+            _ => return,
+        };
+        let size = Size2D::from_untyped(swap_chain.size());
+        let color_texture = device.surface_texture_object(surface_texture);
+        let color_target = device.surface_gl_texture_target();
+        let depth_stencil_texture = self
+            .depth_stencil_textures
+            .get(&layer_id)
+            .cloned()
+            .flatten();
+        let pixels = self.clearer.read_pixels(
+            device,
+            contexts,
+            context_id,
+            layer_id,
+            ColorAttachment::Texture(
+                NonZeroU32::new(color_texture).map(gl::NativeTexture),
+                color_target,
+            ),
+            depth_stencil_texture,
+            gl::TEXTURE_2D,
+            size,
+        );
+        if let Some(pixels) = pixels {
+            callback(layer_id, size, pixels);
+        }
+    }
+
+    fn create_layer_surfaces(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+        size: Size2D<i32, Viewport>,
+        has_depth_stencil: bool,
+    ) -> Result<(), Error> {
+        let access = SurfaceAccess::GPUOnly;
+        let size = size.to_untyped();
         if has_depth_stencil {
             let gl = contexts
                 .bindings(device, context_id)
                 .ok_or(Error::NoMatchingDevice)?;
-            let depth_stencil_texture = unsafe { gl.create_texture().ok() };
-            unsafe {
-                gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
-                gl.tex_image_2d(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::DEPTH24_STENCIL8 as _,
-                    size.width,
-                    size.height,
-                    0,
-                    gl::DEPTH_STENCIL,
-                    gl::UNSIGNED_INT_24_8,
-                    PixelUnpackData::Slice(None),
-                );
-            }
+            let depth_stencil_texture =
+                gl_utils::create_depth_stencil_texture(gl, Size2D::from_untyped(size), 1);
             self.depth_stencil_textures
                 .insert(layer_id, depth_stencil_texture);
         }
@@ -98,25 +224,20 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
             .ok_or(Error::NoMatchingDevice)?;
         self.swap_chains
             .create_detached_swap_chain(layer_id, size, device, context, access)
-            .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
-        self.layers.push((context_id, layer_id));
-        Ok(layer_id)
+            .map_err(|err| Error::SwapchainCreationFailed(Backend::Surfman, format!("{:?}", err)))
     }
 
-    fn destroy_layer(
+    fn destroy_layer_surfaces(
         &mut self,
         device: &mut SurfmanDevice,
         contexts: &mut dyn GLContexts<SurfmanGL>,
         context_id: ContextId,
         layer_id: LayerId,
     ) {
-        self.clearer
-            .destroy_layer(device, contexts, context_id, layer_id);
         let context = match contexts.context(device, context_id) {
             Some(context) => context,
             None => return,
         };
-        self.layers.retain(|&ids| ids != (context_id, layer_id));
         let _ = self.swap_chains.destroy(layer_id, device, context);
         self.surface_textures.remove(&layer_id);
         if let Some(depth_stencil_texture) = self.depth_stencil_textures.remove(&layer_id) {
@@ -128,11 +249,151 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
             }
         }
     }
+}
+
+impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
+    fn create_layer(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        init: LayerInit,
+    ) -> Result<LayerId, Error> {
+        // Surfman surfaces can't be multisampled and are always RGBA8, so layers
+        // are single-sampled whatever they ask for, and fail if RGBA8 won't do.
+        init.pick_color_format(&[ColorFormat::Rgba8])
+            .ok_or_else(|| {
+                Error::SwapchainCreationFailed(
+                    Backend::Surfman,
+                    String::from("surfman layers can only be RGBA8"),
+                )
+            })?;
+        let texture_size = init.texture_size(&self.viewports);
+        let layer_id = LayerId::new();
+        // TODO: Treat depth and stencil separately?
+        let has_depth_stencil = match init {
+            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
+            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
+            LayerInit::CompositionLayer { .. } => false,
+        };
+        self.create_layer_surfaces(
+            device,
+            contexts,
+            context_id,
+            layer_id,
+            texture_size,
+            has_depth_stencil,
+        )?;
+        self.layers.push((context_id, layer_id));
+        Ok(layer_id)
+    }
+
+    fn destroy_layer(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        context_id: ContextId,
+        layer_id: LayerId,
+    ) {
+        self.accounting.layer_destroyed(layer_id);
+        self.clearer
+            .destroy_layer(device, contexts, context_id, layer_id);
+        if contexts.context(device, context_id).is_none() {
+            return;
+        }
+        self.layers.retain(|&ids| ids != (context_id, layer_id));
+        self.destroy_layer_surfaces(device, contexts, context_id, layer_id);
+    }
+
+    fn migrate_context(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        old_context_id: ContextId,
+        new_context_id: ContextId,
+    ) -> Result<(), Error> {
+        for index in 0..self.layers.len() {
+            let (context_id, layer_id) = self.layers[index];
+            if context_id != old_context_id {
+                continue;
+            }
+            let size = self
+                .swap_chains
+                .get(layer_id)
+                .ok_or(Error::NoMatchingDevice)?
+                .size();
+            let size = Size2D::from_untyped(size);
+            let has_depth_stencil = self.depth_stencil_textures.contains_key(&layer_id);
+            self.clearer
+                .destroy_layer(device, contexts, old_context_id, layer_id);
+            if contexts.context(device, old_context_id).is_some() {
+                self.destroy_layer_surfaces(device, contexts, old_context_id, layer_id);
+            } else {
+                // The old context is gone, and its GL resources went with it,
+                // so there is nothing left to destroy. The stale swap chain is
+                // replaced when we create the new one.
+                warn!("Context {:?} lost, leaking its surfaces", old_context_id);
+                if let Some(surface_texture) = self.surface_textures.remove(&layer_id) {
+                    mem::forget(surface_texture);
+                }
+                self.depth_stencil_textures.remove(&layer_id);
+            }
+            self.create_layer_surfaces(
+                device,
+                contexts,
+                new_context_id,
+                layer_id,
+                size,
+                has_depth_stencil,
+            )?;
+            self.layers[index] = (new_context_id, layer_id);
+        }
+        Ok(())
+    }
 
     fn layers(&self) -> &[(ContextId, LayerId)] {
         &self.layers[..]
     }
 
+    fn swapchain_count(&self) -> Option<usize> {
+        // Every layer has its own swap chain
+        Some(self.layers.len())
+    }
+
+    fn texture_memory(&self) -> Option<u64> {
+        let memory = self
+            .layers
+            .iter()
+            .filter_map(|&(_, layer_id)| {
+                let size = Size2D::from_untyped(self.swap_chains.get(layer_id)?.size());
+                let mut memory = gl_utils::texture_memory(size, 1) * SURFACES_PER_SWAP_CHAIN;
+                if let Some(Some(_)) = self.depth_stencil_textures.get(&layer_id) {
+                    memory += gl_utils::texture_memory(size, 1);
+                }
+                Some(memory)
+            })
+            .sum();
+        Some(memory)
+    }
+
+    fn set_vignette(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        strength: f32,
+    ) {
+        self.vignette = strength;
+    }
+
+    fn set_latency_marker(
+        &mut self,
+        _device: &mut SurfmanDevice,
+        _contexts: &mut dyn GLContexts<SurfmanGL>,
+        marker: Option<LatencyMarker>,
+    ) {
+        self.latency_marker = marker;
+    }
+
     fn begin_frame(
         &mut self,
         device: &mut SurfmanDevice,
@@ -153,6 +414,7 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                 let surface_texture = swap_chain
                     .take_surface_texture(device, context)
                     .map_err(|_| Error::NoMatchingDevice)?;
+                self.accounting.created(layer_id);
                 let color_texture = device.surface_texture_object(&surface_texture);
                 let color_target = device.surface_gl_texture_target();
                 let depth_stencil_texture = self
@@ -185,12 +447,18 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                     contexts,
                     context_id,
                     layer_id,
-                    NonZeroU32::new(color_texture).map(gl::NativeTexture),
-                    color_target,
+                    ColorAttachment::Texture(
+                        NonZeroU32::new(color_texture).map(gl::NativeTexture),
+                        color_target,
+                    ),
                     depth_stencil_texture,
+                    gl::TEXTURE_2D,
                 );
                 Ok(SubImages {
                     layer_id,
+                    sample_count: 1,
+                    color_format: ColorFormat::Rgba8,
+                    framebuffer: None,
                     sub_image,
                     view_sub_images,
                 })
@@ -205,12 +473,15 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error> {
         for &(context_id, layer_id) in layers {
+            self.draw_vignette(device, contexts, context_id, layer_id);
+            self.draw_latency_marker(device, contexts, context_id, layer_id);
             let gl = contexts
                 .bindings(device, context_id)
                 .ok_or(Error::NoMatchingDevice)?;
             unsafe {
                 gl.flush();
             }
+            self.export_contents(device, contexts, context_id, layer_id);
             let context = contexts
                 .context(device, context_id)
                 .ok_or(Error::NoMatchingDevice)?;
@@ -224,10 +495,11 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                 .ok_or(Error::NoMatchingDevice)?;
             swap_chain
                 .recycle_surface_texture(device, context, surface_texture)
-                .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+                .map_err(|err| Error::BackendSpecific(Backend::Surfman, format!("{:?}", err)))?;
+            self.accounting.destroyed(layer_id);
             swap_chain
                 .swap_buffers(device, context, PreserveBuffer::No)
-                .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+                .map_err(|err| Error::BackendSpecific(Backend::Surfman, format!("{:?}", err)))?;
         }
         Ok(())
     }